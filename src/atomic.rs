@@ -1,5 +1,9 @@
 use core::sync::atomic::{AtomicU8, Ordering};
 
+use avr_device::interrupt;
+
+use crate::key_defs::Key;
+
 pub struct AtomicU32 {
     inner: [AtomicU8; 4],
 }
@@ -27,3 +31,292 @@ impl AtomicU32 {
         }
     }
 }
+
+pub struct AtomicU16 {
+    inner: [AtomicU8; 2],
+}
+
+impl AtomicU16 {
+    pub const fn new(n: u16) -> Self {
+        let b = n.to_le_bytes();
+        Self { inner: [AtomicU8::new(b[0]), AtomicU8::new(b[1])] }
+    }
+
+    /// Loads the value.
+    ///
+    /// Runs in a critical section, so the bytes cannot be torn by an interrupt.
+    pub fn load(&self, ordering: Ordering) -> u16 {
+        interrupt::free(|_cs| self.load_bytes(ordering))
+    }
+
+    /// Stores the value.
+    ///
+    /// Runs in a critical section, so the bytes cannot be torn by an interrupt.
+    pub fn store(&self, n: u16, ordering: Ordering) {
+        interrupt::free(|_cs| self.store_bytes(n, ordering))
+    }
+
+    /// Adds to the current value, wrapping on overflow, and returns the previous value.
+    ///
+    /// The read-modify-write runs in a critical section, `ordering` is accepted for
+    /// parity with the core atomics.
+    pub fn fetch_add(&self, n: u16, ordering: Ordering) -> u16 {
+        let _ = ordering;
+
+        interrupt::free(|_cs| {
+            let prev = self.load_bytes(Ordering::SeqCst);
+            self.store_bytes(prev.wrapping_add(n), Ordering::SeqCst);
+            prev
+        })
+    }
+
+    /// Stores `new` if the current value is equal to `current`.
+    ///
+    /// Returns the previous value, wrapped in `Ok` if the value was updated, and in `Err`
+    /// otherwise. Runs in a critical section, the orderings are accepted for parity with
+    /// the core atomics.
+    pub fn compare_exchange(
+        &self,
+        current: u16,
+        new: u16,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<u16, u16> {
+        let _ = (success, failure);
+
+        interrupt::free(|_cs| {
+            let prev = self.load_bytes(Ordering::SeqCst);
+
+            if prev == current {
+                self.store_bytes(new, Ordering::SeqCst);
+                Ok(prev)
+            } else {
+                Err(prev)
+            }
+        })
+    }
+
+    fn load_bytes(&self, ordering: Ordering) -> u16 {
+        u16::from_le_bytes([self.inner[0].load(ordering), self.inner[1].load(ordering)])
+    }
+
+    fn store_bytes(&self, n: u16, ordering: Ordering) {
+        let b = n.to_le_bytes();
+
+        for (dst, &src) in self.inner.iter().zip(b.iter()) {
+            dst.store(src, ordering);
+        }
+    }
+}
+
+pub struct AtomicI16 {
+    inner: [AtomicU8; 2],
+}
+
+impl AtomicI16 {
+    pub const fn new(n: i16) -> Self {
+        let b = n.to_le_bytes();
+        Self { inner: [AtomicU8::new(b[0]), AtomicU8::new(b[1])] }
+    }
+
+    /// Loads the value.
+    ///
+    /// Runs in a critical section, so the bytes cannot be torn by an interrupt.
+    pub fn load(&self, ordering: Ordering) -> i16 {
+        interrupt::free(|_cs| self.load_bytes(ordering))
+    }
+
+    /// Stores the value.
+    ///
+    /// Runs in a critical section, so the bytes cannot be torn by an interrupt.
+    pub fn store(&self, n: i16, ordering: Ordering) {
+        interrupt::free(|_cs| self.store_bytes(n, ordering))
+    }
+
+    /// Adds to the current value, wrapping on overflow, and returns the previous value.
+    ///
+    /// The read-modify-write runs in a critical section, `ordering` is accepted for
+    /// parity with the core atomics.
+    pub fn fetch_add(&self, n: i16, ordering: Ordering) -> i16 {
+        let _ = ordering;
+
+        interrupt::free(|_cs| {
+            let prev = self.load_bytes(Ordering::SeqCst);
+            self.store_bytes(prev.wrapping_add(n), Ordering::SeqCst);
+            prev
+        })
+    }
+
+    /// Stores `new` if the current value is equal to `current`.
+    ///
+    /// Returns the previous value, wrapped in `Ok` if the value was updated, and in `Err`
+    /// otherwise. Runs in a critical section, the orderings are accepted for parity with
+    /// the core atomics.
+    pub fn compare_exchange(
+        &self,
+        current: i16,
+        new: i16,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<i16, i16> {
+        let _ = (success, failure);
+
+        interrupt::free(|_cs| {
+            let prev = self.load_bytes(Ordering::SeqCst);
+
+            if prev == current {
+                self.store_bytes(new, Ordering::SeqCst);
+                Ok(prev)
+            } else {
+                Err(prev)
+            }
+        })
+    }
+
+    fn load_bytes(&self, ordering: Ordering) -> i16 {
+        i16::from_le_bytes([self.inner[0].load(ordering), self.inner[1].load(ordering)])
+    }
+
+    fn store_bytes(&self, n: i16, ordering: Ordering) {
+        let b = n.to_le_bytes();
+
+        for (dst, &src) in self.inner.iter().zip(b.iter()) {
+            dst.store(src, ordering);
+        }
+    }
+}
+
+/// A [Key] shared between interrupts and the main loop, without taking a lock.
+///
+/// AVR has no 16-bit atomics, so the key is held in an [AtomicU16], whose accesses run in
+/// critical sections. The orderings are accepted for parity with the core atomics.
+///
+/// Example:
+///
+/// ```rust
+/// use core::sync::atomic::Ordering;
+/// use kaleidoscope::{atomic::AtomicKey, key_defs::*};
+///
+/// static PENDING: AtomicKey = AtomicKey::new(Key_NoKey);
+///
+/// PENDING.store(Key_A, Ordering::SeqCst);
+/// assert_eq!(PENDING.load(Ordering::SeqCst), Key_A);
+///
+/// // Only an empty slot is filled.
+/// assert_eq!(PENDING.compare_exchange(Key_NoKey, Key_B, Ordering::SeqCst, Ordering::SeqCst), Err(Key_A));
+/// assert_eq!(PENDING.swap(Key_NoKey, Ordering::SeqCst), Key_A);
+/// assert_eq!(PENDING.compare_exchange(Key_NoKey, Key_B, Ordering::SeqCst, Ordering::SeqCst), Ok(Key_NoKey));
+/// assert_eq!(PENDING.load(Ordering::SeqCst), Key_B);
+/// ```
+pub struct AtomicKey {
+    inner: AtomicU16,
+}
+
+impl AtomicKey {
+    /// Creates a new [AtomicKey] holding `key`.
+    pub const fn new(key: Key) -> Self {
+        Self { inner: AtomicU16::new(key.raw()) }
+    }
+
+    /// Loads the key.
+    pub fn load(&self, ordering: Ordering) -> Key {
+        Key::from_raw(self.inner.load(ordering))
+    }
+
+    /// Stores the key.
+    pub fn store(&self, key: Key, ordering: Ordering) {
+        self.inner.store(key.raw(), ordering)
+    }
+
+    /// Stores `key`, and returns the previous key.
+    pub fn swap(&self, key: Key, ordering: Ordering) -> Key {
+        let _ = ordering;
+
+        interrupt::free(|_cs| {
+            let prev = self.inner.load_bytes(Ordering::SeqCst);
+            self.inner.store_bytes(key.raw(), Ordering::SeqCst);
+            Key::from_raw(prev)
+        })
+    }
+
+    /// Stores `new` if the current key is equal to `current`.
+    ///
+    /// Returns the previous key, wrapped in `Ok` if the key was updated, and in `Err`
+    /// otherwise.
+    pub fn compare_exchange(
+        &self,
+        current: Key,
+        new: Key,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Key, Key> {
+        self.inner
+            .compare_exchange(current.raw(), new.raw(), success, failure)
+            .map(Key::from_raw)
+            .map_err(Key::from_raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_u16_round_trip() {
+        let atomic = AtomicU16::new(0x1234);
+        assert_eq!(atomic.load(Ordering::SeqCst), 0x1234);
+
+        for n in [0, 1, 0xff, 0x100, 0xabcd, u16::MAX] {
+            atomic.store(n, Ordering::SeqCst);
+            assert_eq!(atomic.load(Ordering::SeqCst), n);
+        }
+    }
+
+    #[test]
+    fn atomic_u16_fetch_add_wraps() {
+        let atomic = AtomicU16::new(0x00ff);
+
+        // Carries into the high byte.
+        assert_eq!(atomic.fetch_add(1, Ordering::SeqCst), 0x00ff);
+        assert_eq!(atomic.load(Ordering::SeqCst), 0x0100);
+
+        atomic.store(u16::MAX - 1, Ordering::SeqCst);
+        assert_eq!(atomic.fetch_add(3, Ordering::SeqCst), u16::MAX - 1);
+        assert_eq!(atomic.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn atomic_u16_compare_exchange() {
+        let atomic = AtomicU16::new(7);
+
+        assert_eq!(atomic.compare_exchange(8, 9, Ordering::SeqCst, Ordering::SeqCst), Err(7));
+        assert_eq!(atomic.compare_exchange(7, 0x1ff, Ordering::SeqCst, Ordering::SeqCst), Ok(7));
+        assert_eq!(atomic.load(Ordering::SeqCst), 0x1ff);
+    }
+
+    #[test]
+    fn atomic_i16_round_trip() {
+        let atomic = AtomicI16::new(-2);
+        assert_eq!(atomic.load(Ordering::SeqCst), -2);
+
+        for n in [0, -1, 1, 0x7f, -0x80, 0x100, i16::MIN, i16::MAX] {
+            atomic.store(n, Ordering::SeqCst);
+            assert_eq!(atomic.load(Ordering::SeqCst), n);
+        }
+    }
+
+    #[test]
+    fn atomic_i16_fetch_add_wraps() {
+        let atomic = AtomicI16::new(-1);
+
+        assert_eq!(atomic.fetch_add(1, Ordering::SeqCst), -1);
+        assert_eq!(atomic.load(Ordering::SeqCst), 0);
+
+        atomic.store(i16::MAX, Ordering::SeqCst);
+        assert_eq!(atomic.fetch_add(1, Ordering::SeqCst), i16::MAX);
+        assert_eq!(atomic.load(Ordering::SeqCst), i16::MIN);
+
+        assert_eq!(atomic.fetch_add(-1, Ordering::SeqCst), i16::MIN);
+        assert_eq!(atomic.load(Ordering::SeqCst), i16::MAX);
+    }
+}
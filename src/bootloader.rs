@@ -1,10 +1,174 @@
 use crate::device::FLASHEND;
+use crate::driver::board::{BoardProps, DeviceProps};
+use crate::driver::bootloader::{avr::{Caterina, Dfu, HalfKay}, Base};
+use crate::lock;
+
+pub use crate::driver::bootloader::avr::{BOOT_KEY, BOOT_KEY_PTR};
 
 pub const NEW_LUFA_SIGNATURE: u16 = 0xdcfb;
 
+/// LUFA bootloader class signature of CDC (Caterina-compatible) bootloaders.
+pub const LUFA_CDC_SIGNATURE: u16 = 0xdf00;
+/// LUFA bootloader class signature of DFU bootloaders.
+pub const LUFA_DFU_SIGNATURE: u16 = 0xdf10;
+
+/// Start of the 512 byte boot section used by HalfKay.
+pub const HALFKAY_START: u16 = 0x7e00;
+/// Start of the 4 KiB boot section used by Caterina and DFU bootloaders.
+pub const BOOT_START_4K: u16 = 0x7000;
+
+/// Value of an erased flash word.
+const ERASED_WORD: u16 = 0xffff;
+
+/// Bootloader selected from the board properties, or detected by the runtime at setup.
+static BOOTLOADER: lock::Spinlock<BootloaderKind> = lock::Spinlock::new(match <DeviceProps as BoardProps>::BOOTLOADER {
+    Some(kind) => kind,
+    None => BootloaderKind::Unknown,
+});
+
+/// Gets the bootloader selected from the board properties, or detected at setup.
+///
+/// Unlike [Runtime::bootloader](crate::runtime::Runtime::bootloader), this does not
+/// borrow the runtime, so it can be used from plugin handlers.
+pub fn active_bootloader() -> BootloaderKind {
+    *BOOTLOADER.read()
+}
+
+/// Sets the bootloader returned by [active_bootloader], called by the runtime at setup.
+pub fn set_active_bootloader(kind: BootloaderKind) {
+    *BOOTLOADER.write() = kind;
+}
+
+/// Bootloaders the firmware knows how to reboot into.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BootloaderKind {
+    /// Arduino Caterina, or another AVR109 (CDC) bootloader.
+    Caterina,
+    /// PJRC HalfKay, used on Teensy boards.
+    HalfKay,
+    /// Atmel or LUFA DFU bootloader.
+    Dfu,
+    /// No known signature was found, rebooting uses the Caterina method.
+    Unknown,
+}
+
+impl BootloaderKind {
+    /// Reboots into this bootloader.
+    pub fn reboot_bootloader(self) -> ! {
+        match self {
+            Self::HalfKay => HalfKay::reboot_bootloader(),
+            Self::Dfu => Dfu::reboot_bootloader(),
+            Self::Caterina | Self::Unknown => Caterina::reboot_bootloader(),
+        }
+    }
+}
+
+/// Clears the Caterina magic key, if a bootloader request left it set.
+///
+/// Caterina clears the key itself when it starts the sketch, but a key written by a
+/// bootloader request that never reset the MCU, or one left over after flashing, would
+/// keep the next watchdog or external reset in the bootloader. Called early in
+/// [Runtime::setup](crate::runtime::Runtime::setup). Returns whether the key was set.
+pub fn clear_boot_key() -> bool {
+    // SAFETY: BOOT_KEY_PTR is the RAM word Caterina reserves for the magic key.
+    clear_boot_key_with(
+        || unsafe { core::ptr::read_volatile(BOOT_KEY_PTR as *const u16) },
+        |value| unsafe { core::ptr::write_volatile(BOOT_KEY_PTR as *mut u16, value) },
+    )
+}
+
+/// Clears the Caterina magic key, using the provided memory reader and writer.
+///
+/// The word is only written when it holds [BOOT_KEY], so memory is left untouched on a
+/// normal power-on.
+///
+/// Example:
+///
+/// ```rust
+/// use core::cell::Cell;
+/// use kaleidoscope::bootloader::{clear_boot_key_with, BOOT_KEY};
+///
+/// let word = Cell::new(BOOT_KEY);
+/// let writes = Cell::new(0);
+/// let write = |value| {
+///     word.set(value);
+///     writes.set(writes.get() + 1);
+/// };
+///
+/// // A stale key is cleared.
+/// assert!(clear_boot_key_with(|| word.get(), write));
+/// assert_eq!(word.get(), 0);
+/// assert_eq!(writes.get(), 1);
+///
+/// // Once cleared, or if it was never set, nothing is written.
+/// assert!(!clear_boot_key_with(|| word.get(), write));
+/// word.set(0x1234);
+/// assert!(!clear_boot_key_with(|| word.get(), write));
+/// assert_eq!(word.get(), 0x1234);
+/// assert_eq!(writes.get(), 1);
+/// ```
+pub fn clear_boot_key_with<R: FnOnce() -> u16, W: FnOnce(u16)>(read: R, write: W) -> bool {
+    if read() != BOOT_KEY {
+        return false;
+    }
+
+    write(0);
+
+    true
+}
+
+/// Reads a word from program memory.
+pub fn read_flash_word(addr: u16) -> u16 {
+    // SAFETY: every address below FLASHEND is valid program memory.
+    unsafe { avr_progmem::raw::read_value(addr as *const u16) }
+}
+
 pub fn is_lufa_bootloader() -> bool {
-    let addr = (FLASHEND - 1) as u32;
-    let sig = unsafe { core::ptr::read_volatile(addr as *const u32) };
+    read_flash_word(FLASHEND as u16 - 1) == NEW_LUFA_SIGNATURE
+}
+
+/// Detects the bootloader from its signature in flash.
+pub fn detect_bootloader() -> BootloaderKind {
+    detect_bootloader_with(read_flash_word)
+}
+
+/// Detects the bootloader from its signature, using the provided flash reader.
+///
+/// LUFA-based bootloaders store a signature at the end of flash, followed by their class.
+/// HalfKay is recognized by its boot section, the only one occupying the last 512 bytes
+/// with the rest of the 4 KiB boot region erased.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{bootloader::*, device::FLASHEND};
+///
+/// let lufa_dfu = |addr: u16| match addr {
+///     a if a == FLASHEND as u16 - 1 => NEW_LUFA_SIGNATURE,
+///     a if a == FLASHEND as u16 - 3 => LUFA_DFU_SIGNATURE,
+///     _ => 0xffff,
+/// };
+/// let halfkay = |addr: u16| if addr == HALFKAY_START { 0x940c } else { 0xffff };
+///
+/// assert_eq!(detect_bootloader_with(lufa_dfu), BootloaderKind::Dfu);
+/// assert_eq!(detect_bootloader_with(halfkay), BootloaderKind::HalfKay);
+/// assert_eq!(detect_bootloader_with(|_| 0xffff), BootloaderKind::Unknown);
+/// ```
+pub fn detect_bootloader_with<F: Fn(u16) -> u16>(read_word: F) -> BootloaderKind {
+    let flashend = FLASHEND as u16;
+
+    if read_word(flashend - 1) == NEW_LUFA_SIGNATURE {
+        return match read_word(flashend - 3) {
+            LUFA_CDC_SIGNATURE => BootloaderKind::Caterina,
+            LUFA_DFU_SIGNATURE => BootloaderKind::Dfu,
+            _ => BootloaderKind::Unknown,
+        };
+    }
+
+    if read_word(BOOT_START_4K) == ERASED_WORD && read_word(HALFKAY_START) != ERASED_WORD {
+        return BootloaderKind::HalfKay;
+    }
 
-    sig == (NEW_LUFA_SIGNATURE as u32)
+    BootloaderKind::Unknown
 }
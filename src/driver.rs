@@ -1,7 +1,12 @@
+pub mod board;
 pub mod bootloader;
 pub mod hid;
 pub mod keyscanner;
 pub mod led;
 pub mod mcu;
+pub mod serial;
+pub mod signature;
+pub mod split;
+pub mod storage;
 //pub mod usb;
 pub mod wdt;
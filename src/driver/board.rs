@@ -0,0 +1,68 @@
+use crate::bootloader::BootloaderKind;
+use crate::device::DeviceOps;
+use crate::driver::hid::settings::{UsbIdentity, UsbPower};
+use crate::driver::keyscanner::KeyScannerProps;
+use crate::driver::led::Rgb;
+use crate::key_addr::KeyAddr;
+
+#[cfg(feature = "atreus")]
+pub use crate::plugins::atreus::{Device, DeviceProps};
+#[cfg(feature = "technomancy_atreus")]
+pub use crate::plugins::technomancy_atreus::{Device, DeviceProps};
+
+/// Static description of a keyboard.
+///
+/// Declaring a new board only requires implementing this trait (along with
+/// [KeyScannerProps]), and [Board] for the device type.
+pub trait BoardProps: KeyScannerProps {
+    /// Short name of the board.
+    const SHORT_NAME: &'static str;
+
+    /// Matrix row pins, must contain [ROWS](KeyScannerProps::ROWS) entries.
+    const MATRIX_ROW_PINS: &'static [u8];
+    /// Matrix column pins, at most [COLS](KeyScannerProps::COLS) entries.
+    ///
+    /// Columns past the last pin have no switches, and are never pressed.
+    const MATRIX_COL_PINS: &'static [u8];
+
+    /// Number of layers in the board's keymap.
+    const NUM_LAYERS: usize;
+
+    /// Number of LEDs on the board.
+    const LED_COUNT: usize = 0;
+
+    /// Bootloader shipped with the board, `None` to detect it from flash at setup.
+    const BOOTLOADER: Option<BootloaderKind> = None;
+
+    /// USB identity the board enumerates with.
+    const USB_IDENTITY: UsbIdentity = UsbIdentity::board_default();
+
+    /// USB power configuration the board advertises, e.g. a lower current for low-power
+    /// boards.
+    const USB_POWER: UsbPower = UsbPower::DEFAULT;
+
+    /// Keys which, all held at power-on, reboot into the bootloader before the firmware
+    /// starts. A recovery path for boards without a reset button, empty to disable.
+    const BOOT_COMBO: &'static [KeyAddr] = &[];
+}
+
+/// Keyboard device driven by the [Runtime](crate::runtime::Runtime).
+pub trait Board: DeviceOps {
+    /// Static description of the board.
+    type Props: BoardProps;
+
+    /// Scans the key matrix, handling any keyswitch events.
+    fn scan_matrix(&mut self);
+
+    /// Gets the number of LEDs on the board.
+    fn led_count() -> usize {
+        Self::Props::LED_COUNT
+    }
+
+    /// Sends the LED colors to the hardware.
+    ///
+    /// Boards without LEDs keep the default, which does nothing.
+    fn sync_leds(&mut self, leds: &[Rgb]) {
+        let _ = leds;
+    }
+}
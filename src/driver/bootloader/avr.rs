@@ -1,3 +1,25 @@
 mod caterina;
+mod dfu;
+mod halfkay;
 
-pub use caterina::Caterina;
+pub use caterina::{Caterina, BOOT_KEY, BOOT_KEY_PTR};
+pub use dfu::Dfu;
+pub use halfkay::HalfKay;
+
+use arduino_hal::pac;
+
+/// Detaches from USB, and disables interrupts, before jumping into a bootloader.
+///
+/// Bootloaders entered by a jump, rather than a reset, start with the peripherals in
+/// whatever state the firmware left them.
+pub(crate) fn prepare_jump() {
+    avr_device::interrupt::disable();
+
+    // SAFETY: interrupts are disabled, and the firmware never runs again after the jump.
+    let usb = unsafe { &*pac::USB_DEVICE::ptr() };
+    usb.udcon.modify(|_, w| w.detach().set_bit());
+    usb.usbcon.modify(|_, w| w.frzclk().set_bit());
+
+    // Give the host time to notice the detach.
+    arduino_hal::delay_ms(5);
+}
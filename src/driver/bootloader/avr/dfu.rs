@@ -0,0 +1,18 @@
+use crate::driver::bootloader::Base;
+
+use super::prepare_jump;
+
+/// Atmel or LUFA DFU bootloader.
+pub struct Dfu;
+
+impl Base for Dfu {
+    /// DFU bootloaders run when entered from the start of the 4 KiB boot section, so the
+    /// firmware detaches from USB, and jumps there.
+    fn reboot_bootloader() -> ! {
+        prepare_jump();
+
+        // SAFETY: the 4 KiB boot section starts at 0x7000 on the ATmega32U4, and the
+        // bootloader never returns.
+        unsafe { core::arch::asm!("jmp 0x7000", options(noreturn)) }
+    }
+}
@@ -0,0 +1,17 @@
+use crate::driver::bootloader::Base;
+
+use super::prepare_jump;
+
+/// PJRC HalfKay bootloader, found on Teensy boards.
+pub struct HalfKay;
+
+impl Base for HalfKay {
+    /// HalfKay has no magic key, so the firmware detaches from USB, and jumps to the
+    /// start of its 512 byte boot section.
+    fn reboot_bootloader() -> ! {
+        prepare_jump();
+
+        // SAFETY: HalfKay starts at 0x7e00 on the ATmega32U4, and never returns.
+        unsafe { core::arch::asm!("jmp 0x7e00", options(noreturn)) }
+    }
+}
@@ -1,6 +1,18 @@
 pub mod base;
 pub mod keyboardio;
+pub mod mouse;
+pub mod protocol;
 pub mod settings;
 
 pub use base::keyboard::{ActiveKeyboard, Keyboard};
 pub use keyboardio::Keyboardio as HIDKeyboard;
+pub use protocol::{HidProtocol, KeyboardProtocol, ProtocolObserver, KEYBOARD_PROTOCOL};
+
+/// Bit layout of the host lock-LED OUTPUT report byte.
+pub struct HostLeds;
+
+impl HostLeds {
+    pub const NUM_LOCK: u8 = 1 << 0;
+    pub const CAPS_LOCK: u8 = 1 << 1;
+    pub const SCROLL_LOCK: u8 = 1 << 2;
+}
@@ -23,12 +23,12 @@ pub trait Keyboard<'k> {
     /// Gets a reference to the keyboard as a [KeyboardOps] object.
     ///
     /// Returns an error if the implementation does not have a set keyboard.
-    fn keyboard(&'k self) -> &'k HIDKeyboard;
+    fn keyboard(&self) -> &HIDKeyboard<'k>;
 
     /// Gets a mutable reference to the keyboard as a [KeyboardOps] object.
     ///
     /// Returns an error if the implementation does not have a set keyboard.
-    fn keyboard_mut(&'k mut self) -> &'k mut HIDKeyboard;
+    fn keyboard_mut(&mut self) -> &mut HIDKeyboard<'k>;
 
     /// Gets the currently [ActiveKeyboard].
     fn active_keyboard(&self) -> ActiveKeyboard;
@@ -43,69 +43,69 @@ pub trait Keyboard<'k> {
     fn set_last_system_control_keycode(&mut self, key_code: u8);
 
     /// Gets an optional reference to the boot keyboard.
-    fn boot_keyboard(&'k self) -> &'k dyn boot::BootKeyboard;
+    fn boot_keyboard(&self) -> &dyn boot::BootKeyboard;
 
     /// Gets an optional mutable reference to the boot keyboard.
-    fn boot_keyboard_mut(&'k mut self) -> &'k mut dyn boot::BootKeyboard;
+    fn boot_keyboard_mut(&mut self) -> &mut dyn boot::BootKeyboard;
 
     /// Gets an optional reference to the NKRO keyboard.
-    fn nkro_keyboard(&'k self) -> &'k dyn nkro::NKROKeyboard;
+    fn nkro_keyboard(&self) -> &dyn nkro::NKROKeyboard;
 
     /// Gets an optional mutable reference to the NKRO keyboard.
-    fn nkro_keyboard_mut(&'k mut self) -> &'k mut dyn nkro::NKROKeyboard;
+    fn nkro_keyboard_mut(&mut self) -> &mut dyn nkro::NKROKeyboard;
 
     /// Gets an optional reference to the consumer control / media keyboard.
-    fn consumer_control(&'k self) -> &'k dyn media::MediaKeyboard;
+    fn consumer_control(&self) -> &dyn media::MediaKeyboard;
 
     /// Gets an optional mutable reference to the consumer control / media keyboard.
-    fn consumer_control_mut(&'k mut self) -> &'k mut dyn media::MediaKeyboard;
+    fn consumer_control_mut(&mut self) -> &mut dyn media::MediaKeyboard;
 
     /// Gets an optional reference to the system control keyboard.
-    fn system_control(&'k self) -> &'k dyn system_control::SystemControlKeyboard;
+    fn system_control(&self) -> &dyn system_control::SystemControlKeyboard;
 
     /// Gets an optional mutable reference to the system control keyboard.
-    fn system_control_mut(&'k mut self) -> &'k mut dyn system_control::SystemControlKeyboard; 
+    fn system_control_mut(&mut self) -> &mut dyn system_control::SystemControlKeyboard; 
 
-    fn setup(&'k mut self) -> Result<()> {
+    fn setup(&mut self) -> Result<()> {
         self.keyboard().begin();
         Ok(())
     }
 
     /// Releases all currently held keys.
-    fn release_all_keys(&'k mut self) -> Result<()> {
+    fn release_all_keys(&mut self) -> Result<()> {
         self.keyboard_mut().release_all();
 
         Ok(())
     }
 
-    fn press_consumer_control(&'k mut self, mapped_key: Key) {
+    fn press_consumer_control(&mut self, mapped_key: Key) {
         self.consumer_control_mut().press(mapped_key.consumer() as u8);
     }
 
-    fn release_consumer_control(&'k mut self, mapped_key: Key) {
+    fn release_consumer_control(&mut self, mapped_key: Key) {
         self.consumer_control_mut().release(mapped_key.consumer() as u8);
     }
 
-    fn press_system_control(&'k mut self, mapped_key: Key);
+    fn press_system_control(&mut self, mapped_key: Key);
 
-    fn release_system_control(&'k mut self, mapped_key: Key) {
+    fn release_system_control(&mut self, mapped_key: Key) {
         let keycode = mapped_key.key_code();
         if keycode == self.last_system_control_keycode() {
             self.system_control_mut().release(keycode);
         }
     }
 
-    fn press_key(&'k mut self, pressed_key: Key);
+    fn press_key(&mut self, pressed_key: Key);
 
-    fn release_key(&'k mut self, released_key: Key);
+    fn release_key(&mut self, released_key: Key);
 
-    fn press_modifiers(&'k mut self, pressed_key: Key);
+    fn press_modifiers(&mut self, pressed_key: Key);
 
-    fn release_modifiers(&'k mut self, released_key: Key);
+    fn release_modifiers(&mut self, released_key: Key);
 
-    fn clear_modifiers(&'k mut self);
+    fn clear_modifiers(&mut self);
 
-    fn press_raw_key(&'k mut self, pressed_key: Key);
+    fn press_raw_key(&mut self, pressed_key: Key);
 
-    fn release_raw_key(&'k mut self, released_key: Key);
+    fn release_raw_key(&mut self, released_key: Key);
 }
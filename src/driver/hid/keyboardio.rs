@@ -2,16 +2,47 @@ use keyboardio_hid::{Keyboard as HIDKeyboard, KeyboardUsbBusAllocator};
 use keyboardio_hid::{boot, media, nkro, system_control};
 
 use super::base::keyboard::{ActiveKeyboard, Keyboard};
+use super::mouse::{to_hid_absolute, Pointer, PointerKind};
 
-use crate::{Result, key_defs::*};
+use crate::{Result, key_defs::*, key_ext::KeyExt, key_flags_ext::KeyFlagsExt};
 
 pub struct Keyboardio<'k> {
     pub boot_keyboard: HIDKeyboard<'k>,
     pub nkro_keyboard: HIDKeyboard<'k>,
     pub media_keyboard: HIDKeyboard<'k>,
     pub system_control_keyboard: HIDKeyboard<'k>,
+    pub mouse: Pointer<'k>,
+    pub absolute_mouse: Pointer<'k>,
     active_keyboard: ActiveKeyboard,
     last_system_control_keycode: u8,
+    // Modifiers added to the report from the flags of other keys, see [modifier_bit].
+    flag_modifiers: u8,
+    // Modifiers added to the report by modifier keys themselves.
+    held_modifiers: u8,
+}
+
+/// Gets the bit of a modifier key in the modifier bitsets, `0` if the key is not a modifier.
+///
+/// Bits follow the HID modifier byte: `LeftControl` is bit 0, `RightGui` is bit 7.
+pub fn modifier_bit(key: &Key) -> u8 {
+    let first = Key_LeftControl.key_code();
+
+    match key.key_code().checked_sub(first) {
+        Some(bit) if bit < 8 => 1 << bit,
+        _ => 0,
+    }
+}
+
+/// Gets the modifier keys set in `flag_modifiers`, but not in `held_modifiers`.
+///
+/// These are the modifiers only added to the report from the flags of other keys, which
+/// [clear_modifiers](Keyboard::clear_modifiers) releases.
+pub fn added_modifiers(flag_modifiers: u8, held_modifiers: u8) -> impl Iterator<Item = Key> {
+    let added = flag_modifiers & !held_modifiers;
+
+    (0..8u8)
+        .filter(move |bit| added & (1 << bit) != 0)
+        .map(|bit| Key::from_raw(Key_LeftControl.raw() + bit as u16))
 }
 
 impl<'k> Keyboardio<'k> {
@@ -28,8 +59,12 @@ impl<'k> Keyboardio<'k> {
             nkro_keyboard: HIDKeyboard::new_nkro(bus),
             media_keyboard: HIDKeyboard::new_media(bus),
             system_control_keyboard: HIDKeyboard::new_system_control(bus),
+            mouse: Pointer::new(bus, PointerKind::Relative),
+            absolute_mouse: Pointer::new(bus, PointerKind::Absolute),
             active_keyboard,
             last_system_control_keycode: 0,
+            flag_modifiers: 0,
+            held_modifiers: 0,
         }
     }
 
@@ -58,6 +93,62 @@ impl<'k> Keyboardio<'k> {
         Ok(())
     }
 
+    /// Sends the current consumer control and system control reports.
+    ///
+    /// Unlike [send_report](Self::send_report), these are sent whichever keyboard is
+    /// active.
+    pub fn send_control_reports(&mut self) -> Result<()> {
+        use media::MediaKeyboard;
+        use system_control::SystemControlKeyboard;
+
+        self.media_keyboard.send_report()?;
+        self.system_control_keyboard.send_report()?;
+
+        Ok(())
+    }
+
+    /// Gets the latest lock-LED state sent by the host in the keyboard OUTPUT report.
+    ///
+    /// See [HostLeds](super::HostLeds) for the bit layout.
+    pub fn leds(&self) -> u8 {
+        match self.active_keyboard {
+            ActiveKeyboard::NKRO => {
+                use nkro::NKROKeyboard;
+                self.nkro_keyboard.get_leds()
+            }
+            _ => {
+                use boot::BootKeyboard;
+                self.boot_keyboard.get_leds()
+            }
+        }
+    }
+
+    /// Moves the mouse cursor relative to its current position.
+    pub fn move_relative(&mut self, dx: i8, dy: i8) -> Result<()> {
+        self.mouse.send_relative(dx, dy)?;
+
+        Ok(())
+    }
+
+    /// Moves the mouse cursor to an absolute screen position.
+    ///
+    /// Coordinates go from `0` (left/top) to `u16::MAX` (right/bottom), and are scaled
+    /// to the HID absolute range, see [to_hid_absolute].
+    pub fn set_absolute_position(&mut self, x: u16, y: u16) -> Result<()> {
+        self.absolute_mouse
+            .send_absolute(to_hid_absolute(x), to_hid_absolute(y))?;
+
+        Ok(())
+    }
+
+    /// Gets whether the host uses the absolute pointer.
+    ///
+    /// Hosts without a driver for it ignore absolute positions, use
+    /// [move_relative](Self::move_relative) instead.
+    pub fn absolute_mouse_supported(&self) -> bool {
+        self.absolute_mouse.is_bound()
+    }
+
     /// Gets whether the provided key is in the current USB report.
     pub fn is_key_pressed(&self, key: &Key) -> bool {
         let key_code = key.key_code();
@@ -78,7 +169,7 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
     type ConsumerControl = HIDKeyboard<'k>;
     type SystemControl = HIDKeyboard<'k>;
 
-    fn keyboard(&'k self) -> &'k HIDKeyboard {
+    fn keyboard(&self) -> &HIDKeyboard<'k> {
         match self.active_keyboard {
             ActiveKeyboard::Boot => &self.boot_keyboard,
             ActiveKeyboard::NKRO => &self.nkro_keyboard,
@@ -88,13 +179,13 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
         }
     }
 
-    fn keyboard_mut(&'k mut self) -> &'k mut HIDKeyboard {
+    fn keyboard_mut(&mut self) -> &mut HIDKeyboard<'k> {
         match self.active_keyboard {
-            ActiveKeyboard::Boot => self.boot_keyboard.as_mut(),
-            ActiveKeyboard::NKRO => self.nkro_keyboard.as_mut(),
-            ActiveKeyboard::Media => self.media_keyboard.as_mut(),
-            ActiveKeyboard::System => self.system_control_keyboard.as_mut(),
-            _ => self.boot_keyboard.as_mut(),
+            ActiveKeyboard::Boot => &mut self.boot_keyboard,
+            ActiveKeyboard::NKRO => &mut self.nkro_keyboard,
+            ActiveKeyboard::Media => &mut self.media_keyboard,
+            ActiveKeyboard::System => &mut self.system_control_keyboard,
+            _ => &mut self.boot_keyboard,
         }
     }
 
@@ -102,35 +193,35 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
         self.active_keyboard
     }
 
-    fn boot_keyboard(&'k self) -> &'k dyn boot::BootKeyboard {
+    fn boot_keyboard(&self) -> &dyn boot::BootKeyboard {
         &self.boot_keyboard
     }
 
-    fn boot_keyboard_mut(&'k mut self) -> &'k mut dyn boot::BootKeyboard {
+    fn boot_keyboard_mut(&mut self) -> &mut dyn boot::BootKeyboard {
         &mut self.boot_keyboard
     }
 
-    fn nkro_keyboard(&'k self) -> &'k dyn nkro::NKROKeyboard {
+    fn nkro_keyboard(&self) -> &dyn nkro::NKROKeyboard {
         &self.nkro_keyboard
     }
 
-    fn nkro_keyboard_mut(&'k mut self) -> &'k mut dyn nkro::NKROKeyboard {
+    fn nkro_keyboard_mut(&mut self) -> &mut dyn nkro::NKROKeyboard {
         &mut self.nkro_keyboard
     }
 
-    fn consumer_control(&'k self) -> &'k dyn media::MediaKeyboard {
+    fn consumer_control(&self) -> &dyn media::MediaKeyboard {
         &self.media_keyboard
     }
 
-    fn consumer_control_mut(&'k mut self) -> &'k mut dyn media::MediaKeyboard {
+    fn consumer_control_mut(&mut self) -> &mut dyn media::MediaKeyboard {
         &mut self.media_keyboard
     }
 
-    fn system_control(&'k self) -> &'k dyn system_control::SystemControlKeyboard {
+    fn system_control(&self) -> &dyn system_control::SystemControlKeyboard {
         &self.system_control_keyboard
     }
 
-    fn system_control_mut(&'k mut self) -> &'k mut dyn system_control::SystemControlKeyboard {
+    fn system_control_mut(&mut self) -> &mut dyn system_control::SystemControlKeyboard {
         &mut self.system_control_keyboard
     }
 
@@ -146,7 +237,7 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
         self.last_system_control_keycode = key_code;
     }
 
-    fn press_system_control(&'k mut self, mapped_key: Key) {
+    fn press_system_control(&mut self, mapped_key: Key) {
         use system_control::SystemControlKeyboard;
 
         let keycode = mapped_key.key_code();
@@ -155,47 +246,50 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
         self.last_system_control_keycode = keycode;
     }
 
-    fn press_key(&'k mut self, pressed_key: Key) {
+    fn release_all_keys(&mut self) -> Result<()> {
+        self.flag_modifiers = 0;
+        self.held_modifiers = 0;
+        self.keyboard_mut().release_all();
+
+        Ok(())
+    }
+
+    fn press_key(&mut self, pressed_key: Key) {
         crate::press_modifiers!(self, pressed_key);
         crate::press_raw_key!(self, pressed_key);
+        self.held_modifiers |= modifier_bit(&pressed_key);
     }
 
-    fn release_key(&'k mut self, released_key: Key) {
+    fn release_key(&mut self, released_key: Key) {
         crate::release_modifiers!(self, released_key);
         crate::release_raw_key!(self, released_key);
+        self.held_modifiers &= !modifier_bit(&released_key);
     }
 
-    fn press_modifiers(&'k mut self, pressed_key: Key) {
+    fn press_modifiers(&mut self, pressed_key: Key) {
         crate::press_modifiers!(self, pressed_key);
     }
 
-    fn release_modifiers(&'k mut self, released_key: Key) {
+    fn release_modifiers(&mut self, released_key: Key) {
         crate::release_modifiers!(self, released_key);
     }
 
-    fn clear_modifiers(&'k mut self) {
-        if self.active_keyboard == ActiveKeyboard::Boot {
-            use boot::BootKeyboard;
-            self.boot_keyboard.release(Key_LeftShift.key_code());
-            self.boot_keyboard.release(Key_LeftControl.key_code());
-            self.boot_keyboard.release(Key_LeftAlt.key_code());
-            self.boot_keyboard.release(Key_RightAlt.key_code());
-            self.boot_keyboard.release(Key_LeftGui.key_code());
-        } else {
-            use nkro::NKROKeyboard;
-            self.nkro_keyboard.release(Key_LeftShift.key_code());
-            self.nkro_keyboard.release(Key_LeftControl.key_code());
-            self.nkro_keyboard.release(Key_LeftAlt.key_code());
-            self.nkro_keyboard.release(Key_RightAlt.key_code());
-            self.nkro_keyboard.release(Key_LeftGui.key_code());
+    /// Releases the modifiers added to the report from key flags.
+    ///
+    /// Modifiers also held by a modifier key stay in the report.
+    fn clear_modifiers(&mut self) {
+        for modifier in added_modifiers(self.flag_modifiers, self.held_modifiers) {
+            crate::release_raw_key!(self, modifier);
         }
+
+        self.flag_modifiers = 0;
     }
 
-    fn press_raw_key(&'k mut self, pressed_key: Key) {
+    fn press_raw_key(&mut self, pressed_key: Key) {
         crate::press_raw_key!(self, pressed_key);
     }
 
-    fn release_raw_key(&'k mut self, released_key: Key) {
+    fn release_raw_key(&mut self, released_key: Key) {
         crate::release_raw_key!(self, released_key);
     }
 }
@@ -208,45 +302,73 @@ impl<'k> Keyboard<'k> for Keyboardio<'k> {
 #[macro_export]
 macro_rules! press_modifiers {
     ($keyboard:tt, $key:tt) => {
-        let flags = $key.flags();
+        let flags = $key.modifiers();
 
-        if flags & KeyFlags::SHIFT_HELD != KeyFlags::NONE {
+        if flags.contains(KeyFlags::shift()) {
             $crate::press_raw_key!($keyboard, Key_LeftShift);
+            $keyboard.flag_modifiers |= $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftShift);
         }
-        if flags & KeyFlags::CTRL_HELD != KeyFlags::NONE {
+        if flags.contains(KeyFlags::ctrl()) {
             $crate::press_raw_key!($keyboard, Key_LeftControl);
+            $keyboard.flag_modifiers |= $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftControl);
         }
-        if flags & KeyFlags::LALT_HELD != KeyFlags::NONE {
+        if flags.contains(KeyFlags::lalt()) {
             $crate::press_raw_key!($keyboard, Key_LeftAlt);
+            $keyboard.flag_modifiers |= $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftAlt);
         }
-        if flags & KeyFlags::RALT_HELD != KeyFlags::NONE {
+        if flags.contains(KeyFlags::ralt()) {
             $crate::press_raw_key!($keyboard, Key_RightAlt);
+            $keyboard.flag_modifiers |= $crate::driver::hid::keyboardio::modifier_bit(&Key_RightAlt);
         }
-        if flags & KeyFlags::GUI_HELD != KeyFlags::NONE {
+        if flags.contains(KeyFlags::gui()) {
             $crate::press_raw_key!($keyboard, Key_LeftGui);
+            $keyboard.flag_modifiers |= $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftGui);
         }
     }
 }
 
+/// Releases the modifiers added by the flags of `$key`.
+///
+/// Modifiers also held by a modifier key stay in the report.
 #[macro_export]
 macro_rules! release_modifiers {
     ($keyboard:tt, $key:tt) => {
-        let flags = $key.flags();
+        let flags = $key.modifiers();
 
-        if flags & KeyFlags::SHIFT_HELD != KeyFlags::NONE {
-            $crate::release_raw_key!($keyboard, Key_LeftShift);
+        if flags.contains(KeyFlags::shift()) {
+            let bit = $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftShift);
+            if $keyboard.held_modifiers & bit == 0 {
+                $crate::release_raw_key!($keyboard, Key_LeftShift);
+            }
+            $keyboard.flag_modifiers &= !bit;
         }
-        if flags & KeyFlags::CTRL_HELD != KeyFlags::NONE {
-            $crate::release_raw_key!($keyboard, Key_LeftControl);
+        if flags.contains(KeyFlags::ctrl()) {
+            let bit = $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftControl);
+            if $keyboard.held_modifiers & bit == 0 {
+                $crate::release_raw_key!($keyboard, Key_LeftControl);
+            }
+            $keyboard.flag_modifiers &= !bit;
         }
-        if flags & KeyFlags::LALT_HELD != KeyFlags::NONE {
-            $crate::release_raw_key!($keyboard, Key_LeftAlt);
+        if flags.contains(KeyFlags::lalt()) {
+            let bit = $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftAlt);
+            if $keyboard.held_modifiers & bit == 0 {
+                $crate::release_raw_key!($keyboard, Key_LeftAlt);
+            }
+            $keyboard.flag_modifiers &= !bit;
         }
-        if flags & KeyFlags::RALT_HELD != KeyFlags::NONE {
-            $crate::release_raw_key!($keyboard, Key_RightAlt);
+        if flags.contains(KeyFlags::ralt()) {
+            let bit = $crate::driver::hid::keyboardio::modifier_bit(&Key_RightAlt);
+            if $keyboard.held_modifiers & bit == 0 {
+                $crate::release_raw_key!($keyboard, Key_RightAlt);
+            }
+            $keyboard.flag_modifiers &= !bit;
         }
-        if flags & KeyFlags::GUI_HELD != KeyFlags::NONE {
-            $crate::release_raw_key!($keyboard, Key_LeftGui);
+        if flags.contains(KeyFlags::gui()) {
+            let bit = $crate::driver::hid::keyboardio::modifier_bit(&Key_LeftGui);
+            if $keyboard.held_modifiers & bit == 0 {
+                $crate::release_raw_key!($keyboard, Key_LeftGui);
+            }
+            $keyboard.flag_modifiers &= !bit;
         }
     }
 }
@@ -276,3 +398,40 @@ macro_rules! release_raw_key {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(keys: &[Key]) -> u8 {
+        keys.iter().fold(0, |bits, key| bits | modifier_bit(key))
+    }
+
+    #[test]
+    fn modifier_bits_follow_the_hid_byte() {
+        assert_eq!(modifier_bit(&Key_LeftControl), 1 << 0);
+        assert_eq!(modifier_bit(&Key_LeftGui), 1 << 3);
+        assert_eq!(modifier_bit(&Key_RightControl), 1 << 4);
+        assert_eq!(modifier_bit(&Key_RightShift), 1 << 5);
+        assert_eq!(modifier_bit(&Key_RightAlt), 1 << 6);
+        assert_eq!(modifier_bit(&Key_RightGui), 1 << 7);
+        assert_eq!(modifier_bit(&Key_A), 0);
+    }
+
+    #[test]
+    fn only_report_added_modifiers_are_cleared() {
+        // Added from the flags of e.g. LSHIFT(Key_1) and RALT(Key_E).
+        let flags = bits(&[Key_LeftShift, Key_RightAlt, Key_LeftControl]);
+        // Held as modifier keys, including the right-hand variants.
+        let held = bits(&[Key_LeftControl, Key_RightShift, Key_RightGui]);
+
+        assert!(added_modifiers(flags, held).eq([Key_LeftShift, Key_RightAlt]));
+
+        // A right-hand modifier held as a key stays in the report too.
+        let held = bits(&[Key_RightAlt]);
+        assert!(added_modifiers(flags, held).eq([Key_LeftControl, Key_LeftShift]));
+
+        assert_eq!(added_modifiers(0, held).count(), 0);
+        assert_eq!(added_modifiers(flags, flags).count(), 0);
+    }
+}
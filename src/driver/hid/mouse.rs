@@ -0,0 +1,232 @@
+use keyboardio_hid::usb_device::class_prelude::*;
+use keyboardio_hid::usb_device::control::{Recipient, Request, RequestType};
+use keyboardio_hid::usb_device::UsbError;
+use keyboardio_hid::{KeyboardUsbBus, KeyboardUsbBusAllocator};
+
+/// Maximum coordinate of an absolute pointer report.
+pub const HID_ABSOLUTE_MAX: u16 = 32767;
+
+/// Number of times a report is retried while the host has not read the previous one.
+const SEND_RETRIES: u16 = 1000;
+
+const USB_CLASS_HID: u8 = 0x03;
+const HID_DESCRIPTOR: u8 = 0x21;
+const HID_REPORT_DESCRIPTOR: u8 = 0x22;
+const HID_SET_IDLE: u8 = 0x0a;
+
+/// Report descriptor of a relative mouse: 3 buttons, and 8-bit X and Y moves.
+const RELATIVE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xa1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Button)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x03, //     Usage Maximum (3)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x03, //     Report Count (3)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x05, //     Report Size (5)
+    0x81, 0x03, //     Input (Constant)
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7f, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x02, //     Report Count (2)
+    0x81, 0x06, //     Input (Data, Variable, Relative)
+    0xc0, //       End Collection
+    0xc0, //     End Collection
+];
+
+/// Report descriptor of an absolute pointer: 3 buttons, and 16-bit X and Y coordinates.
+const ABSOLUTE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xa1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Button)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x03, //     Usage Maximum (3)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x03, //     Report Count (3)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x05, //     Report Size (5)
+    0x81, 0x03, //     Input (Constant)
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x16, 0x00, 0x00, // Logical Minimum (0)
+    0x26, 0xff, 0x7f, // Logical Maximum (32767)
+    0x75, 0x10, //     Report Size (16)
+    0x95, 0x02, //     Report Count (2)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    0xc0, //       End Collection
+    0xc0, //     End Collection
+];
+
+/// Scales a screen coordinate, from `0` (left/top) to `u16::MAX` (right/bottom), to the
+/// HID absolute pointer range.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::hid::mouse::{to_hid_absolute, HID_ABSOLUTE_MAX};
+///
+/// assert_eq!(to_hid_absolute(0), 0);
+/// assert_eq!(to_hid_absolute(u16::MAX / 2), HID_ABSOLUTE_MAX / 2);
+/// assert_eq!(to_hid_absolute(u16::MAX), HID_ABSOLUTE_MAX);
+/// ```
+pub const fn to_hid_absolute(coordinate: u16) -> u16 {
+    ((coordinate as u32 * HID_ABSOLUTE_MAX as u32) / u16::MAX as u32) as u16
+}
+
+/// Kind of pointer reports sent by a [Pointer].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerKind {
+    /// Moves relative to the current cursor position.
+    Relative,
+    /// Positions in the `0..=HID_ABSOLUTE_MAX` range, covering the whole screen.
+    Absolute,
+}
+
+/// HID pointer class, one USB interface with its own interrupt endpoint.
+pub struct Pointer<'a> {
+    kind: PointerKind,
+    interface: InterfaceNumber,
+    endpoint: EndpointIn<'a, KeyboardUsbBus>,
+    bound: bool,
+}
+
+impl<'a> Pointer<'a> {
+    /// Creates a new [Pointer] sending `kind` reports.
+    pub fn new(bus: &'a KeyboardUsbBusAllocator, kind: PointerKind) -> Self {
+        Self {
+            kind,
+            interface: bus.interface(),
+            endpoint: bus.interrupt(8, 10),
+            bound: false,
+        }
+    }
+
+    /// Gets the kind of reports sent by the pointer.
+    pub fn kind(&self) -> PointerKind {
+        self.kind
+    }
+
+    /// Gets whether the host fetched the report descriptor since the last USB reset.
+    ///
+    /// Hosts without a driver for the pointer never fetch it, and ignore its reports.
+    pub fn is_bound(&self) -> bool {
+        self.bound
+    }
+
+    /// Sends a relative move.
+    ///
+    /// Fails with [UsbError::InvalidState] if the pointer is absolute.
+    pub fn send_relative(&mut self, dx: i8, dy: i8) -> Result<(), UsbError> {
+        if self.kind != PointerKind::Relative {
+            return Err(UsbError::InvalidState);
+        }
+
+        self.send(&[0, dx as u8, dy as u8])
+    }
+
+    /// Sends an absolute position, in the `0..=HID_ABSOLUTE_MAX` range.
+    ///
+    /// Fails with [UsbError::InvalidState] if the pointer is relative.
+    pub fn send_absolute(&mut self, x: u16, y: u16) -> Result<(), UsbError> {
+        if self.kind != PointerKind::Absolute {
+            return Err(UsbError::InvalidState);
+        }
+
+        let x = x.min(HID_ABSOLUTE_MAX).to_le_bytes();
+        let y = y.min(HID_ABSOLUTE_MAX).to_le_bytes();
+
+        self.send(&[0, x[0], x[1], y[0], y[1]])
+    }
+
+    /// Writes a report, retrying while the host has not read the previous one.
+    fn send(&mut self, report: &[u8]) -> Result<(), UsbError> {
+        for _ in 0..SEND_RETRIES {
+            match self.endpoint.write(report) {
+                Err(UsbError::WouldBlock) => continue,
+                res => return res.map(|_| ()),
+            }
+        }
+
+        Err(UsbError::WouldBlock)
+    }
+
+    fn report_descriptor(&self) -> &'static [u8] {
+        match self.kind {
+            PointerKind::Relative => RELATIVE_REPORT_DESCRIPTOR,
+            PointerKind::Absolute => ABSOLUTE_REPORT_DESCRIPTOR,
+        }
+    }
+
+    fn hid_descriptor(&self) -> [u8; 7] {
+        let len = (self.report_descriptor().len() as u16).to_le_bytes();
+
+        // HID 1.11, no country code, one report descriptor.
+        [0x11, 0x01, 0x00, 0x01, HID_REPORT_DESCRIPTOR, len[0], len[1]]
+    }
+}
+
+impl UsbClass<KeyboardUsbBus> for Pointer<'_> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> keyboardio_hid::usb_device::Result<()> {
+        writer.interface(self.interface, USB_CLASS_HID, 0, 0)?;
+        writer.write(HID_DESCRIPTOR, &self.hid_descriptor())?;
+        writer.endpoint(&self.endpoint)?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.bound = false;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<KeyboardUsbBus>) {
+        let req = xfer.request();
+
+        if req.request_type != RequestType::Standard
+            || req.recipient != Recipient::Interface
+            || req.request != Request::GET_DESCRIPTOR
+            || req.index != u8::from(self.interface) as u16
+        {
+            return;
+        }
+
+        match (req.value >> 8) as u8 {
+            HID_REPORT_DESCRIPTOR => {
+                self.bound = true;
+                let _ = xfer.accept_with_static(self.report_descriptor());
+            }
+            HID_DESCRIPTOR => {
+                let _ = xfer.accept_with(&self.hid_descriptor());
+            }
+            _ => (),
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<KeyboardUsbBus>) {
+        let req = xfer.request();
+
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.request == HID_SET_IDLE
+            && req.index == u8::from(self.interface) as u16
+        {
+            let _ = xfer.accept();
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use keyboardio_hid::usb_device::class_prelude::*;
+use keyboardio_hid::usb_device::control::{Recipient, RequestType};
+use ufmt::{uWrite, uwrite};
+
+use super::base::keyboard::ActiveKeyboard;
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::persistable::Persistable;
+use crate::{lock, Error, Result};
+
+/// HID class request code for SET_PROTOCOL.
+pub const HID_SET_PROTOCOL: u8 = 0x0b;
+
+static REQUESTED_PROTOCOL: AtomicU8 = AtomicU8::new(HidProtocol::Report as u8);
+static PROTOCOL_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// HID protocol requested by the host, as defined by the `wValue` of SET_PROTOCOL.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HidProtocol {
+    /// Boot protocol, used by BIOS and other boot environments.
+    Boot = 0,
+    /// Report protocol, used by regular operating systems.
+    Report = 1,
+}
+
+impl From<u16> for HidProtocol {
+    fn from(value: u16) -> Self {
+        if value == 0 {
+            Self::Boot
+        } else {
+            Self::Report
+        }
+    }
+}
+
+impl From<HidProtocol> for ActiveKeyboard {
+    fn from(protocol: HidProtocol) -> Self {
+        match protocol {
+            HidProtocol::Boot => Self::Boot,
+            HidProtocol::Report => Self::NKRO,
+        }
+    }
+}
+
+/// Gets the most recent protocol requested by the host.
+///
+/// Hosts that never send SET_PROTOCOL use the report protocol.
+pub fn host_protocol() -> HidProtocol {
+    (REQUESTED_PROTOCOL.load(Ordering::Relaxed) as u16).into()
+}
+
+/// Asks the [Runtime](crate::runtime::Runtime) to switch to the keyboard protocol
+/// resolved from the current [KEYBOARD_PROTOCOL] preference.
+///
+/// Focus handlers run while the runtime is borrowed, so the switch, and the flush of the
+/// in-flight report, happen at the start of the next cycle.
+pub fn request_protocol_update() {
+    PROTOCOL_CHANGED.store(true, Ordering::SeqCst);
+}
+
+/// Takes the most recent protocol requested by the host, if it changed since the last
+/// call, or if an update was requested with [request_protocol_update].
+pub fn take_requested_protocol() -> Option<HidProtocol> {
+    if PROTOCOL_CHANGED.swap(false, Ordering::SeqCst) {
+        Some((REQUESTED_PROTOCOL.load(Ordering::Relaxed) as u16).into())
+    } else {
+        None
+    }
+}
+
+/// Observes HID SET_PROTOCOL requests without handling them.
+///
+/// Must be placed before the HID classes in the list passed to `UsbDevice::poll`. The
+/// observer never accepts the request, so it is still passed on to the HID class that
+/// owns the interface.
+///
+/// The switch itself is performed outside of the interrupt, by the
+/// [Runtime](crate::runtime::Runtime), using [take_requested_protocol].
+pub struct ProtocolObserver;
+
+impl<B: UsbBus> UsbClass<B> for ProtocolObserver {
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.request == HID_SET_PROTOCOL
+        {
+            REQUESTED_PROTOCOL.store(HidProtocol::from(req.value) as u8, Ordering::Relaxed);
+            PROTOCOL_CHANGED.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Global keyboard protocol preference.
+pub static KEYBOARD_PROTOCOL: lock::Spinlock<KeyboardProtocol> = lock::Spinlock::new(KeyboardProtocol::new());
+
+/// User preference for the keyboard protocol, boot or NKRO.
+///
+/// The preference is persisted, and used whenever the host asks for the report
+/// protocol. A host asking for the boot protocol (e.g. a BIOS) always gets it, and
+/// NKRO cannot be selected while the host is using boot protocol.
+///
+/// The `keyboard.protocol` Focus command prints the active protocol, or sets the
+/// preference with `keyboard.protocol boot|nkro`, switching to it at the start of the
+/// next cycle.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::Error;
+/// use kaleidoscope::driver::hid::{ActiveKeyboard, HidProtocol};
+/// use kaleidoscope::driver::hid::protocol::KeyboardProtocol;
+/// use kaleidoscope::persistable::Persistable;
+///
+/// assert_eq!(KeyboardProtocol::from_name("nkro"), Some(ActiveKeyboard::NKRO));
+/// assert_eq!(KeyboardProtocol::from_name("media"), None);
+///
+/// let mut protocol = KeyboardProtocol::new();
+/// assert_eq!(protocol.resolve(HidProtocol::Report), ActiveKeyboard::NKRO);
+///
+/// // NKRO is rejected while the host uses the boot protocol.
+/// assert_eq!(
+///     protocol.set_preferred(ActiveKeyboard::NKRO, HidProtocol::Boot),
+///     Err(Error::BootProtocolActive),
+/// );
+///
+/// assert!(protocol.set_preferred(ActiveKeyboard::Boot, HidProtocol::Report).is_ok());
+/// assert_eq!(protocol.resolve(HidProtocol::Report), ActiveKeyboard::Boot);
+///
+/// let mut buf = [0u8; KeyboardProtocol::SIZE as usize];
+/// protocol.save(&mut buf);
+///
+/// let mut restored = KeyboardProtocol::new();
+/// restored.restore(&buf);
+///
+/// assert_eq!(restored.preferred(), ActiveKeyboard::Boot);
+/// assert_eq!(restored.resolve(HidProtocol::Boot), ActiveKeyboard::Boot);
+/// ```
+pub struct KeyboardProtocol {
+    preferred: ActiveKeyboard,
+    slot: Option<SlotHandle>,
+}
+
+impl KeyboardProtocol {
+    /// Creates a new [KeyboardProtocol] preferring NKRO.
+    pub const fn new() -> Self {
+        Self {
+            preferred: ActiveKeyboard::NKRO,
+            slot: None,
+        }
+    }
+
+    /// Gets the name of a keyboard protocol, as used by the `keyboard.protocol` Focus command.
+    pub const fn name(keyboard: ActiveKeyboard) -> &'static str {
+        match keyboard {
+            ActiveKeyboard::Boot => "boot",
+            ActiveKeyboard::NKRO => "nkro",
+            _ => "none",
+        }
+    }
+
+    /// Gets the keyboard protocol with the provided name.
+    ///
+    /// Only `boot` and `nkro` can be selected.
+    pub fn from_name(name: &str) -> Option<ActiveKeyboard> {
+        match name {
+            "boot" => Some(ActiveKeyboard::Boot),
+            "nkro" => Some(ActiveKeyboard::NKRO),
+            _ => None,
+        }
+    }
+
+    /// Gets the preferred keyboard protocol.
+    pub fn preferred(&self) -> ActiveKeyboard {
+        self.preferred
+    }
+
+    /// Sets the preferred keyboard protocol.
+    ///
+    /// Fails if NKRO is requested while the host uses the boot protocol. Call
+    /// [commit](Persistable::commit) afterwards to keep the preference across reboots.
+    pub fn set_preferred(&mut self, keyboard: ActiveKeyboard, host: HidProtocol) -> Result<()> {
+        if keyboard == ActiveKeyboard::NKRO && host == HidProtocol::Boot {
+            return Err(Error::BootProtocolActive);
+        }
+
+        self.preferred = keyboard;
+
+        Ok(())
+    }
+
+    /// Gets the keyboard protocol to use with the protocol requested by the host.
+    pub fn resolve(&self, host: HidProtocol) -> ActiveKeyboard {
+        match host {
+            HidProtocol::Boot => ActiveKeyboard::Boot,
+            HidProtocol::Report => self.preferred,
+        }
+    }
+}
+
+impl Persistable for KeyboardProtocol {
+    const SIZE: u16 = 1;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[0] = (self.preferred == ActiveKeyboard::NKRO) as u8;
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.preferred = if buf[0] == 0 {
+            ActiveKeyboard::Boot
+        } else {
+            ActiveKeyboard::NKRO
+        };
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for KeyboardProtocol {
+    fn on_name_query() -> crate::event_handler::Result<&'static str> {
+        Ok("KeyboardProtocol")
+    }
+
+    fn on_focus_event(input: &str) -> crate::event_handler::Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("keyboard.protocol\r\n");
+            return Ok(());
+        }
+
+        if command != "keyboard.protocol" {
+            return Ok(());
+        }
+
+        let args = args.trim();
+
+        if args.is_empty() {
+            let active = KEYBOARD_PROTOCOL.read().resolve(host_protocol());
+            uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", Self::name(active)).map_err(|_| EventHandlerError::Error)?;
+
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        let keyboard = Self::from_name(args).ok_or(EventHandlerError::Error)?;
+        let host = host_protocol();
+
+        {
+            let mut protocol = KEYBOARD_PROTOCOL.write();
+
+            if let Err(err) = protocol.set_preferred(keyboard, host) {
+                let description: &'static str = err.into();
+                uwrite!(&mut *FOCUS_OUTPUT.write(), "error: {}\r\n", description).map_err(|_| EventHandlerError::Error)?;
+
+                return Err(EventHandlerError::EventConsumed);
+            }
+
+            protocol.commit().map_err(|_| EventHandlerError::Error)?;
+        }
+
+        // The runtime flushes the in-flight report on the old keyboard before switching.
+        request_protocol_update();
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_update_is_taken_once() {
+        let _ = take_requested_protocol();
+
+        request_protocol_update();
+
+        assert_eq!(take_requested_protocol(), Some(host_protocol()));
+        assert_eq!(take_requested_protocol(), None);
+    }
+}
@@ -2,3 +2,206 @@
 mod atreus;
 #[cfg(feature = "atreus")]
 pub use atreus::*;
+#[cfg(feature = "technomancy_atreus")]
+mod technomancy_atreus;
+#[cfg(feature = "technomancy_atreus")]
+pub use technomancy_atreus::*;
+
+use keyboardio_hid::usb_device::device::UsbVidPid;
+
+use crate::driver::board::{BoardProps, DeviceProps};
+use crate::lock;
+
+/// USB identity used when attaching to the host, see [UsbIdentity].
+pub static USB_IDENTITY: lock::Spinlock<UsbIdentity> = lock::Spinlock::new(<DeviceProps as BoardProps>::USB_IDENTITY);
+
+/// USB identity the keyboard enumerates with.
+///
+/// Boards get their default from [BoardProps::USB_IDENTITY], which reads the board
+/// settings. Changing the identity at runtime requires re-enumeration, see
+/// [Runtime::reattach_with_identity](crate::runtime::Runtime::reattach_with_identity).
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::hid::settings::UsbIdentity;
+///
+/// let identity = UsbIdentity::new(0x1209, 0x2303, "Keyboardio", "Atreus");
+/// let vid_pid = identity.vid_pid();
+///
+/// assert_eq!((vid_pid.0, vid_pid.1), (0x1209, 0x2303));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbIdentity {
+    /// USB vendor ID.
+    pub vid: u16,
+    /// USB product ID.
+    pub pid: u16,
+    /// Manufacturer string.
+    pub manufacturer: &'static str,
+    /// Product string.
+    pub product: &'static str,
+}
+
+impl UsbIdentity {
+    /// Creates a new [UsbIdentity].
+    pub const fn new(vid: u16, pid: u16, manufacturer: &'static str, product: &'static str) -> Self {
+        Self {
+            vid,
+            pid,
+            manufacturer,
+            product,
+        }
+    }
+
+    /// Creates the [UsbIdentity] from the board settings.
+    pub const fn board_default() -> Self {
+        Self::new(USB_VID, USB_PID, MANUFACTURER, PRODUCT)
+    }
+
+    /// Gets the vendor and product IDs.
+    pub fn vid_pid(&self) -> UsbVidPid {
+        UsbVidPid(self.vid, self.pid)
+    }
+}
+
+/// `bmAttributes` bit set for self-powered devices.
+pub const USB_CONFIG_POWERED_MASK: u8 = 0x40;
+/// `bmAttributes` of bus-powered devices.
+pub const USB_CONFIG_BUS_POWERED: u8 = 0x80;
+/// `bmAttributes` of self-powered devices.
+pub const USB_CONFIG_SELF_POWERED: u8 = 0xc0;
+/// `bmAttributes` bit set for devices able to wake the host up.
+pub const USB_CONFIG_REMOTE_WAKEUP: u8 = 0x20;
+
+/// Default, and maximum, current drawn from the bus in mA.
+pub const USB_CONFIG_POWER: u16 = 500;
+
+/// Power configuration advertised in the configuration descriptor.
+///
+/// Boards get theirs from [BoardProps::USB_POWER], used when attaching to the host.
+/// Low-power boards can advertise less current than the default.
+///
+/// The current is encoded in units of 2 mA, so odd values are rounded up, and values
+/// above [USB_CONFIG_POWER] are clamped, so the device never advertises less than it
+/// draws, nor more than the bus allows.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::hid::settings::*;
+///
+/// let default = UsbPower::DEFAULT;
+/// assert_eq!(default.max_power(), 250);
+/// assert_eq!(default.attributes(), USB_CONFIG_BUS_POWERED | USB_CONFIG_REMOTE_WAKEUP);
+///
+/// assert_eq!(UsbPower::new(100, false, false).max_power(), 50);
+/// assert_eq!(UsbPower::new(0, false, false).max_power(), 0);
+///
+/// let odd = UsbPower::new(101, false, false);
+/// assert_eq!(odd.max_power_ma(), 102);
+/// assert_eq!(odd.max_power(), 51);
+///
+/// let over = UsbPower::new(900, false, false);
+/// assert_eq!(over.max_power_ma(), USB_CONFIG_POWER);
+/// assert_eq!(over.max_power(), 250);
+///
+/// let self_powered = UsbPower::new(20, true, false);
+/// assert_eq!(self_powered.max_power(), 10);
+/// assert_eq!(self_powered.attributes(), USB_CONFIG_SELF_POWERED);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbPower {
+    max_power_ma: u16,
+    self_powered: bool,
+    remote_wakeup: bool,
+}
+
+impl UsbPower {
+    /// Bus-powered, drawing up to [USB_CONFIG_POWER], with remote wakeup.
+    pub const DEFAULT: Self = Self::new(USB_CONFIG_POWER, false, true);
+
+    /// Creates a new [UsbPower], clamping and rounding up `max_power_ma`.
+    pub const fn new(max_power_ma: u16, self_powered: bool, remote_wakeup: bool) -> Self {
+        let max_power_ma = if max_power_ma > USB_CONFIG_POWER {
+            USB_CONFIG_POWER
+        } else {
+            max_power_ma
+        };
+
+        Self {
+            max_power_ma: max_power_ma + max_power_ma % 2,
+            self_powered,
+            remote_wakeup,
+        }
+    }
+
+    /// Gets the maximum current drawn from the bus, in mA.
+    pub const fn max_power_ma(&self) -> u16 {
+        self.max_power_ma
+    }
+
+    /// Gets whether the device has its own power supply.
+    pub const fn self_powered(&self) -> bool {
+        self.self_powered
+    }
+
+    /// Gets whether the device can wake the host up.
+    pub const fn remote_wakeup(&self) -> bool {
+        self.remote_wakeup
+    }
+
+    /// Gets the encoded `bmAttributes` descriptor field.
+    pub const fn attributes(&self) -> u8 {
+        let mut attributes = if self.self_powered {
+            USB_CONFIG_SELF_POWERED
+        } else {
+            USB_CONFIG_BUS_POWERED
+        };
+
+        if self.remote_wakeup {
+            attributes |= USB_CONFIG_REMOTE_WAKEUP;
+        }
+
+        attributes
+    }
+
+    /// Gets the encoded `bMaxPower` descriptor field, in units of 2 mA.
+    pub const fn max_power(&self) -> u8 {
+        (self.max_power_ma / 2) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_power() {
+        let power = UsbPower::DEFAULT;
+
+        assert_eq!(power.max_power_ma(), USB_CONFIG_POWER);
+        assert!(!power.self_powered());
+        assert!(power.remote_wakeup());
+        assert_eq!(power.attributes(), 0xa0);
+        assert_eq!(power.max_power(), 250);
+    }
+
+    #[test]
+    fn current_is_clamped_and_rounded_up() {
+        assert_eq!(UsbPower::new(USB_CONFIG_POWER + 1, false, false).max_power_ma(), USB_CONFIG_POWER);
+        assert_eq!(UsbPower::new(u16::MAX, false, false).max_power(), 250);
+        assert_eq!(UsbPower::new(499, false, false).max_power_ma(), 500);
+        assert_eq!(UsbPower::new(1, false, false).max_power(), 1);
+        assert_eq!(UsbPower::new(0, false, false).max_power(), 0);
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(UsbPower::new(100, false, false).attributes(), USB_CONFIG_BUS_POWERED);
+        assert_eq!(UsbPower::new(100, true, false).attributes(), USB_CONFIG_SELF_POWERED);
+        assert_eq!(UsbPower::new(100, true, true).attributes(), 0xe0);
+        assert_eq!(USB_CONFIG_SELF_POWERED & USB_CONFIG_POWERED_MASK, USB_CONFIG_POWERED_MASK);
+        assert_eq!(USB_CONFIG_BUS_POWERED & USB_CONFIG_POWERED_MASK, 0);
+    }
+}
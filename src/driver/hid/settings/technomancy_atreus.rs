@@ -0,0 +1,5 @@
+pub const USB_VID: u16 = 0x1209;
+pub const USB_PID: u16 = 0xa1e5;
+
+pub const MANUFACTURER: &str = "Technomancy";
+pub const PRODUCT: &str = "Atreus";
@@ -1,11 +1,25 @@
 pub(crate) mod atmega;
 pub(crate) mod base;
+#[cfg(feature = "chatter_stats")]
+pub(crate) mod chatter;
+pub(crate) mod debounce;
 
-pub use atmega::Atmega;
+pub use atmega::{combo_held, debounce_cycles, ghost_bits, key_state, read_hot_pins, request_debounce_ms, take_requested_debounce, Atmega, MAX_DEBOUNCE_MS};
+#[cfg(feature = "chatter_stats")]
+pub use atmega::CHATTER_STATS;
+#[cfg(feature = "chatter_stats")]
+pub use chatter::{ChatterStats, CHATTER_WINDOW};
+pub use debounce::{CounterDebouncer, Debounce, Debouncer, IntegratorDebouncer, RowState, DEBOUNCE_COLS};
 
 pub trait KeyScannerProps {
     const ROWS: usize;
     const COLS: usize;
 
     const KEYSCAN_INTERVAL: u16;
+
+    /// Whether a pressed key pulls its column pin low.
+    ///
+    /// Set to `false` for boards with normally-closed switches, or inverted (active-high)
+    /// matrix wiring.
+    const ACTIVE_LOW: bool = true;
 }
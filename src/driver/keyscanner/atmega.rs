@@ -1,38 +1,413 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
 use crate::device::{pins_and_ports::*, F_CPU};
-use crate::driver::keyscanner::{base::Base, KeyScannerProps};
-use crate::{key_addr::KeyAddr, key_defs::Key, util::bits::bit_read};
-use crate::{RUNTIME, return_on_err, tc1, wdt};
+use crate::driver::keyscanner::{base::Base, Debounce, Debouncer, KeyScannerProps, RowState, DEBOUNCE_COLS};
+#[cfg(feature = "chatter_stats")]
+use crate::driver::keyscanner::ChatterStats;
+use crate::{key_addr::KeyAddr, key_addr_ext::KeyAddrExt, key_defs::Key, key_event::KeyEvent, keyswitch_state::KeyswitchState, layers::NUM_KEYS};
+use crate::util::timing::{delay_cycles, us_to_cycles};
+use crate::{millis::millis, runtime::Runtime, RUNTIME, return_on_err, with_tc1, with_wdt};
+#[cfg(feature = "chatter_stats")]
+use crate::lock;
+
+use kaleidoscope_internal::driver::keyscanner::MatrixScanner;
+#[cfg(feature = "chatter_stats")]
+use ufmt::uWrite;
+use ufmt::uwrite;
+
+use crate::device::DeviceOps;
+use crate::event_handler::{self, EventHandler, EventHandlerError};
+use crate::hooks::Hooks;
+use crate::focus::{defer_reply, split_command, FOCUS_OUTPUT};
+
+use crate::driver::board::{BoardProps, DeviceProps};
+
+/// Minimum scan interval accepted by [Atmega::set_scan_cycle_time], in microseconds.
+pub const MIN_SCAN_INTERVAL: u16 = 1;
+
+/// Maximum scan interval accepted by [Atmega::set_scan_cycle_time], in microseconds.
+///
+/// The timer counts eight cycles per microsecond, so the longest interval that fits in
+/// the 16-bit `ICR1` register is `65535 / 8`.
+pub const MAX_SCAN_INTERVAL: u16 = 8191;
+
+/// Maximum debounce time accepted by [Atmega::set_debounce_ms], in milliseconds.
+pub const MAX_DEBOUNCE_MS: u8 = 50;
+
+static REQUESTED_DEBOUNCE: AtomicU8 = AtomicU8::new(0);
+static DEBOUNCE_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Debounce time in effect, mirrored for the `device.debounce` Focus command.
+static DEBOUNCE_MS: AtomicU8 = AtomicU8::new(0);
+
+/// Requests a new debounce time, in milliseconds.
+///
+/// Focus handlers run while the runtime is borrowed, so the time is applied by the
+/// [Runtime](crate::runtime::Runtime) at the start of the next cycle, using
+/// [take_requested_debounce].
+pub fn request_debounce_ms(debounce_ms: u8) {
+    REQUESTED_DEBOUNCE.store(debounce_ms, Ordering::Relaxed);
+    DEBOUNCE_CHANGED.store(true, Ordering::SeqCst);
+}
+
+/// Takes the most recent debounce time requested with [request_debounce_ms], if it
+/// changed since the last call.
+pub fn take_requested_debounce() -> Option<u8> {
+    if DEBOUNCE_CHANGED.swap(false, Ordering::SeqCst) {
+        Some(REQUESTED_DEBOUNCE.load(Ordering::Relaxed))
+    } else {
+        None
+    }
+}
 
-use kaleidoscope_internal::driver::keyscanner::{Atmega as AtmegaInner, MatrixScanner};
+/// Chatter counters of the key scanner.
+///
+/// Kept out of the [Atmega] itself, so the `device.chatter` Focus command can read and
+/// reset them without borrowing the runtime.
+#[cfg(feature = "chatter_stats")]
+pub static CHATTER_STATS: lock::Spinlock<ChatterStats> = lock::Spinlock::new(ChatterStats::new());
+
+/// Gets the number of debouncer cycles covering `debounce_ms`, when scanning every
+/// `interval_us` microseconds.
+///
+/// The result is rounded up, so the debounce time is never shorter than requested, and
+/// clamped to at least one cycle.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::debounce_cycles;
+///
+/// // (interval in microseconds, debounce time in milliseconds, cycles)
+/// let cases = [
+///     (1700, 5, 3),
+///     (1000, 5, 5),
+///     (500, 5, 10),
+///     (1000, 0, 1),
+///     (8191, 1, 1),
+///     (100, 50, 255),
+///     (0, 5, 255),
+/// ];
+///
+/// for (interval, ms, cycles) in cases {
+///     assert_eq!(debounce_cycles(interval, ms), cycles);
+/// }
+/// ```
+pub const fn debounce_cycles(interval_us: u16, debounce_ms: u8) -> u8 {
+    let interval = if interval_us == 0 { 1 } else { interval_us as u32 };
+    let cycles = (debounce_ms as u32 * 1000 + interval - 1) / interval;
+
+    if cycles == 0 {
+        1
+    } else if cycles > u8::MAX as u32 {
+        u8::MAX
+    } else {
+        cycles as u8
+    }
+}
 
-#[cfg(feature = "atreus")]
-use crate::plugins::atreus::DeviceProps;
+// Each matrix row is sampled into a single [RowState].
+const _: () = assert!(
+    DeviceProps::COLS <= DEBOUNCE_COLS,
+    "The matrix has more columns than a row state can hold, enable the `wide_matrix` feature."
+);
+
+/// Debounced state of a matrix row, one bit per column.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct MatrixRow {
+    previous: RowState,
+    current: RowState,
+}
+
+impl MatrixRow {
+    const fn new() -> Self {
+        Self {
+            previous: 0,
+            current: 0,
+        }
+    }
+}
 
 /// Keyscanner implementation for Atmega-based platforms.
 pub struct Atmega {
-    inner: AtmegaInner,
+    do_scan: bool,
+    matrix: [MatrixRow; DeviceProps::ROWS],
+    debouncers: [Debounce; DeviceProps::ROWS],
+    scan_interval: u16,
+    debounce_ms: Option<u8>,
+    #[cfg(feature = "per_key_debounce")]
+    key_debounce: [[u8; DeviceProps::COLS]; DeviceProps::ROWS],
+    repeat_interval: Option<u16>,
+    repeat_times: [u16; NUM_KEYS],
+    ghost_detection: bool,
+}
+
+/// Reads the column pins with `read_pin`, returning the hot columns.
+///
+/// Bit `i` of the result is set when the pin of column `i` differs from the idle level,
+/// i.e. it is low for active-low boards.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::read_hot_pins;
+///
+/// let pins = [10, 11, 12, 13];
+///
+/// for (i, &pressed) in pins.iter().enumerate() {
+///     // Only the pressed column is pulled low.
+///     let hot = read_hot_pins(&pins, true, |pin| pin != pressed);
+///     assert_eq!(hot, 1 << i);
+/// }
+///
+/// // With the `wide_matrix` feature, up to 32 columns are read.
+/// # #[cfg(feature = "wide_matrix")]
+/// # {
+/// let pins: Vec<u8> = (0..24).collect();
+/// let hot = read_hot_pins(&pins, true, |pin| pin != 17 && pin != 23);
+/// assert_eq!(hot, (1 << 17) | (1 << 23));
+/// # }
+/// ```
+pub fn read_hot_pins<F: FnMut(u8) -> bool>(pins: &[u8], active_low: bool, mut read_pin: F) -> RowState {
+    let mut hot_pins: RowState = 0;
+
+    for (i, &col) in pins.iter().enumerate().take(DEBOUNCE_COLS) {
+        hot_pins |= ((read_pin(col) != active_low) as RowState) << i;
+    }
+
+    hot_pins
+}
+
+/// Gets the newly pressed keys of `row` that may be ghosts.
+///
+/// On a matrix without diodes, pressing three corners of a rectangle makes the fourth
+/// one read as pressed. A new key is a possible ghost when its row shares at least two
+/// pressed columns with another row, and the key is in one of them: the three other
+/// corners of the rectangle are then pressed.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::ghost_bits;
+///
+/// // Keys (0, 0), (0, 1), and (1, 0) are pressed, (1, 1) appears as a phantom.
+/// let current = [0b11, 0b11, 0b00];
+/// assert_eq!(ghost_bits(&current, 1, 0b10), 0b10);
+///
+/// // No rectangle: (1, 1) is a real press.
+/// let current = [0b01, 0b10, 0b00];
+/// assert_eq!(ghost_bits(&current, 1, 0b10), 0);
+/// ```
+pub fn ghost_bits(current: &[RowState], row: usize, new_bits: RowState) -> RowState {
+    let mut ghosts = 0;
+
+    for (other_row, &other) in current.iter().enumerate() {
+        if other_row == row {
+            continue;
+        }
+
+        let shared = current[row] & other;
+
+        if shared.count_ones() >= 2 {
+            ghosts |= new_bits & shared;
+        }
+    }
+
+    ghosts
+}
+
+/// Gets whether every key of `combo` is held in the matrix state `rows`.
+///
+/// An empty combo, or one with an address outside `rows`, is never held.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::combo_held;
+/// use kaleidoscope::key_addr::KeyAddr;
+///
+/// let combo = [KeyAddr::create(0, 0), KeyAddr::create(2, 5)];
+///
+/// // Both keys held, along with another one.
+/// assert!(combo_held(&[0b1, 0b100, 1 << 5], &combo));
+///
+/// // Only one of them.
+/// assert!(!combo_held(&[0b1, 0, 0], &combo));
+/// assert!(!combo_held(&[0, 0, 1 << 5], &combo));
+///
+/// // Nothing is configured, or the combo is outside the matrix.
+/// assert!(!combo_held(&[0b1, 0, 0], &[]));
+/// assert!(!combo_held(&[0b1], &combo));
+/// ```
+pub fn combo_held(rows: &[RowState], combo: &[KeyAddr]) -> bool {
+    !combo.is_empty()
+        && combo.iter().all(|addr| {
+            let (row, col) = (addr.row() as usize, addr.col() as u32);
+
+            addr.is_valid() && col < RowState::BITS && rows.get(row).map_or(false, |&state| state & (1 << col) != 0)
+        })
+}
+
+/// Gets the [KeyswitchState] bits of column `col`, from the previous and current
+/// debounced states of its row.
+///
+/// Bit 0 is set when the key was pressed, bit 1 when it is pressed.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::key_state;
+///
+/// // Column 11 toggled on, column 9 toggled off, column 3 stays pressed.
+/// let previous = (1 << 9) | (1 << 3);
+/// let current = (1 << 11) | (1 << 3);
+///
+/// assert_eq!(key_state(previous, current, 11), 0b10);
+/// assert_eq!(key_state(previous, current, 9), 0b01);
+/// assert_eq!(key_state(previous, current, 3), 0b11);
+/// assert_eq!(key_state(previous, current, 0), 0b00);
+///
+/// // With the `wide_matrix` feature, columns past the 16th are scanned too.
+/// # #[cfg(feature = "wide_matrix")]
+/// # {
+/// let previous = 1 << 23;
+/// let current = 1 << 20;
+///
+/// assert_eq!(key_state(previous, current, 20), 0b10);
+/// assert_eq!(key_state(previous, current, 23), 0b01);
+/// # }
+/// ```
+pub const fn key_state(previous: RowState, current: RowState, col: usize) -> u8 {
+    let was_pressed = (previous >> col) & 1;
+    let is_pressed = (current >> col) & 1;
+
+    (was_pressed | (is_pressed << 1)) as u8
 }
 
 impl Atmega {
-    /// Creates a new [Atmega] key scanner.
+    /// Creates a new [Atmega] key scanner using the default counter debouncer.
     pub const fn new() -> Self {
+        Self::with_debouncer(Debounce::counter())
+    }
+
+    /// Creates a new [Atmega] key scanner using the provided debouncer for every row.
+    pub const fn with_debouncer(debouncer: Debounce) -> Self {
         Self {
-            inner: AtmegaInner::new(),
+            do_scan: false,
+            matrix: [MatrixRow::new(); DeviceProps::ROWS],
+            debouncers: [debouncer; DeviceProps::ROWS],
+            scan_interval: DeviceProps::KEYSCAN_INTERVAL,
+            debounce_ms: None,
+            #[cfg(feature = "per_key_debounce")]
+            key_debounce: [[0u8; DeviceProps::COLS]; DeviceProps::ROWS],
+            repeat_interval: None,
+            repeat_times: [0u16; NUM_KEYS],
+            ghost_detection: false,
+        }
+    }
+
+    /// Replaces the debouncer for every row, resetting the debounced state.
+    ///
+    /// Clears the debounce time set with [set_debounce_ms](Self::set_debounce_ms).
+    pub fn set_debouncer(&mut self, debouncer: Debounce) {
+        self.debouncers = [debouncer; DeviceProps::ROWS];
+        self.debounce_ms = None;
+        DEBOUNCE_MS.store(self.debounce_ms(), Ordering::Relaxed);
+
+        // Per-key overrides need an integrator debouncer.
+        #[cfg(feature = "per_key_debounce")]
+        for (debouncer, overrides) in self.debouncers.iter_mut().zip(self.key_debounce.iter()) {
+            if overrides.iter().any(|&cycles| cycles > 0) {
+                debouncer.set_cycles(debouncer.cycles());
+            }
+        }
+    }
+
+    /// Gets the debounce time, in milliseconds.
+    ///
+    /// Without a time set with [set_debounce_ms](Self::set_debounce_ms), it is derived from
+    /// the debouncer cycles and the scan interval, rounded down.
+    pub fn debounce_ms(&self) -> u8 {
+        match self.debounce_ms {
+            Some(ms) => ms,
+            None => {
+                let cycles = self.debouncers.first().map(|d| d.cycles()).unwrap_or(1);
+                (cycles as u32 * self.scan_interval as u32 / 1000).min(u8::MAX as u32) as u8
+            }
+        }
+    }
+
+    /// Sets the debounce time, in milliseconds, clamped to [MAX_DEBOUNCE_MS].
+    ///
+    /// The time is converted to a number of integrator cycles with [debounce_cycles], and
+    /// converted again whenever the scan interval changes. The debounced state is kept.
+    pub fn set_debounce_ms(&mut self, debounce_ms: u8) {
+        let debounce_ms = debounce_ms.min(MAX_DEBOUNCE_MS);
+
+        self.debounce_ms = Some(debounce_ms);
+        self.apply_debounce_ms();
+    }
+
+    /// Gets the debounce cycles override of the key at `addr`, `0` if it uses the global
+    /// setting.
+    ///
+    /// Only available with the `per_key_debounce` feature.
+    #[cfg(feature = "per_key_debounce")]
+    pub fn key_debounce(&self, addr: KeyAddr) -> u8 {
+        self.key_debounce
+            .get(addr.row() as usize)
+            .and_then(|row| row.get(addr.col() as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Sets the number of consistent samples the key at `addr` requires to change state,
+    /// overriding the global setting for bouncier switches. Set to `0` to use the global
+    /// setting again.
+    ///
+    /// A row with an override uses an [IntegratorDebouncer](crate::driver::keyscanner::IntegratorDebouncer).
+    ///
+    /// Only available with the `per_key_debounce` feature.
+    #[cfg(feature = "per_key_debounce")]
+    pub fn set_key_debounce(&mut self, addr: KeyAddr, cycles: u8) -> crate::Result<()> {
+        let (row, col) = (addr.row() as usize, addr.col() as usize);
+
+        if !addr.is_valid() || row >= DeviceProps::ROWS || col >= DeviceProps::COLS {
+            return Err(crate::Error::InvalidKeyAddr);
         }
+
+        self.key_debounce[row][col] = cycles;
+
+        let debouncer = &mut self.debouncers[row];
+        debouncer.set_cycles(debouncer.cycles());
+
+        Ok(())
+    }
+
+    fn apply_debounce_ms(&mut self) {
+        if let Some(ms) = self.debounce_ms {
+            let cycles = debounce_cycles(self.scan_interval, ms);
+
+            for debouncer in self.debouncers.iter_mut() {
+                debouncer.set_cycles(cycles);
+            }
+        }
+
+        DEBOUNCE_MS.store(self.debounce_ms(), Ordering::Relaxed);
     }
 
     /// Gets whether the scanner should scan the keys.
     pub fn do_scan(&self) -> bool {
-        self.inner.do_scan()
+        self.do_scan
     }
 
     /// Sets whether the scanner should scan the keys.
     pub fn set_do_scan(&mut self, do_scan: bool) {
-        self.inner.set_do_scan(do_scan);
+        self.do_scan = do_scan;
     }
 
     /// Setup the row and column pins for the key scanner.
-    pub fn setup(&self) {
+    pub fn setup(&mut self) {
         assert!(
             DeviceProps::MATRIX_ROW_PINS.len() > 0,
             "The key scanner description has an empty array of matrix row pins."
@@ -42,11 +417,7 @@ impl Atmega {
             "The key scanner description has an empty array of matrix column pins."
         );
 
-        let wdt_lock = return_on_err!(wdt());
-
-        avr_device::interrupt::free(|cs| {
-            let wdt = wdt_lock.borrow(cs);
-
+        return_on_err!(with_wdt(|wdt| {
             // Reset the watchdog timer
             avr_device::asm::wdr();
 
@@ -56,22 +427,49 @@ impl Atmega {
 
             // Disable watchdog timer
             wdt.wdtcsr.reset();
-        });
+        }));
+
+        Self::setup_pins();
+
+        self.set_scan_cycle_time(DeviceProps::KEYSCAN_INTERVAL);
+    }
 
-        for pin in DeviceProps::MATRIX_COL_PINS {
+    /// Configures the column pins as inputs, and the row pins as idle outputs.
+    fn setup_pins() {
+        for &pin in DeviceProps::MATRIX_COL_PINS {
             ddr_input(pin.into());
-            enable_pullup(pin.into());
+
+            // Active-high boards are expected to provide their own pull-down resistors.
+            if DeviceProps::ACTIVE_LOW {
+                enable_pullup(pin.into());
+            }
         }
 
-        for pin in DeviceProps::MATRIX_ROW_PINS {
+        for &pin in DeviceProps::MATRIX_ROW_PINS {
             ddr_output(pin.into());
             drive_output_high(pin.into());
         }
+    }
 
-        self.set_scan_cycle_time(DeviceProps::KEYSCAN_INTERVAL);
+    /// Reads the matrix once, synchronously, configuring the pins first.
+    ///
+    /// Nothing is debounced, and no event is generated: this is meant for checks run
+    /// before the event pipeline is up, like the boot combo, see [combo_held].
+    pub fn read_raw_matrix(&self) -> [RowState; DeviceProps::ROWS] {
+        let mut rows = [0; DeviceProps::ROWS];
+
+        Self::setup_pins();
+
+        for (state, &row) in rows.iter_mut().zip(DeviceProps::MATRIX_ROW_PINS.iter()) {
+            output_toggle(row.into());
+            *state = self.read_cols();
+            output_toggle(row.into());
+        }
+
+        rows
     }
 
-    /// Takes a value of between 0 and 8192.
+    /// Takes a value of between 1 and 8191.
     ///
     /// This corresponds (roughly) to the number of microseconds to wait between scanning the key matrix.
     ///
@@ -80,12 +478,15 @@ impl Atmega {
     /// Most normal mechanical switches specify a 5ms debounce period. On an ATMega32U4, 1700 gets you about 5ms of debouncing.
     ///
     /// Because keycanning is triggered by an interrupt but not run in that interrupt, the actual amount of time between scans is prone to a little bit of jitter.
-    pub fn set_scan_cycle_time(&self, interval: u16) {
-        let tc1_lock = return_on_err!(tc1());
-
-        avr_device::interrupt::free(|cs| {
-            let tc1 = tc1_lock.borrow(cs);
-
+    ///
+    /// Values outside [MIN_SCAN_INTERVAL]..=[MAX_SCAN_INTERVAL] are clamped. A debounce time set with
+    /// [set_debounce_ms](Self::set_debounce_ms) is kept, by recomputing the debouncer cycles.
+    pub fn set_scan_cycle_time(&mut self, interval: u16) {
+        let interval = interval.clamp(MIN_SCAN_INTERVAL, MAX_SCAN_INTERVAL);
+        self.scan_interval = interval;
+        self.apply_debounce_ms();
+
+        return_on_err!(with_tc1(|tc1| {
             tc1.tccr1b.modify(|_, w| w.wgm1().bits(0b01));
             tc1.tccr1a.modify(|_, w| unsafe { w.bits(0) });
 
@@ -95,12 +496,47 @@ impl Atmega {
             tc1.tccr1b
                 .write(|w| w.wgm1().bits(0b01).cs1().bits(0b01));
             tc1.timsk1.modify(|_, w| w.toie1().bit(true));
-        });
+        }));
+    }
+
+    /// Gets the scan interval in microseconds.
+    pub fn scan_cycle_time(&self) -> u16 {
+        self.scan_interval
+    }
+
+    /// Gets the interval, in milliseconds, between synthetic "held" events.
+    pub fn repeat_interval(&self) -> Option<u16> {
+        self.repeat_interval
+    }
+
+    /// Sets the interval, in milliseconds, between synthetic "held" events.
+    ///
+    /// While set, keys that remain pressed generate an injected [KeyEvent] with both the
+    /// `was_pressed` and `is_pressed` bits set, every `interval` milliseconds, passed to
+    /// [Runtime::handle_held_event](crate::runtime::Runtime::handle_held_event).
+    ///
+    /// Set to `None` (the default) to only generate toggle events.
+    pub fn set_repeat_interval(&mut self, interval: Option<u16>) {
+        self.repeat_interval = interval;
+    }
+
+    /// Gets whether phantom key presses are suppressed.
+    pub fn ghost_detection(&self) -> bool {
+        self.ghost_detection
+    }
+
+    /// Sets whether phantom key presses are suppressed, see [ghost_bits].
+    ///
+    /// Only useful for matrices without diodes, and disabled by default. While enabled,
+    /// a new press forming the fourth corner of a pressed rectangle produces no event,
+    /// until the rectangle is broken. This also drops a real press of all four corners.
+    pub fn set_ghost_detection(&mut self, enabled: bool) {
+        self.ghost_detection = enabled;
     }
 
     /// Read the key matrix.
     pub fn read_matrix(&mut self) {
-        let mut any_debounced_changes = 0u16;
+        let mut any_debounced_changes: RowState = 0;
 
         for (i, &row) in DeviceProps::MATRIX_ROW_PINS.iter().enumerate() {
             output_toggle(row.into());
@@ -110,13 +546,35 @@ impl Atmega {
             any_debounced_changes |= self.debounce(hot_pins, i);
 
             if any_debounced_changes != 0 {
-                let matrix_len = self.inner.matrix_state().len();
-                for i in 0..matrix_len {
-                    let dbn_state = self.inner.matrix_state()[i].debouncer.debounced_state;
-                    self.inner.matrix_state_mut()[i].current = dbn_state;
+                for (state, debouncer) in self.matrix.iter_mut().zip(self.debouncers.iter()) {
+                    state.current = debouncer.debounced_state();
                 }
             }
         }
+
+        if self.ghost_detection {
+            self.suppress_ghosts();
+        }
+    }
+
+    /// Clears newly pressed phantom keys from the matrix state, before any event is
+    /// generated for them.
+    fn suppress_ghosts(&mut self) {
+        let mut current: [RowState; DeviceProps::ROWS] = [0; DeviceProps::ROWS];
+
+        for (row, state) in current.iter_mut().zip(self.matrix.iter()) {
+            *row = state.current;
+        }
+
+        for row in 0..DeviceProps::ROWS {
+            let state = &self.matrix[row];
+            let new_bits = state.current & !state.previous;
+
+            if new_bits != 0 {
+                let ghosts = ghost_bits(&current, row, new_bits);
+                self.matrix[row].current &= !ghosts;
+            }
+        }
     }
 
     /// In the C++ library, no loop unrolling + a nop instruction is used to slow down
@@ -139,57 +597,147 @@ impl Atmega {
     ///
     /// Do not remove the attribute!
     /// ```
-    pub fn read_cols(&self) -> u16 {
-        let mut hot_pins = 0u16;
-        for (i, col) in DeviceProps::MATRIX_COL_PINS.iter().enumerate() {
-            // Should be roughly equivalent to no loop unrolling + a nop instruction...
-            arduino_hal::delay_us(1);
+    pub fn read_cols(&self) -> RowState {
+        read_hot_pins(DeviceProps::MATRIX_COL_PINS, DeviceProps::ACTIVE_LOW, |col| {
+            // Give the column pin one microsecond to settle before reading it.
+            delay_cycles(us_to_cycles(1));
 
-            hot_pins |= (!read_pin((col << i).into())) as u16;
-        }
-        hot_pins
+            read_pin(col.into())
+        })
     }
 
     pub fn act_on_matrix_scan(&mut self) {
+        let now = millis() as u16;
+
         for row in 0..DeviceProps::ROWS {
             for col in 0..DeviceProps::COLS {
-                let matrix_state = self.inner.matrix_state();
-                let key_state = (bit_read(matrix_state[row].previous as u8, col as u8) << 0)
-                    | (bit_read(matrix_state[row].current as u8, col as u8) << 1);
+                let key_state = key_state(self.matrix[row].previous, self.matrix[row].current, col);
+                #[cfg(feature = "chatter_stats")]
+                if key_state == 0b01 || key_state == 0b10 {
+                    CHATTER_STATS.write().record(KeyAddr::create(row as u8, col as u8).index(), now);
+                }
+
+                if key_state == 0b01 || key_state == 0b10 {
+                    let _ = Hooks::on_physical_key(KeyAddr::create(row as u8, col as u8), key_state.into());
+                }
+
                 if key_state != 0 {
+                    let mut runtime = RUNTIME.write();
+
                     self.handle_keyswitch_event(
-                        &mut RUNTIME.write(),
+                        &mut runtime,
                         Key::default(),
                         KeyAddr::create(row as u8, col as u8),
                         key_state.into(),
                     );
+
+                    if let Some(interval) = self.repeat_interval {
+                        self.repeat_held_key(
+                            &mut runtime,
+                            KeyAddr::create(row as u8, col as u8),
+                            key_state,
+                            interval,
+                            now,
+                        );
+                    }
                 }
             }
-            let current = self.inner.matrix_state()[row].current;
-            self.inner.matrix_state_mut()[row].previous = current;
+            self.matrix[row].previous = self.matrix[row].current;
         }
     }
 
-    fn debounce(&mut self, sample: u16, row: usize) -> u16 {
-        let debouncer = &mut self.inner.matrix_state_mut()[row].debouncer;
+    /// Sets the debounced state of `row`, as if [read_matrix](Self::read_matrix) had just
+    /// read it.
+    #[cfg(test)]
+    pub(crate) fn seed_row(&mut self, row: usize, current: RowState) {
+        self.matrix[row].current = current;
+    }
 
-        // Use xor to detect changes from last stable state:
-        // if a key has changed, it's bit will be 1, otherwise 0
-        let delta = sample ^ debouncer.debounced_state;
+    /// Gets the state of `row` handled by the last scan.
+    #[cfg(test)]
+    pub(crate) fn previous_row(&self, row: usize) -> RowState {
+        self.matrix[row].previous
+    }
 
-        // Increment counters and reset any unchanged bits:
-        // increment bit 1 for all changed keys
-        debouncer.db1 = (debouncer.db1 ^ debouncer.db0) & delta;
-        // increment bit 0 for all changed keys
-        debouncer.db0 = !debouncer.db0 & delta;
+    /// Forces a single matrix scan, independent of the scan timer interrupt.
+    ///
+    /// The resulting keyswitch events are handled as usual. The interrupt-driven scan flag
+    /// is cleared, so the next cycle does not scan the matrix a second time.
+    ///
+    /// Run by the runtime for the `hardware.scanOnce` Focus command, see
+    /// [Runtime::request_scan_once](crate::runtime::Runtime::request_scan_once).
+    pub fn scan_once(&mut self) {
+        self.set_do_scan(false);
+        self.read_matrix();
+        self.act_on_matrix_scan();
+    }
 
-        // Calculate returned change set: if delta is still true
-        // and the counter has wrapped back to 0, the key is changed.
-        let changes = !(!delta | (debouncer.db0 | debouncer.db1));
-        // Update state: in this case use xor to flip any bit that is true in changes.
-        debouncer.debounced_state ^= changes;
+    /// Gets the number of times the key at `key_addr` toggled faster than the
+    /// [CHATTER_WINDOW](crate::driver::keyscanner::CHATTER_WINDOW).
+    ///
+    /// Only available with the `chatter_stats` feature.
+    #[cfg(feature = "chatter_stats")]
+    pub fn chatter_count(key_addr: KeyAddr) -> u16 {
+        CHATTER_STATS.read().count(key_addr.index())
+    }
 
-        changes
+    /// Clears the chatter counters of every key.
+    #[cfg(feature = "chatter_stats")]
+    pub fn reset_chatter_stats() {
+        CHATTER_STATS.write().reset();
+    }
+
+    /// Writes the keys that chatter the most, one `row col count` line each.
+    #[cfg(feature = "chatter_stats")]
+    pub fn write_chatter_stats<W: uWrite>(w: &mut W) -> core::result::Result<(), W::Error> {
+        let mut worst = [(0usize, 0u16); 8];
+        let len = CHATTER_STATS.read().worst_offenders(&mut worst);
+
+        let cols = KeyAddr::cols() as usize;
+
+        for &(index, count) in worst[..len].iter() {
+            uwrite!(w, "{} {} {}\r\n", index / cols, index % cols, count)?;
+        }
+
+        Ok(())
+    }
+
+    fn repeat_held_key(
+        &mut self,
+        runtime: &mut Runtime,
+        key_addr: KeyAddr,
+        key_state: u8,
+        interval: u16,
+        now: u16,
+    ) {
+        let index = key_addr.index();
+        if index >= NUM_KEYS {
+            return;
+        }
+
+        let state = KeyswitchState::from(key_state);
+
+        if state.key_toggled_on() {
+            self.repeat_times[index] = now;
+        } else if state.key_is_pressed()
+            && state.key_was_pressed()
+            && now.wrapping_sub(self.repeat_times[index]) >= interval
+        {
+            self.repeat_times[index] = now;
+
+            let mut state = state;
+            state.set_injected(true);
+
+            runtime.handle_held_event(KeyEvent::next(key_addr, state));
+        }
+    }
+
+    fn debounce(&mut self, sample: RowState, row: usize) -> RowState {
+        #[cfg(feature = "per_key_debounce")]
+        return self.debouncers[row].update_with_overrides(sample, &self.key_debounce[row]);
+
+        #[cfg(not(feature = "per_key_debounce"))]
+        self.debouncers[row].update(sample)
     }
 }
 
@@ -205,3 +753,136 @@ impl MatrixScanner for Atmega {
         self.act_on_matrix_scan();
     }
 }
+
+impl EventHandler for Atmega {
+    /// Handles the `hardware.scanOnce` Focus command, used by host-driven test rigs to
+    /// step the key scanner deterministically, replying with one `row col key state` line
+    /// per resulting key event, and the `device.debounce` command, printing
+    /// or setting the debounce time in milliseconds.
+    fn on_focus_event(input: &str) -> event_handler::Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("hardware.scanOnce\r\n");
+            let _ = FOCUS_OUTPUT.write().write_str("device.debounce\r\n");
+            #[cfg(feature = "chatter_stats")]
+            let _ = FOCUS_OUTPUT.write().write_str("device.chatter\r\n");
+            return Ok(());
+        }
+
+        #[cfg(feature = "chatter_stats")]
+        if command == "device.chatter" {
+            let (_, args) = split_command(input);
+
+            if args.trim() == "reset" {
+                Self::reset_chatter_stats();
+            } else {
+                Self::write_chatter_stats(&mut *FOCUS_OUTPUT.write()).map_err(|_| EventHandlerError::Error)?;
+            }
+
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if command == "device.debounce" {
+            let (_, args) = split_command(input);
+            let args = args.trim();
+
+            if args.is_empty() {
+                uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", DEBOUNCE_MS.load(Ordering::Relaxed))
+                    .map_err(|_| EventHandlerError::Error)?;
+            } else {
+                request_debounce_ms(args.parse().map_err(|_| EventHandlerError::Error)?);
+            }
+
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if command != "hardware.scanOnce" {
+            return Ok(());
+        }
+
+        // The runtime scans the matrix later in this cycle, and sends the reply.
+        Runtime::request_scan_once();
+        defer_reply();
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key_Inactive, LAYER, LIVE_KEYS};
+
+    #[test]
+    fn combo_held_needs_every_key() {
+        let combo = [KeyAddr::create(0, 1), KeyAddr::create(1, 2), KeyAddr::create(3, 0)];
+
+        assert!(combo_held(&[0b10, 0b100, 0, 0b1], &combo));
+        assert!(combo_held(&[0b11, 0b110, 0b1, 0b11], &combo));
+        assert!(!combo_held(&[0b10, 0b100, 0, 0], &combo));
+        assert!(!combo_held(&[0, 0, 0, 0], &combo));
+    }
+
+    #[test]
+    fn combo_held_single_key() {
+        let combo = [KeyAddr::create(2, 3)];
+
+        assert!(combo_held(&[0, 0, 1 << 3], &combo));
+        assert!(!combo_held(&[1 << 3, 1 << 3, 0], &combo));
+    }
+
+    #[test]
+    fn combo_held_rejects_invalid_combos() {
+        let rows = [RowState::MAX; 4];
+
+        assert!(!combo_held(&rows, &[]));
+        assert!(!combo_held(&rows, &[KeyAddr::default()]));
+        assert!(!combo_held(&rows, &[KeyAddr::create(0, 0), KeyAddr::default()]));
+        assert!(!combo_held(&[], &[KeyAddr::create(0, 0)]));
+    }
+
+    #[test]
+    fn hot_pins_follow_the_active_level() {
+        let pins = [4, 5, 6];
+
+        // Active-low: a pressed key pulls its pin low.
+        assert_eq!(read_hot_pins(&pins, true, |pin| pin != 5), 0b010);
+        assert_eq!(read_hot_pins(&pins, true, |_| true), 0);
+
+        // Active-high: a pressed key drives its pin high.
+        assert_eq!(read_hot_pins(&pins, false, |pin| pin == 5), 0b010);
+        assert_eq!(read_hot_pins(&pins, false, |pin| pin != 4), 0b110);
+        assert_eq!(read_hot_pins(&pins, false, |_| false), 0);
+    }
+
+    #[test]
+    fn requested_debounce_is_taken_once() {
+        assert_eq!(take_requested_debounce(), None);
+
+        request_debounce_ms(12);
+        request_debounce_ms(8);
+
+        assert_eq!(take_requested_debounce(), Some(8));
+        assert_eq!(take_requested_debounce(), None);
+    }
+
+    #[test]
+    fn forced_scan_processes_seeded_changes() {
+        let mut scanner = Atmega::new();
+        let addr = KeyAddr::create(1, 3);
+        let key = LAYER.read().lookup_on_active_layer(&addr);
+
+        // Seed a press, as if read_matrix had just debounced it.
+        scanner.matrix[1].current = 1 << 3;
+        scanner.act_on_matrix_scan();
+
+        assert_eq!(LIVE_KEYS.read()[addr], key);
+        assert_eq!(scanner.matrix[1].previous, 1 << 3);
+
+        scanner.matrix[1].current = 0;
+        scanner.act_on_matrix_scan();
+
+        assert_eq!(LIVE_KEYS.read()[addr], Key_Inactive);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::layers::NUM_KEYS;
+
+/// Time, in milliseconds, under which two toggles of the same key count as chatter.
+///
+/// Debounced toggles this close together are faster than a finger can press and release
+/// a key, so they come from a flaky switch.
+pub const CHATTER_WINDOW: u16 = 10;
+
+/// Per-key chatter counters, for diagnosing flaky switches.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::keyscanner::ChatterStats;
+///
+/// let mut stats = ChatterStats::new();
+///
+/// // Press and release 3 ms apart, then press again 40 ms later.
+/// stats.record(0, 100);
+/// stats.record(0, 103);
+/// stats.record(0, 143);
+///
+/// assert_eq!(stats.count(0), 1);
+///
+/// stats.reset();
+/// assert_eq!(stats.count(0), 0);
+/// ```
+pub struct ChatterStats {
+    last_toggle: [u16; NUM_KEYS],
+    counts: [u8; NUM_KEYS],
+}
+
+impl ChatterStats {
+    /// Creates new, empty [ChatterStats].
+    pub const fn new() -> Self {
+        Self {
+            last_toggle: [0u16; NUM_KEYS],
+            counts: [0u8; NUM_KEYS],
+        }
+    }
+
+    /// Records a toggle of the key at `index`, at `now` milliseconds.
+    ///
+    /// The first toggle of a key after a reset is never counted.
+    pub fn record(&mut self, index: usize, now: u16) {
+        if index >= NUM_KEYS {
+            return;
+        }
+
+        let last = core::mem::replace(&mut self.last_toggle[index], now);
+
+        if last != 0 && now.wrapping_sub(last) < CHATTER_WINDOW {
+            self.counts[index] = self.counts[index].saturating_add(1);
+        }
+    }
+
+    /// Gets the chatter count of the key at `index`.
+    pub fn count(&self, index: usize) -> u16 {
+        self.counts.get(index).copied().unwrap_or(0) as u16
+    }
+
+    /// Fills `out` with the `(index, count)` of the keys with the highest chatter counts,
+    /// highest first.
+    ///
+    /// Returns the number of entries written, keys without chatter are skipped.
+    pub fn worst_offenders(&self, out: &mut [(usize, u16)]) -> usize {
+        let mut len = 0;
+
+        for (index, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+
+            // Insertion sort into the bounded output, dropping the lowest entry when full.
+            let mut pos = len;
+            while pos > 0 && out[pos - 1].1 < count as u16 {
+                pos -= 1;
+            }
+
+            if pos >= out.len() {
+                continue;
+            }
+
+            let end = core::cmp::min(len, out.len() - 1);
+            out.copy_within(pos..end, pos + 1);
+            out[pos] = (index, count as u16);
+            len = core::cmp::min(len + 1, out.len());
+        }
+
+        len
+    }
+
+    /// Clears all counters.
+    pub fn reset(&mut self) {
+        self.last_toggle = [0u16; NUM_KEYS];
+        self.counts = [0u8; NUM_KEYS];
+    }
+}
@@ -0,0 +1,337 @@
+/// Column samples and debounced state of a single matrix row, one bit per column.
+///
+/// 16 bits wide by default, which keeps the debouncers small on boards like the Atreus.
+/// The `wide_matrix` feature makes it 32 bits wide, for matrices with up to 32 columns.
+#[cfg(not(feature = "wide_matrix"))]
+pub type RowState = u16;
+
+/// Column samples and debounced state of a single matrix row, one bit per column.
+///
+/// 32 bits wide with the `wide_matrix` feature, for matrices with up to 32 columns.
+#[cfg(feature = "wide_matrix")]
+pub type RowState = u32;
+
+/// Number of columns (bits) a single debouncer row can track.
+pub const DEBOUNCE_COLS: usize = RowState::BITS as usize;
+
+/// Default number of consistent samples required by the [IntegratorDebouncer].
+pub const DEFAULT_INTEGRATOR_CYCLES: u8 = 4;
+
+/// Debounces the raw column samples of a single matrix row.
+pub trait Debouncer {
+    /// Feeds a new raw sample to the debouncer.
+    ///
+    /// Returns the mask of bits whose debounced state changed.
+    fn update(&mut self, sample: RowState) -> RowState;
+
+    /// Gets the current debounced state.
+    fn debounced_state(&self) -> RowState;
+}
+
+/// Vertical counter debouncer, requiring four consistent samples before a change is
+/// reported.
+///
+/// This is the algorithm used by the original Kaleidoscope ATmega key scanner.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CounterDebouncer {
+    db0: RowState,
+    db1: RowState,
+    debounced_state: RowState,
+}
+
+impl CounterDebouncer {
+    /// Creates a new [CounterDebouncer].
+    pub const fn new() -> Self {
+        Self {
+            db0: 0,
+            db1: 0,
+            debounced_state: 0,
+        }
+    }
+}
+
+impl Debouncer for CounterDebouncer {
+    fn update(&mut self, sample: RowState) -> RowState {
+        // Use xor to detect changes from last stable state:
+        // if a key has changed, it's bit will be 1, otherwise 0
+        let delta = sample ^ self.debounced_state;
+
+        // Increment counters and reset any unchanged bits:
+        // increment bit 1 for all changed keys
+        self.db1 = (self.db1 ^ self.db0) & delta;
+        // increment bit 0 for all changed keys
+        self.db0 = !self.db0 & delta;
+
+        // Calculate returned change set: if delta is still true
+        // and the counter has wrapped back to 0, the key is changed.
+        let changes = !(!delta | (self.db0 | self.db1));
+        // Update state: in this case use xor to flip any bit that is true in changes.
+        self.debounced_state ^= changes;
+
+        changes
+    }
+
+    fn debounced_state(&self) -> RowState {
+        self.debounced_state
+    }
+}
+
+/// Integrating debouncer, requiring a configurable number of consecutive consistent
+/// samples before a change is reported.
+///
+/// Trades latency for chatter resistance: more cycles means more resistance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IntegratorDebouncer {
+    cycles: u8,
+    counters: [u8; DEBOUNCE_COLS],
+    debounced_state: RowState,
+}
+
+impl IntegratorDebouncer {
+    /// Creates a new [IntegratorDebouncer] requiring `cycles` consistent samples.
+    ///
+    /// A value of zero is treated as one.
+    pub const fn new(cycles: u8) -> Self {
+        Self {
+            cycles: if cycles == 0 { 1 } else { cycles },
+            counters: [0u8; DEBOUNCE_COLS],
+            debounced_state: 0,
+        }
+    }
+
+    /// Gets the number of consistent samples required to report a change.
+    pub const fn cycles(&self) -> u8 {
+        self.cycles
+    }
+
+    /// Sets the number of consistent samples required to report a change, keeping the
+    /// debounced state.
+    ///
+    /// A value of zero is treated as one.
+    pub fn set_cycles(&mut self, cycles: u8) {
+        self.cycles = cycles.max(1);
+        self.counters = [0u8; DEBOUNCE_COLS];
+    }
+}
+
+impl Default for IntegratorDebouncer {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTEGRATOR_CYCLES)
+    }
+}
+
+impl IntegratorDebouncer {
+    /// Feeds a new raw sample to the debouncer, with per-bit cycle overrides.
+    ///
+    /// Bit `i` requires `overrides[i]` consistent samples, or the global
+    /// [cycles](Self::cycles) if the override is zero or missing.
+    ///
+    /// Returns the mask of bits whose debounced state changed.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::driver::keyscanner::IntegratorDebouncer;
+    ///
+    /// // Bit 1 bounces more than bit 0, and needs 6 samples instead of 2.
+    /// let mut debouncer = IntegratorDebouncer::new(2);
+    /// let overrides = [0, 6];
+    ///
+    /// // Both keys are pressed together, and bounce once.
+    /// let samples = [0b11, 0b00, 0b11, 0b11, 0b11, 0b11, 0b11, 0b11, 0b11];
+    /// let changes: Vec<_> = samples
+    ///     .iter()
+    ///     .map(|&sample| debouncer.update_with_overrides(sample, &overrides))
+    ///     .collect();
+    ///
+    /// assert_eq!(changes, [0, 0, 0, 0b01, 0, 0, 0, 0b10, 0]);
+    ///
+    /// // With the `wide_matrix` feature, columns past the 16th debounce the same way.
+    /// # #[cfg(feature = "wide_matrix")]
+    /// # {
+    /// use kaleidoscope::driver::keyscanner::Debouncer;
+    ///
+    /// let mut debouncer = IntegratorDebouncer::new(2);
+    /// let mut overrides = [0; 24];
+    /// overrides[23] = 3;
+    ///
+    /// let (col_20, col_23) = (1 << 20, 1 << 23);
+    /// let samples = [col_20 | col_23, col_20 | col_23, col_20 | col_23, 0];
+    /// let changes: Vec<_> = samples
+    ///     .iter()
+    ///     .map(|&sample| debouncer.update_with_overrides(sample, &overrides))
+    ///     .collect();
+    ///
+    /// assert_eq!(changes, [0, col_20, col_23, 0]);
+    /// assert_eq!(debouncer.debounced_state(), col_20 | col_23);
+    /// # }
+    /// ```
+    pub fn update_with_overrides(&mut self, sample: RowState, overrides: &[u8]) -> RowState {
+        let delta = sample ^ self.debounced_state;
+        let mut changes: RowState = 0;
+
+        for (bit, counter) in self.counters.iter_mut().enumerate() {
+            if delta & (1 << bit) == 0 {
+                *counter = 0;
+                continue;
+            }
+
+            let cycles = match overrides.get(bit) {
+                Some(&cycles) if cycles > 0 => cycles,
+                _ => self.cycles,
+            };
+
+            *counter = counter.saturating_add(1);
+
+            if *counter >= cycles {
+                changes |= 1 << bit;
+                *counter = 0;
+            }
+        }
+
+        self.debounced_state ^= changes;
+
+        changes
+    }
+}
+
+impl Debouncer for IntegratorDebouncer {
+    fn update(&mut self, sample: RowState) -> RowState {
+        let delta = sample ^ self.debounced_state;
+        let mut changes: RowState = 0;
+
+        for (bit, counter) in self.counters.iter_mut().enumerate() {
+            if delta & (1 << bit) == 0 {
+                *counter = 0;
+                continue;
+            }
+
+            *counter = counter.saturating_add(1);
+
+            if *counter >= self.cycles {
+                changes |= 1 << bit;
+                *counter = 0;
+            }
+        }
+
+        self.debounced_state ^= changes;
+
+        changes
+    }
+
+    fn debounced_state(&self) -> RowState {
+        self.debounced_state
+    }
+}
+
+/// Debouncer selected at key scanner construction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Debounce {
+    Counter(CounterDebouncer),
+    Integrator(IntegratorDebouncer),
+}
+
+impl Debounce {
+    /// Creates a new [CounterDebouncer] selection.
+    pub const fn counter() -> Self {
+        Self::Counter(CounterDebouncer::new())
+    }
+
+    /// Creates a new [IntegratorDebouncer] selection requiring `cycles` consistent samples.
+    pub const fn integrator(cycles: u8) -> Self {
+        Self::Integrator(IntegratorDebouncer::new(cycles))
+    }
+
+    /// Gets the number of consistent samples required to report a change.
+    pub const fn cycles(&self) -> u8 {
+        match self {
+            Self::Counter(_) => DEFAULT_INTEGRATOR_CYCLES,
+            Self::Integrator(d) => d.cycles(),
+        }
+    }
+
+    /// Sets the number of consistent samples required to report a change.
+    ///
+    /// A [CounterDebouncer] is replaced by an [IntegratorDebouncer]. The debounced state is
+    /// kept, so held keys are not reported again.
+    pub fn set_cycles(&mut self, cycles: u8) {
+        match self {
+            Self::Counter(d) => {
+                let mut integrator = IntegratorDebouncer::new(cycles);
+                integrator.debounced_state = d.debounced_state;
+                *self = Self::Integrator(integrator);
+            }
+            Self::Integrator(d) => d.set_cycles(cycles),
+        }
+    }
+}
+
+impl Debounce {
+    /// Feeds a new raw sample to the debouncer, with per-bit cycle overrides.
+    ///
+    /// Overrides only apply to an [IntegratorDebouncer], see
+    /// [update_with_overrides](IntegratorDebouncer::update_with_overrides).
+    pub fn update_with_overrides(&mut self, sample: RowState, overrides: &[u8]) -> RowState {
+        match self {
+            Self::Counter(d) => d.update(sample),
+            Self::Integrator(d) => d.update_with_overrides(sample, overrides),
+        }
+    }
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Self::counter()
+    }
+}
+
+impl Debouncer for Debounce {
+    fn update(&mut self, sample: RowState) -> RowState {
+        match self {
+            Self::Counter(d) => d.update(sample),
+            Self::Integrator(d) => d.update(sample),
+        }
+    }
+
+    fn debounced_state(&self) -> RowState {
+        match self {
+            Self::Counter(d) => d.debounced_state(),
+            Self::Integrator(d) => d.debounced_state(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Key 0 is pressed with a bounce, key 1 chatters before settling, then key 0 is
+    // released while key 1 stays held.
+    const NOISY: [RowState; 12] = [0b01, 0b00, 0b01, 0b11, 0b01, 0b11, 0b11, 0b11, 0b10, 0b10, 0b10, 0b10];
+
+    fn run<D: Debouncer>(debouncer: &mut D) -> [RowState; 12] {
+        let mut changes = [0; 12];
+
+        for (change, &sample) in changes.iter_mut().zip(NOISY.iter()) {
+            *change = debouncer.update(sample);
+        }
+
+        changes
+    }
+
+    #[test]
+    fn counter_debouncer_filters_noise() {
+        let mut debouncer = CounterDebouncer::new();
+
+        assert_eq!(run(&mut debouncer), [0, 0, 0, 0, 0, 0b01, 0, 0, 0b10, 0, 0, 0b01]);
+        assert_eq!(debouncer.debounced_state(), 0b10);
+    }
+
+    #[test]
+    fn integrator_debouncer_filters_noise() {
+        let mut debouncer = IntegratorDebouncer::new(3);
+
+        assert_eq!(run(&mut debouncer), [0, 0, 0, 0, 0b01, 0, 0, 0b10, 0, 0, 0b01, 0]);
+        assert_eq!(debouncer.debounced_state(), 0b10);
+    }
+}
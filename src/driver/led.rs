@@ -1,3 +1,217 @@
+use crate::driver::board::{BoardProps, DeviceProps};
+use crate::event_handler::EventHandler;
+use crate::{hooks::Hooks, lock};
+
+/// Color of a single LED, as `[r, g, b]`.
+pub type Rgb = [u8; 3];
+
+/// Number of LEDs on the board.
+pub const LED_COUNT: usize = <DeviceProps as BoardProps>::LED_COUNT;
+
+/// Default time, in milliseconds, between two syncs of the LEDs with the hardware.
+pub const DEFAULT_LED_SYNC_INTERVAL: u16 = 32;
+
+/// Global LED state.
+pub static LED_CONTROL: lock::Spinlock<LedControl> = lock::Spinlock::new(LedControl::new());
+
 pub trait Led {
     fn led_count(&self) -> usize;
 }
+
+/// An LED mode, computing the LED colors.
+///
+/// Register modes with [LedControl::set_modes], the current mode is updated before each
+/// sync with the hardware. Keep the work done per update bounded, since it runs in the
+/// main loop.
+pub trait LedMode: Send + Sync {
+    /// Called before [update](Self::update) with the time at the start of the cycle, in
+    /// milliseconds.
+    fn tick(&mut self, now: u32) {
+        let _ = now;
+    }
+
+    /// Computes the LED colors.
+    fn update(&mut self, leds: &mut [Rgb]);
+}
+
+/// A registered [LedMode].
+pub type LedModeRef = &'static lock::Spinlock<dyn LedMode>;
+
+/// Tracks the LED colors and the current LED mode.
+///
+/// Colors are sent to the hardware by the [Runtime](crate::runtime::Runtime) at the end
+/// of a cycle, at most once per sync interval. The color array is sized by the board's
+/// [LED_COUNT](BoardProps::LED_COUNT), so boards without LEDs pay nothing for it.
+pub struct LedControl {
+    leds: [Rgb; LED_COUNT],
+    mode: u8,
+    modes: &'static [LedModeRef],
+    sync_interval: u16,
+    last_sync: u32,
+}
+
+impl LedControl {
+    /// Creates a new [LedControl] with all LEDs off.
+    pub const fn new() -> Self {
+        Self {
+            leds: [[0u8; 3]; LED_COUNT],
+            mode: 0,
+            modes: &[],
+            sync_interval: DEFAULT_LED_SYNC_INTERVAL,
+            last_sync: 0,
+        }
+    }
+
+    /// Gets the LED colors.
+    pub fn leds(&self) -> &[Rgb] {
+        &self.leds
+    }
+
+    /// Gets the LED colors mutably.
+    pub fn leds_mut(&mut self) -> &mut [Rgb] {
+        &mut self.leds
+    }
+
+    /// Gets the color of the LED at `index`.
+    pub fn crgb_at(&self, index: usize) -> Option<Rgb> {
+        self.leds.get(index).copied()
+    }
+
+    /// Sets the color of the LED at `index`. Out of range indices are ignored.
+    pub fn set_crgb_at(&mut self, index: usize, color: Rgb) {
+        if let Some(led) = self.leds.get_mut(index) {
+            *led = color;
+        }
+    }
+
+    /// Sets the color of every LED.
+    pub fn set_all(&mut self, color: Rgb) {
+        self.leds = [color; LED_COUNT];
+    }
+
+    /// Gets the current LED mode index.
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    /// Gets the number of LED modes.
+    pub fn num_modes(&self) -> u8 {
+        self.modes.len() as u8
+    }
+
+    /// Sets the LED modes cycled through by [next_mode](Self::next_mode) and
+    /// [prev_mode](Self::prev_mode), resetting the current mode if it is now out of range.
+    pub fn set_modes(&mut self, modes: &'static [LedModeRef]) {
+        self.modes = modes;
+
+        if self.mode >= self.num_modes() {
+            self.mode = 0;
+        }
+    }
+
+    /// Updates the LED colors using the current mode.
+    pub fn update(&mut self, now: u32) {
+        if let Some(mode) = self.modes.get(self.mode as usize) {
+            let mut mode = mode.write();
+
+            mode.tick(now);
+            mode.update(&mut self.leds);
+        }
+    }
+
+    /// Gets the time, in milliseconds, between two syncs with the hardware.
+    pub fn sync_interval(&self) -> u16 {
+        self.sync_interval
+    }
+
+    /// Sets the time, in milliseconds, between two syncs with the hardware.
+    pub fn set_sync_interval(&mut self, interval: u16) {
+        self.sync_interval = interval;
+    }
+
+    /// Gets whether the LEDs should be synced with the hardware.
+    pub fn sync_due(&self, now: u32) -> bool {
+        now.wrapping_sub(self.last_sync) >= self.sync_interval as u32
+    }
+
+    /// Records a sync with the hardware.
+    pub fn mark_synced(&mut self, now: u32) {
+        self.last_sync = now;
+    }
+
+    /// Switches to the next LED mode, wrapping around.
+    pub fn next_mode() {
+        Self::update_mode(|mode, num_modes| (mode + 1) % num_modes);
+    }
+
+    /// Switches to the previous LED mode, wrapping around.
+    pub fn prev_mode() {
+        Self::update_mode(|mode, num_modes| mode.checked_sub(1).unwrap_or(num_modes - 1));
+    }
+
+    /// Switches to the LED mode at `index`. Out of range indices are ignored.
+    pub fn set_mode(index: u8) {
+        Self::update_mode(|mode, num_modes| if index < num_modes { index } else { mode });
+    }
+
+    /// Updates the mode, then calls the `on_led_mode_change()` plugin handlers if it
+    /// changed.
+    ///
+    /// The lock is released before calling the handlers, so they may use it.
+    fn update_mode(f: impl FnOnce(u8, u8) -> u8) {
+        let changed = {
+            let mut leds = LED_CONTROL.write();
+
+            let num_modes = leds.num_modes();
+
+            if num_modes == 0 {
+                return;
+            }
+
+            let mode = f(leds.mode, num_modes);
+            let changed = mode != leds.mode;
+            leds.mode = mode;
+
+            changed
+        };
+
+        if changed {
+            let _ = Hooks::on_led_mode_change();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syncs_are_throttled() {
+        let mut leds = LedControl::new();
+        let interval = leds.sync_interval() as u32;
+
+        assert!(!leds.sync_due(interval - 1));
+        assert!(leds.sync_due(interval));
+
+        leds.mark_synced(100);
+
+        assert!(!leds.sync_due(100 + interval - 1));
+        assert!(leds.sync_due(100 + interval));
+
+        // The interval survives `millis` wrapping around.
+        leds.mark_synced(u32::MAX);
+        assert!(!leds.sync_due(interval - 2));
+        assert!(leds.sync_due(interval - 1));
+    }
+
+    #[test]
+    fn out_of_range_leds_are_ignored() {
+        let mut leds = LedControl::new();
+
+        leds.set_all([1, 2, 3]);
+        leds.set_crgb_at(LED_COUNT, [4, 5, 6]);
+
+        assert_eq!(leds.crgb_at(LED_COUNT), None);
+        assert!(leds.leds().iter().all(|&led| led == [1, 2, 3]));
+    }
+}
@@ -1,6 +1,10 @@
 use crate::error::Result;
 
-mod atreus;
+#[cfg(feature = "atmega32u4")]
+mod atmega32u4;
+mod usb_suspend;
+
+pub use usb_suspend::*;
 
 pub trait Mcu {
     const DISABLE_JTAG: bool;
@@ -57,4 +61,18 @@ pub trait Mcu {
     fn usb_configured() -> bool {
         true
     }
+
+    /// Poll the USB bus suspend state.
+    ///
+    /// Returns whether the host has suspended the bus.
+    fn poll_usb_suspend() -> bool {
+        false
+    }
+
+    /// Signals a remote wakeup to the host.
+    ///
+    /// Only has an effect while the bus is suspended, and the host enabled remote wakeup.
+    fn remote_wakeup() -> Result<()> {
+        Ok(())
+    }
 }
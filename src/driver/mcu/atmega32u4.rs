@@ -0,0 +1,114 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use arduino_hal::pac;
+use avr_device::interrupt;
+use keyboardio_hid::usb_device::device::UsbDeviceState;
+
+use super::{Mcu, UsbSuspend, UDINT_SUSPI, UDINT_WAKEUPI};
+use crate::{detach_from_host, driver::board::Board, init_usb_device, error::Result, lock, return_on_err, usb, with_cpu, with_usb_device};
+
+static WAS_CONFIGURED: AtomicBool = AtomicBool::new(false);
+static USB_SUSPEND: lock::Spinlock<UsbSuspend> = lock::Spinlock::new(UsbSuspend::new());
+
+/// Every ATmega32U4-based [Board] shares the same MCU setup and USB handling.
+impl<D: Board> Mcu for D {
+    const DISABLE_JTAG: bool = false;
+    const DISABLE_CLOCK_DIVISION: bool = false;
+
+    fn detach_from_host() -> Result<()> {
+        detach_from_host()
+    }
+
+    fn attach_to_host() -> Result<()> {
+        init_usb_device(usb()?);
+        Ok(())
+    }
+
+    fn poll_usb_reset() -> bool {
+        let mut ret = false;
+
+        if WAS_CONFIGURED.load(Ordering::Relaxed) {
+            if !Self::usb_configured() {
+                WAS_CONFIGURED.store(false, Ordering::SeqCst);
+                ret = true;
+            }
+        } else {
+            if Self::usb_configured() {
+                WAS_CONFIGURED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        ret
+    }
+
+    fn usb_configured() -> bool {
+        with_usb_device(|usb| usb.state() == UsbDeviceState::Configured).unwrap_or(false)
+    }
+
+    fn poll_usb_suspend() -> bool {
+        // SAFETY: the flags are only read, the USB bus driver clears them.
+        let mut udint = unsafe { &*pac::USB_DEVICE::ptr() }.udint.read().bits() & (UDINT_SUSPI | UDINT_WAKEUPI);
+
+        // The bus driver may clear the flags before they are read here, in which case its
+        // view of the bus state is used instead.
+        if udint == 0 {
+            if let Ok(state) = with_usb_device(|usb| usb.state()) {
+                udint = if state == UsbDeviceState::Suspend {
+                    UDINT_SUSPI
+                } else {
+                    UDINT_WAKEUPI
+                };
+            }
+        }
+
+        USB_SUSPEND.write().update(udint)
+    }
+
+    fn remote_wakeup() -> Result<()> {
+        if !USB_SUSPEND.read().is_suspended() || !with_usb_device(|usb| usb.remote_wakeup_enabled())? {
+            return Ok(());
+        }
+
+        interrupt::free(|_cs| {
+            // SAFETY: interrupts are disabled, so the USB interrupt does not race these
+            // writes.
+            let usb = unsafe { &*pac::USB_DEVICE::ptr() };
+
+            // The USB clock is frozen during suspend, and must run to drive the bus.
+            usb.usbcon.modify(|_, w| w.frzclk().clear_bit());
+            usb.udcon.modify(|_, w| w.rmwkup().set_bit());
+        });
+
+        Ok(())
+    }
+
+    fn disable_jtag() -> Result<()> {
+        with_cpu(|cpu| cpu.mcucr.modify(|_, w| w.jtd().set_bit().jtd().set_bit()))
+    }
+
+    fn disable_clock_division() -> Result<()> {
+        with_cpu(|cpu| {
+            cpu.clkpr.modify(|_, w| {
+                // Enable writing the CLKPS bits.
+                //
+                // See CLKPR in the Microchip documentation.
+                w.clkpce().set_bit();
+
+                // Setting CLKPS to 0b0000 sets clock division to 1.
+                //
+                // See CLKPR in the Microchip documentation.
+                w.clkps().val_0x00()
+            });
+        })
+    }
+
+    fn setup() {
+        if Self::DISABLE_JTAG {
+            return_on_err!(Self::disable_jtag());
+        }
+
+        if Self::DISABLE_CLOCK_DIVISION {
+            return_on_err!(Self::disable_clock_division());
+        }
+    }
+}
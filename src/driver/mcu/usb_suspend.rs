@@ -0,0 +1,53 @@
+/// `SUSPI` bit of the `UDINT` register, set when the host suspends the bus.
+pub const UDINT_SUSPI: u8 = 1 << 0;
+/// `WAKEUPI` bit of the `UDINT` register, set when bus activity resumes.
+pub const UDINT_WAKEUPI: u8 = 1 << 4;
+
+/// Tracks whether the USB bus is suspended, from the `UDINT` interrupt flags.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::mcu::{UsbSuspend, UDINT_SUSPI, UDINT_WAKEUPI};
+///
+/// let mut state = UsbSuspend::new();
+///
+/// assert!(!state.update(0));
+/// assert!(state.update(UDINT_SUSPI));
+/// // No new flags, the bus stays suspended.
+/// assert!(state.update(0));
+/// assert!(!state.update(UDINT_WAKEUPI));
+/// // Wakeup is handled last, when both flags are set the bus has resumed.
+/// assert!(!state.update(UDINT_SUSPI | UDINT_WAKEUPI));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbSuspend {
+    suspended: bool,
+}
+
+impl UsbSuspend {
+    /// Creates a new [UsbSuspend], with the bus active.
+    pub const fn new() -> Self {
+        Self { suspended: false }
+    }
+
+    /// Gets whether the bus is suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Updates the state from the pending `UDINT` flags.
+    ///
+    /// Returns whether the bus is suspended.
+    pub fn update(&mut self, udint: u8) -> bool {
+        if udint & UDINT_SUSPI != 0 {
+            self.suspended = true;
+        }
+
+        if udint & UDINT_WAKEUPI != 0 {
+            self.suspended = false;
+        }
+
+        self.suspended
+    }
+}
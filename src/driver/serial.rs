@@ -0,0 +1,86 @@
+use embedded_hal::serial;
+use keyboardio_hid::usb_device::UsbError;
+use keyboardio_hid::{KeyboardUsbBus, KeyboardUsbBusAllocator};
+use usbd_serial::SerialPort;
+
+use crate::irq_cell::IrqCell;
+use crate::{Error, Result};
+
+/// USB CDC-ACM serial port, polled by the USB interrupts along with the HID classes.
+pub static USB_SERIAL: IrqCell<SerialPort<'static, KeyboardUsbBus>> = IrqCell::new();
+
+/// Allocates the CDC-ACM class on the USB bus.
+///
+/// Must be called before [init_usb_device](crate::init_usb_device), which freezes the
+/// bus allocation.
+pub fn init_usb_serial(usb_bus: &'static KeyboardUsbBusAllocator) {
+    let _ = USB_SERIAL.set(SerialPort::new(usb_bus));
+}
+
+/// Serial port handle on [USB_SERIAL].
+///
+/// Every byte is read and written in its own critical section, so the USB interrupt can
+/// run in between, and blocking with `nb::block!` does not stall the USB device.
+///
+/// Output is dropped with an error while no host program has the port open (DTR is
+/// cleared), instead of blocking forever on a full buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UsbSerial;
+
+impl UsbSerial {
+    /// Writes raw bytes to the port, blocking until they are sent.
+    ///
+    /// Returns an error, dropping the rest of the bytes, if no host program has the
+    /// port open.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        use serial::Write;
+
+        for &b in bytes.iter() {
+            nb::block!(self.write(b))?;
+        }
+
+        nb::block!(self.flush())
+    }
+
+    /// Calls `f` with the port, if a host program has it open.
+    fn with_open_port<F>(f: F) -> nb::Result<(), Error>
+    where
+        F: FnOnce(&mut SerialPort<'static, KeyboardUsbBus>) -> nb::Result<(), UsbError>,
+    {
+        USB_SERIAL
+            .with(|port| if port.dtr() { Some(f(port)) } else { None })
+            .flatten()
+            .unwrap_or(Err(nb::Error::Other(UsbError::InvalidState)))
+            .map_err(map_nb_error)
+    }
+}
+
+fn map_nb_error(err: nb::Error<UsbError>) -> nb::Error<Error> {
+    match err {
+        nb::Error::WouldBlock => nb::Error::WouldBlock,
+        nb::Error::Other(err) => nb::Error::Other(err.into()),
+    }
+}
+
+impl serial::Read<u8> for UsbSerial {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        USB_SERIAL
+            .with(|port| serial::Read::read(port))
+            .unwrap_or(Err(nb::Error::Other(UsbError::InvalidState)))
+            .map_err(map_nb_error)
+    }
+}
+
+impl serial::Write<u8> for UsbSerial {
+    type Error = Error;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Error> {
+        Self::with_open_port(|port| serial::Write::write(port, byte))
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Error> {
+        Self::with_open_port(|port| serial::Write::flush(port))
+    }
+}
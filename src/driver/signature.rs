@@ -0,0 +1,98 @@
+use avr_device::interrupt;
+
+use crate::irq_cell::IrqOnce;
+
+/// Offset of the unique serial number in the ATmega32U4 signature row.
+pub const SERIAL_NUMBER_OFFSET: u8 = 0x0e;
+/// Length, in bytes, of the unique serial number in the signature row.
+pub const SERIAL_NUMBER_LEN: usize = 10;
+/// Length of the serial number string, two hex digits per byte.
+pub const SERIAL_NUMBER_STR_LEN: usize = SERIAL_NUMBER_LEN * 2;
+
+/// Serial number sent when the signature row holds no serial number.
+pub const FALLBACK_SERIAL_NUMBER: &str = "00000000000000000000";
+
+/// `SIGRD | SPMEN` bits of the `SPMCSR` register.
+const SPMCSR_SIGRD: u8 = (1 << 5) | (1 << 0);
+
+// Borrowed for `'static` by the USB device descriptor.
+static SERIAL_NUMBER: IrqOnce<[u8; SERIAL_NUMBER_STR_LEN]> = IrqOnce::new();
+
+/// Reads a byte of the signature row.
+pub fn read_signature_byte(addr: u8) -> u8 {
+    let byte: u8;
+
+    interrupt::free(|_cs| {
+        // SAFETY: setting `SIGRD` makes the next `lpm`, which must follow within three
+        // cycles, read the signature row instead of program memory. Interrupts are
+        // disabled, so nothing runs in between.
+        unsafe {
+            core::arch::asm!(
+                "out 0x37, {sigrd}",
+                "lpm {byte}, Z",
+                sigrd = in(reg) SPMCSR_SIGRD,
+                byte = out(reg) byte,
+                in("Z") addr as u16,
+            );
+        }
+    });
+
+    byte
+}
+
+/// Formats the signature row serial number as upper-case hex.
+///
+/// Returns `None` if the bytes are all erased (`0xff`) or all zero, i.e. the chip has no
+/// serial number.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::signature::format_serial_number;
+///
+/// let mut buf = [0u8; 20];
+/// let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x01, 0x23];
+///
+/// assert_eq!(format_serial_number(&bytes, &mut buf), Some("123456789ABCDEF00123"));
+/// assert_eq!(format_serial_number(&[0xff; 10], &mut buf), None);
+/// ```
+pub fn format_serial_number<'b>(
+    bytes: &[u8; SERIAL_NUMBER_LEN],
+    buf: &'b mut [u8; SERIAL_NUMBER_STR_LEN],
+) -> Option<&'b str> {
+    if bytes.iter().all(|&b| b == 0xff) || bytes.iter().all(|&b| b == 0) {
+        return None;
+    }
+
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    for (i, b) in bytes.iter().enumerate() {
+        buf[i * 2] = HEX[(b >> 4) as usize];
+        buf[i * 2 + 1] = HEX[(b & 0xf) as usize];
+    }
+
+    core::str::from_utf8(buf).ok()
+}
+
+/// Gets the USB serial number string, built from the chip's unique serial number.
+///
+/// Falls back to [FALLBACK_SERIAL_NUMBER] when the signature row holds none.
+pub fn serial_number() -> &'static str {
+    if SERIAL_NUMBER.get().is_none() {
+        let mut bytes = [0u8; SERIAL_NUMBER_LEN];
+        let mut buf = [0u8; SERIAL_NUMBER_STR_LEN];
+
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = read_signature_byte(SERIAL_NUMBER_OFFSET + i as u8);
+        }
+
+        if format_serial_number(&bytes, &mut buf).is_some() {
+            let _ = SERIAL_NUMBER.set(buf);
+        }
+    }
+
+    SERIAL_NUMBER
+        .get()
+        .and_then(|buf| core::str::from_utf8(buf).ok())
+        .unwrap_or(FALLBACK_SERIAL_NUMBER)
+}
@@ -0,0 +1,335 @@
+use embedded_hal::serial;
+
+use crate::driver::keyscanner::{RowState, DEBOUNCE_COLS};
+use crate::error::{Error, Result};
+
+/// Maximum number of matrix rows sent by a split half.
+pub const SPLIT_MAX_ROWS: usize = 8;
+
+/// Time, in milliseconds, without a frame from the remote half after which its keys are
+/// treated as released.
+pub const SPLIT_LINK_TIMEOUT: u32 = 100;
+
+/// First byte of every frame.
+pub const SPLIT_FRAME_SYNC: u8 = 0xa5;
+
+/// Maximum length of a frame: sync byte, row count, two bytes per row, and checksum.
+pub const SPLIT_FRAME_MAX: usize = 3 + 2 * SPLIT_MAX_ROWS;
+
+/// Debounced matrix state of the remote half, one `u16` per row, one bit per column.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RemoteMatrix {
+    rows: [u16; SPLIT_MAX_ROWS],
+    len: u8,
+}
+
+impl RemoteMatrix {
+    /// Creates a new [RemoteMatrix] from the provided rows.
+    ///
+    /// Rows past [SPLIT_MAX_ROWS] are dropped.
+    pub fn new(rows: &[u16]) -> Self {
+        let len = rows.len().min(SPLIT_MAX_ROWS);
+        let mut matrix = Self {
+            rows: [0u16; SPLIT_MAX_ROWS],
+            len: len as u8,
+        };
+
+        matrix.rows[..len].copy_from_slice(&rows[..len]);
+
+        matrix
+    }
+
+    /// Gets the rows of the remote matrix.
+    pub fn rows(&self) -> &[u16] {
+        &self.rows[..self.len as usize]
+    }
+
+    /// Gets the state of `row`, all released if the remote half did not send it.
+    pub fn row(&self, row: usize) -> u16 {
+        self.rows().get(row).copied().unwrap_or(0)
+    }
+}
+
+/// Link between the two halves of a split keyboard.
+///
+/// The secondary half sends its debounced matrix with [send](Self::send), the primary
+/// half (the one connected over USB) merges the frames returned by [poll](Self::poll)
+/// into its own scan.
+pub trait SplitLink {
+    /// Sends the local matrix rows to the other half.
+    fn send(&mut self, rows: &[u16]) -> Result<()>;
+
+    /// Gets the most recent matrix received from the other half, if a complete frame
+    /// arrived since the last poll.
+    fn poll(&mut self) -> Option<RemoteMatrix>;
+}
+
+/// Encodes `rows` into a frame, returning its length.
+///
+/// Frames start with [SPLIT_FRAME_SYNC], then the row count, the rows as little-endian
+/// `u16`, and the XOR of the count and row bytes.
+pub fn encode_frame(rows: &[u16], buf: &mut [u8; SPLIT_FRAME_MAX]) -> usize {
+    let rows = &rows[..rows.len().min(SPLIT_MAX_ROWS)];
+
+    buf[0] = SPLIT_FRAME_SYNC;
+    buf[1] = rows.len() as u8;
+
+    let mut checksum = buf[1];
+    let mut len = 2;
+
+    for row in rows.iter() {
+        for byte in row.to_le_bytes() {
+            buf[len] = byte;
+            checksum ^= byte;
+            len += 1;
+        }
+    }
+
+    buf[len] = checksum;
+
+    len + 1
+}
+
+/// Decodes frames from a byte stream.
+///
+/// Bytes before a sync byte are skipped. Frames with an invalid row count or checksum
+/// are dropped.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::split::*;
+///
+/// let mut buf = [0u8; SPLIT_FRAME_MAX];
+/// let len = encode_frame(&[0b1001, 0, 0b0110, 0], &mut buf);
+///
+/// let mut decoder = FrameDecoder::new();
+/// let decode = |decoder: &mut FrameDecoder, bytes: &[u8]| {
+///     bytes.iter().filter_map(|&b| decoder.feed(b)).last()
+/// };
+///
+/// // Line noise before the frame is skipped.
+/// assert_eq!(decode(&mut decoder, &[0x00, 0x42]), None);
+///
+/// let matrix = decode(&mut decoder, &buf[..len]).unwrap();
+/// assert_eq!(matrix.rows(), &[0b1001, 0, 0b0110, 0]);
+///
+/// // A corrupted frame is dropped.
+/// buf[2] ^= 0x01;
+/// assert_eq!(decode(&mut decoder, &buf[..len]), None);
+/// ```
+pub struct FrameDecoder {
+    buf: [u8; SPLIT_FRAME_MAX],
+    len: usize,
+}
+
+impl FrameDecoder {
+    /// Creates a new [FrameDecoder], waiting for a sync byte.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; SPLIT_FRAME_MAX],
+            len: 0,
+        }
+    }
+
+    /// Feeds a byte to the decoder, returning the matrix when it completes a valid frame.
+    pub fn feed(&mut self, byte: u8) -> Option<RemoteMatrix> {
+        if self.len == 0 && byte != SPLIT_FRAME_SYNC {
+            return None;
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        if self.len < 2 {
+            return None;
+        }
+
+        let rows = self.buf[1] as usize;
+
+        if rows > SPLIT_MAX_ROWS {
+            self.len = 0;
+            return None;
+        }
+
+        let frame_len = 3 + 2 * rows;
+
+        if self.len < frame_len {
+            return None;
+        }
+
+        self.len = 0;
+
+        let data = &self.buf[2..frame_len - 1];
+        let checksum = data.iter().fold(self.buf[1], |acc, &b| acc ^ b);
+
+        if checksum != self.buf[frame_len - 1] {
+            return None;
+        }
+
+        let mut matrix = RemoteMatrix {
+            rows: [0u16; SPLIT_MAX_ROWS],
+            len: rows as u8,
+        };
+
+        for (row, bytes) in matrix.rows.iter_mut().zip(data.chunks_exact(2)) {
+            *row = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        Some(matrix)
+    }
+}
+
+/// [SplitLink] over a serial port.
+///
+/// The ATmega32U4 has a single USART, on pins the Atreus matrix uses, so boards wire
+/// their own port to the link.
+pub struct SerialLink<S> {
+    serial: S,
+    decoder: FrameDecoder,
+}
+
+impl<S> SerialLink<S>
+where
+    S: serial::Read<u8> + serial::Write<u8>,
+{
+    /// Creates a new [SerialLink] over the provided serial port.
+    pub const fn new(serial: S) -> Self {
+        Self {
+            serial,
+            decoder: FrameDecoder::new(),
+        }
+    }
+}
+
+impl<S> SplitLink for SerialLink<S>
+where
+    S: serial::Read<u8> + serial::Write<u8>,
+{
+    fn send(&mut self, rows: &[u16]) -> Result<()> {
+        let mut buf = [0u8; SPLIT_FRAME_MAX];
+        let len = encode_frame(rows, &mut buf);
+
+        for &b in buf[..len].iter() {
+            nb::block!(self.serial.write(b)).map_err(|_| Error::SplitLink)?;
+        }
+
+        nb::block!(self.serial.flush()).map_err(|_| Error::SplitLink)
+    }
+
+    fn poll(&mut self) -> Option<RemoteMatrix> {
+        let mut latest = None;
+
+        while let Ok(byte) = self.serial.read() {
+            if let Some(matrix) = self.decoder.feed(byte) {
+                latest = Some(matrix);
+            }
+        }
+
+        latest
+    }
+}
+
+/// Last matrix received from the remote half.
+///
+/// If no frame arrived for [SPLIT_LINK_TIMEOUT] milliseconds (e.g. the cable was
+/// unplugged), the matrix is dropped and every remote key is treated as released, so no
+/// key stays stuck. Times are full [millis](crate::millis::millis) values, so a stale
+/// matrix can't come back when a shorter counter wraps.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::split::{RemoteHalf, RemoteMatrix, SPLIT_LINK_TIMEOUT};
+///
+/// let mut remote = RemoteHalf::new();
+/// assert_eq!(remote.row(0, 0), 0);
+///
+/// remote.update(Some(RemoteMatrix::new(&[0b101, 0b010])), 10);
+/// assert_eq!(remote.row(1, 10), 0b010);
+///
+/// // Polls without a new frame keep the last matrix, until the link times out.
+/// remote.update(None, 50);
+/// assert_eq!(remote.row(0, 50), 0b101);
+///
+/// remote.update(None, 10 + SPLIT_LINK_TIMEOUT + 1);
+/// assert!(!remote.is_connected(10 + SPLIT_LINK_TIMEOUT + 1));
+///
+/// // Once timed out, the matrix stays released until a new frame arrives.
+/// assert_eq!(remote.row(0, 10 + u16::MAX as u32 + 1), 0);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RemoteHalf {
+    matrix: Option<RemoteMatrix>,
+    last_seen: u32,
+}
+
+impl RemoteHalf {
+    /// Creates a new [RemoteHalf], with no matrix received yet.
+    pub const fn new() -> Self {
+        Self {
+            matrix: None,
+            last_seen: 0,
+        }
+    }
+
+    /// Records the result of a link poll at `now` milliseconds.
+    ///
+    /// Drops the last matrix once the link timed out.
+    pub fn update(&mut self, matrix: Option<RemoteMatrix>, now: u32) {
+        if let Some(matrix) = matrix {
+            self.matrix = Some(matrix);
+            self.last_seen = now;
+        } else if !self.is_connected(now) {
+            self.matrix = None;
+        }
+    }
+
+    /// Gets whether a frame arrived within the last [SPLIT_LINK_TIMEOUT] milliseconds.
+    pub fn is_connected(&self, now: u32) -> bool {
+        self.matrix.is_some() && now.wrapping_sub(self.last_seen) <= SPLIT_LINK_TIMEOUT
+    }
+
+    /// Gets the state of remote `row` at `now` milliseconds, all released if the link
+    /// dropped.
+    pub fn row(&self, row: usize, now: u32) -> u16 {
+        match self.matrix {
+            Some(matrix) if self.is_connected(now) => matrix.row(row),
+            _ => 0,
+        }
+    }
+}
+
+/// Merges a remote row into a local row, after the `local_cols` local columns.
+///
+/// Remote column `c` becomes column `local_cols + c`, so remote [KeyAddr](crate::key_addr::KeyAddr)s
+/// are offset by the local column count. Columns that don't fit in a [RowState] are
+/// dropped.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::driver::split::merge_remote;
+///
+/// // 12 local columns: remote column 0 is column 12, remote column 3 is column 15.
+/// let merged = merge_remote(0b1000_0000_0001, 0b1001, 12);
+/// assert_eq!(merged, (1 << 15) | (1 << 12) | (1 << 11) | 1);
+///
+/// // Stale bits past the local columns are replaced by the remote state.
+/// assert_eq!(merge_remote(merged, 0, 12), (1 << 11) | 1);
+///
+/// // With the `wide_matrix` feature, two 12-column halves fit in a row.
+/// # #[cfg(feature = "wide_matrix")]
+/// # {
+/// assert_eq!(merge_remote(0, 1 << 11, 12), 1 << 23);
+/// # }
+/// ```
+pub fn merge_remote(local: RowState, remote: u16, local_cols: usize) -> RowState {
+    if local_cols >= DEBOUNCE_COLS {
+        return local;
+    }
+
+    let local_mask = ((1 as RowState) << local_cols) - 1;
+
+    (local & local_mask) | ((remote as RowState) << local_cols)
+}
@@ -0,0 +1,372 @@
+use arduino_hal::pac;
+
+use crate::{with_eeprom, error::{Error, Result}, lock};
+
+/// Size of the ATmega32U4 EEPROM in bytes.
+pub const EEPROM_SIZE: u16 = 1024;
+
+/// Number of bytes a versioned settings blob adds around its data: one version byte,
+/// and a two-byte CRC.
+pub const BLOB_OVERHEAD: u16 = 3;
+
+/// Global settings storage backed by the MCU EEPROM.
+pub static STORAGE: lock::Spinlock<Storage<Eeprom>> = lock::Spinlock::new(Storage::new(Eeprom));
+
+/// Byte-level access to a non-volatile storage backend.
+pub trait Backend {
+    /// Total size of the backend in bytes.
+    const SIZE: u16;
+
+    /// Reads the byte at the provided address.
+    fn read_byte(&self, addr: u16) -> Result<u8>;
+
+    /// Writes the byte at the provided address.
+    fn write_byte(&mut self, addr: u16, value: u8) -> Result<()>;
+}
+
+/// EEPROM backend using the ATmega32U4 EEPROM registers.
+pub struct Eeprom;
+
+impl Eeprom {
+    /// Waits for any previous write to complete, using the EEPE ready flag.
+    fn wait_ready(eeprom: &pac::EEPROM) {
+        while eeprom.eecr.read().eepe().bit_is_set() {}
+    }
+}
+
+impl Backend for Eeprom {
+    const SIZE: u16 = EEPROM_SIZE;
+
+    fn read_byte(&self, addr: u16) -> Result<u8> {
+        with_eeprom(|eeprom| {
+            Self::wait_ready(eeprom);
+
+            eeprom.eear.write(|w| w.bits(addr));
+            eeprom.eecr.write(|w| w.eere().set_bit());
+
+            eeprom.eedr.read().bits()
+        })
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) -> Result<()> {
+        with_eeprom(|eeprom| {
+            Self::wait_ready(eeprom);
+
+            eeprom.eear.write(|w| w.bits(addr));
+            eeprom.eedr.write(|w| w.bits(value));
+
+            // Setting EEMPE, then EEPE within four clock cycles starts an atomic
+            // erase-and-write operation.
+            eeprom.eecr.write(|w| w.eempe().set_bit());
+            eeprom.eecr.write(|w| w.eempe().set_bit().eepe().set_bit());
+        })
+    }
+}
+
+/// A region of storage claimed by a plugin with [Storage::reserve].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlotHandle {
+    start: u16,
+    len: u16,
+}
+
+impl SlotHandle {
+    /// Gets the start address of the slot.
+    pub const fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Gets the length of the slot in bytes.
+    pub const fn len(&self) -> u16 {
+        self.len
+    }
+
+    /// Gets whether the slot is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Gets the slot length needed to store a versioned blob of `data_len` bytes with
+/// [Storage::commit].
+pub const fn blob_slot_len(data_len: u16) -> u16 {
+    data_len + BLOB_OVERHEAD
+}
+
+/// Non-volatile settings storage.
+///
+/// Plugins claim non-overlapping regions with [reserve](Self::reserve) during setup,
+/// then read and write within those regions.
+pub struct Storage<B: Backend> {
+    backend: B,
+    next_free: u16,
+}
+
+impl<B: Backend> Storage<B> {
+    /// Creates a new [Storage] using the provided backend.
+    pub const fn new(backend: B) -> Self {
+        Self {
+            backend,
+            next_free: 0,
+        }
+    }
+
+    /// Gets the number of unreserved bytes.
+    pub fn available(&self) -> u16 {
+        B::SIZE - self.next_free
+    }
+
+    /// Claims a region of `len` bytes.
+    ///
+    /// Returns an error if there is not enough space left.
+    pub fn reserve(&mut self, len: u16) -> Result<SlotHandle> {
+        if len > self.available() {
+            return Err(Error::Storage);
+        }
+
+        let slot = SlotHandle {
+            start: self.next_free,
+            len,
+        };
+
+        self.next_free += len;
+
+        Ok(slot)
+    }
+
+    /// Reads `buf.len()` bytes starting at `addr`.
+    pub fn read(&self, addr: u16, buf: &mut [u8]) -> Result<()> {
+        Self::check_bounds(addr, buf.len(), B::SIZE)?;
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = self.backend.read_byte(addr + i as u16)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `buf` starting at `addr`.
+    ///
+    /// Bytes that already hold the new value are not rewritten, to save wear.
+    pub fn write(&mut self, addr: u16, buf: &[u8]) -> Result<()> {
+        Self::check_bounds(addr, buf.len(), B::SIZE)?;
+
+        for (i, &b) in buf.iter().enumerate() {
+            let byte_addr = addr + i as u16;
+
+            if self.backend.read_byte(byte_addr)? != b {
+                self.backend.write_byte(byte_addr, b)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes at `offset` within the provided slot.
+    pub fn read_slot(&self, slot: SlotHandle, offset: u16, buf: &mut [u8]) -> Result<()> {
+        Self::check_bounds(offset, buf.len(), slot.len)?;
+        self.read(slot.start + offset, buf)
+    }
+
+    /// Writes `buf` at `offset` within the provided slot.
+    pub fn write_slot(&mut self, slot: SlotHandle, offset: u16, buf: &[u8]) -> Result<()> {
+        Self::check_bounds(offset, buf.len(), slot.len)?;
+        self.write(slot.start + offset, buf)
+    }
+
+    /// Stores a versioned, CRC-checked settings blob in the provided slot.
+    ///
+    /// The slot must be at least [blob_slot_len] bytes long for the data length.
+    pub fn commit(&mut self, slot: SlotHandle, version: u8, data: &[u8]) -> Result<()> {
+        if blob_slot_len(data.len() as u16) > slot.len {
+            return Err(Error::Storage);
+        }
+
+        let crc = crc16(crc16_update(CRC16_INIT, version), data);
+
+        self.write_slot(slot, 0, &[version])?;
+        self.write_slot(slot, 1, data)?;
+        self.write_slot(slot, 1 + data.len() as u16, &crc.to_le_bytes())
+    }
+
+    /// Restores a settings blob stored with [commit](Self::commit).
+    ///
+    /// Returns an error, and leaves `data` untouched, if the stored version does not match,
+    /// or the CRC check fails (e.g. on a freshly erased EEPROM).
+    pub fn restore(&self, slot: SlotHandle, version: u8, data: &mut [u8]) -> Result<()> {
+        if blob_slot_len(data.len() as u16) > slot.len {
+            return Err(Error::Storage);
+        }
+
+        let mut stored_version = [0u8; 1];
+        self.read_slot(slot, 0, &mut stored_version)?;
+
+        if stored_version[0] != version {
+            return Err(Error::StorageCorrupt);
+        }
+
+        let data_len = data.len() as u16;
+        let mut crc = crc16_update(CRC16_INIT, version);
+
+        for i in 0..data_len {
+            crc = crc16_update(crc, self.backend.read_byte(slot.start + 1 + i)?);
+        }
+
+        let mut stored_crc = [0u8; 2];
+        self.read_slot(slot, 1 + data_len, &mut stored_crc)?;
+
+        if u16::from_le_bytes(stored_crc) != crc {
+            return Err(Error::StorageCorrupt);
+        }
+
+        self.read_slot(slot, 1, data)
+    }
+
+    fn check_bounds(addr: u16, len: usize, size: u16) -> Result<()> {
+        if addr as usize + len > size as usize {
+            Err(Error::Storage)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+const CRC16_INIT: u16 = 0xffff;
+
+/// CRC-16/CCITT update for a single byte.
+const fn crc16_update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ ((byte as u16) << 8);
+    let mut i = 0;
+
+    while i < 8 {
+        crc = if crc & 0x8000 != 0 {
+            (crc << 1) ^ 0x1021
+        } else {
+            crc << 1
+        };
+        i += 1;
+    }
+
+    crc
+}
+
+fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &b in data.iter() {
+        crc = crc16_update(crc, b);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory [Backend], counting the bytes written.
+    struct MockEeprom {
+        bytes: [u8; 64],
+        writes: usize,
+    }
+
+    impl MockEeprom {
+        /// Creates an erased mock EEPROM.
+        const fn new() -> Self {
+            Self {
+                bytes: [0xff; 64],
+                writes: 0,
+            }
+        }
+    }
+
+    impl Backend for MockEeprom {
+        const SIZE: u16 = 64;
+
+        fn read_byte(&self, addr: u16) -> Result<u8> {
+            self.bytes.get(addr as usize).copied().ok_or(Error::EEPROM)
+        }
+
+        fn write_byte(&mut self, addr: u16, value: u8) -> Result<()> {
+            *self.bytes.get_mut(addr as usize).ok_or(Error::EEPROM)? = value;
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reserved_slots_do_not_overlap() {
+        let mut storage = Storage::new(MockEeprom::new());
+
+        let first = storage.reserve(10).unwrap();
+        let second = storage.reserve(blob_slot_len(4)).unwrap();
+        let empty = storage.reserve(0).unwrap();
+
+        assert_eq!((first.start(), first.len()), (0, 10));
+        assert_eq!((second.start(), second.len()), (10, 7));
+        assert_eq!(empty.start(), 17);
+        assert!(empty.is_empty());
+        assert_eq!(storage.available(), 47);
+
+        assert_eq!(storage.reserve(48), Err(Error::Storage));
+        assert!(storage.reserve(47).is_ok());
+        assert_eq!(storage.available(), 0);
+    }
+
+    #[test]
+    fn slot_access_stays_in_bounds() {
+        let mut storage = Storage::new(MockEeprom::new());
+        let _ = storage.reserve(4).unwrap();
+        let slot = storage.reserve(4).unwrap();
+
+        storage.write_slot(slot, 2, &[1, 2]).unwrap();
+        assert_eq!(storage.backend.bytes[4..8], [0xff, 0xff, 1, 2]);
+
+        assert_eq!(storage.write_slot(slot, 3, &[1, 2]), Err(Error::Storage));
+        assert_eq!(storage.read_slot(slot, 0, &mut [0; 5]), Err(Error::Storage));
+        assert_eq!(storage.write(63, &[0, 0]), Err(Error::Storage));
+    }
+
+    #[test]
+    fn unchanged_bytes_are_not_rewritten() {
+        let mut storage = Storage::new(MockEeprom::new());
+
+        storage.write(0, &[1, 2, 3]).unwrap();
+        assert_eq!(storage.backend.writes, 3);
+
+        storage.write(0, &[1, 2, 3]).unwrap();
+        assert_eq!(storage.backend.writes, 3);
+
+        storage.write(0, &[1, 5, 3, 0xff]).unwrap();
+        assert_eq!(storage.backend.writes, 4);
+
+        let mut buf = [0u8; 4];
+        storage.read(0, &mut buf).unwrap();
+        assert_eq!(buf, [1, 5, 3, 0xff]);
+    }
+
+    #[test]
+    fn commit_and_restore_check_version_and_crc() {
+        let mut storage = Storage::new(MockEeprom::new());
+        let slot = storage.reserve(blob_slot_len(3)).unwrap();
+        let mut data = [0u8; 3];
+
+        // A freshly erased EEPROM fails the check, and leaves the data alone.
+        assert_eq!(storage.restore(slot, 0xff, &mut data), Err(Error::StorageCorrupt));
+        assert_eq!(data, [0; 3]);
+
+        storage.commit(slot, 1, &[7, 8, 9]).unwrap();
+
+        storage.restore(slot, 1, &mut data).unwrap();
+        assert_eq!(data, [7, 8, 9]);
+
+        // An outdated version is rejected.
+        let mut data = [0u8; 3];
+        assert_eq!(storage.restore(slot, 2, &mut data), Err(Error::StorageCorrupt));
+
+        // So is a corrupted data byte.
+        storage.backend.bytes[2] ^= 0x01;
+        assert_eq!(storage.restore(slot, 1, &mut data), Err(Error::StorageCorrupt));
+        assert_eq!(data, [0; 3]);
+
+        // The slot must hold the version and CRC too.
+        assert_eq!(storage.commit(slot, 1, &[1, 2, 3, 4]), Err(Error::Storage));
+    }
+}
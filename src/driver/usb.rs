@@ -1,4 +1,5 @@
 use crate::device::{EPDIR, EPTYPE0, EPTYPE1};
+use crate::driver::hid::settings::UsbPower;
 
 #[cfg(feature = "atreus")]
 mod atmega32u4;
@@ -77,12 +78,10 @@ pub const TRANSFER_PGM: u8 = 0x80;
 pub const TRANSFER_RELEASE: u8 = 0x40;
 pub const TRANSFER_ZERO: u8 = 0x20;
 
-pub const USB_CONFIG_POWERED_MASK: u8 = 0x40;
-pub const USB_CONFIG_BUS_POWERED: u8 = 0x80;
-pub const USB_CONFIG_SELF_POWERED: u8 = 0xc0;
-pub const USB_CONFIG_REMOTE_WAKEUP: u8 = 0x20;
-
-pub const USB_CONFIG_POWER: u16 = 500;
+pub use crate::driver::hid::settings::{
+    USB_CONFIG_BUS_POWERED, USB_CONFIG_POWER, USB_CONFIG_POWERED_MASK, USB_CONFIG_REMOTE_WAKEUP,
+    USB_CONFIG_SELF_POWERED,
+};
 
 #[cfg(feature = "arduino")]
 pub const USB_VID: u16 = 0x2341;
@@ -110,8 +109,9 @@ pub const USB_ENDPOINT_DESCRIPTOR_TYPE: u8 = 5;
 pub const SUSPI: u8 = 1 << 0;
 pub const WAKEUPI: u8 = 1 << 4;
 
+/// Encodes a current in mA for the `bMaxPower` descriptor field, see [UsbPower::max_power].
 pub const fn usb_config_power_ma(ma: u16) -> u16 {
-    ma / 2
+    UsbPower::new(ma, false, false).max_power() as u16
 }
 
 #[repr(C)]
@@ -226,7 +226,15 @@ impl ConfigDescriptor {
 
     /// Creates a new [ConfigDescriptor] from total length (`clen`),
     /// and number of interfaces (`num_interfaces`).
+    ///
+    /// The device is bus-powered, see [UsbPower::DEFAULT].
     pub const fn new(clen: u16, num_interfaces: u8) -> Self {
+        Self::with_power(clen, num_interfaces, UsbPower::DEFAULT)
+    }
+
+    /// Creates a new [ConfigDescriptor] from total length (`clen`),
+    /// number of interfaces (`num_interfaces`), and power configuration.
+    pub const fn with_power(clen: u16, num_interfaces: u8, power: UsbPower) -> Self {
         let clen_bytes = clen.to_le_bytes();
 
         Self {
@@ -238,8 +246,8 @@ impl ConfigDescriptor {
                 num_interfaces,
                 1,
                 0,
-                USB_CONFIG_BUS_POWERED | USB_CONFIG_REMOTE_WAKEUP,
-                usb_config_power_ma(USB_CONFIG_POWER) as u8,
+                power.attributes(),
+                power.max_power(),
             ],
         }
     }
@@ -1,8 +1,8 @@
 #[cfg(feature = "atmega32u4")]
 use atmega_hal::{pac::WDT, wdt::Timeout};
-use avr_device::interrupt;
 
-use crate::{error::Result, wdt};
+use crate::driver::bootloader::avr::BOOT_KEY_PTR;
+use crate::{error::Result, with_wdt};
 
 /// Taken from [atmega-hal] implementation.
 #[cfg(feature = "atmega32u4")]
@@ -25,20 +25,57 @@ fn set_timeout(wdt: &WDT, timeout: Timeout) {
     };
 }
 
+/// Watchdog timeouts, in milliseconds, from shortest to longest.
+const TIMEOUTS_MS: [u16; 10] = [16, 32, 64, 125, 250, 500, 1000, 2000, 4000, 8000];
+
+/// Selects the shortest watchdog timeout lasting at least `ms` milliseconds.
+///
+/// Requests above 8 seconds get the longest timeout.
+///
+/// Example:
+///
+/// ```rust
+/// use atmega_hal::wdt::Timeout;
+/// use kaleidoscope::driver::wdt::timeout_at_least;
+///
+/// assert!(matches!(timeout_at_least(0), Timeout::Ms16));
+/// assert!(matches!(timeout_at_least(16), Timeout::Ms16));
+/// assert!(matches!(timeout_at_least(17), Timeout::Ms32));
+/// assert!(matches!(timeout_at_least(100), Timeout::Ms125));
+/// assert!(matches!(timeout_at_least(2000), Timeout::Ms2000));
+/// assert!(matches!(timeout_at_least(u16::MAX), Timeout::Ms8000));
+/// ```
+pub const fn timeout_at_least(ms: u16) -> Timeout {
+    let mut i = 0;
+
+    while i < TIMEOUTS_MS.len() - 1 && TIMEOUTS_MS[i] < ms {
+        i += 1;
+    }
+
+    match i {
+        0 => Timeout::Ms16,
+        1 => Timeout::Ms32,
+        2 => Timeout::Ms64,
+        3 => Timeout::Ms125,
+        4 => Timeout::Ms250,
+        5 => Timeout::Ms500,
+        6 => Timeout::Ms1000,
+        7 => Timeout::Ms2000,
+        8 => Timeout::Ms4000,
+        _ => Timeout::Ms8000,
+    }
+}
+
 /// Enable the watchdog timer.
 ///
 /// Taken from [avr-hal-generic].
 #[inline]
 pub fn wdt_enable(timeout: Timeout) -> Result<()> {
-    let wdt_lock = wdt()?;
-
-    interrupt::free(|cs| {
+    with_wdt(|wdt| {
         // Reset the watchdog timer.
         wdt_reset();
 
         // Enable watchdog configuration mode.
-        let wdt = wdt_lock.borrow(cs);
-
         wdt.wdtcsr
             .modify(|_, w| w.wdce().set_bit().wde().set_bit());
 
@@ -48,9 +85,7 @@ pub fn wdt_enable(timeout: Timeout) -> Result<()> {
         // Disable watchdog configuration mode.
         wdt.wdtcsr
             .modify(|_, w| w.wde().set_bit().wdce().clear_bit());
-    });
-
-    Ok(())
+    })
 }
 
 /// Disable the watchdog timer.
@@ -58,8 +93,6 @@ pub fn wdt_enable(timeout: Timeout) -> Result<()> {
 /// Taken from [avr-hal-generic].
 #[inline]
 pub fn wdt_disable() -> Result<()> {
-    let wdt_lock = wdt()?;
-
     // The sequence for clearing WDE is as follows:
     //
     //     1. In the same operation, write a logic one to the Watchdog change enable bit
@@ -67,21 +100,17 @@ pub fn wdt_disable() -> Result<()> {
     //        previous value of the WDE bit.
     //     2. Within the next four clock cycles, clear the WDE and WDCE bits.
     //        This must be done in one operation.
-    avr_device::interrupt::free(|cs| {
+    with_wdt(|wdt| {
         // Reset the watchdog timer.
         wdt_reset();
 
-        let wdt = wdt_lock.borrow(cs);
-
         // Enable watchdog configuration mode.
         wdt.wdtcsr
             .modify(|_, w| w.wdce().set_bit().wde().set_bit());
 
         // Disable watchdog.
         wdt.wdtcsr.reset();
-    });
-
-    Ok(())
+    })
 }
 
 /// Reset the watchdog timer.
@@ -91,3 +120,29 @@ pub fn wdt_disable() -> Result<()> {
 pub fn wdt_reset() {
     avr_device::asm::wdr();
 }
+
+/// Resets the MCU into the application, through the watchdog.
+///
+/// Arms the watchdog with its shortest timeout, and spins until it fires. Unlike
+/// [BootloaderKind::reboot_bootloader](crate::bootloader::BootloaderKind::reboot_bootloader),
+/// the Caterina magic key is cleared instead, so the bootloader starts the sketch right
+/// away.
+///
+/// Callers should release all keys and detach from the host first, see
+/// [Runtime::reboot](crate::runtime::Runtime::reboot).
+pub fn system_reset() -> ! {
+    // A magic key left over from an earlier bootloader request would keep Caterina
+    // waiting in the bootloader.
+    unsafe {
+        core::ptr::write_volatile(BOOT_KEY_PTR as *mut u16, 0);
+    }
+
+    if let Err(_err) = wdt_enable(timeout_at_least(0)) {
+        // FIXME: log error
+    }
+
+    loop {
+        // Nothing else runs before the watchdog resets us.
+        avr_device::asm::nop();
+    }
+}
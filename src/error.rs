@@ -1,3 +1,5 @@
+use core::fmt;
+
 use keyboardio_hid::usb_device::UsbError;
 
 use crate::event_handler::EventHandlerError;
@@ -13,29 +15,106 @@ pub enum Error {
     HID,
     TC1,
     WDT,
+    EEPROM,
+    Storage,
+    StorageCorrupt,
     Layer,
+    SchedulerFull,
+    InjectQueueFull,
+    InvalidKeyAddr,
+    InvalidCodePoint,
+    BootProtocolActive,
+    SplitLink,
+    NotAscii,
+    InvalidKeymap,
+    InvalidKeyList,
     EventConsumed,
     EventAbort,
     EventError,
+    TokenOverflow,
 }
 
-impl Into<&'static str> for Error {
-    fn into(self) -> &'static str {
-        match self {
-            Self::Peripherals => "unable to acquire peripherals",
-            Self::USB => "USB error",
-            Self::HID => "HID error",
-            Self::CPU => "CPU error",
-            Self::TC1 => "TC1 error",
-            Self::WDT => "WDT error",
-            Self::Layer => "Layer error",
-            Self::EventConsumed => "Event handler consumed the event",
-            Self::EventAbort => "Event handler aborted",
-            Self::EventError => "Event handler raised an unknown error",
+impl From<Error> for &'static str {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Peripherals => "unable to acquire peripherals",
+            Error::USB => "USB error",
+            Error::HID => "HID error",
+            Error::CPU => "CPU error",
+            Error::TC1 => "TC1 error",
+            Error::WDT => "WDT error",
+            Error::EEPROM => "EEPROM error",
+            Error::Storage => "Storage out of bounds",
+            Error::StorageCorrupt => "Stored settings are corrupt or outdated",
+            Error::Layer => "Layer error",
+            Error::SchedulerFull => "Scheduled event queue is full",
+            Error::InjectQueueFull => "Injected event queue is full",
+            Error::InvalidKeyAddr => "Key address is outside the matrix",
+            Error::InvalidCodePoint => "Not a Unicode scalar value",
+            Error::BootProtocolActive => "Host is using the boot protocol, NKRO is unavailable",
+            Error::SplitLink => "Split link error",
+            Error::NotAscii => "Not a printable ASCII character",
+            Error::InvalidKeymap => "Keymap data is invalid or out of bounds",
+            Error::InvalidKeyList => "Key list is malformed or has the wrong length",
+            Error::EventConsumed => "Event handler consumed the event",
+            Error::EventAbort => "Event handler aborted",
+            Error::EventError => "Event handler raised an unknown error",
+            Error::TokenOverflow => "Token is longer than its buffer",
         }
     }
 }
 
+/// Formats the error with its description.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::Error;
+///
+/// let errors = [
+///     (Error::Peripherals, "unable to acquire peripherals"),
+///     (Error::USB, "USB error"),
+///     (Error::CPU, "CPU error"),
+///     (Error::HID, "HID error"),
+///     (Error::TC1, "TC1 error"),
+///     (Error::WDT, "WDT error"),
+///     (Error::EEPROM, "EEPROM error"),
+///     (Error::Storage, "Storage out of bounds"),
+///     (Error::StorageCorrupt, "Stored settings are corrupt or outdated"),
+///     (Error::Layer, "Layer error"),
+///     (Error::SchedulerFull, "Scheduled event queue is full"),
+///     (Error::InjectQueueFull, "Injected event queue is full"),
+///     (Error::InvalidKeyAddr, "Key address is outside the matrix"),
+///     (Error::InvalidCodePoint, "Not a Unicode scalar value"),
+///     (Error::BootProtocolActive, "Host is using the boot protocol, NKRO is unavailable"),
+///     (Error::SplitLink, "Split link error"),
+///     (Error::NotAscii, "Not a printable ASCII character"),
+///     (Error::InvalidKeymap, "Keymap data is invalid or out of bounds"),
+///     (Error::InvalidKeyList, "Key list is malformed or has the wrong length"),
+///     (Error::EventConsumed, "Event handler consumed the event"),
+///     (Error::EventAbort, "Event handler aborted"),
+///     (Error::EventError, "Event handler raised an unknown error"),
+///     (Error::TokenOverflow, "Token is longer than its buffer"),
+/// ];
+///
+/// for (err, expected) in errors {
+///     assert_eq!(format!("{err}"), expected);
+/// }
+/// ```
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        let s: &'static str = (*self).into();
+        defmt::write!(f, "{=str}", s);
+    }
+}
+
 impl From<EventHandlerError> for Error {
     fn from(event: EventHandlerError) -> Self {
         match event {
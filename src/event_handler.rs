@@ -1,8 +1,11 @@
-use crate::{key_defs::Key, key_event::KeyEvent};
+use core::fmt;
+
+use crate::{key_addr::KeyAddr, key_defs::Key, key_event::KeyEvent, keyswitch_state::KeyswitchState, sketch::Sketch, Error};
 
 /// This is the set of return values for event handlers. Event handlers for
 /// plugins are called in sequence by the corresponding hook function, in plugin
-/// initialization order. The interpretation of these return values can vary
+/// registration order (by [priority](EventHandler::PRIORITY) first, for the key
+/// event handlers). The interpretation of these return values can vary
 /// based on the needs of the hook function, but should be as follows:
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -23,12 +26,185 @@ pub enum EventHandlerError {
     Error,
 }
 
+impl From<EventHandlerError> for &'static str {
+    fn from(err: EventHandlerError) -> Self {
+        Error::from(err).into()
+    }
+}
+
+/// Formats the error with the description of the matching [Error].
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::event_handler::EventHandlerError;
+///
+/// assert_eq!(format!("{}", EventHandlerError::EventConsumed), "Event handler consumed the event");
+/// assert_eq!(format!("{}", EventHandlerError::Abort), "Event handler aborted");
+/// assert_eq!(format!("{}", EventHandlerError::Error), "Event handler raised an unknown error");
+/// ```
+impl fmt::Display for EventHandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for EventHandlerError {
+    fn format(&self, f: defmt::Formatter) {
+        let s: &'static str = (*self).into();
+        defmt::write!(f, "{=str}", s);
+    }
+}
+
+/// Sorts plugin priorities, highest first, for the `kaleidoscope_plugins!` dispatch.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::event_handler::sort_priorities;
+///
+/// assert_eq!(sort_priorities([0, 10, -5, 0, 20]), [20, 10, 0, 0, -5]);
+/// ```
+pub const fn sort_priorities<const N: usize>(mut priorities: [i8; N]) -> [i8; N] {
+    let mut i = 1;
+
+    while i < N {
+        let mut j = i;
+
+        while j > 0 && priorities[j - 1] < priorities[j] {
+            let prev = priorities[j - 1];
+            priorities[j - 1] = priorities[j];
+            priorities[j] = prev;
+            j -= 1;
+        }
+
+        i += 1;
+    }
+
+    priorities
+}
+
 /// Continue processing the event. The calling hook function should
 /// continue calling next event handler in the sequence. If all event
 /// handlers return `OK`, finish processing the event.
 pub type Result<T> = core::result::Result<T, EventHandlerError>;
 
 pub trait EventHandler {
+    /// Whether the plugin sees injected events, i.e. events generated by plugins or the
+    /// runtime rather than by a physical keyswitch, see [KeyEvent::is_injected].
+    ///
+    /// When `false`, the plugin's [on_keyswitch_event](Self::on_keyswitch_event),
+    /// [on_key_event](Self::on_key_event), [before_reporting_state](Self::before_reporting_state),
+    /// and [after_reporting_state](Self::after_reporting_state) handlers are skipped for
+    /// injected events, and the dispatch goes on with the next plugin. Plugins that
+    /// inject events themselves can opt out to never re-process their own output.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use core::sync::atomic::{AtomicU8, Ordering};
+    /// use kaleidoscope::event_handler::{EventHandler, Result};
+    /// use kaleidoscope::{kaleidoscope_plugins, key_addr::KeyAddr, key_event::KeyEvent, keyswitch_state::KeyswitchState};
+    ///
+    /// static PHYSICAL: AtomicU8 = AtomicU8::new(0);
+    /// static ALL: AtomicU8 = AtomicU8::new(0);
+    ///
+    /// struct PhysicalOnly;
+    /// struct Everything;
+    ///
+    /// impl EventHandler for PhysicalOnly {
+    ///     const PROCESS_INJECTED: bool = false;
+    ///
+    ///     fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+    ///         assert!(!event.is_injected());
+    ///         PHYSICAL.fetch_add(1, Ordering::Relaxed);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// impl EventHandler for Everything {
+    ///     fn on_key_event(_: &mut KeyEvent) -> Result<()> {
+    ///         ALL.fetch_add(1, Ordering::Relaxed);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct MyHooks;
+    ///
+    /// kaleidoscope_plugins![MyHooks; PhysicalOnly, Everything];
+    ///
+    /// let mut state = KeyswitchState::default();
+    /// state.set_is_pressed(true);
+    /// let mut physical = KeyEvent::next(KeyAddr::new(0), state);
+    ///
+    /// state.set_injected(true);
+    /// let mut injected = KeyEvent::next(KeyAddr::new(0), state);
+    /// assert!(injected.is_injected());
+    ///
+    /// assert_eq!(MyHooks::on_key_event(&mut physical), Ok(()));
+    /// assert_eq!(MyHooks::on_key_event(&mut injected), Ok(()));
+    ///
+    /// // The injected event skipped the opted out plugin only.
+    /// assert_eq!(PHYSICAL.load(Ordering::Relaxed), 1);
+    /// assert_eq!(ALL.load(Ordering::Relaxed), 2);
+    /// ```
+    const PROCESS_INJECTED: bool = true;
+
+    /// Priority of the plugin's [on_keyswitch_event](Self::on_keyswitch_event) and
+    /// [on_key_event](Self::on_key_event) handlers.
+    ///
+    /// Plugins with a higher priority see key events first, whatever their registration
+    /// order, so a plugin can declare that it must run before another one. Plugins with
+    /// the same priority are called in registration order. Other handlers are always
+    /// called in registration order.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::event_handler::{EventHandler, Result};
+    /// use kaleidoscope::{kaleidoscope_plugins, key_event::KeyEvent, lock::Spinlock};
+    ///
+    /// // The plugin names, in call order, and how many were called.
+    /// static CALLS: Spinlock<([&str; 5], usize)> = Spinlock::new(([""; 5], 0));
+    ///
+    /// macro_rules! plugin {
+    ///     ($name:ident, $priority:expr) => {
+    ///         struct $name;
+    ///
+    ///         impl EventHandler for $name {
+    ///             const PRIORITY: i8 = $priority;
+    ///
+    ///             fn on_key_event(_: &mut KeyEvent) -> Result<()> {
+    ///                 let mut calls = CALLS.write();
+    ///                 let (names, len) = &mut *calls;
+    ///
+    ///                 names[*len] = stringify!($name);
+    ///                 *len += 1;
+    ///
+    ///                 Ok(())
+    ///             }
+    ///         }
+    ///     };
+    /// }
+    ///
+    /// plugin!(Macros, 0);
+    /// plugin!(Qukeys, 10);
+    /// plugin!(Early, 10);
+    /// plugin!(Late, -5);
+    /// plugin!(Other, 0);
+    ///
+    /// struct MyHooks;
+    ///
+    /// kaleidoscope_plugins![MyHooks; Late, Macros, Qukeys, Other, Early];
+    ///
+    /// assert_eq!(MyHooks::on_key_event(&mut KeyEvent::new()), Ok(()));
+    ///
+    /// // By priority, then in registration order for equal priorities.
+    /// assert_eq!(*CALLS.read(), (["Qukeys", "Early", "Macros", "Other", "Late"], 5));
+    /// ```
+    const PRIORITY: i8 = 0;
+
     /// Called by Focus, when handling the `plugins` command.
     /// Should send the plugin name if that makes sense,
     /// but can be no-op.
@@ -41,6 +217,14 @@ pub trait EventHandler {
         Ok(())
     }
 
+    /// Called once, at the start of the first cycle in which the USB
+    /// device is configured by the host. Use this for setup work that
+    /// must wait for enumeration to complete, like sending an initial
+    /// report.
+    fn on_host_connected() -> Result<()> {
+        Ok(())
+    }
+
     /// Called at the very start of each cycle, before gathering
     /// events, before doing anything else.
     fn before_each_cycle() -> Result<()> {
@@ -63,6 +247,67 @@ pub trait EventHandler {
         Ok(())
     }
 
+    /// Called for every physical keyswitch toggle (on or off), straight
+    /// from the matrix scan, before the event goes through
+    /// [`on_keyswitch_event()`](Self::on_keyswitch_event) and the rest of
+    /// the event pipeline. For observation only, e.g. driving a buzzer or
+    /// logging: the event cannot be changed, every plugin is called, and
+    /// the return value is ignored. This runs inside the scan loop, so
+    /// handlers must be side-effect-light and return quickly, to keep the
+    /// scan timing.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use core::sync::atomic::{AtomicU8, Ordering};
+    /// use kaleidoscope::event_handler::{EventHandler, EventHandlerError, Result};
+    /// use kaleidoscope::{kaleidoscope_plugins, key_addr::KeyAddr, keyswitch_state::KeyswitchState};
+    ///
+    /// static PRESSES: AtomicU8 = AtomicU8::new(0);
+    /// static RELEASES: AtomicU8 = AtomicU8::new(0);
+    ///
+    /// struct Failing;
+    /// struct Logger;
+    ///
+    /// impl EventHandler for Failing {
+    ///     fn on_physical_key(_: KeyAddr, _: KeyswitchState) -> Result<()> {
+    ///         Err(EventHandlerError::Abort)
+    ///     }
+    /// }
+    ///
+    /// impl EventHandler for Logger {
+    ///     fn on_physical_key(addr: KeyAddr, state: KeyswitchState) -> Result<()> {
+    ///         assert_eq!(addr, KeyAddr::create(1, 2));
+    ///
+    ///         if state.key_toggled_on() {
+    ///             PRESSES.fetch_add(1, Ordering::Relaxed);
+    ///         } else if state.key_toggled_off() {
+    ///             RELEASES.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct MyHooks;
+    ///
+    /// kaleidoscope_plugins![MyHooks; Failing, Logger];
+    ///
+    /// let addr = KeyAddr::create(1, 2);
+    ///
+    /// // The state holds the previous scan in bit 0, and the current scan in bit 1.
+    /// assert_eq!(MyHooks::on_physical_key(addr, KeyswitchState::from(0b10)), Ok(()));
+    /// assert_eq!(MyHooks::on_physical_key(addr, KeyswitchState::from(0b01)), Ok(()));
+    ///
+    /// // The error of the first plugin does not stop the dispatch.
+    /// assert_eq!(PRESSES.load(Ordering::Relaxed), 1);
+    /// assert_eq!(RELEASES.load(Ordering::Relaxed), 1);
+    /// ```
+    fn on_physical_key(addr: KeyAddr, state: KeyswitchState) -> Result<()> {
+        let _ = (addr, state);
+        Ok(())
+    }
+
     /// Function called for every logical key event, including ones that
     /// originate from a physical keyswitch and ones that are injected
     /// by plugins. The `event` parameter is passed by reference so its
@@ -103,6 +348,15 @@ pub trait EventHandler {
         Ok(())
     }
 
+    /// Called when the host changes the lock-LED state (the keyboard
+    /// OUTPUT report). `leds` has NumLock at bit 0, CapsLock at bit 1,
+    /// and ScrollLock at bit 2. Only called on an actual change, not
+    /// for repeated identical reports.
+    fn on_host_led_change(leds: u8) -> Result<()> {
+        let _ = leds;
+        Ok(())
+    }
+
     /// Called when the LED mode changes. If one needs to know what
     /// from and what to the mode changed, they should track that
     /// themselves.
@@ -134,16 +388,51 @@ pub trait EventHandler {
         Ok(())
     }
 
+    /// Called once when no key event has been handled for the idle
+    /// timeout set with
+    /// [`Runtime::set_idle_timeout()`](crate::runtime::Runtime::set_idle_timeout).
+    /// Not called again until another key event has been handled.
+    fn on_idle() -> Result<()> {
+        Ok(())
+    }
+
     /// Called at the very end of a cycle, after everything's
     /// said and done.
     fn after_each_cycle() -> Result<()> {
         Ok(())
     }
 
-    /// Called before setup to enable plugins at compile time
-    /// to explore the sketch.
-    fn explore_sketch<Sketch>(sketch: Sketch) -> Result<()> {
+    /// Called once during setup, before `on_setup()`, with a description of
+    /// the sketch. Plugins can check that the addresses, layers, or other
+    /// plugins they refer to exist, and return an error to fail setup.
+    fn explore_sketch(sketch: &Sketch) -> Result<()> {
         let _ = sketch;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_priorities_trivial() {
+        assert_eq!(sort_priorities([]), []);
+        assert_eq!(sort_priorities([7]), [7]);
+        assert_eq!(sort_priorities([0, 0, 0]), [0, 0, 0]);
+    }
+
+    #[test]
+    fn sort_priorities_highest_first() {
+        assert_eq!(sort_priorities([1, 2, 3, 4]), [4, 3, 2, 1]);
+        assert_eq!(sort_priorities([4, 3, 2, 1]), [4, 3, 2, 1]);
+        assert_eq!(sort_priorities([i8::MIN, 0, i8::MAX, -1]), [i8::MAX, 0, -1, i8::MIN]);
+    }
+
+    #[test]
+    fn sort_priorities_in_const() {
+        const SORTED: [i8; 5] = sort_priorities([0, 20, 0, 10, 0]);
+
+        assert_eq!(SORTED, [20, 10, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,317 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use ufmt::{uWrite, uwrite};
+
+use crate::error::{self, Error};
+use crate::{key_defs::Key, key_ext::KeyExt, lock};
+
+/// Maximum number of bytes a Focus command response can hold before it is flushed.
+pub const FOCUS_OUTPUT_LEN: usize = 128;
+
+/// Global buffer holding the response to the Focus command currently being handled.
+pub static FOCUS_OUTPUT: lock::Spinlock<FocusBuffer> = lock::Spinlock::new(FocusBuffer::new());
+
+/// Part of the command line currently being handled, see [line_part].
+static LINE_PART: AtomicU8 = AtomicU8::new(LinePart::Whole as u8);
+
+/// Whether a handler accepted the rest of a streamed line, see [accept_stream].
+static STREAM_ACCEPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a handler deferred the reply to the current command, see [defer_reply].
+static REPLY_DEFERRED: AtomicBool = AtomicBool::new(false);
+
+/// Part of a Focus command line passed to the `on_focus_event()` handlers.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinePart {
+    /// The whole line.
+    Whole,
+    /// The start of a line too long for the input buffer.
+    First,
+    /// More values of a streamed line.
+    Next,
+    /// The last values of a streamed line.
+    Last,
+}
+
+/// Gets the part of the command line currently being handled.
+///
+/// Lines longer than the input buffer of the transport, e.g. a whole colormap, are
+/// passed to the handlers in parts. Every part starts with the command, followed by
+/// complete values only. The rest of the line is only passed on if a handler calls
+/// [accept_stream] while handling the [First](LinePart::First) part, otherwise the line
+/// is rejected. The reply is sent after the [Last](LinePart::Last) part.
+pub fn line_part() -> LinePart {
+    match LINE_PART.load(Ordering::Acquire) {
+        1 => LinePart::First,
+        2 => LinePart::Next,
+        3 => LinePart::Last,
+        _ => LinePart::Whole,
+    }
+}
+
+/// Sets the part of the command line about to be dispatched, for transports.
+///
+/// Clears any previous [accept_stream] call.
+pub fn set_line_part(part: LinePart) {
+    STREAM_ACCEPTED.store(false, Ordering::Release);
+    LINE_PART.store(part as u8, Ordering::Release);
+}
+
+/// Accepts the rest of a streamed command line, see [line_part].
+pub fn accept_stream() {
+    STREAM_ACCEPTED.store(true, Ordering::Release);
+}
+
+/// Gets whether a handler accepted the rest of the streamed command line.
+pub fn stream_accepted() -> bool {
+    STREAM_ACCEPTED.load(Ordering::Acquire)
+}
+
+/// Defers the reply to the current command line.
+///
+/// For commands completed later in the cycle, e.g. by the runtime. The transport keeps
+/// [FOCUS_OUTPUT] once the handlers return, and the reply is only sent once the command
+/// completes, see [finish_reply](crate::plugins::focus_serial::finish_reply).
+pub fn defer_reply() {
+    REPLY_DEFERRED.store(true, Ordering::Release);
+}
+
+/// Takes whether a handler deferred the reply to the current command line, for
+/// transports.
+pub fn take_reply_deferred() -> bool {
+    REPLY_DEFERRED.swap(false, Ordering::AcqRel)
+}
+
+/// Error returned when a Focus response exceeds the output buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FocusOverflow;
+
+/// Fixed-size byte buffer that Focus handlers write their responses into.
+///
+/// The buffer is drained by the transport (e.g. the serial port) after the command
+/// has been dispatched to the plugins.
+pub struct FocusBuffer {
+    buf: [u8; FOCUS_OUTPUT_LEN],
+    len: usize,
+}
+
+impl FocusBuffer {
+    /// Creates a new, empty [FocusBuffer].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; FOCUS_OUTPUT_LEN],
+            len: 0,
+        }
+    }
+
+    /// Gets the buffered response bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Gets the number of buffered response bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the buffer holds any response bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Clears the buffered response.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl uWrite for FocusBuffer {
+    type Error = FocusOverflow;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        let bytes = s.as_bytes();
+        let end = self.len + bytes.len();
+
+        if end > self.buf.len() {
+            return Err(FocusOverflow);
+        }
+
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+/// Splits a Focus input line into the command, and the remaining arguments.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::focus::split_command;
+///
+/// assert_eq!(split_command("device.layers"), ("device.layers", ""));
+/// assert_eq!(split_command("keymap.map 1 2 3"), ("keymap.map", "1 2 3"));
+/// ```
+pub fn split_command(input: &str) -> (&str, &str) {
+    let input = input.trim();
+
+    match input.find(' ') {
+        Some(pos) => (&input[..pos], input[pos + 1..].trim_start()),
+        None => (input, ""),
+    }
+}
+
+/// Writes `keys` as space-separated [Focus values](KeyExt::to_focus), followed by a line
+/// break.
+pub fn write_keys<W, I>(w: &mut W, keys: I) -> core::result::Result<(), W::Error>
+where
+    W: uWrite,
+    I: IntoIterator<Item = Key>,
+{
+    for (i, key) in keys.into_iter().enumerate() {
+        if i > 0 {
+            w.write_str(" ")?;
+        }
+        uwrite!(w, "{}", key.to_focus())?;
+    }
+
+    w.write_str("\r\n")
+}
+
+/// Parses space-separated [Focus values](KeyExt::from_focus) into `out`, returning the
+/// number of keys.
+///
+/// Returns [Error::InvalidKeyList], and leaves `out` untouched, if a value is not a
+/// `u16`, or there are more values than `out` holds. The input is checked as a whole
+/// first, so no scratch buffer is needed.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_defs::*, key_ext::KeyExt, Error};
+/// use kaleidoscope::focus::{parse_keys, write_keys, FocusBuffer};
+///
+/// let keys = [Key_A, Key_Transparent, Key_LeftShift];
+///
+/// let mut output = FocusBuffer::new();
+/// write_keys(&mut output, keys).unwrap();
+///
+/// let text = core::str::from_utf8(output.as_bytes()).unwrap();
+/// assert_eq!(text, format!("{} {} {}\r\n", Key_A.to_focus(), Key_Transparent.to_focus(), Key_LeftShift.to_focus()));
+///
+/// let mut parsed = [Key_NoKey; 4];
+/// assert_eq!(parse_keys(text, &mut parsed), Ok(3));
+/// assert_eq!(parsed[..3], keys);
+///
+/// assert_eq!(parse_keys("4 5 6 7 8", &mut parsed), Err(Error::InvalidKeyList));
+/// assert_eq!(parse_keys("4 five", &mut parsed), Err(Error::InvalidKeyList));
+/// assert_eq!(parse_keys("4 70000", &mut parsed), Err(Error::InvalidKeyList));
+/// assert_eq!(parsed[..3], keys);
+/// ```
+pub fn parse_keys(input: &str, out: &mut [Key]) -> error::Result<usize> {
+    let mut count = 0;
+
+    for value in input.split_whitespace() {
+        value.parse::<u16>().map_err(|_| Error::InvalidKeyList)?;
+        count += 1;
+    }
+
+    if count > out.len() {
+        return Err(Error::InvalidKeyList);
+    }
+
+    for (key, value) in out.iter_mut().zip(input.split_whitespace()) {
+        *key = Key::from_focus(value.parse().unwrap_or(0));
+    }
+
+    Ok(count)
+}
+
+/// Parses exactly `out.len()` space-separated Focus values into `out`, see [parse_keys].
+///
+/// Returns [Error::InvalidKeyList], and leaves `out` untouched, if there are fewer values.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_defs::*, Error};
+/// use kaleidoscope::focus::{parse_keys_exact, write_keys, FocusBuffer};
+///
+/// // A keymap row round-trips.
+/// let row = [Key_Q, Key_W, Key_E, Key_R, Key_T, Key_Transparent, Key_Y, Key_U, Key_I, Key_O, Key_P];
+///
+/// let mut output = FocusBuffer::new();
+/// write_keys(&mut output, row).unwrap();
+///
+/// let mut parsed = [Key_NoKey; 11];
+/// let text = core::str::from_utf8(output.as_bytes()).unwrap();
+/// assert_eq!(parse_keys_exact(text, &mut parsed), Ok(()));
+/// assert_eq!(parsed, row);
+///
+/// // Short rows are rejected as a whole.
+/// let mut short = [Key_NoKey; 3];
+/// assert_eq!(parse_keys_exact("4 5", &mut short), Err(Error::InvalidKeyList));
+/// assert_eq!(short, [Key_NoKey; 3]);
+/// ```
+pub fn parse_keys_exact(input: &str, out: &mut [Key]) -> error::Result<()> {
+    if input.split_whitespace().count() != out.len() {
+        return Err(Error::InvalidKeyList);
+    }
+
+    parse_keys(input, out).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_defs::*;
+
+    #[test]
+    fn parse_keys_empty() {
+        let mut out = [Key_A; 2];
+
+        assert_eq!(parse_keys("", &mut out), Ok(0));
+        assert_eq!(parse_keys(" \t\r\n", &mut out), Ok(0));
+        assert_eq!(out, [Key_A; 2]);
+    }
+
+    #[test]
+    fn parse_keys_whitespace() {
+        let mut out = [Key_NoKey; 3];
+
+        assert_eq!(parse_keys("  4\t5 \r\n", &mut out), Ok(2));
+        assert_eq!(out, [Key::from_focus(4), Key::from_focus(5), Key_NoKey]);
+    }
+
+    #[test]
+    fn parse_keys_exact_fit() {
+        let mut out = [Key_NoKey; 2];
+
+        assert_eq!(parse_keys("0 65535", &mut out), Ok(2));
+        assert_eq!(out, [Key::from_focus(0), Key::from_focus(u16::MAX)]);
+    }
+
+    #[test]
+    fn parse_keys_invalid_leaves_output() {
+        let mut out = [Key_A; 2];
+
+        assert_eq!(parse_keys("-1", &mut out), Err(Error::InvalidKeyList));
+        assert_eq!(parse_keys("4 0x05", &mut out), Err(Error::InvalidKeyList));
+        assert_eq!(parse_keys("4 5 6", &mut out), Err(Error::InvalidKeyList));
+        assert_eq!(out, [Key_A; 2]);
+    }
+
+    #[test]
+    fn parse_keys_exact_count() {
+        let mut out = [Key_A; 2];
+
+        assert_eq!(parse_keys_exact("4 5 6", &mut out), Err(Error::InvalidKeyList));
+        assert_eq!(parse_keys_exact("4", &mut out), Err(Error::InvalidKeyList));
+        assert_eq!(out, [Key_A; 2]);
+
+        assert_eq!(parse_keys_exact("4 5", &mut out), Ok(()));
+        assert_eq!(out, [Key::from_focus(4), Key::from_focus(5)]);
+    }
+}
@@ -1,5 +1,108 @@
-use crate::event_handler::EventHandler;
+use crate::driver::hid::{KeyboardProtocol, KEYBOARD_PROTOCOL};
+use crate::driver::keyscanner::Atmega;
+use crate::error;
+use crate::layers::Layer;
+use crate::persistable::Persistable;
+#[cfg(feature = "device_reset")]
+use crate::plugins::device_reset::DeviceReset;
+#[cfg(feature = "cycle_time")]
+use crate::runtime::CycleTime;
+use crate::runtime::{LastError, Reboot};
+use crate::plugins::{
+    colormap::{Colormap, COLORMAP},
+    combos::Combos,
+    consumer_mute::{ConsumerMute, CONSUMER_MUTE},
+    cycle::Cycle,
+    dynamic_keymap::{DynamicKeymap, DYNAMIC_KEYMAP},
+    dynamic_macros::{DynamicMacros, DYNAMIC_MACROS},
+    focus_serial::FocusSerial,
+    heatmap::Heatmap,
+    host_os::{HostOS, HOST_OS},
+    key_repeat::{KeyRepeat, KEY_REPEAT},
+    layer_highlight::LayerHighlight,
+    leader::Leader,
+    led_effects::LedEffects,
+    macros::Macros,
+    magic_combo::MagicCombo,
+    mouse_warp::MouseWarp,
+    one_shot::OneShot,
+    qukeys::{Qukeys, QUKEYS},
+    redial::Redial,
+    space_cadet::SpaceCadet,
+    steno::Steno,
+    sticky_keys::StickyKeys,
+    syster::Syster,
+    topsy_turvy::TopsyTurvy,
+    turbo::Turbo,
+    typing_stats::{TypingStats, TYPING_STATS},
+    unicode::Unicode,
+};
+use crate::driver::serial::UsbSerial;
+use crate::LAYER;
 
 pub struct Hooks;
 
-impl EventHandler for Hooks {}
+impl Hooks {
+    /// Claims storage for every persistable plugin, and restores their settings.
+    ///
+    /// Slots are claimed in a fixed order, so the storage layout is stable across reboots.
+    pub fn setup_storage() -> error::Result<()> {
+        CONSUMER_MUTE.write().setup_storage()?;
+        DYNAMIC_MACROS.write().setup_storage()?;
+        LAYER.write().setup_storage()?;
+        QUKEYS.write().setup_storage()?;
+        HOST_OS.write().setup_storage()?;
+        TYPING_STATS.write().setup_storage()?;
+        KEYBOARD_PROTOCOL.write().setup_storage()?;
+        KEY_REPEAT.write().setup_storage()?;
+        DYNAMIC_KEYMAP.write().setup_storage()?;
+        COLORMAP.write().setup_storage()?;
+
+        Ok(())
+    }
+}
+
+// Plugins are called in this order for every handler, except the key event handlers,
+// which follow the plugin priorities first (e.g. Combos see keyswitch events before
+// Qukeys). Relative order matters where plugins handle the same events: e.g.
+// TypingStats must count key events before other plugins consume them, and the
+// `before_each_cycle()` timeouts run Leader, Qukeys, SpaceCadet, Turbo, then Combos.
+crate::kaleidoscope_plugins![
+    Hooks;
+    Layer,
+    #[cfg(feature = "device_reset")]
+    DeviceReset,
+    #[cfg(feature = "cycle_time")]
+    CycleTime,
+    LastError,
+    Reboot,
+    HostOS,
+    KeyboardProtocol,
+    FocusSerial<UsbSerial>,
+    StickyKeys,
+    OneShot,
+    TypingStats,
+    Heatmap,
+    DynamicMacros,
+    DynamicKeymap,
+    Macros,
+    Leader,
+    Qukeys,
+    SpaceCadet,
+    Cycle,
+    Redial,
+    Turbo,
+    Combos,
+    KeyRepeat,
+    Syster,
+    TopsyTurvy,
+    Steno,
+    ConsumerMute,
+    LedEffects,
+    Colormap,
+    LayerHighlight,
+    MagicCombo,
+    MouseWarp,
+    Unicode,
+    Atmega,
+];
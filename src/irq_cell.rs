@@ -0,0 +1,198 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use avr_device::interrupt::{self, CriticalSection};
+
+/// Global cell for peripherals and drivers set up once at startup.
+///
+/// Replaces `static mut` globals: the value is set once, inside `interrupt::free`, and is
+/// only reachable through a guard borrowed for the duration of a critical section, see
+/// [borrow_mut](Self::borrow_mut) and [with](Self::with). No reference to the value can
+/// outlive the critical section, so the main loop and the interrupt handlers never hold
+/// aliasing references. A "taken" flag rejects nested borrows within the same critical
+/// section.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::irq_cell::IrqCell;
+///
+/// static CELL: IrqCell<u8> = IrqCell::new();
+///
+/// assert_eq!(CELL.with(|value| *value), None);
+///
+/// assert!(CELL.set(1).is_ok());
+///
+/// // The value is only set once.
+/// assert_eq!(CELL.set(3), Err(3));
+///
+/// CELL.with(|value| {
+///     *value += 1;
+///
+///     // Only one borrow at a time.
+///     assert!(CELL.is_borrowed());
+///     assert_eq!(CELL.with(|value| *value), None);
+/// });
+///
+/// assert!(!CELL.is_borrowed());
+/// assert_eq!(CELL.with(|value| *value), Some(2));
+/// ```
+pub struct IrqCell<T> {
+    value: UnsafeCell<Option<T>>,
+    taken: AtomicBool,
+}
+
+// SAFETY: the MCU has a single core, the value is only reached with interrupts disabled,
+// and exclusive borrows are tracked by the `taken` flag.
+unsafe impl<T> Sync for IrqCell<T> {}
+
+impl<T> IrqCell<T> {
+    /// Creates a new, empty [IrqCell].
+    pub const fn new() -> Self {
+        Self {
+            value: UnsafeCell::new(None),
+            taken: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the value.
+    ///
+    /// The value can only be set once: returns the value back if the cell is already set.
+    /// Replace the value through [with](Self::with) instead.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        interrupt::free(|_| {
+            // SAFETY: interrupts are disabled, and no guard exists on an empty cell.
+            let slot = unsafe { &mut *self.value.get() };
+
+            if self.taken.load(Ordering::Acquire) || slot.is_some() {
+                Err(value)
+            } else {
+                *slot = Some(value);
+                Ok(())
+            }
+        })
+    }
+
+    /// Borrows the value exclusively, for at most the duration of the critical section
+    /// `cs`.
+    ///
+    /// Returns `None` if the cell is empty, or already borrowed.
+    pub fn borrow_mut<'cs>(&'cs self, _cs: CriticalSection<'cs>) -> Option<IrqRefMut<'cs, T>> {
+        if self.taken.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: interrupts are disabled, and there is no other guard.
+        let is_empty = unsafe { (*self.value.get()).is_none() };
+
+        if is_empty {
+            None
+        } else {
+            self.taken.store(true, Ordering::Release);
+            Some(IrqRefMut { cell: self })
+        }
+    }
+
+    /// Calls `f` with exclusive access to the value, with interrupts disabled.
+    ///
+    /// Returns `None`, without calling `f`, if the cell is empty, or already borrowed.
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        interrupt::free(|cs| self.borrow_mut(cs).map(|mut value| f(&mut value)))
+    }
+
+    /// Gets whether the value is exclusively borrowed.
+    pub fn is_borrowed(&self) -> bool {
+        self.taken.load(Ordering::Acquire)
+    }
+}
+
+/// Exclusive borrow of an [IrqCell] value, released on drop.
+pub struct IrqRefMut<'cs, T> {
+    cell: &'cs IrqCell<T>,
+}
+
+impl<T> Deref for IrqRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the cell is taken, and only filled cells can be borrowed.
+        unsafe { (*self.cell.value.get()).as_ref().unwrap_unchecked() }
+    }
+}
+
+impl<T> DerefMut for IrqRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the cell is taken, and only filled cells can be borrowed.
+        unsafe { (*self.cell.value.get()).as_mut().unwrap_unchecked() }
+    }
+}
+
+impl<T> Drop for IrqRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.taken.store(false, Ordering::Release);
+    }
+}
+
+/// Global cell for values set once at startup, and never changed afterwards.
+///
+/// Unlike [IrqCell], shared `'static` references to the value are handed out. This is
+/// only meant for values other drivers must borrow for `'static`, like the USB bus
+/// allocator, or the USB serial number string. The value is set inside
+/// `interrupt::free`, and can't be replaced or dropped, so the references stay valid.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::irq_cell::IrqOnce;
+///
+/// static ONCE: IrqOnce<u8> = IrqOnce::new();
+///
+/// assert_eq!(ONCE.get(), None);
+/// assert!(ONCE.set(1).is_ok());
+/// assert_eq!(ONCE.set(2), Err(2));
+/// assert_eq!(ONCE.get(), Some(&1));
+/// ```
+pub struct IrqOnce<T> {
+    value: UnsafeCell<Option<T>>,
+    ready: AtomicBool,
+}
+
+// SAFETY: the MCU has a single core, the value is written once with interrupts disabled,
+// before `ready` is set, and is never written again.
+unsafe impl<T> Sync for IrqOnce<T> {}
+
+impl<T> IrqOnce<T> {
+    /// Creates a new, empty [IrqOnce].
+    pub const fn new() -> Self {
+        Self {
+            value: UnsafeCell::new(None),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the value, returning it back if the cell is already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        interrupt::free(|_| {
+            if self.ready.load(Ordering::Acquire) {
+                Err(value)
+            } else {
+                // SAFETY: interrupts are disabled, and no reference exists before `ready`
+                // is set.
+                unsafe { *self.value.get() = Some(value) };
+                self.ready.store(true, Ordering::Release);
+                Ok(())
+            }
+        })
+    }
+
+    /// Gets the value, `None` if it is not set yet.
+    pub fn get(&self) -> Option<&T> {
+        if self.ready.load(Ordering::Acquire) {
+            // SAFETY: the value is never written again once `ready` is set.
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+}
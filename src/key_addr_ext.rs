@@ -0,0 +1,56 @@
+use crate::key_addr::KeyAddr;
+
+/// Row and column helpers for [KeyAddr].
+///
+/// [KeyAddr] only stores a linear index, these helpers decode it back into the matrix
+/// coordinates passed to [KeyAddr::create].
+pub trait KeyAddrExt: Sized {
+    /// Creates a [KeyAddr] from matrix coordinates, alias for [KeyAddr::create].
+    fn from_row_col(row: u8, col: u8) -> Self;
+
+    /// Gets the number of columns per row in the address space.
+    fn cols() -> u8;
+
+    /// Gets the matrix row of the address.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_addr::KeyAddr, key_addr_ext::KeyAddrExt};
+    ///
+    /// for addr in KeyAddr::iter() {
+    ///     let (row, col) = (addr.row(), addr.col());
+    ///     assert_eq!(KeyAddr::create(row, col).row(), row);
+    ///     assert_eq!(KeyAddr::create(row, col).col(), col);
+    /// }
+    /// ```
+    fn row(&self) -> u8;
+
+    /// Gets the matrix column of the address.
+    fn col(&self) -> u8;
+
+    /// Gets whether the address is on the provided matrix row.
+    fn is_on_row(&self, row: u8) -> bool {
+        self.row() == row
+    }
+}
+
+impl KeyAddrExt for KeyAddr {
+    fn from_row_col(row: u8, col: u8) -> Self {
+        Self::create(row, col)
+    }
+
+    fn cols() -> u8 {
+        // The distance between the first keys of two rows is the row length used by
+        // `create`, so decoding always agrees with it.
+        Self::create(1, 0).index() as u8
+    }
+
+    fn row(&self) -> u8 {
+        (self.index() / Self::cols() as usize) as u8
+    }
+
+    fn col(&self) -> u8 {
+        (self.index() % Self::cols() as usize) as u8
+    }
+}
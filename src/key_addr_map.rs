@@ -118,8 +118,19 @@ impl<'m, const N: usize> IndexMut<KeyAddr>
     }
 }
 
+/// Iterator over the entries of a [KeyAddrMap], in [KeyAddr] order.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{Key, key_addr_map::KeyAddrMap};
+///
+/// let key_array: KeyAddrMap<10> = KeyAddrMap::new();
+///
+/// assert_eq!(key_array.iter().count(), 10);
+/// assert!(key_array.iter().all(|&key| key == Key::default()));
+/// ```
 pub struct KeyAddrMapIter<'m> {
-    index: KeyAddr,
     map_iter: core::slice::Iter<'m, Key>,
 }
 
@@ -127,14 +138,11 @@ impl<'m> Iterator for KeyAddrMapIter<'m> {
     type Item = &'m Key;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let index = self.index;
+        self.map_iter.next()
+    }
 
-        if usize::from(index) < self.map_iter.len() {
-            self.index += 1;
-            self.map_iter.nth(index.into())
-        } else {
-            None
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_iter.size_hint()
     }
 }
 
@@ -144,14 +152,27 @@ impl<'m, const N: usize> IntoIterator for &'m KeyAddrMap<N> {
 
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
-            index: KeyAddr::new(0),
             map_iter: self.values.iter(),
         }
     }
 }
 
+/// Mutable iterator over the entries of a [KeyAddrMap], in [KeyAddr] order.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{Key_A, key_addr_map::KeyAddrMap};
+///
+/// let mut key_array: KeyAddrMap<10> = KeyAddrMap::new();
+///
+/// for key in key_array.iter_mut() {
+///     *key = Key_A;
+/// }
+///
+/// assert_eq!(key_array.iter().filter(|&&key| key == Key_A).count(), 10);
+/// ```
 pub struct KeyAddrMapIterMut<'m> {
-    index: KeyAddr,
     map_iter: core::slice::IterMut<'m, Key>,
 }
 
@@ -159,14 +180,11 @@ impl<'m> Iterator for KeyAddrMapIterMut<'m> {
     type Item = &'m mut Key;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let index = self.index;
+        self.map_iter.next()
+    }
 
-        if usize::from(index) < self.map_iter.len() {
-            self.index += 1;
-            self.map_iter.nth(index.into())
-        } else {
-            None
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.map_iter.size_hint()
     }
 }
 
@@ -176,7 +194,6 @@ impl<'m, const N: usize> IntoIterator for &'m mut KeyAddrMap<N> {
 
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
-            index: KeyAddr::new(0),
             map_iter: self.values.iter_mut(),
         }
     }
@@ -5,9 +5,17 @@ use core::{
 
 use crate::{key_addr::KeyAddr, key_defs::Key, keyswitch_state::KeyswitchState};
 
+/// ID of the last event created by [KeyEvent::next].
+///
+/// Reset by [KeyEvent::reset_id_counter] on setup, so IDs start from a known baseline
+/// after any reboot.
 static LAST_ID: AtomicI8 = AtomicI8::new(0);
 
 /// It's important that this is a signed integer, not unsigned.
+///
+/// IDs are cyclic: after `127`, the next ID is `-128`. Compare IDs with
+/// [is_after](Self::is_after), which uses the signed wrapping distance, and stays coherent
+/// across the boundary for IDs less than 128 events apart.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct KeyEventId(i8);
 
@@ -15,12 +23,29 @@ impl KeyEventId {
     pub const fn default() -> Self {
         Self(0)
     }
+
+    /// Gets whether this ID was assigned after `other`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::key_event::KeyEventId;
+    ///
+    /// let last = KeyEventId::default() + 127;
+    /// let next = last + 1;
+    ///
+    /// assert!(next.is_after(&last));
+    /// assert!(!last.is_after(&next));
+    /// ```
+    pub fn is_after(&self, other: &KeyEventId) -> bool {
+        self.0.wrapping_sub(other.0) > 0
+    }
 }
 
 impl Add for &KeyEventId {
     type Output = KeyEventId;
     fn add(self, oth: Self) -> Self::Output {
-        KeyEventId(self.0 + oth.0)
+        KeyEventId(self.0.wrapping_add(oth.0))
     }
 }
 
@@ -28,14 +53,14 @@ impl Add<i8> for &KeyEventId {
     type Output = KeyEventId;
 
     fn add(self, oth: i8) -> Self::Output {
-        KeyEventId(self.0 + oth)
+        KeyEventId(self.0.wrapping_add(oth))
     }
 }
 
 impl Add for KeyEventId {
     type Output = Self;
     fn add(self, oth: Self) -> Self::Output {
-        KeyEventId(self.0 + oth.0)
+        KeyEventId(self.0.wrapping_add(oth.0))
     }
 }
 
@@ -43,7 +68,7 @@ impl Add<i8> for KeyEventId {
     type Output = Self;
 
     fn add(self, oth: i8) -> Self {
-        KeyEventId(self.0 + oth)
+        KeyEventId(self.0.wrapping_add(oth))
     }
 }
 
@@ -69,8 +94,13 @@ impl KeyEvent {
     }
 
     /// For use by keyscanner creating a new event from a physical keyswitch toggle on or off.
+    ///
+    /// Each event gets the ID following the previous one, wrapping around after `127`, see
+    /// [KeyEventId]. The sequence only holds since the last
+    /// [reset_id_counter](Self::reset_id_counter): IDs of events from before a reboot
+    /// must not be compared with new ones.
     pub fn next(addr: KeyAddr, state: KeyswitchState) -> Self {
-        let id = LAST_ID.load(Ordering::Relaxed) + 1;
+        let id = LAST_ID.load(Ordering::Relaxed).wrapping_add(1);
         LAST_ID.store(id, Ordering::SeqCst);
 
         Self {
@@ -82,6 +112,43 @@ impl KeyEvent {
         }
     }
 
+    /// Resets the event IDs, so the next event created by [next](Self::next) gets ID `1`.
+    ///
+    /// Called by [Runtime::setup](crate::runtime::Runtime::setup), before any event is
+    /// created. The counter is stored in a single atomic write, so an event created by an
+    /// interrupt handler gets either the old or the new sequence.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::key_addr::KeyAddr;
+    /// use kaleidoscope::key_event::{KeyEvent, KeyEventId};
+    /// use kaleidoscope::keyswitch_state::KeyswitchState;
+    ///
+    /// let next = || KeyEvent::next(KeyAddr::default(), KeyswitchState::default()).id();
+    ///
+    /// next();
+    /// next();
+    /// KeyEvent::reset_id_counter();
+    ///
+    /// // IDs restart predictably, ascending by one.
+    /// assert_eq!(next(), KeyEventId::default() + 1);
+    /// assert_eq!(next(), KeyEventId::default() + 2);
+    ///
+    /// // And still wrap around after `127`.
+    /// for _ in 3..=127 {
+    ///     next();
+    /// }
+    /// let last = KeyEventId::default() + 127;
+    /// let wrapped = next();
+    ///
+    /// assert_eq!(wrapped, KeyEventId::default() + -128);
+    /// assert!(wrapped.is_after(&last));
+    /// ```
+    pub fn reset_id_counter() {
+        LAST_ID.store(0, Ordering::SeqCst);
+    }
+
     /// Get the key address
     pub fn addr(&self) -> &KeyAddr {
         &self.addr
@@ -92,6 +159,12 @@ impl KeyEvent {
         self.state
     }
 
+    /// Gets whether the event was generated by a plugin or the runtime, rather than by a
+    /// physical keyswitch, see [KeyswitchState::key_is_injected].
+    pub fn is_injected(&self) -> bool {
+        self.state.key_is_injected()
+    }
+
     /// Gets the [Key].
     pub fn key(&self) -> &Key {
         &self.key
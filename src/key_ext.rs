@@ -0,0 +1,137 @@
+use crate::key_defs::{Key, KeyFlags, IS_CONSUMER, IS_SYSCTL, SYNTHETIC};
+use crate::key_flags_ext::KeyFlagsExt;
+
+/// Constructors and helpers for [Key].
+///
+/// The constructors set the type bits of the key's flags byte, so the classification
+/// predicates (`is_keyboard_key`, `is_consumer_control_key`, `is_system_control_key`)
+/// agree with the constructed key.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_defs::*, key_ext::KeyExt};
+///
+/// assert!(Key::consumer_control(0x00e2).is_consumer_control_key());
+/// assert!(Key::system_control(0x81).is_system_control_key());
+/// assert!(Key::keyboard(Key_A.key_code(), KeyFlags::SHIFT_HELD).is_keyboard_key());
+/// ```
+pub trait KeyExt: Sized {
+    /// Creates a Consumer Control key from its 10-bit HID usage code.
+    ///
+    /// Named `consumer_control` because `Key::consumer` already decodes the usage code.
+    fn consumer_control(code: u16) -> Self;
+
+    /// Creates a System Control key from its HID usage code.
+    fn system_control(code: u8) -> Self;
+
+    /// Creates a Keyboard key from its HID usage code, and modifier flags.
+    fn keyboard(code: u8, flags: KeyFlags) -> Self;
+
+    /// Returns a copy of the key, with the provided modifier flags added to its own.
+    fn with_flags(self, flags: KeyFlags) -> Self;
+
+    /// Returns a copy of the key, without modifier flags.
+    ///
+    /// Only Keyboard keys carry modifier flags, other keys use the flags byte for their
+    /// type, and are returned unchanged.
+    fn base(&self) -> Self;
+
+    /// Gets whether both keys have the same type and keycode, ignoring modifier flags.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_defs::*, key_ext::KeyExt};
+    ///
+    /// let shifted = Key_A.with_flags(KeyFlags::SHIFT_HELD);
+    ///
+    /// assert!(shifted.eq_ignoring_flags(&Key_A));
+    /// assert!(shifted != Key_A);
+    /// ```
+    fn eq_ignoring_flags(&self, other: &Self) -> bool;
+
+    /// Gets the modifier flags of the key.
+    ///
+    /// Only Keyboard keys carry modifier flags, other keys return [KeyFlags::NONE].
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_defs::*, key_ext::KeyExt, key_flags_ext::KeyFlagsExt};
+    ///
+    /// let key = Key_A.with_flags(KeyFlags::ctrl() | KeyFlags::shift());
+    ///
+    /// assert!(key.modifiers() == KeyFlags::ctrl() | KeyFlags::shift());
+    /// assert!(Key::consumer_control(0x00e2).modifiers() == KeyFlags::NONE);
+    /// ```
+    fn modifiers(&self) -> KeyFlags;
+
+    /// Gets the value sent for the key by Focus commands: its raw value.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_defs::*, key_ext::KeyExt};
+    ///
+    /// let key = Key_A.with_flags(KeyFlags::SHIFT_HELD);
+    ///
+    /// assert_eq!(key.to_focus(), key.raw());
+    /// assert_eq!(Key::from_focus(key.to_focus()), key);
+    /// ```
+    fn to_focus(&self) -> u16;
+
+    /// Creates a key from the value received by Focus commands, see [to_focus](Self::to_focus).
+    fn from_focus(value: u16) -> Self;
+}
+
+impl KeyExt for Key {
+    fn consumer_control(code: u16) -> Self {
+        let flags = (SYNTHETIC | IS_CONSUMER) as u16 | ((code >> 8) & 0b11);
+
+        Key::from_raw((flags << 8) | (code & 0xff))
+    }
+
+    fn system_control(code: u8) -> Self {
+        Key::from_raw((((SYNTHETIC | IS_SYSCTL) as u16) << 8) | code as u16)
+    }
+
+    fn keyboard(code: u8, flags: KeyFlags) -> Self {
+        let mut key = Key::from_raw(code as u16);
+        key.set_flags(flags);
+        key
+    }
+
+    fn with_flags(mut self, flags: KeyFlags) -> Self {
+        self.set_flags(self.flags() | flags);
+        self
+    }
+
+    fn base(&self) -> Self {
+        if self.is_keyboard_key() {
+            Key::keyboard(self.key_code(), KeyFlags::NONE)
+        } else {
+            *self
+        }
+    }
+
+    fn eq_ignoring_flags(&self, other: &Self) -> bool {
+        self.base() == other.base()
+    }
+
+    fn modifiers(&self) -> KeyFlags {
+        if self.is_keyboard_key() {
+            self.flags() & KeyFlags::modifiers()
+        } else {
+            KeyFlags::NONE
+        }
+    }
+
+    fn to_focus(&self) -> u16 {
+        self.raw()
+    }
+
+    fn from_focus(value: u16) -> Self {
+        Key::from_raw(value)
+    }
+}
@@ -0,0 +1,129 @@
+use core::fmt;
+
+use crate::key_defs::KeyFlags;
+
+/// Modifier flags, paired with the name used when listing them.
+const MODIFIER_NAMES: [(KeyFlags, &str); 5] = [
+    (KeyFlags::CTRL_HELD, "Ctrl"),
+    (KeyFlags::SHIFT_HELD, "Shift"),
+    (KeyFlags::LALT_HELD, "LAlt"),
+    (KeyFlags::RALT_HELD, "RAlt"),
+    (KeyFlags::GUI_HELD, "Gui"),
+];
+
+/// Combinators for the modifier bits of [KeyFlags].
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_defs::KeyFlags, key_flags_ext::KeyFlagsExt};
+///
+/// let flags = KeyFlags::ctrl() | KeyFlags::shift();
+///
+/// assert!(flags.contains(KeyFlags::ctrl()));
+/// assert!(flags.contains(KeyFlags::ctrl() | KeyFlags::shift()));
+/// assert!(!flags.contains(KeyFlags::gui()));
+/// assert!(flags.contains(KeyFlags::NONE));
+///
+/// assert!(flags | KeyFlags::ctrl() == flags);
+/// assert!(flags & KeyFlags::shift() == KeyFlags::shift());
+/// assert!(KeyFlags::modifiers().contains(flags));
+/// ```
+pub trait KeyFlagsExt: Sized {
+    /// Left Control held.
+    fn ctrl() -> Self;
+
+    /// Left Shift held.
+    fn shift() -> Self;
+
+    /// Left Alt held.
+    fn lalt() -> Self;
+
+    /// Right Alt (AltGr) held.
+    fn ralt() -> Self;
+
+    /// Left Gui held.
+    fn gui() -> Self;
+
+    /// All modifier flags.
+    fn modifiers() -> Self;
+
+    /// Gets whether all the flags set in `other` are also set in `self`.
+    fn contains(&self, other: Self) -> bool;
+
+    /// Gets a [Display](fmt::Display) adapter listing the set modifiers.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_defs::KeyFlags, key_flags_ext::KeyFlagsExt};
+    ///
+    /// let flags = KeyFlags::gui() | KeyFlags::ctrl();
+    ///
+    /// assert_eq!(format!("{}", flags.display()), "Ctrl+Gui");
+    /// assert_eq!(format!("{}", KeyFlags::NONE.display()), "None");
+    /// ```
+    fn display(self) -> ModifierNames;
+}
+
+impl KeyFlagsExt for KeyFlags {
+    fn ctrl() -> Self {
+        KeyFlags::CTRL_HELD
+    }
+
+    fn shift() -> Self {
+        KeyFlags::SHIFT_HELD
+    }
+
+    fn lalt() -> Self {
+        KeyFlags::LALT_HELD
+    }
+
+    fn ralt() -> Self {
+        KeyFlags::RALT_HELD
+    }
+
+    fn gui() -> Self {
+        KeyFlags::GUI_HELD
+    }
+
+    fn modifiers() -> Self {
+        MODIFIER_NAMES
+            .iter()
+            .fold(KeyFlags::NONE, |acc, (flag, _)| acc | *flag)
+    }
+
+    fn contains(&self, other: Self) -> bool {
+        *self & other == other
+    }
+
+    fn display(self) -> ModifierNames {
+        ModifierNames(self)
+    }
+}
+
+/// Lists the modifiers set in [KeyFlags], joined with `+`, or `None` if no modifier is set.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ModifierNames(pub KeyFlags);
+
+impl fmt::Display for ModifierNames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+
+        for (flag, name) in MODIFIER_NAMES.iter() {
+            if self.0.contains(*flag) {
+                if !first {
+                    f.write_str("+")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+
+        if first {
+            f.write_str("None")?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,28 @@
 use core::sync::atomic::{AtomicU8, Ordering};
 
+use ufmt::{uWrite, uwrite};
+
 use crate::driver::keyscanner::KeyScannerProps;
-use crate::{Error, EventHandler, Hooks, Key, KeyAddr, KeyEvent, Key_NoKey, Key_Transparent, Result, shift_to_layer};
+use crate::event_handler::{self, EventHandlerError};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::{Error, EventHandler, Hooks, LAYER, Key, KeyAddr, KeyEvent, Key_NoKey, Key_Transparent, Result, shift_to_layer};
 use crate::{KEYMAP_NEXT, KEYMAP_PREVIOUS, LAYER_MOVE_OFFSET, LAYER_SHIFT_OFFSET, LIVE_KEYS};
-#[cfg(feature = "atreus")]
-use crate::plugins::atreus::DeviceProps;
+use crate::driver::board::DeviceProps;
+use crate::driver::storage::SlotHandle;
+use crate::persistable::Persistable;
+use crate::plugins::dynamic_keymap::DYNAMIC_KEYMAP;
+
+mod layer_tap;
+pub use layer_tap::{LayerTap, DEFAULT_LAYER_TAP_TIMEOUT};
 
 #[cfg(feature = "atreus")]
 mod atreus;
 #[cfg(feature = "atreus")]
 pub use atreus::*;
+#[cfg(feature = "technomancy_atreus")]
+mod technomancy_atreus;
+#[cfg(feature = "technomancy_atreus")]
+pub use technomancy_atreus::*;
 
 pub const MAX_ACTIVE_LAYERS: usize = 16;
 pub const NUM_KEYS: usize = DeviceProps::ROWS * DeviceProps::COLS;
@@ -20,17 +33,90 @@ pub static LAYER_COUNT: AtomicU8 = AtomicU8::new(1);
 /// Macro for defining the keymap. This should be used in the sketch
 /// file (*.ino) to define the keymap[] array that holds the user's
 /// layers. It also computes the number of layers in that keymap.
+///
+/// Each layer is checked with [keymap_layer] at compile time, so a layer with the wrong
+/// number of keys fails the build instead of being read out of bounds.
 #[macro_export]
 macro_rules! keymaps {
-    {$keymap:ident, $keymaps:tt, $layers:tt} => {
+    {$keymap:ident, [$([$($key:expr),* $(,)?]),* $(,)?], $layers:tt} => {
         avr_progmem::progmem! {
-            pub static progmem $keymap: [[$crate::key_defs::Key; $crate::layers::NUM_KEYS]; $layers] = $keymaps;
+            pub static progmem $keymap: [[$crate::key_defs::Key; $crate::layers::NUM_KEYS]; $layers] = [
+                $($crate::layers::keymap_layer(&[$($key),*])),*
+            ];
         }
     }
 }
 
+/// Converts a keymap layer to a fixed-size array, checking it has [NUM_KEYS] keys.
+///
+/// Used by the [keymaps](crate::keymaps) macro. In a const context, a layer with the
+/// wrong number of keys fails to compile:
+///
+/// ```compile_fail
+/// use kaleidoscope::key_defs::*;
+/// use kaleidoscope::layers::{keymap_layer, NUM_KEYS};
+///
+/// const LAYER: [Key; NUM_KEYS] = keymap_layer(&[Key_A, Key_B]);
+/// ```
+pub const fn keymap_layer(keys: &[Key]) -> [Key; NUM_KEYS] {
+    if keys.len() != NUM_KEYS {
+        panic!("keymap layer must have exactly NUM_KEYS (ROWS * COLS) keys");
+    }
+
+    let mut layer = [Key_NoKey; NUM_KEYS];
+    let mut i = 0;
+
+    while i < NUM_KEYS {
+        layer[i] = keys[i];
+        i += 1;
+    }
+
+    layer
+}
+
 pub type ForEachHandler = fn(index: usize, layer: u8);
 
+/// How a layer is active on the layer stack.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerKind {
+    /// Toggled or moved to, active until deactivated.
+    Locked,
+    /// Momentarily shifted to, e.g. while a `MO!` key is held.
+    Shifted,
+}
+
+impl LayerKind {
+    /// Splits a layer stack entry into its unshifted layer index, and how it is active.
+    ///
+    /// Entries from `LAYER_SHIFT_OFFSET` on are shifted layers.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::key_defs::LAYER_SHIFT_OFFSET;
+    /// use kaleidoscope::layers::LayerKind;
+    ///
+    /// // Default layer 0, layer 2 toggled on, then layer 3 held.
+    /// let stack = [0, 2, 3 + LAYER_SHIFT_OFFSET];
+    ///
+    /// assert_eq!(
+    ///     stack.map(LayerKind::decode),
+    ///     [(0, LayerKind::Locked), (2, LayerKind::Locked), (3, LayerKind::Shifted)],
+    /// );
+    ///
+    /// // The same layer can be both toggled on and held.
+    /// assert_eq!(LayerKind::decode(2 + LAYER_SHIFT_OFFSET), (2, LayerKind::Shifted));
+    /// ```
+    pub const fn decode(layer: u8) -> (u8, LayerKind) {
+        if layer >= LAYER_SHIFT_OFFSET {
+            (layer - LAYER_SHIFT_OFFSET, LayerKind::Shifted)
+        } else {
+            (layer, LayerKind::Locked)
+        }
+    }
+}
+
 /// Represents active keymap layers.
 ///
 /// Used to perform layer activation/deactivation, and other layer management functions.
@@ -40,6 +126,11 @@ pub struct Layer {
     active_layer_count: usize,
     active_layers: [u8; MAX_ACTIVE_LAYERS],
     active_layer_keymap: [u8; NUM_KEYS],
+    sticky_layers: u32,
+    default_layer: u8,
+    one_shot_layer: Option<u8>,
+    layer_tap: LayerTap,
+    slot: Option<SlotHandle>,
 }
 
 impl Layer {
@@ -49,14 +140,50 @@ impl Layer {
             active_layer_count: 1,
             active_layers: [0u8; MAX_ACTIVE_LAYERS],
             active_layer_keymap: ZERO_LAYER_KEYMAP,
+            sticky_layers: 0,
+            default_layer: 0,
+            one_shot_layer: None,
+            layer_tap: LayerTap::new(),
+            slot: None,
         }
     }
 
-    /// Setup the active layers.
+    /// Setup the active layers, starting from the default layer.
     pub fn setup(&mut self) {
+        if self.default_layer as usize >= self.layer_count() {
+            self.default_layer = 0;
+        }
+
+        self.active_layer_count = 1;
+        self.active_layers[0] = self.default_layer;
+
         self.update_active_layers();
     }
 
+    /// Gets the default layer.
+    pub fn default_layer(&self) -> u8 {
+        self.default_layer
+    }
+
+    /// Sets the default layer, and moves to it.
+    ///
+    /// The default layer replaces layer 0 as the base: keys transparent on every active
+    /// layer are looked up on it, and deactivating the sole active layer moves back to it.
+    /// The choice is committed to storage, so it survives reboots.
+    pub fn set_default_layer(&mut self, layer: u8) -> Result<()> {
+        if layer as usize >= self.layer_count() {
+            return Err(Error::Layer);
+        }
+
+        self.default_layer = layer;
+
+        if self.slot.is_some() {
+            self.commit()?;
+        }
+
+        self.move_layer(layer)
+    }
+
     /// There are two lookup functions here, for historical reasons. Previously,
     /// Kaleidoscope would need to look up a value for each active keyswitch in
     /// every cycle, and pass that value on to the "event" handlers. Most of these
@@ -89,10 +216,24 @@ impl Layer {
         self.active_layer_keymap[key_addr.index()]
     }
 
-    /// Get a keymap [Key] from the PROGMEM keymap 2D-array.
+    /// Gets a keymap [Key], from the stored
+    /// [DynamicKeymap](crate::plugins::dynamic_keymap::DynamicKeymap) if it is active, or
+    /// the PROGMEM keymap 2D-array.
     pub fn key(&self, layer: usize, key_addr: &KeyAddr) -> Key {
+        match DYNAMIC_KEYMAP.read().key(layer, key_addr) {
+            Some(key) => key,
+            None => self.default_key(layer, key_addr),
+        }
+    }
+
+    /// Get a keymap [Key] from the PROGMEM keymap 2D-array.
+    pub fn default_key(&self, layer: usize, key_addr: &KeyAddr) -> Key {
         if layer >= NUM_LAYERS || !key_addr.is_valid() {
             Key_NoKey
+        } else if cfg!(debug_assertions) && key_addr.index() >= NUM_KEYS {
+            // The address is past the end of the keymap, e.g. a matrix larger than the
+            // keymap the sketch was built for.
+            Key_NoKey
         } else {
             KEYMAP_LINEAR.load_at(layer)[key_addr.index()]
         }
@@ -108,17 +249,37 @@ impl Layer {
         LAYER_COUNT.store(count as u8, Ordering::SeqCst);
     }
 
+    /// Gets the human-readable layer names, if the keymap defines them.
+    pub fn layer_names(&self) -> Option<&'static [&'static str]> {
+        LAYER_NAMES
+    }
+
+    /// Writes the layer count, followed by one line per layer, to the provided writer.
+    ///
+    /// Each layer line contains the layer index, followed by the layer name if the keymap
+    /// defines a layer-name table, see [write_layer_list]:
+    ///
+    /// ```text
+    /// 3
+    /// 0 QWERTY
+    /// 1 FUN
+    /// 2 UPPER
+    /// ```
+    pub fn write_layers<W: uWrite>(&self, w: &mut W) -> core::result::Result<(), W::Error> {
+        write_layer_list(w, self.layer_count(), self.layer_names())
+    }
+
     /// Update the active layer keymap with all non-transparent keys 
     pub fn update_active_layers(&mut self) {
         // First, set every entry in the active layer keymap to point to the default
-        // layer (layer 0).
-        self.active_layer_keymap.copy_from_slice(ZERO_LAYER_KEYMAP.as_ref());
+        // layer.
+        self.active_layer_keymap = [self.default_layer; NUM_KEYS];
 
         // For each key address, set its entry in the active layer keymap to the value
         // of the top active layer that has a non-transparent entry for that address.
         for key_addr in KeyAddr::iter() {
             for i in (0..self.active_layer_count).rev() {
-                let layer = self.unshifted(self.active_layers[i - 1]);
+                let layer = self.unshifted(self.active_layers[i]);
                 let key = self.key(layer as usize, &key_addr);
 
                 if key != Key_Transparent {
@@ -128,10 +289,9 @@ impl Layer {
             }
         }
         // Even if there are no active layers (a situation that should be prevented by
-        // `deactivate()`), each key will be mapped from the base layer (layer
-        // 0). Likewise, for any address where all active layers have a transparent
-        // entry, that key will be mapped from the base layer, even if the base layer
-        // has been deactivated.
+        // `deactivate()`), each key will be mapped from the default layer. Likewise, for
+        // any address where all active layers have a transparent entry, that key will be
+        // mapped from the default layer, even if it has been deactivated.
     }
 
     /// Handles layer key events.
@@ -215,6 +375,49 @@ impl Layer {
         Ok(())
     }
 
+    /// Handles layer-tap key events, see [LayerTap].
+    ///
+    /// The layer is shifted to on press, and deactivated on release. Returns the tap key
+    /// the caller should send, if the key was tapped.
+    pub fn handle_layer_tap_event(&mut self, event: &KeyEvent, now: u32) -> Result<Option<Key>> {
+        if event.state().key_toggled_on() {
+            if let Some(layer) = self.layer_tap.press(*event.addr(), event.key(), now) {
+                let shifted = layer + LAYER_SHIFT_OFFSET;
+
+                if self.stack_position(shifted).is_err() {
+                    self.activate(shifted)?;
+                }
+            }
+
+            return Ok(None);
+        }
+
+        if let Some((layer, _)) = LayerTap::decode(event.key()) {
+            // The layer may already be gone, e.g. after a `move_layer`.
+            let _ = self.deactivate(layer + LAYER_SHIFT_OFFSET);
+        }
+
+        Ok(self.layer_tap.release(*event.addr(), now))
+    }
+
+    /// Records the press of a key other than a held layer-tap key, resolving the
+    /// layer-tap key to its layer.
+    pub fn interrupt_layer_tap(&mut self, key_addr: KeyAddr) {
+        self.layer_tap.interrupt(key_addr);
+    }
+
+    /// Gets the time, in milliseconds, within which a released layer-tap key sends its
+    /// tap key.
+    pub fn layer_tap_timeout(&self) -> u16 {
+        self.layer_tap.timeout()
+    }
+
+    /// Sets the time, in milliseconds, within which a released layer-tap key sends its
+    /// tap key.
+    pub fn set_layer_tap_timeout(&mut self, timeout: u16) {
+        self.layer_tap.set_timeout(timeout);
+    }
+
     /// Does pretty much what `activate` does, except we do everything
     /// unconditionally, to make sure all parts of the firmware are aware of the
     /// layer change.
@@ -225,6 +428,7 @@ impl Layer {
 
         self.active_layer_count = 1;
         self.active_layers[0] = layer;
+        self.one_shot_layer = None;
 
         self.update_active_layers();
 
@@ -272,10 +476,10 @@ impl Layer {
     pub fn deactivate(&mut self, layer: u8) -> Result<()> {
         let current_pos = self.stack_position(layer)?;
 
-        // If the sole active layer is being deactivated, turn on the base layer and
+        // If the sole active layer is being deactivated, turn on the default layer and
         // return so we always have at least one layer active.
         if self.active_layer_count <= 1 {
-            self.move_layer(0)?;
+            self.move_layer(self.default_layer)?;
             return Ok(());
         }
 
@@ -288,9 +492,145 @@ impl Layer {
         Ok(())
     }
 
+    /// Activates the provided layer for the next key only.
+    ///
+    /// The layer is shifted on, and deactivated by the runtime once the next physical key,
+    /// other than a layer key, has been handled. Layer keys pressed in between are applied
+    /// on top, and leave the one-shot layer pending. If the layer is removed before the
+    /// next key (e.g. by [move_layer](Self::move_layer)), the one-shot is dropped.
+    ///
+    /// Sticky layers are not deactivated, see [set_sticky](Self::set_sticky).
+    pub fn activate_one_shot(&mut self, layer: u8) -> Result<()> {
+        let shifted = self.unshifted(layer) + LAYER_SHIFT_OFFSET;
+
+        if let Some(previous) = self.one_shot_layer.take() {
+            if previous != shifted {
+                self.auto_deactivate(previous)?;
+            }
+        }
+
+        self.activate(shifted)?;
+
+        if self.stack_position(shifted).is_ok() {
+            self.one_shot_layer = Some(shifted);
+        }
+
+        Ok(())
+    }
+
+    /// Gets the pending one-shot layer, as shifted on the stack.
+    pub fn one_shot_layer(&self) -> Option<u8> {
+        self.one_shot_layer
+    }
+
+    /// Deactivates the pending one-shot layer, if it is still active.
+    pub fn release_one_shot(&mut self) -> Result<()> {
+        match self.one_shot_layer.take() {
+            Some(layer) if self.stack_position(layer).is_ok() => self.auto_deactivate(layer),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets whether the provided layer is sticky.
+    ///
+    /// Sticky layers ignore automatic deactivation (e.g. idle timeouts, or one-shot
+    /// layers), but still respond to explicit toggles.
+    pub fn set_sticky(&mut self, layer: u8, sticky: bool) {
+        let layer = self.unshifted(layer);
+
+        if layer < 32 {
+            if sticky {
+                self.sticky_layers |= 1 << layer;
+            } else {
+                self.sticky_layers &= !(1 << layer);
+            }
+        }
+    }
+
+    /// Tests whether the provided layer is sticky.
+    pub fn is_sticky(&self, layer: u8) -> bool {
+        let layer = self.unshifted(layer);
+
+        layer < 32 && self.sticky_layers & (1 << layer) != 0
+    }
+
+    /// Deactivates the provided layer, unless it is sticky.
+    ///
+    /// Used by automatic deactivation logic, instead of [deactivate](Self::deactivate).
+    pub fn auto_deactivate(&mut self, layer: u8) -> Result<()> {
+        if self.is_sticky(layer) {
+            return Ok(());
+        }
+
+        self.deactivate(layer)
+    }
+
+    /// Deactivates every active layer that is not sticky.
+    ///
+    /// Used by automatic return-to-base logic, like idle timeouts. Always leaves at least
+    /// one layer active.
+    pub fn auto_return(&mut self) -> Result<()> {
+        let mut changed = false;
+
+        for i in (0..self.active_layer_count).rev() {
+            if self.active_layer_count <= 1 {
+                break;
+            }
+
+            if !self.is_sticky(self.active_layers[i]) {
+                self.remove(i);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.update_active_layers();
+            Hooks::on_layer_change()?;
+        }
+
+        Ok(())
+    }
+
     /// Tests whether the provided layer is active.
+    ///
+    /// A layer is active if it is on the stack either locked (unshifted), or
+    /// momentarily shifted.
     pub fn is_active(&self, layer: u8) -> bool {
-        self.stack_position(layer).is_ok() && self.stack_position(layer + LAYER_SHIFT_OFFSET).is_ok()
+        let layer = self.unshifted(layer);
+
+        self.stack_position(layer).is_ok() || self.stack_position(layer + LAYER_SHIFT_OFFSET).is_ok()
+    }
+
+    /// Tests whether the provided layer is active in exactly the provided form.
+    ///
+    /// Pass the unshifted layer index to test for a locked layer, or the index plus
+    /// `LAYER_SHIFT_OFFSET` to test for a momentarily shifted layer.
+    pub fn is_active_exact(&self, layer: u8) -> bool {
+        self.stack_position(layer).is_ok()
+    }
+
+    /// Gets a bitmask of the active layers.
+    ///
+    /// Each bit represents an unshifted layer index, and is set if that layer is active
+    /// either locked or momentarily shifted. Layers with an index above 31 are not
+    /// represented.
+    pub fn get_layer_state(&self) -> u32 {
+        let mut state = 0u32;
+
+        for &layer in self.active_layers[..self.active_layer_count].iter() {
+            let layer = self.unshifted(layer);
+
+            if layer < 32 {
+                state |= 1 << layer;
+            }
+        }
+
+        state
+    }
+
+    /// Gets the unshifted index of the most recently activated layer.
+    pub fn most_recent_layer(&self) -> u8 {
+        self.unshifted(self.last_layer())
     }
 
     /// Activates the next layer.
@@ -312,19 +652,60 @@ impl Layer {
         }
     }
 
+    /// Gets the active layers, from the bottom of the stack to the top, with how each is
+    /// active.
+    ///
+    /// Unlike [for_each_active_layer](Self::for_each_active_layer), held layers can be
+    /// told apart from toggled ones, e.g. to light layer indicators differently. A layer
+    /// both toggled on and held is yielded once for each.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::key_defs::LAYER_SHIFT_OFFSET;
+    /// use kaleidoscope::layers::{Layer, LayerKind};
+    ///
+    /// let mut layer = Layer::new();
+    /// layer.set_layer_count(4);
+    ///
+    /// // Layer 2 toggled on, then layers 3 and 2 held.
+    /// layer.activate(2).unwrap();
+    /// layer.activate(3 + LAYER_SHIFT_OFFSET).unwrap();
+    /// layer.activate(2 + LAYER_SHIFT_OFFSET).unwrap();
+    ///
+    /// let mut active = layer.active_layers_detailed();
+    /// assert_eq!(active.next(), Some((0, LayerKind::Locked)));
+    /// assert_eq!(active.next(), Some((2, LayerKind::Locked)));
+    /// assert_eq!(active.next(), Some((3, LayerKind::Shifted)));
+    /// assert_eq!(active.next(), Some((2, LayerKind::Shifted)));
+    /// assert_eq!(active.next(), None);
+    /// drop(active);
+    ///
+    /// // Releasing the held layer 2 leaves it toggled on.
+    /// layer.deactivate(2 + LAYER_SHIFT_OFFSET).unwrap();
+    /// assert_eq!(layer.most_recent_layer(), 3);
+    /// assert!(layer.active_layers_detailed().eq([
+    ///     (0, LayerKind::Locked),
+    ///     (2, LayerKind::Locked),
+    ///     (3, LayerKind::Shifted),
+    /// ]));
+    /// ```
+    pub fn active_layers_detailed(&self) -> impl Iterator<Item = (u8, LayerKind)> + '_ {
+        self.active_layers[..self.active_layer_count]
+            .iter()
+            .map(|&layer| LayerKind::decode(layer))
+    }
+
     fn last_layer(&self) -> u8 {
         self.active_layers[self.active_layer_count - 1]
     }
 
-    fn unshifted(&self, mut layer: u8) -> u8 {
-        if layer >= LAYER_SHIFT_OFFSET {
-            layer -= LAYER_SHIFT_OFFSET;
-        }
-        layer
+    fn unshifted(&self, layer: u8) -> u8 {
+        LayerKind::decode(layer).0
     }
 
     fn remove(&mut self, i: usize) {
-        self.active_layers.copy_within((i+1)..(self.active_layer_count - (i + 1)), i);
+        self.active_layers.copy_within((i + 1)..self.active_layer_count, i);
         self.active_layer_count -= 1;
     }
 
@@ -338,3 +719,245 @@ impl Layer {
         Err(Error::Layer)
     }
 }
+
+impl Persistable for Layer {
+    const SIZE: u16 = 1;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[0] = self.default_layer;
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.default_layer = buf[0];
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+/// Gets a bitmask of the active layers from the global [LAYER] state.
+///
+/// See [Layer::get_layer_state] for the bitmask layout.
+pub fn layer_state() -> u32 {
+    LAYER.read().get_layer_state()
+}
+
+/// Gets the most recently activated layer from the global [LAYER] state.
+pub fn most_recent_layer() -> u8 {
+    LAYER.read().most_recent_layer()
+}
+
+/// Writes `count`, followed by one line per layer, with the layer index and, if `names`
+/// has one, the layer name.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::focus::FocusBuffer;
+/// use kaleidoscope::layers::write_layer_list;
+///
+/// let mut out = FocusBuffer::new();
+/// write_layer_list(&mut out, 3, Some(&["QWERTY", "FUN"])).unwrap();
+/// assert_eq!(out.as_bytes(), b"3\r\n0 QWERTY\r\n1 FUN\r\n2\r\n");
+///
+/// let mut out = FocusBuffer::new();
+/// write_layer_list(&mut out, 2, None).unwrap();
+/// assert_eq!(out.as_bytes(), b"2\r\n0\r\n1\r\n");
+/// ```
+pub fn write_layer_list<W: uWrite>(
+    w: &mut W,
+    count: usize,
+    names: Option<&[&str]>,
+) -> core::result::Result<(), W::Error> {
+    uwrite!(w, "{}\r\n", count)?;
+
+    for i in 0..count {
+        match names.and_then(|n| n.get(i)) {
+            Some(name) => uwrite!(w, "{} {}\r\n", i, *name)?,
+            None => uwrite!(w, "{}\r\n", i)?,
+        }
+    }
+
+    Ok(())
+}
+
+impl EventHandler for Layer {
+    /// Handles the `device.layers` Focus command.
+    ///
+    /// Reports the layer count and the layer names, so host tools can build a layer picker.
+    fn on_focus_event(input: &str) -> event_handler::Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("device.layers\r\n");
+            return Ok(());
+        }
+
+        if command != "device.layers" {
+            return Ok(());
+        }
+
+        LAYER
+            .read()
+            .write_layers(&mut *FOCUS_OUTPUT.write())
+            .map_err(|_| EventHandlerError::Error)?;
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers() -> Layer {
+        let layer = Layer::new();
+        layer.set_layer_count(3);
+        layer
+    }
+
+    #[test]
+    fn non_zero_default_layer_is_the_base() {
+        let mut layer = layers();
+
+        assert_eq!(layer.set_default_layer(3), Err(Error::Layer));
+        assert_eq!(layer.default_layer(), 0);
+
+        layer.set_default_layer(2).unwrap();
+
+        assert_eq!(layer.default_layer(), 2);
+        assert!(layer.is_active(2));
+        assert!(!layer.is_active(0));
+
+        // Deactivating the only active layer falls back to the default layer.
+        layer.move_layer(1).unwrap();
+        layer.deactivate(1).unwrap();
+
+        assert!(layer.is_active(2));
+        assert!(!layer.is_active(1));
+
+        layer.deactivate(2).unwrap();
+        assert!(layer.is_active(2));
+    }
+
+    #[test]
+    fn one_shot_layer_deactivates_once_released() {
+        let mut layer = layers();
+        let shifted = 1 + LAYER_SHIFT_OFFSET;
+
+        layer.activate_one_shot(1).unwrap();
+
+        assert_eq!(layer.one_shot_layer(), Some(shifted));
+        assert!(layer.is_active(1));
+
+        // Released by the runtime, after the next non-layer key.
+        layer.release_one_shot().unwrap();
+
+        assert_eq!(layer.one_shot_layer(), None);
+        assert!(!layer.is_active(1));
+    }
+
+    #[test]
+    fn layer_key_before_one_shot_fires_stacks_on_top() {
+        let mut layer = layers();
+        let shifted = 1 + LAYER_SHIFT_OFFSET;
+
+        layer.activate_one_shot(1).unwrap();
+        layer.activate(2 + LAYER_SHIFT_OFFSET).unwrap();
+
+        // The one-shot layer is still pending under the new layer.
+        assert_eq!(layer.one_shot_layer(), Some(shifted));
+        assert!(layer.is_active(1));
+        assert!(layer.is_active(2));
+
+        layer.release_one_shot().unwrap();
+
+        assert!(!layer.is_active(1));
+        assert!(layer.is_active(2));
+
+        // A second one-shot replaces the pending one.
+        layer.activate_one_shot(1).unwrap();
+        layer.activate_one_shot(0).unwrap();
+
+        assert_eq!(layer.one_shot_layer(), Some(LAYER_SHIFT_OFFSET));
+        assert!(!layer.is_active(1));
+    }
+
+    #[test]
+    fn idle_return_keeps_sticky_layers() {
+        let mut layer = layers();
+
+        layer.activate(1).unwrap();
+        layer.activate(2).unwrap();
+        layer.set_sticky(2, true);
+
+        // Run by the runtime when the idle timeout expires.
+        layer.auto_return().unwrap();
+
+        assert!(!layer.is_active(1));
+        assert!(layer.is_active(2));
+
+        // Sticky layers still respond to explicit toggles.
+        layer.deactivate(2).unwrap();
+        assert!(!layer.is_active(2));
+        assert!(layer.is_active(0));
+    }
+
+    #[test]
+    fn auto_deactivate_skips_sticky_layers() {
+        let mut layer = layers();
+
+        layer.activate(1).unwrap();
+        layer.set_sticky(1, true);
+        layer.auto_deactivate(1).unwrap();
+        assert!(layer.is_active(1));
+
+        layer.set_sticky(1, false);
+        layer.auto_deactivate(1).unwrap();
+        assert!(!layer.is_active(1));
+    }
+
+    #[test]
+    fn locked_and_shifted_layers_are_active() {
+        let mut layer = layers();
+
+        layer.activate(1).unwrap();
+        layer.activate(2 + LAYER_SHIFT_OFFSET).unwrap();
+
+        assert!(layer.is_active(1));
+        assert!(layer.is_active(2));
+        assert!(layer.is_active(2 + LAYER_SHIFT_OFFSET));
+
+        assert!(layer.is_active_exact(1));
+        assert!(!layer.is_active_exact(1 + LAYER_SHIFT_OFFSET));
+        assert!(layer.is_active_exact(2 + LAYER_SHIFT_OFFSET));
+        assert!(!layer.is_active_exact(2));
+
+        layer.deactivate(2 + LAYER_SHIFT_OFFSET).unwrap();
+        assert!(!layer.is_active(2));
+    }
+
+    #[test]
+    fn layer_state_tracks_active_layers() {
+        let mut layer = layers();
+
+        assert_eq!(layer.get_layer_state(), 0b001);
+        assert_eq!(layer.most_recent_layer(), 0);
+
+        layer.activate(2).unwrap();
+        layer.activate(1 + LAYER_SHIFT_OFFSET).unwrap();
+
+        assert_eq!(layer.get_layer_state(), 0b111);
+        assert_eq!(layer.most_recent_layer(), 1);
+
+        layer.deactivate(1 + LAYER_SHIFT_OFFSET).unwrap();
+
+        assert_eq!(layer.get_layer_state(), 0b101);
+        assert_eq!(layer.most_recent_layer(), 2);
+    }
+}
@@ -1,15 +1,20 @@
 #![allow(non_upper_case_globals)]
 #![allow(dead_code)]
 
-use crate::{key_defs::*, keymaps};
+use crate::{driver::board::{BoardProps, DeviceProps}, key_defs::*, keymaps};
+use crate::plugins::macros::MACRO_VERSION_INFO;
 
 pub const QWERTY: u8 = 0;
 pub const FUN: u8 = 1;
 pub const UPPER: u8 = 2;
 
 const MACRO_QWERTY: u8 = 0;
-const MACRO_VERSION_INFO: u8 = 1;
-pub const NUM_LAYERS: usize = 3;
+pub const NUM_LAYERS: usize = DeviceProps::NUM_LAYERS;
+
+/// Human-readable layer names, indexed by layer number.
+///
+/// Set to `None` if the keymap does not name its layers.
+pub const LAYER_NAMES: Option<&[&str]> = Some(&["QWERTY", "FUN", "UPPER"]);
 
 pub const Key_Exclamation: Key = lshift!(Key_1);
 pub const Key_At: Key = lshift!(Key_2);
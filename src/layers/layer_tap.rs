@@ -0,0 +1,151 @@
+use crate::plugins::ranges::{LAYER_TAP_FIRST, LAYER_TAP_LAST};
+use crate::{key_ext::KeyExt, Key, KeyAddr, KeyFlags};
+
+/// Default time, in milliseconds, within which a released layer-tap key sends its tap key.
+pub const DEFAULT_LAYER_TAP_TIMEOUT: u16 = 200;
+
+/// Creates a layer-tap key, shifting to `layer` while held, and sending `key` when tapped.
+///
+/// Unlike the Qukeys `LT!`, the layer is shifted to as soon as the key is pressed, so
+/// other keys are never held back.
+#[macro_export]
+macro_rules! LTAP {
+    ($layer:expr, $key:expr) => {
+        $crate::key_defs::Key::from_raw(
+            $crate::plugins::ranges::LAYER_TAP_FIRST + (($layer as u16) << 8) + $key.key_code() as u16,
+        )
+    };
+}
+
+/// A held layer-tap key, that may still send its tap key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PendingTap {
+    addr: KeyAddr,
+    tap: Key,
+    start_time: u32,
+    interrupted: bool,
+}
+
+/// Resolves layer-tap keys to a tap or a hold.
+///
+/// A layer-tap key shifts to its layer when pressed, like `MO!`. When it is released, it
+/// also sends its tap key, unless it was held for the timeout or longer, or another key
+/// was pressed in between. Keys pressed during the hold are looked up on the shifted
+/// layer.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_addr::KeyAddr, key_defs::*, LTAP};
+/// use kaleidoscope::layers::LayerTap;
+///
+/// let key = LTAP!(2, Key_Spacebar);
+/// assert_eq!(LayerTap::decode(&key), Some((2, Key_Spacebar)));
+///
+/// let (addr, other) = (KeyAddr::new(0), KeyAddr::new(1));
+/// let mut layer_tap = LayerTap::new();
+///
+/// // Tapped: the layer is shifted to while held, and the tap key sent on release.
+/// assert_eq!(layer_tap.press(addr, &key, 1000), Some(2));
+/// assert!(layer_tap.is_held());
+/// assert_eq!(layer_tap.release(addr, 1100), Some(Key_Spacebar));
+/// assert!(!layer_tap.is_held());
+///
+/// // Held past the timeout: only the layer.
+/// layer_tap.press(addr, &key, 2000);
+/// assert_eq!(layer_tap.release(addr, 2000 + layer_tap.timeout() as u32), None);
+///
+/// // Another key pressed during the hold: only the layer, even if released quickly.
+/// layer_tap.press(addr, &key, 3000);
+/// layer_tap.interrupt(other);
+/// assert_eq!(layer_tap.release(addr, 3050), None);
+///
+/// assert_eq!(layer_tap.press(addr, &Key_A, 4000), None);
+/// ```
+pub struct LayerTap {
+    timeout: u16,
+    pending: Option<PendingTap>,
+}
+
+impl LayerTap {
+    /// Creates a new [LayerTap] with the default timeout.
+    pub const fn new() -> Self {
+        Self {
+            timeout: DEFAULT_LAYER_TAP_TIMEOUT,
+            pending: None,
+        }
+    }
+
+    /// Gets the layer and tap key of a layer-tap key, `None` for other keys.
+    pub fn decode(key: &Key) -> Option<(u8, Key)> {
+        let raw = key.raw();
+
+        if !(LAYER_TAP_FIRST..LAYER_TAP_LAST).contains(&raw) {
+            return None;
+        }
+
+        let offset = raw - LAYER_TAP_FIRST;
+
+        Some(((offset >> 8) as u8, Key::keyboard((offset & 0xff) as u8, KeyFlags::NONE)))
+    }
+
+    /// Gets the time, in milliseconds, within which a released layer-tap key sends its
+    /// tap key.
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Sets the time, in milliseconds, within which a released layer-tap key sends its
+    /// tap key.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout;
+    }
+
+    /// Gets whether a layer-tap key is held.
+    pub fn is_held(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Records the press of `key` at `now` milliseconds.
+    ///
+    /// Returns the layer to shift to, `None` if `key` is not a layer-tap key. A layer-tap
+    /// key pressed while another is held resolves the held one to its layer.
+    pub fn press(&mut self, addr: KeyAddr, key: &Key, now: u32) -> Option<u8> {
+        let (layer, tap) = Self::decode(key)?;
+
+        self.pending = Some(PendingTap {
+            addr,
+            tap,
+            start_time: now,
+            interrupted: false,
+        });
+
+        Some(layer)
+    }
+
+    /// Records the press of another key at `addr`, resolving the held layer-tap key to its
+    /// layer.
+    pub fn interrupt(&mut self, addr: KeyAddr) {
+        if let Some(pending) = self.pending.as_mut() {
+            if pending.addr != addr {
+                pending.interrupted = true;
+            }
+        }
+    }
+
+    /// Records the release of the key at `addr`, at `now` milliseconds.
+    ///
+    /// Returns the tap key to send, if the held layer-tap key was tapped.
+    pub fn release(&mut self, addr: KeyAddr, now: u32) -> Option<Key> {
+        match self.pending {
+            Some(pending) if pending.addr == addr => {
+                self.pending = None;
+
+                let tapped = !pending.interrupted && now.wrapping_sub(pending.start_time) < self.timeout as u32;
+
+                tapped.then_some(pending.tap)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+#![allow(non_upper_case_globals)]
+#![allow(dead_code)]
+
+use crate::{driver::board::{BoardProps, DeviceProps}, key_defs::*, keymaps};
+
+pub const QWERTY: u8 = 0;
+pub const FUN: u8 = 1;
+pub const NUM_LAYERS: usize = DeviceProps::NUM_LAYERS;
+
+/// Human-readable layer names, indexed by layer number.
+///
+/// Set to `None` if the keymap does not name its layers.
+pub const LAYER_NAMES: Option<&[&str]> = Some(&["QWERTY", "FUN"]);
+
+pub const Key_LeftParen: Key = lshift!(Key_9);
+pub const Key_RightParen: Key = lshift!(Key_0);
+
+// The middle column only has keys on the bottom row. The last column pads the keymap to
+// the 4x12 KeyAddr bounds, and has no switches.
+#[rustfmt::skip(keymaps)]
+keymaps! {
+    KEYMAP_LINEAR,
+    [
+        [   // QWERTY
+            Key_Q,      Key_W,   Key_E,       Key_R,         Key_T,         XXX,             Key_Y,        Key_U,    Key_I,     Key_O,      Key_P,         XXX,
+            Key_A,      Key_S,   Key_D,       Key_F,         Key_G,         XXX,             Key_H,        Key_J,    Key_K,     Key_L,      Key_Semicolon, XXX,
+            Key_Z,      Key_X,   Key_C,       Key_V,         Key_B,         XXX,             Key_N,        Key_M,    Key_Comma, Key_Period, Key_Slash,     XXX,
+            Key_Escape, Key_Tab, Key_LeftGui, Key_LeftShift, Key_Backspace, Key_LeftControl, Key_Spacebar, MO!(FUN), Key_Minus, Key_Quote,  Key_Enter,     XXX,
+        ],
+
+        [   // FUN
+            Key_1,         Key_2,         Key_UpArrow,   Key_4,          Key_5,      XXX,             Key_PageUp,   Key_7, Key_8,      Key_9, Key_Backspace, XXX,
+            Key_LeftParen, Key_LeftArrow, Key_DownArrow, Key_RightArrow, Key_RightParen, XXX,         Key_PageDown, Key_4, Key_5,      Key_6, ___,           XXX,
+            Key_LeftBracket, Key_RightBracket, Key_3,    Key_Backtick,   Key_Backslash, XXX,          Key_Equals,   Key_1, Key_2,      Key_3, Key_Enter,     XXX,
+            ___,           Key_Insert,    Key_LeftGui,   Key_LeftShift,  Key_Delete, Key_LeftControl, Key_Spacebar, ___,  Key_Period, Key_0, Key_Equals,     XXX,
+        ],
+    ],
+    NUM_LAYERS
+}
@@ -3,9 +3,9 @@
 #![cfg_attr(target_arch = "avr", feature(asm_experimental_arch))]
 
 use arduino_hal::pac;
-use avr_device::interrupt::{CriticalSection, Mutex};
+use avr_device::interrupt::{self, CriticalSection};
 use keyboardio_hid::{KeyboardUsbBus, KeyboardUsbBusAllocator};
-use keyboardio_hid::usb_device::device::{UsbDevice, UsbDeviceBuilder, UsbVidPid};
+use keyboardio_hid::usb_device::device::{UsbDevice, UsbDeviceBuilder};
 
 #[macro_use(bitfield)]
 extern crate bitfield;
@@ -16,7 +16,7 @@ extern crate kaleidoscope_internal;
 
 pub use kaleidoscope_internal::{device, hid_tables, key_addr, key_defs, matrix_addr};
 
-/// Atomic helper structs for atomic integral types larger than 16-bits
+/// Atomic helper structs for atomic integral types larger than 8-bits
 pub mod atomic;
 /// Bootloader utilities
 pub mod bootloader;
@@ -28,12 +28,22 @@ pub mod error;
 pub mod ffi;
 /// Event handler trait definition
 pub mod event_handler;
+/// Focus protocol helpers
+pub mod focus;
 /// Event hook definitions
 pub mod hooks;
+/// Interrupt-safe cell for global peripherals
+pub mod irq_cell;
+/// Key address helpers
+pub mod key_addr_ext;
 /// Key address map definitions
 pub mod key_addr_map;
 /// Key event definitions
 pub mod key_event;
+/// Key constructors and helpers
+pub mod key_ext;
+/// Key modifier flag combinators
+pub mod key_flags_ext;
 /// Key map definitions
 pub mod key_map;
 /// Keyswitch state definitions
@@ -47,10 +57,14 @@ pub mod lock;
 mod macros;
 /// millis implementation based on the [avr-hal/uno-millis](https://github.com/Rahix/avr-hal/blob/main/examples/arduino-uno/src/bin/uno-millis.rs) example
 pub mod millis;
+/// Persistable plugin state
+pub mod persistable;
 /// Board-specific plugins
 pub mod plugins;
 /// Runtime definitions
 pub mod runtime;
+/// Sketch description passed to plugins at setup
+pub mod sketch;
 /// Various utilities
 pub mod util;
 
@@ -59,28 +73,37 @@ pub use event_handler::*;
 pub use ffi::*;
 pub use hooks::*;
 pub use key_addr::*;
+pub use key_addr_ext::KeyAddrExt;
 pub use key_defs::*;
 pub use key_event::*;
+pub use key_ext::KeyExt;
+pub use key_flags_ext::KeyFlagsExt;
 pub use key_map::*;
 pub use layers::*;
 pub use live_keys::*;
 pub use millis::*;
+pub use driver::serial::init_usb_serial;
 pub use runtime::Runtime;
 
-use driver::hid::{ActiveKeyboard, HIDKeyboard};
+use driver::hid::{ActiveKeyboard, HIDKeyboard, ProtocolObserver};
 pub use error::{Error, Result};
+use irq_cell::{IrqCell, IrqOnce};
 
-pub static mut CPU: Option<Mutex<pac::CPU>> = None;
-pub static mut TC1: Option<Mutex<pac::TC1>> = None;
-pub static mut WDT: Option<Mutex<pac::WDT>> = None;
+pub static CPU: IrqCell<pac::CPU> = IrqCell::new();
+pub static TC1: IrqCell<pac::TC1> = IrqCell::new();
+pub static WDT: IrqCell<pac::WDT> = IrqCell::new();
+pub static EEPROM: IrqCell<pac::EEPROM> = IrqCell::new();
 
-pub static mut HID: Option<HIDKeyboard> = None;
-pub static mut USB: Option<KeyboardUsbBusAllocator> = None;
-pub static mut USB_DEVICE: Option<UsbDevice<'static, KeyboardUsbBus>> = None;
+pub static HID: IrqCell<HIDKeyboard> = IrqCell::new();
+// Borrowed for `'static` by the USB device and classes, so never replaced.
+pub static USB: IrqOnce<KeyboardUsbBusAllocator> = IrqOnce::new();
+pub static USB_DEVICE: IrqCell<UsbDevice<'static, KeyboardUsbBus>> = IrqCell::new();
 
-pub static RUNTIME: lock::Spinlock<Runtime> = lock::Spinlock::new(Runtime::new());
+pub static RUNTIME: lock::Spinlock<Runtime> = lock::Spinlock::new(Runtime::new(driver::board::Device::new()));
 pub static LIVE_KEYS: lock::Spinlock<LiveKeys> = lock::Spinlock::new(LiveKeys::new());
 pub static LAYER: lock::Spinlock<Layer> = lock::Spinlock::new(Layer::new());
+// Recorded by the runtime and plugins alike, so kept out of the runtime itself.
+pub static LAST_ERROR: lock::Spinlock<runtime::LastError> = lock::Spinlock::new(runtime::LastError::new());
 
 #[allow(dead_code)]
 type RX = atmega_hal::port::Pin<atmega_hal::port::mode::Input, atmega_hal::port::PD2>;
@@ -92,48 +115,78 @@ type Clock = arduino_hal::DefaultClock;
 type Serial = atmega_hal::usart::Usart<atmega_hal::pac::USART1, RX, TX, Clock>;
 
 pub fn init_cpu(cpu: pac::CPU) {
-    unsafe { CPU.replace(Mutex::new(cpu)); }
+    let _ = CPU.set(cpu);
 }
 
-pub fn cpu() -> Result<&'static Mutex<pac::CPU>> {
-    unsafe { CPU.as_ref().ok_or(Error::CPU) }
+/// Calls `f` with the CPU registers, with interrupts disabled.
+pub fn with_cpu<R, F: FnOnce(&mut pac::CPU) -> R>(f: F) -> Result<R> {
+    CPU.with(f).ok_or(Error::CPU)
 }
 
 pub fn init_usb(usb: pac::USB_DEVICE) {
-    unsafe { USB.replace(KeyboardUsbBus::new(usb)); }
+    let _ = USB.set(KeyboardUsbBus::new(usb));
 }
 
 pub fn usb() -> Result<&'static KeyboardUsbBusAllocator> {
-    unsafe { USB.as_ref().ok_or(Error::USB) }
+    USB.get().ok_or(Error::USB)
 }
 
 pub fn init_usb_device(usb_bus: &'static KeyboardUsbBusAllocator) {
-    let usb_device = attach_to_host(usb_bus);
-
-    unsafe { USB_DEVICE.replace(usb_device); }
-}
-
-pub fn usb_device() -> Result<&'static UsbDevice<'static, KeyboardUsbBus>> {
-    unsafe { USB_DEVICE.as_ref().ok_or(Error::USB) }
-}
-
-pub fn usb_device_mut() -> Result<&'static mut UsbDevice<'static, KeyboardUsbBus>> {
-    unsafe { USB_DEVICE.as_mut().ok_or(Error::USB) }
+    if let Err(usb_device) = USB_DEVICE.set(attach_to_host(usb_bus)) {
+        // Re-attaching: the previous device is replaced under the exclusive borrow.
+        let _ = USB_DEVICE.with(|current| *current = usb_device);
+    }
 }
 
-/// Attaches the device to the host.
+/// Calls `f` with the USB device, with interrupts disabled.
+pub fn with_usb_device<R, F: FnOnce(&mut UsbDevice<'static, KeyboardUsbBus>) -> R>(f: F) -> Result<R> {
+    USB_DEVICE.with(f).ok_or(Error::USB)
+}
+
+/// Polls the USB device with every USB class, to be called from the USB interrupts.
+pub fn poll_usb() {
+    interrupt::free(|cs| {
+        let (Some(mut usb_device), Some(mut hid), Some(mut serial)) = (
+            USB_DEVICE.borrow_mut(cs),
+            HID.borrow_mut(cs),
+            driver::serial::USB_SERIAL.borrow_mut(cs),
+        ) else {
+            return;
+        };
+        let hid = &mut *hid;
+
+        usb_device.poll(&mut [
+            &mut ProtocolObserver,
+            hid.boot_keyboard.hid_class_mut(),
+            hid.nkro_keyboard.hid_class_mut(),
+            hid.media_keyboard.hid_class_mut(),
+            hid.system_control_keyboard.hid_class_mut(),
+            &mut hid.mouse,
+            &mut hid.absolute_mouse,
+            &mut *serial,
+        ]);
+    });
+}
+
+/// Attaches the device to the host, using the current [USB_IDENTITY](driver::hid::settings::USB_IDENTITY),
+/// and the board's [USB_POWER](driver::board::BoardProps::USB_POWER).
 pub fn attach_to_host(
     usb_bus: &'static KeyboardUsbBusAllocator,
 ) -> UsbDevice<'static, KeyboardUsbBus> {
-    use driver::hid::settings;
+    use driver::board::{BoardProps, DeviceProps};
+
+    let identity = *driver::hid::settings::USB_IDENTITY.read();
+    let power = <DeviceProps as BoardProps>::USB_POWER;
 
     // Creating the UsbDevice freezes allocation, and calls UsbBus::enable.
     // UsbBus::enable clears the UDCON::detach bit.
-    let usb_vid_pid = UsbVidPid(settings::USB_VID, settings::USB_PID);
-
-    UsbDeviceBuilder::new(usb_bus, usb_vid_pid)
-        .manufacturer(settings::MANUFACTURER)
-        .product(settings::PRODUCT)
+    UsbDeviceBuilder::new(usb_bus, identity.vid_pid())
+        .manufacturer(identity.manufacturer)
+        .product(identity.product)
+        .serial_number(driver::signature::serial_number())
+        .max_power(power.max_power_ma() as usize)
+        .self_powered(power.self_powered())
+        .supports_remote_wakeup(power.remote_wakeup())
         .build()
 }
 
@@ -141,42 +194,53 @@ pub fn attach_to_host(
 ///
 /// After re-attaching, all state is reset the originally configured values.
 pub fn detach_from_host() -> Result<()> {
-    unsafe {
-        USB_DEVICE
-            .as_mut()
-            .ok_or(Error::USB)?
-            .force_reset()?;
-    }
+    with_usb_device(|usb_device| usb_device.force_reset())??;
 
     Ok(())
 }
 
 pub fn init_hid(usb_bus: &'static KeyboardUsbBusAllocator) {
-    unsafe { HID.replace(HIDKeyboard::new(usb_bus, ActiveKeyboard::Boot)); }
+    let _ = HID.set(HIDKeyboard::new(usb_bus, ActiveKeyboard::Boot));
 }
 
-pub fn hid() -> Result<&'static HIDKeyboard<'static>> {
-    unsafe { HID.as_ref().ok_or(Error::HID) }
+/// Calls `f` with the HID keyboard, with interrupts disabled.
+///
+/// The USB interrupt polls the HID classes, so the keyboard is only reachable for the
+/// duration of `f`.
+pub fn with_hid<R, F: FnOnce(&mut HIDKeyboard<'static>) -> R>(f: F) -> Result<R> {
+    HID.with(f).ok_or(Error::HID)
 }
 
-pub fn hid_mut() -> Result<&'static mut HIDKeyboard<'static>> {
-    unsafe { HID.as_mut().ok_or(Error::HID) }
+/// Like [with_hid], for HID keyboard calls which can fail.
+pub fn try_with_hid<R, F: FnOnce(&mut HIDKeyboard<'static>) -> Result<R>>(f: F) -> Result<R> {
+    with_hid(f)?
 }
 
 pub fn init_tc1(tc1: pac::TC1) {
-    unsafe { TC1.replace(Mutex::new(tc1)); }
+    let _ = TC1.set(tc1);
 }
 
-pub fn tc1() -> Result<&'static Mutex<pac::TC1>> {
-    unsafe { TC1.as_ref().ok_or(Error::TC1) }
+/// Calls `f` with the TC1 timer registers, with interrupts disabled.
+pub fn with_tc1<R, F: FnOnce(&mut pac::TC1) -> R>(f: F) -> Result<R> {
+    TC1.with(f).ok_or(Error::TC1)
 }
 
 pub fn init_wdt(wdt: pac::WDT) {
-    unsafe { WDT.replace(Mutex::new(wdt)); }
+    let _ = WDT.set(wdt);
+}
+
+/// Calls `f` with the watchdog registers, with interrupts disabled.
+pub fn with_wdt<R, F: FnOnce(&mut pac::WDT) -> R>(f: F) -> Result<R> {
+    WDT.with(f).ok_or(Error::WDT)
+}
+
+pub fn init_eeprom(eeprom: pac::EEPROM) {
+    let _ = EEPROM.set(eeprom);
 }
 
-pub fn wdt() -> Result<&'static Mutex<pac::WDT>> {
-    unsafe { WDT.as_ref().ok_or(Error::WDT) }
+/// Calls `f` with the EEPROM registers, with interrupts disabled.
+pub fn with_eeprom<R, F: FnOnce(&mut pac::EEPROM) -> R>(f: F) -> Result<R> {
+    EEPROM.with(f).ok_or(Error::EEPROM)
 }
 
 // SAFETY: this function should only be called after disabling interrupts
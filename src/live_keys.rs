@@ -55,6 +55,15 @@ impl LiveKeys {
         }
     }
 
+    /// Mask every active entry, so keys held now are ignored until released.
+    pub fn mask_all(&mut self) {
+        for key in self.key_map.iter_mut() {
+            if Self::is_active(*key) {
+                *key = Key_Masked;
+            }
+        }
+    }
+
     /// Clear the entire array by setting all values to [KEY_INACTIVE].
     pub fn clear_all(&mut self) {
         for key_addr in self.key_map.iter_mut() {
@@ -62,6 +71,101 @@ impl LiveKeys {
         }
     }
 
+    /// Counts the active entries, i.e. neither [KEY_INACTIVE] nor [KEY_MASKED].
+    ///
+    /// Masked keys are excluded, the same way they are left out of the HID reports.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{Key_A, Key_B, KeyAddr, LiveKeys};
+    ///
+    /// let mut live_keys = LiveKeys::new();
+    /// live_keys.clear_all();
+    ///
+    /// live_keys.activate(KeyAddr::create(0, 0), Key_A);
+    /// live_keys.activate(KeyAddr::create(0, 1), Key_B);
+    /// live_keys.mask(KeyAddr::create(1, 0));
+    /// assert_eq!(live_keys.active_count(), 2);
+    ///
+    /// live_keys.clear(KeyAddr::create(0, 0));
+    /// assert_eq!(live_keys.active_count(), 1);
+    /// assert!(live_keys.any_active());
+    /// ```
+    pub fn active_count(&self) -> usize {
+        KeyAddr::iter()
+            .filter(|&key_addr| Self::is_active(self[key_addr]))
+            .count()
+    }
+
+    /// Gets whether the entry at `key_addr` is active, i.e. neither [KEY_INACTIVE] nor
+    /// [KEY_MASKED].
+    ///
+    /// A masked key is usually still held down, but it is not sending anything, and it
+    /// stays masked until it is released. It is therefore not considered pressed, the
+    /// same way it is left out of the HID reports. Invalid addresses are never pressed.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{Key_A, KeyAddr, LiveKeys};
+    ///
+    /// let mut live_keys = LiveKeys::new();
+    /// live_keys.clear_all();
+    ///
+    /// let addr = KeyAddr::create(1, 2);
+    /// assert!(!live_keys.is_pressed(addr));
+    ///
+    /// live_keys.activate(addr, Key_A);
+    /// assert!(live_keys.is_pressed(addr));
+    ///
+    /// live_keys.mask(addr);
+    /// assert!(!live_keys.is_pressed(addr));
+    ///
+    /// live_keys.activate(addr, Key_A);
+    /// live_keys.clear(addr);
+    /// assert!(!live_keys.is_pressed(addr));
+    ///
+    /// assert!(!live_keys.is_pressed(KeyAddr::default()));
+    /// ```
+    pub fn is_pressed(&self, key_addr: KeyAddr) -> bool {
+        Self::is_active(self[key_addr])
+    }
+
+    /// Gets whether any entry is active, stopping at the first one.
+    pub fn any_active(&self) -> bool {
+        KeyAddr::iter().any(|key_addr| Self::is_active(self[key_addr]))
+    }
+
+    /// Returns an iterator over the active entries, and their addresses.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{Key_A, Key_B, KeyAddr, LiveKeys};
+    ///
+    /// let mut live_keys = LiveKeys::new();
+    /// live_keys.clear_all();
+    ///
+    /// live_keys.activate(KeyAddr::create(0, 1), Key_A);
+    /// live_keys.activate(KeyAddr::create(2, 3), Key_B);
+    ///
+    /// let mut active = live_keys.iter_active();
+    /// assert_eq!(active.next(), Some((KeyAddr::create(0, 1), Key_A)));
+    /// assert_eq!(active.next(), Some((KeyAddr::create(2, 3), Key_B)));
+    /// assert_eq!(active.next(), None);
+    /// ```
+    pub fn iter_active(&self) -> LiveKeysActiveIter {
+        LiveKeysActiveIter {
+            live_keys: self,
+            key_addr: KeyAddr::new(0),
+        }
+    }
+
+    fn is_active(key: Key) -> bool {
+        key != Key_Inactive && key != Key_Masked
+    }
+
     /// Returns an iterator for use in range-based for loops.
     ///
     /// Example:
@@ -97,6 +201,30 @@ impl LiveKeys {
     }
 }
 
+/// Iterator over the active entries of [LiveKeys], see [LiveKeys::iter_active].
+pub struct LiveKeysActiveIter<'l> {
+    live_keys: &'l LiveKeys,
+    key_addr: KeyAddr,
+}
+
+impl<'l> Iterator for LiveKeysActiveIter<'l> {
+    type Item = (KeyAddr, Key);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.key_addr.is_valid() {
+            let key_addr = self.key_addr;
+            self.key_addr += 1;
+
+            let key = self.live_keys[key_addr];
+            if LiveKeys::is_active(key) {
+                return Some((key_addr, key));
+            }
+        }
+
+        None
+    }
+}
+
 impl Index<KeyAddr> for LiveKeys {
     type Output = Key;
 
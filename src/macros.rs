@@ -8,3 +8,241 @@ macro_rules! return_on_err {
         }
     };
 }
+
+/// Like [return_on_err], but records the error in `$last_error` (a
+/// [LastError](crate::runtime::LastError)) before returning.
+///
+/// The error is converted with `Into<Error>`, so both [Error](crate::Error) and
+/// [EventHandlerError](crate::event_handler::EventHandlerError) results are accepted.
+#[macro_export]
+macro_rules! record_on_err {
+    ($last_error:expr, $errfn:expr) => {
+        match $errfn {
+            Ok(val) => val,
+            Err(err) => {
+                $last_error.record(err.into());
+                return;
+            }
+        }
+    };
+}
+
+/// Implements [EventHandler](crate::event_handler::EventHandler) for `$hooks`, dispatching
+/// every handler to the listed plugins.
+///
+/// Plugins are called in declaration order, which is fixed at compile time, except for
+/// the `on_keyswitch_event()` and `on_key_event()` handlers, which call plugins by
+/// decreasing [PRIORITY](crate::event_handler::EventHandler::PRIORITY) first, and in
+/// declaration order between plugins of the same priority. The first
+/// plugin returning an error (including [EventConsumed](crate::event_handler::EventHandlerError::EventConsumed)
+/// and [Abort](crate::event_handler::EventHandlerError::Abort)) stops the dispatch, and
+/// the error is returned to the caller, except for the observation-only
+/// `on_physical_key()`, which is always dispatched to every plugin. Entries can be gated with `#[cfg(...)]`. The
+/// macro also defines `$hooks::PLUGIN_NAMES`, the names of the enabled plugins, in
+/// declaration order.
+///
+/// Example:
+///
+/// ```rust
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use kaleidoscope::event_handler::{EventHandler, EventHandlerError, Result};
+/// use kaleidoscope::kaleidoscope_plugins;
+///
+/// // Each plugin appends its digit: `123` means First, Consumer, then Last.
+/// static CALLS: AtomicU32 = AtomicU32::new(0);
+///
+/// fn call(digit: u32) {
+///     CALLS.store(CALLS.load(Ordering::Relaxed) * 10 + digit, Ordering::Relaxed);
+/// }
+///
+/// struct First;
+/// struct Consumer;
+/// struct Last;
+///
+/// impl EventHandler for First {
+///     fn on_focus_event(_: &str) -> Result<()> {
+///         call(1);
+///         Ok(())
+///     }
+/// }
+///
+/// impl EventHandler for Consumer {
+///     fn on_focus_event(input: &str) -> Result<()> {
+///         call(2);
+///         if input == "consume" {
+///             return Err(EventHandlerError::EventConsumed);
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// impl EventHandler for Last {
+///     fn on_focus_event(_: &str) -> Result<()> {
+///         call(3);
+///         Ok(())
+///     }
+/// }
+///
+/// struct MyHooks;
+///
+/// kaleidoscope_plugins![MyHooks; First, Consumer, Last];
+///
+/// assert_eq!(MyHooks::PLUGIN_NAMES, &["First", "Consumer", "Last"]);
+///
+/// assert_eq!(MyHooks::on_focus_event("help"), Ok(()));
+/// assert_eq!(CALLS.swap(0, Ordering::Relaxed), 123);
+///
+/// assert_eq!(MyHooks::on_focus_event("consume"), Err(EventHandlerError::EventConsumed));
+/// assert_eq!(CALLS.swap(0, Ordering::Relaxed), 12);
+/// ```
+#[macro_export]
+macro_rules! kaleidoscope_plugins {
+    ($hooks:ty; $($(#[$meta:meta])* $plugin:ident $(<$($generic:ty),+>)?),+ $(,)?) => {
+        impl $hooks {
+            /// Names of the registered plugins, in dispatch order.
+            pub const PLUGIN_NAMES: &'static [&'static str] = &[
+                $($(#[$meta])* stringify!($plugin),)+
+            ];
+
+            /// Priorities of the registered plugins, highest first.
+            const PLUGIN_PRIORITIES: &'static [i8] = &$crate::event_handler::sort_priorities([
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PRIORITY,)+
+            ]);
+        }
+
+        impl $crate::event_handler::EventHandler for $hooks {
+            fn on_setup() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_setup()?;)+
+                Ok(())
+            }
+
+            fn on_host_connected() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_host_connected()?;)+
+                Ok(())
+            }
+
+            fn before_each_cycle() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::before_each_cycle()?;)+
+                Ok(())
+            }
+
+            fn on_keyswitch_event(event: &mut $crate::key_event::KeyEvent) -> $crate::event_handler::Result<()> {
+                let priorities = Self::PLUGIN_PRIORITIES;
+
+                for (i, &priority) in priorities.iter().enumerate() {
+                    // Each priority once, plugins sharing it are called in declaration order.
+                    if i > 0 && priorities[i - 1] == priority {
+                        continue;
+                    }
+
+                    $(
+                        $(#[$meta])*
+                        if <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PRIORITY == priority
+                            && (<$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PROCESS_INJECTED || !event.is_injected())
+                        {
+                            <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_keyswitch_event(event)?;
+                        }
+                    )+
+                }
+
+                Ok(())
+            }
+
+            fn on_physical_key(
+                addr: $crate::key_addr::KeyAddr,
+                state: $crate::keyswitch_state::KeyswitchState,
+            ) -> $crate::event_handler::Result<()> {
+                // Observation only: every plugin sees the toggle, errors are ignored.
+                $($(#[$meta])* let _ = <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_physical_key(addr, state);)+
+                Ok(())
+            }
+
+            fn on_key_event(event: &mut $crate::key_event::KeyEvent) -> $crate::event_handler::Result<()> {
+                let priorities = Self::PLUGIN_PRIORITIES;
+
+                for (i, &priority) in priorities.iter().enumerate() {
+                    // Each priority once, plugins sharing it are called in declaration order.
+                    if i > 0 && priorities[i - 1] == priority {
+                        continue;
+                    }
+
+                    $(
+                        $(#[$meta])*
+                        if <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PRIORITY == priority
+                            && (<$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PROCESS_INJECTED || !event.is_injected())
+                        {
+                            <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_key_event(event)?;
+                        }
+                    )+
+                }
+
+                Ok(())
+            }
+
+            fn on_add_to_report(key: $crate::key_defs::Key) -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_add_to_report(key)?;)+
+                Ok(())
+            }
+
+            fn on_focus_event(input: &str) -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_focus_event(input)?;)+
+                Ok(())
+            }
+
+            fn on_layer_change() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_layer_change()?;)+
+                Ok(())
+            }
+
+            fn on_host_led_change(leds: u8) -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_host_led_change(leds)?;)+
+                Ok(())
+            }
+
+            fn on_led_mode_change() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_led_mode_change()?;)+
+                Ok(())
+            }
+
+            fn before_syncing_leds() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::before_syncing_leds()?;)+
+                Ok(())
+            }
+
+            fn before_reporting_state(event: &$crate::key_event::KeyEvent) -> $crate::event_handler::Result<()> {
+                $(
+                    $(#[$meta])*
+                    if <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PROCESS_INJECTED || !event.is_injected() {
+                        <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::before_reporting_state(event)?;
+                    }
+                )+
+                Ok(())
+            }
+
+            fn after_reporting_state(event: &$crate::key_event::KeyEvent) -> $crate::event_handler::Result<()> {
+                $(
+                    $(#[$meta])*
+                    if <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::PROCESS_INJECTED || !event.is_injected() {
+                        <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::after_reporting_state(event)?;
+                    }
+                )+
+                Ok(())
+            }
+
+            fn on_idle() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::on_idle()?;)+
+                Ok(())
+            }
+
+            fn after_each_cycle() -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::after_each_cycle()?;)+
+                Ok(())
+            }
+
+            fn explore_sketch(sketch: &$crate::sketch::Sketch) -> $crate::event_handler::Result<()> {
+                $($(#[$meta])* <$plugin $(<$($generic),+>)? as $crate::event_handler::EventHandler>::explore_sketch(sketch)?;)+
+                Ok(())
+            }
+        }
+    };
+}
@@ -4,16 +4,10 @@
 
 use panic_halt as _;
 
-use kaleidoscope::{return_on_err, hid_mut, usb_device_mut};
-
 #[arduino_hal::entry]
 fn main() -> ! {
     let dp = arduino_hal::Peripherals::take().expect("failed to get peripherals");
 
-    let pins = arduino_hal::pins!(dp);
-
-    let _serial = arduino_hal::default_serial!(dp, pins, 9600);
-
     kaleidoscope::init_cpu(dp.CPU);
 
     kaleidoscope::init_millis(dp.TC0);
@@ -21,11 +15,14 @@ fn main() -> ! {
 
     kaleidoscope::init_wdt(dp.WDT);
 
+    kaleidoscope::init_eeprom(dp.EEPROM);
+
     kaleidoscope::init_usb(dp.USB_DEVICE);
 
     let usb = kaleidoscope::usb().expect("null USB");
 
     kaleidoscope::init_hid(usb);
+    kaleidoscope::init_usb_serial(usb);
 
     kaleidoscope::init_usb_device(usb);
 
@@ -38,20 +35,10 @@ fn main() -> ! {
 
 #[avr_device::interrupt(atmega32u4)]
 fn USB_GEN() {
-    return_on_err!(usb_device_mut()).poll(&mut [
-                    return_on_err!(hid_mut()).boot_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).nkro_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).media_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).system_control_keyboard.hid_class_mut(),
-    ]);
+    kaleidoscope::poll_usb();
 }
 
 #[avr_device::interrupt(atmega32u4)]
 fn USB_COM() {
-    return_on_err!(usb_device_mut()).poll(&mut [
-                    return_on_err!(hid_mut()).boot_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).nkro_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).media_keyboard.hid_class_mut(),
-                    return_on_err!(hid_mut()).system_control_keyboard.hid_class_mut(),
-    ]);
+    kaleidoscope::poll_usb();
 }
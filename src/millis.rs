@@ -12,6 +12,7 @@
 use core::sync::atomic::Ordering;
 
 use crate::atomic::AtomicU32;
+use crate::irq_cell::IrqCell;
 
 // Possible Values:
 //
@@ -27,10 +28,13 @@ use crate::atomic::AtomicU32;
 const PRESCALER: u32 = 1024;
 const TIMER_COUNTS: u32 = 125;
 
-const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
+/// Milliseconds added to [millis] on every timer interrupt, i.e. its resolution.
+pub const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
 
 static MILLIS_COUNTER: AtomicU32 = AtomicU32::new(0);
 
+static TC0: IrqCell<arduino_hal::pac::TC0> = IrqCell::new();
+
 pub fn init_millis(tc0: arduino_hal::pac::TC0) {
     // Configure the timer for the above interval (in CTC mode)
     // and enable its interrupt.
@@ -47,6 +51,9 @@ pub fn init_millis(tc0: arduino_hal::pac::TC0) {
 
     // Reset the global millisecond counter
     MILLIS_COUNTER.store(0, Ordering::SeqCst);
+
+    // Keep the timer around, so `micros()` can read the current count.
+    let _ = TC0.set(tc0);
 }
 
 #[avr_device::interrupt(atmega32u4)]
@@ -58,3 +65,23 @@ fn TIMER0_COMPA() {
 pub fn millis() -> u32 {
     MILLIS_COUNTER.load(Ordering::Relaxed)
 }
+
+/// Gets the number of microseconds since [init_millis] was called.
+///
+/// The resolution is one timer tick (`PRESCALER / 16` microseconds). The value wraps
+/// around after roughly 71 minutes, so compare timestamps with `wrapping_sub`.
+pub fn micros() -> u32 {
+    TC0.with(|tc0| {
+        let mut m = MILLIS_COUNTER.load(Ordering::Relaxed);
+        let ticks = tc0.tcnt0.read().bits() as u32;
+
+        // Account for a compare match whose interrupt hasn't been serviced yet.
+        if tc0.tifr0.read().ocf0a().bit_is_set() && ticks < TIMER_COUNTS {
+            m += MILLIS_INCREMENT;
+        }
+
+        m.wrapping_mul(1000)
+            .wrapping_add(ticks * PRESCALER / 16)
+    })
+    .unwrap_or_else(|| millis().wrapping_mul(1000))
+}
@@ -0,0 +1,117 @@
+use crate::driver::storage::{blob_slot_len, SlotHandle, STORAGE};
+use crate::error::{Error, Result};
+
+/// Maximum number of bytes a single [Persistable] can store.
+pub const MAX_PERSIST_LEN: usize = 32;
+
+/// Plugin state that can be saved to, and restored from, non-volatile storage.
+///
+/// Implementors only describe how to (de)serialize their settings. Claiming storage,
+/// versioning, and CRC checks are handled by [setup_storage](Self::setup_storage) and
+/// [commit](Self::commit).
+pub trait Persistable {
+    /// Number of bytes needed to store the settings. Must not exceed [MAX_PERSIST_LEN].
+    const SIZE: u16;
+
+    /// Layout version of the stored settings. Bump this when the layout changes, so
+    /// stale settings are discarded rather than misread.
+    const VERSION: u8 = 1;
+
+    /// Serializes the settings into `buf`, which is exactly [SIZE](Self::SIZE) bytes long.
+    fn save(&self, buf: &mut [u8]);
+
+    /// Deserializes the settings from `buf`, which is exactly [SIZE](Self::SIZE) bytes long.
+    fn restore(&mut self, buf: &[u8]);
+
+    /// Gets the storage slot claimed by [setup_storage](Self::setup_storage).
+    fn slot(&self) -> Option<SlotHandle>;
+
+    /// Sets the storage slot claimed by [setup_storage](Self::setup_storage).
+    fn set_slot(&mut self, slot: SlotHandle);
+
+    /// Claims a storage slot, and restores any previously committed settings.
+    ///
+    /// If the stored settings are missing, outdated, or corrupt, the current (default)
+    /// settings are kept.
+    fn setup_storage(&mut self) -> Result<()> {
+        let len = Self::SIZE as usize;
+
+        if len > MAX_PERSIST_LEN {
+            return Err(Error::Storage);
+        }
+
+        let slot = STORAGE.write().reserve(blob_slot_len(Self::SIZE))?;
+        self.set_slot(slot);
+
+        let mut buf = [0u8; MAX_PERSIST_LEN];
+
+        match STORAGE.read().restore(slot, Self::VERSION, &mut buf[..len]) {
+            Ok(()) => {
+                self.restore(&buf[..len]);
+                Ok(())
+            }
+            Err(Error::StorageCorrupt) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Commits the current settings to storage.
+    ///
+    /// Returns an error if [setup_storage](Self::setup_storage) has not claimed a slot.
+    fn commit(&self) -> Result<()> {
+        let slot = self.slot().ok_or(Error::Storage)?;
+        let len = Self::SIZE as usize;
+
+        let mut buf = [0u8; MAX_PERSIST_LEN];
+        self.save(&mut buf[..len]);
+
+        STORAGE.write().commit(slot, Self::VERSION, &buf[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::consumer_mute::{ConsumerMute, MutePolicy};
+
+    /// Settings too large to be persisted.
+    struct Oversized;
+
+    impl Persistable for Oversized {
+        const SIZE: u16 = MAX_PERSIST_LEN as u16 + 1;
+
+        fn save(&self, _buf: &mut [u8]) {}
+
+        fn restore(&mut self, _buf: &[u8]) {}
+
+        fn slot(&self) -> Option<SlotHandle> {
+            None
+        }
+
+        fn set_slot(&mut self, _slot: SlotHandle) {}
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut mute = ConsumerMute::new();
+        mute.set_policy(MutePolicy::Momentary);
+
+        let mut buf = [0u8; ConsumerMute::SIZE as usize];
+        mute.save(&mut buf);
+
+        let mut restored = ConsumerMute::new();
+        restored.restore(&buf);
+
+        assert_eq!(restored.policy(), MutePolicy::Momentary);
+    }
+
+    #[test]
+    fn commit_needs_a_slot() {
+        assert_eq!(ConsumerMute::new().commit(), Err(Error::Storage));
+    }
+
+    #[test]
+    fn oversized_settings_are_rejected() {
+        assert_eq!(Oversized.setup_storage(), Err(Error::Storage));
+    }
+}
@@ -1,4 +1,64 @@
 /// Keyboardio Atreus hardware support
+#[cfg(feature = "atreus")]
 pub mod atreus;
+/// Per-key static LED colors, per layer
+pub mod colormap;
+/// Keys pressed together producing another key
+pub mod combos;
+/// Consumer-control mute policies
+pub mod consumer_mute;
+/// Cycle the previously typed key through a list of options
+pub mod cycle;
+/// Focus command rebooting into the bootloader
+#[cfg(feature = "device_reset")]
+pub mod device_reset;
+/// Keymap stored in EEPROM, editable over Focus
+pub mod dynamic_keymap;
+/// Runtime-recorded macros
+pub mod dynamic_macros;
+/// Focus protocol over a serial port
+pub mod focus_serial;
+/// Key press heatmap LED mode
+pub mod heatmap;
+/// Host operating system selection
+pub mod host_os;
+/// Typematic repeat of the held key
+pub mod key_repeat;
+/// Key colors following the active layer
+pub mod layer_highlight;
+/// Key sequences typed after a leader key
+pub mod leader;
+/// Solid color and breathing LED modes
+pub mod led_effects;
+/// Actions run while sets of keys are held together
+pub mod magic_combo;
+/// Macro key actions, with a built-in version info macro
 pub mod macros;
+/// Jump the mouse cursor by successively dividing the screen into quarters
+pub mod mouse_warp;
+/// One-shot modifiers and layers, with sticky variants
+pub mod one_shot;
+/// Dual-use keys resolving to a tap or a hold key
+pub mod qukeys;
 pub mod ranges;
+/// Repeat the last key pressed
+pub mod redial;
+/// Modifiers that send a symbol when tapped
+pub mod space_cadet;
+/// Stenography chords over the GeminiPR protocol
+pub mod steno;
+/// Accessibility sticky modifiers, on the regular modifier keys
+pub mod sticky_keys;
+/// Abbreviation expansion after the Syster key
+pub mod syster;
+/// Technomancy Atreus hardware support
+#[cfg(feature = "technomancy_atreus")]
+pub mod technomancy_atreus;
+/// Keys with inverted Shift behavior
+pub mod topsy_turvy;
+/// Repeat a key while the Turbo key is held
+pub mod turbo;
+/// Persistent key press counter
+pub mod typing_stats;
+/// Type Unicode code points through the host input method
+pub mod unicode;
@@ -1,7 +1,8 @@
 use kaleidoscope_internal::driver::keyscanner::MatrixScanner;
 
+use crate::bootloader::BootloaderKind;
 use crate::device::{pins_and_ports::*, DeviceOps};
-use crate::driver::{bootloader::avr::Caterina, keyscanner::{Atmega, KeyScannerProps}};
+use crate::driver::{bootloader::avr::Caterina, board::{Board, BoardProps}, keyscanner::{Atmega, KeyScannerProps}};
 
 pub type KeyScanner = Atmega;
 pub type Bootloader = Caterina;
@@ -13,25 +14,27 @@ impl KeyScannerProps for AtreusProps {
     const KEYSCAN_INTERVAL: u16 = 1500;
 }
 
-pub struct AtreusProps;
-
-impl AtreusProps {
-    pub const SHORT_NAME: &'static str = "atreus";
+impl BoardProps for AtreusProps {
+    const SHORT_NAME: &'static str = "atreus";
 
-    pub const MATRIX_ROW_PINS: [u8; Self::ROWS] = [PIN_F6, PIN_F5, PIN_F4, PIN_F1];
-    pub const MATRIX_COL_PINS: [u8; Self::COLS] = [
+    const MATRIX_ROW_PINS: &'static [u8] = &[PIN_F6, PIN_F5, PIN_F4, PIN_F1];
+    const MATRIX_COL_PINS: &'static [u8] = &[
         PIN_F7, PIN_E2, PIN_C7, PIN_C6, PIN_B6, PIN_B5, PIN_D7, PIN_D6, PIN_D4, PIN_D5, PIN_D3,
         PIN_D2,
     ];
+
+    const BOOTLOADER: Option<BootloaderKind> = Some(BootloaderKind::Caterina);
+
+    const NUM_LAYERS: usize = 3;
 }
 
+pub struct AtreusProps;
+
 pub struct Atreus {
     key_scanner: KeyScanner,
 }
 
 impl Atreus {
-    const LED_COUNT: usize = 0;
-
     pub const fn new() -> Self {
         Self {
             key_scanner: KeyScanner::new(),
@@ -39,10 +42,14 @@ impl Atreus {
     }
 
     pub const fn led_count() -> usize {
-        Self::LED_COUNT
+        <AtreusProps as BoardProps>::LED_COUNT
     }
+}
+
+impl Board for Atreus {
+    type Props = AtreusProps;
 
-    pub fn scan_matrix(&mut self) {
+    fn scan_matrix(&mut self) {
         self.key_scanner.scan_matrix();
     }
 }
@@ -0,0 +1,569 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::driver::led::{Rgb, LED_CONTROL, LED_COUNT};
+use crate::driver::storage::{blob_slot_len, SlotHandle, STORAGE};
+use crate::error::{self, Error};
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{accept_stream, line_part, split_command, LinePart, FOCUS_OUTPUT, FOCUS_OUTPUT_LEN};
+use crate::plugins::focus_serial::flush_output;
+use crate::layers::NUM_LAYERS;
+use crate::{lock, LAYER};
+
+/// Number of colors in the palette.
+pub const COLORMAP_PALETTE_LEN: usize = 16;
+
+/// Number of bytes of an encoded palette.
+pub const COLORMAP_PALETTE_BYTES: usize = 3 * COLORMAP_PALETTE_LEN;
+
+/// Number of color indices in the colormap, all layers included.
+pub const COLORMAP_LEN: usize = LED_COUNT * NUM_LAYERS;
+
+/// Number of bytes of a layer of packed color indices, two per byte.
+pub const COLORMAP_LAYER_BYTES: usize = (LED_COUNT + 1) / 2;
+
+/// Number of bytes of the packed color indices of all layers.
+pub const COLORMAP_MAP_BYTES: usize = COLORMAP_LAYER_BYTES * NUM_LAYERS;
+
+/// Layout version of the stored palette.
+const PALETTE_VERSION: u8 = 1;
+
+/// Number of bytes before the stored color indices: the stored index count.
+const HEADER_LEN: u16 = 2;
+
+/// Number of color indices per layer, at least one so boards without LEDs still build.
+const LAYER_STRIDE: usize = if LED_COUNT > 0 { LED_COUNT } else { 1 };
+
+/// Number of bytes staged by a Focus write: the encoded palette, or the packed colormap.
+const STAGED_LEN: usize = if COLORMAP_MAP_BYTES > COLORMAP_PALETTE_BYTES {
+    COLORMAP_MAP_BYTES
+} else {
+    COLORMAP_PALETTE_BYTES
+};
+
+/// Global colormap state.
+pub static COLORMAP: lock::Spinlock<Colormap> = lock::Spinlock::new(Colormap::new());
+
+/// Colors referenced by the colormap indices.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::colormap::{Palette, COLORMAP_PALETTE_BYTES};
+///
+/// let mut palette = Palette::new();
+/// palette.set_color(1, [255, 0, 0]);
+/// palette.set_color(15, [0, 16, 32]);
+///
+/// let mut buf = [0u8; COLORMAP_PALETTE_BYTES];
+/// palette.encode(&mut buf);
+/// assert_eq!(&buf[..6], &[0, 0, 0, 255, 0, 0]);
+/// assert_eq!(&buf[45..], &[0, 16, 32]);
+///
+/// let decoded = Palette::decode(&buf);
+/// assert_eq!(decoded.color(1), [255, 0, 0]);
+/// assert_eq!(decoded.color(15), [0, 16, 32]);
+/// assert_eq!(decoded, palette);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    colors: [Rgb; COLORMAP_PALETTE_LEN],
+}
+
+impl Palette {
+    /// Creates a new [Palette], all black.
+    pub const fn new() -> Self {
+        Self {
+            colors: [[0u8; 3]; COLORMAP_PALETTE_LEN],
+        }
+    }
+
+    /// Gets the color at `index`, wrapping around the palette.
+    pub fn color(&self, index: u8) -> Rgb {
+        self.colors[index as usize % COLORMAP_PALETTE_LEN]
+    }
+
+    /// Sets the color at `index`, ignoring indices past the palette.
+    pub fn set_color(&mut self, index: u8, color: Rgb) {
+        if let Some(c) = self.colors.get_mut(index as usize) {
+            *c = color;
+        }
+    }
+
+    /// Encodes the palette as `r, g, b` bytes, color by color.
+    pub fn encode(&self, buf: &mut [u8; COLORMAP_PALETTE_BYTES]) {
+        for (bytes, color) in buf.chunks_exact_mut(3).zip(self.colors.iter()) {
+            bytes.copy_from_slice(color);
+        }
+    }
+
+    /// Decodes a palette encoded with [encode](Self::encode).
+    pub fn decode(buf: &[u8; COLORMAP_PALETTE_BYTES]) -> Self {
+        let mut palette = Self::new();
+
+        for (color, bytes) in palette.colors.iter_mut().zip(buf.chunks_exact(3)) {
+            *color = [bytes[0], bytes[1], bytes[2]];
+        }
+
+        palette
+    }
+}
+
+/// Gets the color index of LED `led` in packed indices, two per byte, the first in the
+/// high nibble.
+pub const fn unpack_index(packed: &[u8], led: usize) -> u8 {
+    let byte = packed[led / 2];
+
+    if led % 2 == 0 {
+        byte >> 4
+    } else {
+        byte & 0x0f
+    }
+}
+
+/// Sets the color index of LED `led` in packed indices, see [unpack_index].
+pub fn pack_index(packed: &mut [u8], led: usize, index: u8) {
+    let byte = &mut packed[led / 2];
+
+    if led % 2 == 0 {
+        *byte = (*byte & 0x0f) | (index << 4);
+    } else {
+        *byte = (*byte & 0xf0) | (index & 0x0f);
+    }
+}
+
+/// Gets the color of LED `led`, from a layer of packed indices.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::colormap::{lookup, pack_index, Palette};
+///
+/// let mut palette = Palette::new();
+/// palette.set_color(2, [0, 255, 0]);
+/// palette.set_color(7, [0, 0, 255]);
+///
+/// let mut layer = [0u8; 2];
+/// pack_index(&mut layer, 1, 2);
+/// pack_index(&mut layer, 2, 7);
+/// assert_eq!(layer, [0x02, 0x70]);
+///
+/// assert_eq!(lookup(&layer, &palette, 0), [0, 0, 0]);
+/// assert_eq!(lookup(&layer, &palette, 1), [0, 255, 0]);
+/// assert_eq!(lookup(&layer, &palette, 2), [0, 0, 255]);
+/// ```
+pub fn lookup(packed: &[u8], palette: &Palette, led: usize) -> Rgb {
+    palette.color(unpack_index(packed, led))
+}
+
+/// Static per-key LED colors, per layer, stored in EEPROM.
+///
+/// Each key of each layer has a color index into a [Palette] of [COLORMAP_PALETTE_LEN]
+/// colors. Before each LED sync, keys are set to their color on the top active layer,
+/// over the current LED mode. LEDs are indexed like [KeyAddr](crate::key_addr::KeyAddr)s,
+/// one LED per key.
+///
+/// The colormap is used once it has been written. The palette and the colormap are
+/// edited with the Focus commands of Chrysalis:
+///
+/// - `palette` prints the [COLORMAP_PALETTE_LEN] colors as `r g b` triples,
+/// - `palette r g b...` overwrites the whole palette,
+/// - `colormap.map` prints the [COLORMAP_LEN] color indices, layer by layer,
+/// - `colormap.map i...` overwrites the whole colormap.
+///
+/// The lines are longer than the Focus buffers, so they are streamed, see
+/// [line_part](crate::focus::line_part). Writes are staged until the end of the line,
+/// and only applied if they hold exactly as many values as the palette (three per
+/// color) or the colormap, all valid. On boards without LEDs, the commands print
+/// nothing.
+pub struct Colormap {
+    palette: Palette,
+    layer: [u8; COLORMAP_LAYER_BYTES],
+    cached_layer: Option<u8>,
+    active: bool,
+    palette_slot: Option<SlotHandle>,
+    map_slot: Option<SlotHandle>,
+    staged: [u8; STAGED_LEN],
+    staged_count: usize,
+    staged_valid: bool,
+}
+
+impl Colormap {
+    /// Creates a new [Colormap], inactive until it is written.
+    pub const fn new() -> Self {
+        Self {
+            palette: Palette::new(),
+            layer: [0u8; COLORMAP_LAYER_BYTES],
+            cached_layer: None,
+            active: false,
+            palette_slot: None,
+            map_slot: None,
+            staged: [0u8; STAGED_LEN],
+            staged_count: 0,
+            staged_valid: false,
+        }
+    }
+
+    /// Gets the palette.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Gets whether the stored colormap is applied.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Claims storage slots for the palette and the colormap, and restores them.
+    ///
+    /// Boards without LEDs claim no storage. A stored colormap with a count other than
+    /// [COLORMAP_LEN] (e.g. after a keymap change) is ignored.
+    pub fn setup_storage(&mut self) -> error::Result<()> {
+        if LED_COUNT == 0 {
+            return Ok(());
+        }
+
+        let palette_slot = STORAGE.write().reserve(blob_slot_len(COLORMAP_PALETTE_BYTES as u16))?;
+        self.palette_slot = Some(palette_slot);
+
+        let mut buf = [0u8; COLORMAP_PALETTE_BYTES];
+
+        match STORAGE.read().restore(palette_slot, PALETTE_VERSION, &mut buf) {
+            Ok(()) => self.palette = Palette::decode(&buf),
+            Err(Error::StorageCorrupt) => (),
+            Err(err) => return Err(err),
+        }
+
+        let map_slot = STORAGE
+            .write()
+            .reserve(HEADER_LEN + (COLORMAP_LAYER_BYTES * NUM_LAYERS) as u16)?;
+        self.map_slot = Some(map_slot);
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        STORAGE.read().read_slot(map_slot, 0, &mut header)?;
+
+        self.active = u16::from_le_bytes(header) as usize == COLORMAP_LEN;
+
+        Ok(())
+    }
+
+    /// Gets the stored color index at `index`, across all layers.
+    pub fn read_index(&self, index: usize) -> error::Result<u8> {
+        let slot = self.map_slot.ok_or(Error::Storage)?;
+
+        if index >= COLORMAP_LEN {
+            return Err(Error::InvalidKeymap);
+        }
+
+        if !self.active {
+            return Ok(0);
+        }
+
+        let mut byte = [0u8; 1];
+        STORAGE.read().read_slot(slot, Self::offset(index), &mut byte)?;
+
+        Ok(unpack_index(&byte, index % LAYER_STRIDE % 2))
+    }
+
+    /// Overwrites the stored color indices from `index` on, across all layers.
+    ///
+    /// Nothing is written if the indices don't fit in the colormap, or one is past the
+    /// palette. Writing to an inactive colormap clears it first, then activates it.
+    pub fn write_indices(&mut self, index: usize, indices: &[u8]) -> error::Result<()> {
+        let slot = self.map_slot.ok_or(Error::Storage)?;
+
+        if index + indices.len() > COLORMAP_LEN || indices.iter().any(|&i| i as usize >= COLORMAP_PALETTE_LEN) {
+            return Err(Error::InvalidKeymap);
+        }
+
+        let mut storage = STORAGE.write();
+
+        if !self.active {
+            for i in 0..COLORMAP_LAYER_BYTES * NUM_LAYERS {
+                storage.write_slot(slot, HEADER_LEN + i as u16, &[0])?;
+            }
+            storage.write_slot(slot, 0, &(COLORMAP_LEN as u16).to_le_bytes())?;
+            self.active = true;
+        }
+
+        for (i, &color) in indices.iter().enumerate() {
+            let offset = Self::offset(index + i);
+
+            let mut byte = [0u8; 1];
+            storage.read_slot(slot, offset, &mut byte)?;
+            pack_index(&mut byte, (index + i) % LAYER_STRIDE % 2, color);
+            storage.write_slot(slot, offset, &byte)?;
+        }
+
+        self.cached_layer = None;
+
+        Ok(())
+    }
+
+    /// Overwrites palette colors from `index` on, and commits the palette.
+    ///
+    /// Nothing is written if the colors don't fit in the palette.
+    pub fn write_palette(&mut self, index: usize, colors: &[Rgb]) -> error::Result<()> {
+        let slot = self.palette_slot.ok_or(Error::Storage)?;
+
+        if index + colors.len() > COLORMAP_PALETTE_LEN {
+            return Err(Error::InvalidKeymap);
+        }
+
+        for (i, &color) in colors.iter().enumerate() {
+            self.palette.set_color((index + i) as u8, color);
+        }
+
+        let mut buf = [0u8; COLORMAP_PALETTE_BYTES];
+        self.palette.encode(&mut buf);
+
+        STORAGE.write().commit(slot, PALETTE_VERSION, &buf)
+    }
+
+    /// Overwrites every color index with `packed` indices, two per byte, layer by layer,
+    /// and activates the colormap.
+    pub fn write_map(&mut self, packed: &[u8; COLORMAP_MAP_BYTES]) -> error::Result<()> {
+        let slot = self.map_slot.ok_or(Error::Storage)?;
+        let mut storage = STORAGE.write();
+
+        for (i, &byte) in packed.iter().enumerate() {
+            storage.write_slot(slot, HEADER_LEN + i as u16, &[byte])?;
+        }
+
+        storage.write_slot(slot, 0, &(COLORMAP_LEN as u16).to_le_bytes())?;
+        self.active = true;
+        self.cached_layer = None;
+
+        Ok(())
+    }
+
+    /// Offset of the byte holding color index `index` in the colormap slot.
+    const fn offset(index: usize) -> u16 {
+        let (layer, led) = (index / LAYER_STRIDE, index % LAYER_STRIDE);
+
+        HEADER_LEN + (layer * COLORMAP_LAYER_BYTES + led / 2) as u16
+    }
+
+    /// Loads the packed color indices of `layer` from storage, if it is not cached yet.
+    fn load_layer(&mut self, layer: u8) -> error::Result<()> {
+        if self.cached_layer == Some(layer) {
+            return Ok(());
+        }
+
+        let slot = self.map_slot.ok_or(Error::Storage)?;
+        let start = HEADER_LEN + (layer as usize * COLORMAP_LAYER_BYTES) as u16;
+
+        STORAGE.read().read_slot(slot, start, &mut self.layer)?;
+        self.cached_layer = Some(layer);
+
+        Ok(())
+    }
+
+    /// Stages the values of the part of a `palette` or `colormap.map` write being
+    /// handled, see [line_part].
+    ///
+    /// `stage` stores value `i` of the line in the staging buffer, and returns whether it
+    /// is valid. Returns whether the line is complete, and holds exactly `len` valid
+    /// values.
+    fn stage_values<F>(&mut self, values: &str, len: usize, stage: F) -> bool
+    where
+        F: Fn(&mut [u8; STAGED_LEN], usize, u8) -> bool,
+    {
+        let part = line_part();
+
+        if matches!(part, LinePart::Whole | LinePart::First) {
+            self.staged_count = 0;
+            self.staged_valid = true;
+        }
+
+        if part == LinePart::First {
+            accept_stream();
+        }
+
+        for value in values.split_whitespace() {
+            match value.parse::<u8>() {
+                Ok(value) if self.staged_count < len && stage(&mut self.staged, self.staged_count, value) => {
+                    self.staged_count += 1;
+                }
+                _ => self.staged_valid = false,
+            }
+        }
+
+        matches!(part, LinePart::Whole | LinePart::Last) && self.staged_valid && self.staged_count == len
+    }
+
+    /// Writes a value of a reply longer than the Focus output buffer, sending the buffered
+    /// reply first if the value might not fit.
+    fn write_value(value: u8, last: bool) -> Result<()> {
+        if FOCUS_OUTPUT.read().len() + "255\r\n".len() > FOCUS_OUTPUT_LEN {
+            flush_output();
+        }
+
+        let separator = if last { "\r\n" } else { " " };
+
+        uwrite!(&mut *FOCUS_OUTPUT.write(), "{}{}", value, separator).map_err(|_| EventHandlerError::Error)
+    }
+
+    fn on_palette_command(args: &str) -> Result<()> {
+        if args.is_empty() && line_part() == LinePart::Whole {
+            let palette = *COLORMAP.read().palette();
+            let mut buf = [0u8; COLORMAP_PALETTE_BYTES];
+            palette.encode(&mut buf);
+
+            for (i, &value) in buf.iter().enumerate() {
+                Self::write_value(value, i + 1 == buf.len())?;
+            }
+
+            return Ok(());
+        }
+
+        let mut colormap = COLORMAP.write();
+
+        if !colormap.stage_values(args, COLORMAP_PALETTE_BYTES, |staged, i, value| {
+            staged[i] = value;
+            true
+        }) {
+            return Self::incomplete_write();
+        }
+
+        let mut colors = [[0u8; 3]; COLORMAP_PALETTE_LEN];
+        for (color, rgb) in colors.iter_mut().zip(colormap.staged.chunks_exact(3)) {
+            *color = [rgb[0], rgb[1], rgb[2]];
+        }
+
+        colormap.write_palette(0, &colors).map_err(|_| EventHandlerError::Error)
+    }
+
+    fn on_colormap_command(args: &str) -> Result<()> {
+        if args.is_empty() && line_part() == LinePart::Whole {
+            let colormap = COLORMAP.read();
+
+            for i in 0..COLORMAP_LEN {
+                let color = colormap.read_index(i).map_err(|_| EventHandlerError::Error)?;
+                Self::write_value(color, i + 1 == COLORMAP_LEN)?;
+            }
+
+            return Ok(());
+        }
+
+        let mut colormap = COLORMAP.write();
+
+        if !colormap.stage_values(args, COLORMAP_LEN, |staged, i, color| {
+            if color as usize >= COLORMAP_PALETTE_LEN {
+                return false;
+            }
+
+            let byte = (Self::offset(i) - HEADER_LEN) as usize;
+            pack_index(&mut staged[byte..], i % LAYER_STRIDE % 2, color);
+            true
+        }) {
+            return Self::incomplete_write();
+        }
+
+        let mut packed = [0u8; COLORMAP_MAP_BYTES];
+        packed.copy_from_slice(&colormap.staged[..COLORMAP_MAP_BYTES]);
+
+        colormap.write_map(&packed).map_err(|_| EventHandlerError::Error)
+    }
+
+    /// Result of a write that was not applied: fine while the line is still streaming, an
+    /// error once it is complete.
+    fn incomplete_write() -> Result<()> {
+        match line_part() {
+            LinePart::First | LinePart::Next => Ok(()),
+            LinePart::Whole | LinePart::Last => Err(EventHandlerError::Error),
+        }
+    }
+}
+
+impl EventHandler for Colormap {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Colormap")
+    }
+
+    fn before_syncing_leds() -> Result<()> {
+        if LED_COUNT == 0 {
+            return Ok(());
+        }
+
+        let mut colormap = COLORMAP.write();
+
+        if !colormap.active {
+            return Ok(());
+        }
+
+        let layer = LAYER.read().most_recent_layer();
+        colormap.load_layer(layer).map_err(|_| EventHandlerError::Error)?;
+
+        let mut leds = LED_CONTROL.write();
+
+        for i in 0..LED_COUNT {
+            leds.set_crgb_at(i, lookup(&colormap.layer, &colormap.palette, i));
+        }
+
+        Ok(())
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let mut output = FOCUS_OUTPUT.write();
+            let _ = output.write_str("palette\r\n");
+            let _ = output.write_str("colormap.map\r\n");
+            return Ok(());
+        }
+
+        match command {
+            // Boards without LEDs answer with an empty response.
+            "palette" | "colormap.map" if LED_COUNT == 0 => (),
+            "palette" => Self::on_palette_command(args)?,
+            "colormap.map" => Self::on_colormap_command(args)?,
+            _ => return Ok(()),
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_unpack_round_trip() {
+        let mut packed = [0u8; 4];
+
+        for led in 0..8 {
+            for index in 0..16 {
+                pack_index(&mut packed, led, index);
+                assert_eq!(unpack_index(&packed, led), index);
+            }
+        }
+    }
+
+    #[test]
+    fn pack_leaves_other_leds() {
+        let mut packed = [0u8; 2];
+
+        pack_index(&mut packed, 0, 0xa);
+        pack_index(&mut packed, 1, 0x5);
+        pack_index(&mut packed, 3, 0xf);
+        assert_eq!(packed, [0xa5, 0x0f]);
+
+        pack_index(&mut packed, 1, 0x3);
+        assert_eq!(packed, [0xa3, 0x0f]);
+        assert_eq!(unpack_index(&packed, 0), 0xa);
+        assert_eq!(unpack_index(&packed, 2), 0);
+    }
+
+    #[test]
+    fn pack_keeps_index_in_its_nibble() {
+        let mut packed = [0u8; 1];
+
+        pack_index(&mut packed, 1, 0xff);
+        assert_eq!(packed, [0x0f]);
+
+        pack_index(&mut packed, 0, 0x12);
+        assert_eq!(packed, [0x2f]);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::runtime::Runtime;
+use crate::{key_defs::*, key_event::{KeyEvent, KeyEventId}, lock, millis::millis, LIVE_KEYS};
+
+/// Maximum number of keys in a combo.
+pub const MAX_COMBO_KEYS: usize = 4;
+
+/// Default time, in milliseconds, within which all keys of a combo must be pressed.
+pub const DEFAULT_COMBO_WINDOW: u16 = 50;
+
+/// Combo definition: the keys pressed together, and the key they produce.
+pub type ComboEntry = (&'static [Key], Key);
+
+/// Global combos state.
+pub static COMBOS: lock::Spinlock<Combos> = lock::Spinlock::new(Combos::new());
+
+/// Result of matching the held back keys against the combos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ComboMatch {
+    /// Index of the combo made of exactly the held back keys.
+    exact: Option<usize>,
+    /// Whether a longer combo contains all the held back keys.
+    longer: bool,
+}
+
+/// Maps keys pressed together to another key.
+///
+/// Presses of keys that are part of a combo are held back for up to the resolution
+/// window. If all keys of a combo are pressed within the window, the individual keys are
+/// suppressed, and the combo's output key is pressed instead, until the first key of the
+/// combo is released.
+///
+/// Overlapping combos use a longest-match rule: when the held back keys complete a combo
+/// that is also part of a longer one, resolution waits for the longer combo until the
+/// window expires, or a key is released.
+///
+/// If no combo is completed, the held back events are handled as normal key presses,
+/// in the order they occurred. Resolved events are re-injected as keyswitch events (see
+/// [Runtime::queue_keyswitch_event]), so the plugins after Combos see them too. Events
+/// keep their ID when re-injected, by Combos or by a later plugin, and Combos lets
+/// events it has already seen through.
+pub struct Combos {
+    combos: &'static [ComboEntry],
+    pending: [Option<KeyEvent>; MAX_COMBO_KEYS],
+    len: usize,
+    // ID of the last new event, older events are re-injected ones.
+    last_id: KeyEventId,
+    start_time: u32,
+    window: u16,
+}
+
+impl Combos {
+    /// Creates a new [Combos] with no combos.
+    pub const fn new() -> Self {
+        Self {
+            combos: &[],
+            pending: [None; MAX_COMBO_KEYS],
+            len: 0,
+            last_id: KeyEventId::default(),
+            start_time: 0,
+            window: DEFAULT_COMBO_WINDOW,
+        }
+    }
+
+    /// Sets the combo definitions.
+    pub fn set_combos(&mut self, combos: &'static [ComboEntry]) {
+        self.combos = combos;
+    }
+
+    /// Gets the resolution window in milliseconds.
+    pub fn window(&self) -> u16 {
+        self.window
+    }
+
+    /// Sets the resolution window in milliseconds.
+    pub fn set_window(&mut self, window: u16) {
+        self.window = window;
+    }
+
+    /// Gets whether key presses are being held back.
+    pub fn is_pending(&self) -> bool {
+        self.len > 0
+    }
+
+    /// Matches the held back keys, plus `key`, against the combos.
+    fn lookup(&self, key: Option<&Key>) -> ComboMatch {
+        let mut result = ComboMatch {
+            exact: None,
+            longer: false,
+        };
+
+        let count = self.len + key.is_some() as usize;
+
+        for (i, (keys, _)) in self.combos.iter().enumerate() {
+            let contains_all = self.pending[..self.len]
+                .iter()
+                .flatten()
+                .map(|e| e.key())
+                .chain(key)
+                .all(|k| keys.contains(k));
+
+            if !contains_all {
+                continue;
+            }
+
+            if keys.len() == count {
+                result.exact.get_or_insert(i);
+            } else if keys.len() > count {
+                result.longer = true;
+            }
+        }
+
+        result
+    }
+
+    /// Holds back a key press.
+    fn push(&mut self, event: KeyEvent, now: u32) {
+        if self.len == 0 {
+            self.start_time = now;
+        }
+
+        self.pending[self.len] = Some(event);
+        self.len += 1;
+    }
+
+    /// Takes the held back events, in the order they occurred.
+    fn take_pending(&mut self) -> ([Option<KeyEvent>; MAX_COMBO_KEYS], usize) {
+        let len = core::mem::replace(&mut self.len, 0);
+
+        (core::mem::replace(&mut self.pending, [None; MAX_COMBO_KEYS]), len)
+    }
+
+    /// Takes the held back events, with the output of the combo they complete, if any.
+    fn take_resolution(&mut self) -> (Option<Key>, [Option<KeyEvent>; MAX_COMBO_KEYS], usize) {
+        let output = self
+            .lookup(None)
+            .exact
+            .and_then(|i| self.combos.get(i))
+            .map(|(_, output)| *output);
+        let (pending, len) = self.take_pending();
+
+        (output, pending, len)
+    }
+
+    /// Gets whether the resolution window of the held back keys expired at `now`.
+    fn expired(&self, now: u32) -> bool {
+        self.is_pending() && now.wrapping_sub(self.start_time) >= self.window as u32
+    }
+
+    /// Re-injects `event`, keeping its ID, so it is let through when it comes back.
+    fn reinject(event: KeyEvent) {
+        let _ = Runtime::queue_keyswitch_event(event);
+    }
+
+    /// Records the ID of `event`, returning whether it is a new event rather than a
+    /// re-injected one.
+    fn see(&mut self, event: &KeyEvent) -> bool {
+        if !event.id().is_after(&self.last_id) {
+            return false;
+        }
+
+        self.last_id = event.id();
+        true
+    }
+
+    /// Resolves the held back keys: presses the output of the completed combo, if any,
+    /// or re-injects the held back events as normal key presses.
+    ///
+    /// Must be called without holding the [COMBOS] lock.
+    fn resolve() {
+        let (output, pending, len) = COMBOS.write().take_resolution();
+        let mut events = pending[..len].iter().flatten().copied();
+
+        match output {
+            Some(output) => {
+                let Some(mut first) = events.next() else {
+                    return;
+                };
+
+                // Mask the other keys, so their releases are ignored.
+                for event in events {
+                    LIVE_KEYS.write().activate(*event.addr(), Key_Masked);
+                }
+
+                first.set_key(output);
+                Self::reinject(first);
+            }
+            None => {
+                for event in events {
+                    Self::reinject(event);
+                }
+            }
+        }
+    }
+
+    fn is_combo_key(&self, key: &Key) -> bool {
+        self.combos.iter().any(|(keys, _)| keys.contains(key))
+    }
+}
+
+impl EventHandler for Combos {
+    const PROCESS_INJECTED: bool = false;
+    // Combos see keyswitch events before Qukeys holds them back.
+    const PRIORITY: i8 = 20;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Combos")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let expired = COMBOS.read().expired(millis());
+
+        if expired {
+            Self::resolve();
+        }
+
+        Ok(())
+    }
+
+    fn on_keyswitch_event(event: &mut KeyEvent) -> Result<()> {
+        if !COMBOS.write().see(event) {
+            return Ok(());
+        }
+
+        if event.state().key_toggled_off() {
+            // Releasing a held back key ends the window early.
+            let held_back = COMBOS
+                .read()
+                .pending
+                .iter()
+                .flatten()
+                .any(|e| e.addr() == event.addr());
+
+            if held_back {
+                // The release must follow the re-injected presses.
+                Self::resolve();
+                Self::reinject(*event);
+                return Err(EventHandlerError::Abort);
+            }
+
+            return Ok(());
+        }
+
+        if !event.state().key_toggled_on() {
+            return Ok(());
+        }
+
+        let key = *event.key();
+        let (is_combo_key, lookup, full) = {
+            let combos = COMBOS.read();
+            (combos.is_combo_key(&key), combos.lookup(Some(&key)), combos.len == MAX_COMBO_KEYS)
+        };
+
+        if !is_combo_key || (lookup.exact.is_none() && !lookup.longer) || full {
+            // The key cannot complete a combo with the held back keys: handle them first,
+            // then start over with this key.
+            if COMBOS.read().is_pending() {
+                Self::resolve();
+
+                if !is_combo_key {
+                    Self::reinject(*event);
+                    return Err(EventHandlerError::Abort);
+                }
+            }
+
+            if !is_combo_key {
+                return Ok(());
+            }
+        }
+
+        COMBOS.write().push(*event, millis());
+
+        let lookup = COMBOS.read().lookup(None);
+
+        if lookup.exact.is_some() && !lookup.longer {
+            Self::resolve();
+        }
+
+        Err(EventHandlerError::Abort)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_addr::KeyAddr, keyswitch_state::KeyswitchState};
+
+    static COMBO_DEFS: [ComboEntry; 2] = [(&[Key_J, Key_K], Key_Escape), (&[Key_J, Key_K, Key_L], Key_Tab)];
+
+    fn press(col: u8, key: Key) -> KeyEvent {
+        let mut event = KeyEvent::next(KeyAddr::create(0, col), KeyswitchState::from(0b10));
+        event.set_key(key);
+        event
+    }
+
+    fn combos() -> Combos {
+        let mut combos = Combos::new();
+        combos.set_combos(&COMBO_DEFS);
+        combos
+    }
+
+    #[test]
+    fn completed_combo_outputs_its_key() {
+        let mut combos = combos();
+
+        combos.push(press(0, Key_J), 100);
+        combos.push(press(1, Key_K), 110);
+        combos.push(press(2, Key_L), 120);
+
+        assert_eq!(combos.lookup(None), ComboMatch { exact: Some(1), longer: false });
+
+        let (output, _, len) = combos.take_resolution();
+
+        assert_eq!(output, Some(Key_Tab));
+        assert_eq!(len, 3);
+        assert!(!combos.is_pending());
+    }
+
+    #[test]
+    fn timeout_flushes_held_back_keys_in_order() {
+        let mut combos = combos();
+        let (first, second) = (press(1, Key_K), press(0, Key_J));
+
+        combos.push(first, 100);
+        combos.push(second, 120);
+
+        assert!(!combos.expired(149));
+        assert!(combos.expired(150));
+
+        // J and K complete a combo, but the window expired waiting for L.
+        let (output, pending, len) = combos.take_resolution();
+
+        assert_eq!(output, Some(Key_Escape));
+        assert_eq!(len, 2);
+        assert_eq!(pending[0].map(|e| e.id()), Some(first.id()));
+        assert_eq!(pending[1].map(|e| e.id()), Some(second.id()));
+        assert!(second.id().is_after(&first.id()));
+
+        // Keys that complete no combo are flushed as they are.
+        combos.push(press(2, Key_L), 200);
+        assert!(combos.expired(250));
+        assert_eq!(combos.take_resolution().0, None);
+    }
+
+    #[test]
+    fn overlapping_combos_wait_for_the_longest_match() {
+        let mut combos = combos();
+
+        combos.push(press(0, Key_J), 100);
+        assert_eq!(combos.lookup(None), ComboMatch { exact: None, longer: true });
+
+        // J+K is complete, but part of J+K+L: resolution waits.
+        combos.push(press(1, Key_K), 110);
+        assert_eq!(combos.lookup(None), ComboMatch { exact: Some(0), longer: true });
+
+        assert_eq!(combos.lookup(Some(&Key_L)), ComboMatch { exact: Some(1), longer: false });
+        assert_eq!(combos.lookup(Some(&Key_A)), ComboMatch { exact: None, longer: false });
+    }
+}
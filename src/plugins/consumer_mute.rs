@@ -0,0 +1,195 @@
+use keyboardio_hid::media::MediaKeyboard;
+
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::persistable::Persistable;
+use crate::{with_hid, key_defs::*, key_event::KeyEvent, lock, return_on_err};
+
+/// Global mute state and policy.
+pub static CONSUMER_MUTE: lock::Spinlock<ConsumerMute> = lock::Spinlock::new(ConsumerMute::new());
+
+/// How the `Consumer_Mute` key is reported to the host.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MutePolicy {
+    /// Send the raw HID usage on press, and release it on release.
+    #[default]
+    Raw,
+    /// Each tap toggles the tracked mute state.
+    Toggle,
+    /// Mute while the key is held, and unmute on release.
+    Momentary,
+}
+
+impl From<u8> for MutePolicy {
+    fn from(b: u8) -> Self {
+        match b {
+            1 => Self::Toggle,
+            2 => Self::Momentary,
+            _ => Self::Raw,
+        }
+    }
+}
+
+/// Tracks the host mute state, and reports the `Consumer_Mute` key based on a [MutePolicy].
+///
+/// HID Mute is a toggle usage: every press/release pair flips the host state. The
+/// tracked state can drift if the host is muted by other means, use
+/// [set_muted](Self::set_muted) to resynchronize.
+pub struct ConsumerMute {
+    policy: MutePolicy,
+    muted: bool,
+    slot: Option<SlotHandle>,
+}
+
+impl ConsumerMute {
+    /// Creates a new [ConsumerMute] using the [MutePolicy::Raw] policy.
+    pub const fn new() -> Self {
+        Self {
+            policy: MutePolicy::Raw,
+            muted: false,
+            slot: None,
+        }
+    }
+
+    /// Gets the mute [MutePolicy].
+    pub fn policy(&self) -> MutePolicy {
+        self.policy
+    }
+
+    /// Sets the mute [MutePolicy].
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the policy across reboots.
+    pub fn set_policy(&mut self, policy: MutePolicy) {
+        self.policy = policy;
+    }
+
+    /// Gets the tracked mute state.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Sets the tracked mute state, without sending anything to the host.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Handles a `Consumer_Mute` key event.
+    ///
+    /// Returns whether the mute usage should be tapped.
+    pub fn handle(&mut self, toggled_on: bool) -> bool {
+        let tap = match self.policy {
+            MutePolicy::Raw => false,
+            MutePolicy::Toggle => toggled_on,
+            MutePolicy::Momentary => toggled_on != self.muted,
+        };
+
+        if tap {
+            self.muted = !self.muted;
+        }
+
+        tap
+    }
+
+    fn tap_mute() {
+        let keycode = Consumer_Mute.consumer() as u8;
+
+        // Sent in two steps, so the USB interrupt can send the press in between.
+        return_on_err!(with_hid(|hid| {
+            hid.media_keyboard.press(keycode);
+            let _ = hid.media_keyboard.send_report();
+        }));
+        return_on_err!(with_hid(|hid| {
+            hid.media_keyboard.release(keycode);
+            let _ = hid.media_keyboard.send_report();
+        }));
+    }
+}
+
+impl Persistable for ConsumerMute {
+    const SIZE: u16 = 1;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[0] = self.policy as u8;
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.policy = buf[0].into();
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for ConsumerMute {
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if *event.key() != Consumer_Mute {
+            return Ok(());
+        }
+
+        let mut mute = CONSUMER_MUTE.write();
+
+        if mute.policy() == MutePolicy::Raw {
+            return Ok(());
+        }
+
+        if mute.handle(event.state().key_toggled_on()) {
+            Self::tap_mute();
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mute(policy: MutePolicy) -> ConsumerMute {
+        let mut mute = ConsumerMute::new();
+        mute.set_policy(policy);
+        mute
+    }
+
+    #[test]
+    fn toggle_taps_on_each_press() {
+        let mut mute = mute(MutePolicy::Toggle);
+
+        assert!(mute.handle(true));
+        assert!(!mute.handle(false));
+        assert!(mute.is_muted());
+
+        assert!(mute.handle(true));
+        assert!(!mute.handle(false));
+        assert!(!mute.is_muted());
+    }
+
+    #[test]
+    fn momentary_mutes_while_held() {
+        let mut mute = mute(MutePolicy::Momentary);
+
+        assert!(mute.handle(true));
+        assert!(mute.is_muted());
+        assert!(mute.handle(false));
+        assert!(!mute.is_muted());
+
+        // Already muted by other means, pressing the key keeps it muted.
+        mute.set_muted(true);
+
+        assert!(!mute.handle(true));
+        assert!(mute.handle(false));
+        assert!(!mute.is_muted());
+    }
+
+    #[test]
+    fn policy_is_decoded_from_u8() {
+        assert_eq!(MutePolicy::from(MutePolicy::Toggle as u8), MutePolicy::Toggle);
+        assert_eq!(MutePolicy::from(MutePolicy::Momentary as u8), MutePolicy::Momentary);
+        assert_eq!(MutePolicy::from(0xff), MutePolicy::Raw);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::CYCLE;
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event;
+use crate::{key_defs::*, key_event::KeyEvent, lock};
+
+/// Key replacing the previously typed key with the next option of its cycle.
+#[allow(non_upper_case_globals)]
+pub const Key_Cycle: Key = Key::from_raw(CYCLE);
+
+/// Cycle lists: tapping the Cycle key after typing a key of a list replaces it with the
+/// next key of that list.
+pub type CycleTable = &'static [&'static [Key]];
+
+/// Global cycle state.
+pub static CYCLE_STATE: lock::Spinlock<Cycle> = lock::Spinlock::new(Cycle::new());
+
+/// Replaces the previously typed key with the next option of a user-supplied cycle.
+///
+/// With a cycle list of `[Key_A, Key_B, Key_C]`, typing `a` then tapping the Cycle key
+/// twice produces `c`: each tap sends a `Backspace`, followed by the next key of the list,
+/// wrapping around at its end. Pressing any other key ends the cycle.
+pub struct Cycle {
+    table: CycleTable,
+    last_key: Option<Key>,
+    position: Option<(usize, usize)>,
+}
+
+impl Cycle {
+    /// Creates a new [Cycle] with an empty table.
+    pub const fn new() -> Self {
+        Self {
+            table: &[],
+            last_key: None,
+            position: None,
+        }
+    }
+
+    /// Sets the cycle lists.
+    pub fn set_table(&mut self, table: CycleTable) {
+        self.table = table;
+        self.reset();
+    }
+
+    /// Forgets the previously typed key, and ends the current cycle.
+    pub fn reset(&mut self) {
+        self.last_key = None;
+        self.position = None;
+    }
+
+    /// Records a key typed outside of a cycle, ending the current cycle.
+    pub fn set_last_key(&mut self, key: Key) {
+        self.last_key = Some(key);
+        self.position = None;
+    }
+
+    /// Advances the cycle.
+    ///
+    /// Returns the next key to type, or `None` if the previously typed key is not part of
+    /// a cycle list.
+    pub fn advance(&mut self) -> Option<Key> {
+        let (list, pos) = match self.position {
+            Some(position) => position,
+            None => {
+                let last_key = self.last_key?;
+
+                self.table
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, keys)| keys.iter().position(|k| *k == last_key).map(|pos| (i, pos)))?
+            }
+        };
+
+        let keys = self.table.get(list).filter(|keys| !keys.is_empty())?;
+        let next = (pos + 1) % keys.len();
+
+        self.position = Some((list, next));
+
+        keys.get(next).copied()
+    }
+}
+
+impl EventHandler for Cycle {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Cycle")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if event.state().key_is_injected() || !event.state().key_toggled_on() {
+            return if *event.key() == Key_Cycle {
+                Err(EventHandlerError::EventConsumed)
+            } else {
+                Ok(())
+            };
+        }
+
+        if *event.key() != Key_Cycle {
+            if event.key().is_keyboard_key() && !event.key().is_keyboard_modifier() {
+                CYCLE_STATE.write().set_last_key(*event.key());
+            } else if !event.key().is_keyboard_modifier() {
+                CYCLE_STATE.write().reset();
+            }
+            return Ok(());
+        }
+
+        let next = CYCLE_STATE.write().advance();
+
+        if let Some(key) = next {
+            let events = [
+                injected_event(Key_Backspace, true),
+                injected_event(Key_Backspace, false),
+                injected_event(key, true),
+                injected_event(key, false),
+            ];
+
+            let _ = Runtime::queue_key_events(&events);
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TABLE: [&[Key]; 2] = [&[Key_A, Key_B, Key_C], &[Key_1, Key_2]];
+
+    fn cycle() -> Cycle {
+        let mut cycle = Cycle::new();
+        cycle.set_table(&TABLE);
+        cycle
+    }
+
+    #[test]
+    fn taps_advance_and_wrap_around() {
+        let mut cycle = cycle();
+
+        cycle.set_last_key(Key_A);
+
+        assert_eq!(cycle.advance(), Some(Key_B));
+        assert_eq!(cycle.advance(), Some(Key_C));
+        assert_eq!(cycle.advance(), Some(Key_A));
+    }
+
+    #[test]
+    fn another_key_starts_a_new_cycle() {
+        let mut cycle = cycle();
+
+        cycle.set_last_key(Key_B);
+        assert_eq!(cycle.advance(), Some(Key_C));
+
+        cycle.set_last_key(Key_2);
+        assert_eq!(cycle.advance(), Some(Key_1));
+    }
+
+    #[test]
+    fn keys_outside_the_table_do_not_cycle() {
+        let mut cycle = cycle();
+
+        assert_eq!(cycle.advance(), None);
+
+        cycle.set_last_key(Key_Z);
+        assert_eq!(cycle.advance(), None);
+
+        cycle.set_last_key(Key_A);
+        cycle.reset();
+        assert_eq!(cycle.advance(), None);
+    }
+}
@@ -0,0 +1,47 @@
+use ufmt::uWrite;
+
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::bootloader::active_bootloader;
+use crate::try_with_hid;
+
+/// Adds the `device.reset` Focus command, rebooting into the bootloader.
+///
+/// This lets Chrysalis flash new firmware without pressing the physical reset button.
+/// Before rebooting, an empty report is sent, so no key stays stuck on the host.
+///
+/// The `device.reboot` command, restarting the firmware instead, is always available,
+/// see [Reboot](crate::runtime::Reboot).
+///
+/// The reboot uses the [bootloader](crate::bootloader::active_bootloader) selected by the
+/// runtime. For Caterina, the magic key `0x7777` is stored at address `0x0800`, and the
+/// watchdog is armed with a 125 ms timeout. When the watchdog resets the MCU, Caterina
+/// finds the magic key, and stays in the bootloader instead of starting the sketch.
+///
+/// Only available with the `device_reset` feature, which can be disabled for builds
+/// where the host must not be able to enter the bootloader, e.g. behind a KVM switch.
+pub struct DeviceReset;
+
+impl EventHandler for DeviceReset {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("DeviceReset")
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("device.reset\r\n");
+            return Ok(());
+        }
+
+        if command != "device.reset" {
+            return Ok(());
+        }
+
+        try_with_hid(|hid| hid.release_all_keys()).map_err(|_| EventHandlerError::Error)?;
+        try_with_hid(|hid| hid.send_report()).map_err(|_| EventHandlerError::Error)?;
+
+        active_bootloader().reboot_bootloader()
+    }
+}
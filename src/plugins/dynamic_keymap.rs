@@ -0,0 +1,255 @@
+use core::ops::Range;
+
+use ufmt::uWrite;
+
+use crate::driver::storage::{SlotHandle, STORAGE};
+use crate::error::{self, Error};
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{parse_keys_exact, split_command, write_keys, FOCUS_OUTPUT};
+use crate::layers::{NUM_KEYS, NUM_LAYERS};
+use crate::{key_addr::KeyAddr, key_defs::*, lock, LAYER};
+
+/// Number of keys in the stored keymap, all layers included.
+pub const DYNAMIC_KEYMAP_LEN: usize = NUM_KEYS * NUM_LAYERS;
+
+/// Number of keys read or written by a single Focus command.
+///
+/// A page write, e.g. `keymap.page 123` followed by eight values of up to five digits,
+/// fits in the [FOCUS_INPUT_LEN](crate::plugins::focus_serial::FOCUS_INPUT_LEN) byte
+/// Focus input buffer.
+pub const DYNAMIC_KEYMAP_PAGE: usize = 8;
+
+/// Number of [DYNAMIC_KEYMAP_PAGE] key pages in the stored keymap. The last page may be
+/// shorter.
+pub const DYNAMIC_KEYMAP_PAGES: usize = (DYNAMIC_KEYMAP_LEN + DYNAMIC_KEYMAP_PAGE - 1) / DYNAMIC_KEYMAP_PAGE;
+
+/// Number of bytes before the stored keys: the stored key count.
+const HEADER_LEN: u16 = 2;
+
+/// Global dynamic keymap state.
+pub static DYNAMIC_KEYMAP: lock::Spinlock<DynamicKeymap> = lock::Spinlock::new(DynamicKeymap::new());
+
+/// Keymap stored in EEPROM, editable at runtime over Focus.
+///
+/// On first boot, or after a firmware update changing the keymap size, the PROGMEM
+/// keymap is copied to storage. From then on, [Layer::key](crate::layers::Layer::key)
+/// (and so [Runtime::lookup_key](crate::runtime::Runtime::lookup_key)) reads the stored
+/// keymap instead.
+///
+/// Keys are addressed by their index across all layers: `layer * NUM_KEYS + key`. The
+/// Focus input and output buffers can't hold a whole keymap, so instead of the
+/// full-keymap commands Chrysalis uses, the keymap is read and written in pages of
+/// [DYNAMIC_KEYMAP_PAGE] keys, page `n` starting at key `n * DYNAMIC_KEYMAP_PAGE`:
+///
+/// - `keymap.page n` prints the stored keys of page `n`,
+/// - `keymap.page n key...` overwrites page `n`,
+/// - `keymap.default n` prints the PROGMEM keys of page `n`,
+/// - `keymap.reset` copies the PROGMEM keymap to storage again.
+///
+/// A write must hold exactly the keys of the page, all valid, or it is rejected as a
+/// whole.
+pub struct DynamicKeymap {
+    slot: Option<SlotHandle>,
+    active: bool,
+}
+
+impl DynamicKeymap {
+    /// Creates a new [DynamicKeymap], inactive until its storage is set up.
+    pub const fn new() -> Self {
+        Self {
+            slot: None,
+            active: false,
+        }
+    }
+
+    /// Gets whether the stored keymap is used.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Claims a storage slot for the keymap.
+    ///
+    /// If the stored key count does not match [DYNAMIC_KEYMAP_LEN], the PROGMEM keymap is
+    /// copied to storage.
+    pub fn setup_storage(&mut self) -> error::Result<()> {
+        let slot = STORAGE.write().reserve(HEADER_LEN + 2 * DYNAMIC_KEYMAP_LEN as u16)?;
+        self.slot = Some(slot);
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        STORAGE.read().read_slot(slot, 0, &mut header)?;
+
+        if u16::from_le_bytes(header) as usize == DYNAMIC_KEYMAP_LEN {
+            self.active = true;
+            Ok(())
+        } else {
+            self.reset()
+        }
+    }
+
+    /// Gets the stored key at `key_addr` on `layer`, `None` if the stored keymap is
+    /// inactive.
+    pub fn key(&self, layer: usize, key_addr: &KeyAddr) -> Option<Key> {
+        if !self.active || layer >= NUM_LAYERS || !key_addr.is_valid() || key_addr.index() >= NUM_KEYS {
+            return None;
+        }
+
+        self.read(layer * NUM_KEYS + key_addr.index()).ok()
+    }
+
+    /// Gets the stored key at `index`, across all layers.
+    pub fn read(&self, index: usize) -> error::Result<Key> {
+        let slot = self.slot.ok_or(Error::Storage)?;
+
+        if index >= DYNAMIC_KEYMAP_LEN {
+            return Err(Error::InvalidKeymap);
+        }
+
+        let mut raw = [0u8; 2];
+        STORAGE.read().read_slot(slot, Self::offset(index), &mut raw)?;
+
+        Ok(Key::from_raw(u16::from_le_bytes(raw)))
+    }
+
+    /// Overwrites the stored keys from `index` on, across all layers.
+    ///
+    /// Nothing is written if the keys don't fit in the keymap.
+    pub fn write(&mut self, index: usize, keys: &[Key]) -> error::Result<()> {
+        let slot = self.slot.ok_or(Error::Storage)?;
+
+        if index + keys.len() > DYNAMIC_KEYMAP_LEN {
+            return Err(Error::InvalidKeymap);
+        }
+
+        let mut storage = STORAGE.write();
+
+        for (i, key) in keys.iter().enumerate() {
+            storage.write_slot(slot, Self::offset(index + i), &key.raw().to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the PROGMEM keymap to storage, and uses it.
+    pub fn reset(&mut self) -> error::Result<()> {
+        let slot = self.slot.ok_or(Error::Storage)?;
+
+        // Deactivate first, so `Layer::key` reads the PROGMEM keymap.
+        self.active = false;
+
+        for index in 0..DYNAMIC_KEYMAP_LEN {
+            self.write(index, &[Self::default_key(index)])?;
+        }
+
+        STORAGE
+            .write()
+            .write_slot(slot, 0, &(DYNAMIC_KEYMAP_LEN as u16).to_le_bytes())?;
+        self.active = true;
+
+        Ok(())
+    }
+
+    /// Gets the PROGMEM key at `index`, across all layers.
+    pub fn default_key(index: usize) -> Key {
+        let addr = KeyAddr::new((index % NUM_KEYS) as u8);
+
+        LAYER.read().default_key(index / NUM_KEYS, &addr)
+    }
+
+    const fn offset(index: usize) -> u16 {
+        HEADER_LEN + 2 * index as u16
+    }
+
+    /// Gets the range of key indices of `page`, `None` past the last page.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::plugins::dynamic_keymap::*;
+    ///
+    /// assert_eq!(DynamicKeymap::page_range(0), Some(0..DYNAMIC_KEYMAP_PAGE));
+    ///
+    /// let last = DynamicKeymap::page_range(DYNAMIC_KEYMAP_PAGES - 1).unwrap();
+    /// assert_eq!(last.end, DYNAMIC_KEYMAP_LEN);
+    /// assert!(!last.is_empty() && last.len() <= DYNAMIC_KEYMAP_PAGE);
+    ///
+    /// assert_eq!(DynamicKeymap::page_range(DYNAMIC_KEYMAP_PAGES), None);
+    /// ```
+    pub fn page_range(page: usize) -> Option<Range<usize>> {
+        if page >= DYNAMIC_KEYMAP_PAGES {
+            return None;
+        }
+
+        let start = page * DYNAMIC_KEYMAP_PAGE;
+
+        Some(start..(start + DYNAMIC_KEYMAP_PAGE).min(DYNAMIC_KEYMAP_LEN))
+    }
+
+    /// Parses the page number Focus argument, returning the key indices of the page with
+    /// the remaining arguments.
+    fn parse_page(args: &str) -> Result<(Range<usize>, &str)> {
+        let (page, rest) = split_command(args);
+        let page: usize = page.parse().map_err(|_| EventHandlerError::Error)?;
+
+        Self::page_range(page)
+            .map(|range| (range, rest))
+            .ok_or(EventHandlerError::Error)
+    }
+}
+
+impl EventHandler for DynamicKeymap {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("DynamicKeymap")
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let mut output = FOCUS_OUTPUT.write();
+            let _ = output.write_str("keymap.page\r\n");
+            let _ = output.write_str("keymap.default\r\n");
+            let _ = output.write_str("keymap.reset\r\n");
+            return Ok(());
+        }
+
+        match command {
+            "keymap.page" | "keymap.default" => {
+                let (range, values) = Self::parse_page(args)?;
+
+                if command == "keymap.page" && !values.is_empty() {
+                    let mut keys = [Key_NoKey; DYNAMIC_KEYMAP_PAGE];
+                    let keys = &mut keys[..range.len()];
+                    parse_keys_exact(values, keys).map_err(|_| EventHandlerError::Error)?;
+
+                    DYNAMIC_KEYMAP
+                        .write()
+                        .write(range.start, keys)
+                        .map_err(|_| EventHandlerError::Error)?;
+                } else {
+                    let keymap = DYNAMIC_KEYMAP.read();
+
+                    let keys = range.map(|i| {
+                        if command == "keymap.page" && keymap.is_active() {
+                            keymap.read(i).unwrap_or(Key_NoKey)
+                        } else {
+                            Self::default_key(i)
+                        }
+                    });
+
+                    write_keys(&mut *FOCUS_OUTPUT.write(), keys).map_err(|_| EventHandlerError::Error)?;
+
+                    return Err(EventHandlerError::EventConsumed);
+                }
+            }
+            "keymap.reset" => {
+                DYNAMIC_KEYMAP.write().reset().map_err(|_| EventHandlerError::Error)?;
+            }
+            _ => return Ok(()),
+        }
+
+        // Transparent keys may have changed, so the active layer of each key too.
+        LAYER.write().update_active_layers();
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
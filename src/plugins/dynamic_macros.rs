@@ -0,0 +1,313 @@
+use crate::driver::storage::{blob_slot_len, SlotHandle, STORAGE};
+use crate::error::{self, Error};
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{DYNAMIC_MACRO_FIRST, DYNAMIC_MACRO_LAST};
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event;
+use crate::{key_defs::Key, key_event::KeyEvent, lock};
+
+/// Number of dynamic macros. The last key of the `DYNAMIC_MACRO` range is the record key.
+pub const NUM_DYNAMIC_MACROS: u8 = (DYNAMIC_MACRO_LAST - DYNAMIC_MACRO_FIRST) as u8;
+
+/// Total number of bytes available for all recorded macros, including one end marker per macro.
+pub const DYNAMIC_MACROS_LEN: usize = 256;
+
+/// Settings layout version of the stored macros.
+const DYNAMIC_MACROS_VERSION: u8 = 1;
+
+/// Marks the end of a macro.
+const MACRO_ACTION_END: u8 = 0;
+/// Presses the recorded key.
+const MACRO_ACTION_PRESS: u8 = 1;
+/// Releases the recorded key.
+const MACRO_ACTION_RELEASE: u8 = 2;
+
+/// Number of bytes used by a recorded step: the action, and the raw key.
+const STEP_LEN: usize = 3;
+
+/// Key that starts, and stops, a recording.
+#[allow(non_upper_case_globals)]
+pub const Key_DynamicMacroRecord: Key = Key::from_raw(DYNAMIC_MACRO_LAST);
+
+/// Creates the key playing back the dynamic macro `n`.
+#[macro_export]
+macro_rules! DM {
+    ($n:tt) => {
+        $crate::key_defs::Key::from_raw($crate::plugins::ranges::DYNAMIC_MACRO_FIRST + $n as u16)
+    };
+}
+
+/// Global dynamic macros state.
+pub static DYNAMIC_MACROS: lock::Spinlock<DynamicMacros> = lock::Spinlock::new(DynamicMacros::new());
+
+/// Recording state of the [DynamicMacros] plugin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordState {
+    /// Not recording.
+    Idle,
+    /// The record key was pressed, waiting for a `DM(n)` key to select the macro to record.
+    Armed,
+    /// Recording key events into the selected macro.
+    Recording(u8),
+}
+
+/// Records sequences of key events at runtime, and plays them back.
+///
+/// Press [Key_DynamicMacroRecord], then a `DM(n)` key to start recording macro `n`. Every
+/// following key event is recorded until [Key_DynamicMacroRecord] is pressed again, which
+/// stores the macro in EEPROM. Pressing `DM(n)` afterwards replays the macro.
+///
+/// All macros share a single buffer of [DYNAMIC_MACROS_LEN] bytes. Recording a macro
+/// replaces its previous contents. If the buffer fills up, the recording is aborted, and
+/// the macro is left empty.
+pub struct DynamicMacros {
+    buf: [u8; DYNAMIC_MACROS_LEN],
+    len: usize,
+    state: RecordState,
+    cursor: usize,
+    slot: Option<SlotHandle>,
+}
+
+impl DynamicMacros {
+    /// Creates a new [DynamicMacros] with all macros empty.
+    pub const fn new() -> Self {
+        Self {
+            buf: [MACRO_ACTION_END; DYNAMIC_MACROS_LEN],
+            len: NUM_DYNAMIC_MACROS as usize,
+            state: RecordState::Idle,
+            cursor: 0,
+            slot: None,
+        }
+    }
+
+    /// Gets the recording state.
+    pub fn state(&self) -> RecordState {
+        self.state
+    }
+
+    /// Gets the number of bytes used by all macros.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether all macros are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == NUM_DYNAMIC_MACROS as usize
+    }
+
+    /// Claims a storage slot, and restores any previously recorded macros.
+    ///
+    /// If the stored macros are missing or corrupt, all macros are left empty.
+    pub fn setup_storage(&mut self) -> error::Result<()> {
+        let slot = STORAGE.write().reserve(blob_slot_len(DYNAMIC_MACROS_LEN as u16))?;
+        self.slot = Some(slot);
+
+        let mut buf = [MACRO_ACTION_END; DYNAMIC_MACROS_LEN];
+
+        match STORAGE.read().restore(slot, DYNAMIC_MACROS_VERSION, &mut buf) {
+            Ok(()) => {
+                if let Some(len) = Self::used_len(&buf) {
+                    self.buf = buf;
+                    self.len = len;
+                }
+                Ok(())
+            }
+            Err(Error::StorageCorrupt) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Commits all macros to storage.
+    pub fn commit(&self) -> error::Result<()> {
+        let slot = self.slot.ok_or(Error::Storage)?;
+        STORAGE.write().commit(slot, DYNAMIC_MACROS_VERSION, &self.buf)
+    }
+
+    /// Starts recording macro `index`, discarding its previous contents.
+    pub fn start_recording(&mut self, index: u8) {
+        if index >= NUM_DYNAMIC_MACROS {
+            self.state = RecordState::Idle;
+            return;
+        }
+
+        let start = self.macro_start(index);
+        let end = self.macro_end(start);
+
+        self.remove(start, end - start);
+
+        self.cursor = start;
+        self.state = RecordState::Recording(index);
+    }
+
+    /// Stops recording, and stores the macros.
+    pub fn stop_recording(&mut self) -> error::Result<()> {
+        let was_recording = matches!(self.state, RecordState::Recording(_));
+
+        self.state = RecordState::Idle;
+
+        if was_recording {
+            self.commit()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Appends a key event to the macro being recorded.
+    ///
+    /// If the buffer is full, the recording is aborted, and the partially recorded macro
+    /// is discarded.
+    pub fn record(&mut self, key: Key, toggled_on: bool) {
+        let index = match self.state {
+            RecordState::Recording(index) => index,
+            _ => return,
+        };
+
+        if self.len + STEP_LEN > DYNAMIC_MACROS_LEN {
+            let start = self.macro_start(index);
+            self.remove(start, self.cursor - start);
+            let _ = self.stop_recording();
+            return;
+        }
+
+        let action = if toggled_on {
+            MACRO_ACTION_PRESS
+        } else {
+            MACRO_ACTION_RELEASE
+        };
+        let [hi, lo] = key.raw().to_be_bytes();
+
+        self.buf.copy_within(self.cursor..self.len, self.cursor + STEP_LEN);
+        self.buf[self.cursor..self.cursor + STEP_LEN].copy_from_slice(&[action, hi, lo]);
+
+        self.cursor += STEP_LEN;
+        self.len += STEP_LEN;
+    }
+
+    /// Gets the step of macro `index` at `offset` bytes from its start.
+    ///
+    /// Returns the key, whether it is pressed, and the offset of the next step, or `None`
+    /// at the end of the macro.
+    pub fn step(&self, index: u8, offset: usize) -> Option<(Key, bool, usize)> {
+        if index >= NUM_DYNAMIC_MACROS {
+            return None;
+        }
+
+        let pos = self.macro_start(index) + offset;
+
+        match self.buf.get(pos..pos + STEP_LEN)? {
+            &[MACRO_ACTION_END, ..] => None,
+            &[action, hi, lo] => Some((
+                Key::from_raw(u16::from_be_bytes([hi, lo])),
+                action == MACRO_ACTION_PRESS,
+                offset + STEP_LEN,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Plays back macro `index`, injecting each step as a key event.
+    ///
+    /// The steps are queued with [Runtime::queue_key_source], and played once the
+    /// current event handlers return.
+    pub fn play(index: u8) {
+        let _ = Runtime::queue_key_source(Self::play_event, index as u32);
+    }
+
+    /// Gets the key event of step `step` of macro `index`.
+    ///
+    /// The lock is only held while reading the step, since the injected events pass
+    /// through the event handlers again.
+    fn play_event(index: u32, step: usize) -> Option<KeyEvent> {
+        let (key, pressed, _) = DYNAMIC_MACROS.read().step(index as u8, step * STEP_LEN)?;
+
+        Some(injected_event(key, pressed))
+    }
+
+    fn macro_start(&self, index: u8) -> usize {
+        let mut pos = 0;
+
+        for _ in 0..index {
+            pos = self.macro_end(pos) + 1;
+        }
+
+        pos
+    }
+
+    fn macro_end(&self, mut pos: usize) -> usize {
+        while pos < self.len && self.buf[pos] != MACRO_ACTION_END {
+            pos += STEP_LEN;
+        }
+
+        pos
+    }
+
+    fn remove(&mut self, start: usize, count: usize) {
+        self.buf.copy_within(start + count..self.len, start);
+        self.len -= count;
+        self.buf[self.len..].fill(MACRO_ACTION_END);
+    }
+
+    /// Validates a restored buffer, returning the number of bytes used by all macros.
+    fn used_len(buf: &[u8]) -> Option<usize> {
+        let mut pos = 0;
+
+        for _ in 0..NUM_DYNAMIC_MACROS {
+            loop {
+                match buf.get(pos)? {
+                    &MACRO_ACTION_END => break,
+                    &MACRO_ACTION_PRESS | &MACRO_ACTION_RELEASE => pos += STEP_LEN,
+                    _ => return None,
+                }
+            }
+            pos += 1;
+        }
+
+        Some(pos)
+    }
+}
+
+impl EventHandler for DynamicMacros {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("DynamicMacros")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let raw = event.key().raw();
+        let toggled_on = event.state().key_toggled_on();
+
+        if !(DYNAMIC_MACRO_FIRST..=DYNAMIC_MACRO_LAST).contains(&raw) {
+            // Played back events are not recorded again.
+            if !event.state().key_is_injected() {
+                DYNAMIC_MACROS.write().record(*event.key(), toggled_on);
+            }
+            return Ok(());
+        }
+
+        if !toggled_on {
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        let state = DYNAMIC_MACROS.read().state();
+
+        if raw == DYNAMIC_MACRO_LAST {
+            match state {
+                RecordState::Idle => DYNAMIC_MACROS.write().state = RecordState::Armed,
+                _ => DYNAMIC_MACROS
+                    .write()
+                    .stop_recording()
+                    .map_err(|_| EventHandlerError::Error)?,
+            }
+        } else {
+            let index = (raw - DYNAMIC_MACRO_FIRST) as u8;
+
+            match state {
+                RecordState::Armed => DYNAMIC_MACROS.write().start_recording(index),
+                // Playing back while recording could recurse into the macro being recorded.
+                RecordState::Recording(_) => (),
+                RecordState::Idle => Self::play(index),
+            }
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
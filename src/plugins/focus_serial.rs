@@ -0,0 +1,310 @@
+use embedded_hal::serial;
+use ufmt::uWrite;
+
+use crate::driver::serial::UsbSerial;
+use crate::event_handler::{EventHandler, Result};
+use crate::focus::{set_line_part, split_command, stream_accepted, take_reply_deferred, LinePart, FOCUS_OUTPUT};
+use crate::{hooks::Hooks, lock, runtime::Runtime};
+
+/// Maximum length of a single Focus command line, including arguments.
+pub const FOCUS_INPUT_LEN: usize = 64;
+
+/// Global Focus serial handler, reading from the USB CDC-ACM serial port.
+pub static FOCUS_SERIAL: lock::Spinlock<FocusSerial<UsbSerial>> = lock::Spinlock::new(FocusSerial::new(UsbSerial));
+
+/// Sends the reply written to [FOCUS_OUTPUT] so far, making room for more.
+///
+/// For replies longer than [FOCUS_OUTPUT_LEN](crate::focus::FOCUS_OUTPUT_LEN), e.g. a
+/// whole colormap. The rest of the reply is sent once the handlers return, as usual.
+pub fn flush_output() {
+    let mut output = FOCUS_OUTPUT.write();
+    let _ = UsbSerial.write_bytes(output.as_bytes());
+    output.clear();
+}
+
+/// Sends the reply to a command deferred with [defer_reply](crate::focus::defer_reply),
+/// followed by the end-of-reply marker.
+pub fn finish_reply() {
+    flush_output();
+    let _ = UsbSerial.write_bytes(b".\r\n");
+}
+
+/// Reads newline-delimited Focus commands from a serial port, and dispatches them to
+/// the plugins via [Runtime::on_focus_event].
+///
+/// Partial lines are buffered across cycles. A line that does not fit in the input
+/// buffer is passed to the plugins in parts, see [line_part](crate::focus::line_part).
+/// If no plugin accepts the rest of the line, or a single value does not fit, the line is
+/// discarded, and answered with an error once its terminating newline arrives.
+pub struct FocusSerial<S> {
+    serial: S,
+    buf: [u8; FOCUS_INPUT_LEN],
+    len: usize,
+    streaming: bool,
+    reject: Option<&'static [u8]>,
+}
+
+impl<S> FocusSerial<S>
+where
+    S: serial::Read<u8> + serial::Write<u8>,
+{
+    /// Creates a new [FocusSerial] reading from the provided serial port.
+    pub const fn new(serial: S) -> Self {
+        Self {
+            serial,
+            buf: [0u8; FOCUS_INPUT_LEN],
+            len: 0,
+            streaming: false,
+            reject: None,
+        }
+    }
+
+    /// Reads all pending bytes from the serial port, and dispatches any complete command.
+    pub fn poll(&mut self) {
+        loop {
+            let byte = match self.serial.read() {
+                Ok(b) => b,
+                Err(_) => return,
+            };
+
+            match byte {
+                b'\r' => (),
+                b'\n' => {
+                    if let Some(error) = self.reject {
+                        self.write_bytes(error);
+                        self.write_bytes(b".\r\n");
+                    } else if self.streaming {
+                        self.dispatch(LinePart::Last);
+                    } else if self.len > 0 {
+                        self.dispatch(LinePart::Whole);
+                    }
+
+                    self.len = 0;
+                    self.streaming = false;
+                    self.reject = None;
+                }
+                // Drop the rest of a rejected line.
+                _ if self.reject.is_some() => (),
+                _ => {
+                    if self.len == self.buf.len() {
+                        self.stream_values();
+                    }
+
+                    if self.reject.is_none() && self.len < self.buf.len() {
+                        self.buf[self.len] = byte;
+                        self.len += 1;
+                    } else {
+                        // Reject the command rather than overflowing.
+                        self.reject.get_or_insert(b"error: command too long\r\n");
+                        self.len = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dispatches the complete values of a line that fills the input buffer, keeping the
+    /// command, and the value being received, for the next part.
+    ///
+    /// Leaves the buffer full if the line has no complete value to dispatch.
+    fn stream_values(&mut self) {
+        let line = &self.buf[..self.len];
+
+        let (Some(command_end), Some(values_end)) = (
+            line.iter().position(|&b| b == b' '),
+            line.iter().rposition(|&b| b == b' '),
+        ) else {
+            return;
+        };
+
+        if values_end == command_end {
+            return;
+        }
+
+        let len = self.len;
+        let part = if self.streaming { LinePart::Next } else { LinePart::First };
+
+        self.len = values_end;
+        self.dispatch(part);
+
+        if self.reject.is_some() {
+            return;
+        }
+
+        if !self.streaming && !stream_accepted() {
+            self.reject = Some(b"error: command too long\r\n");
+            return;
+        }
+
+        self.streaming = true;
+        self.buf.copy_within(values_end..len, command_end);
+        self.len = command_end + (len - values_end);
+    }
+
+    /// Dispatches the buffered line, or part of line, to the plugins.
+    ///
+    /// The reply is only sent after a [Whole](LinePart::Whole) line, or the
+    /// [Last](LinePart::Last) part of a streamed line.
+    fn dispatch(&mut self, part: LinePart) {
+        let is_end = matches!(part, LinePart::Whole | LinePart::Last);
+
+        set_line_part(part);
+
+        if matches!(part, LinePart::Whole | LinePart::First) {
+            FOCUS_OUTPUT.write().clear();
+        }
+
+        let line = match core::str::from_utf8(&self.buf[..self.len]) {
+            Ok(line) => line,
+            Err(_) if is_end => {
+                self.write_bytes(b"error: invalid command\r\n.\r\n");
+                return;
+            }
+            Err(_) => {
+                self.reject = Some(b"error: invalid command\r\n");
+                return;
+            }
+        };
+
+        let (command, _) = split_command(line);
+
+        match command {
+            "help" => {
+                let _ = FOCUS_OUTPUT.write().write_str("help\r\nplugins\r\nversion\r\n");
+                // Let the plugins append their own commands.
+                let _ = Runtime::on_focus_event(line);
+            }
+            "plugins" => {
+                let mut output = FOCUS_OUTPUT.write();
+                for name in Hooks::PLUGIN_NAMES.iter().filter(|name| !name.is_empty()) {
+                    let _ = output.write_str(name);
+                    let _ = output.write_str("\r\n");
+                }
+            }
+            "version" => {
+                let _ = FOCUS_OUTPUT
+                    .write()
+                    .write_str(concat!("Kaleidoscope ", env!("CARGO_PKG_VERSION"), "\r\n"));
+            }
+            _ => {
+                let _ = Runtime::on_focus_event(line);
+            }
+        }
+
+        if !is_end || take_reply_deferred() {
+            return;
+        }
+
+        let mut output = FOCUS_OUTPUT.write();
+        self.write_bytes(output.as_bytes());
+        self.write_bytes(b".\r\n");
+        output.clear();
+    }
+
+    /// Writes raw bytes to the serial port, blocking until they are sent.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter() {
+            if nb::block!(self.serial.write(b)).is_err() {
+                return;
+            }
+        }
+        let _ = nb::block!(self.serial.flush());
+    }
+}
+
+impl EventHandler for FocusSerial<UsbSerial> {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("FocusSerial")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        FOCUS_SERIAL.write().poll();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serial port replaying the provided input, and recording the output.
+    struct MockSerial<'a> {
+        input: &'a [u8],
+        output: [u8; 64],
+        output_len: usize,
+    }
+
+    impl<'a> MockSerial<'a> {
+        fn new(input: &'a [u8]) -> Self {
+            Self {
+                input,
+                output: [0u8; 64],
+                output_len: 0,
+            }
+        }
+
+        fn output(&self) -> &[u8] {
+            &self.output[..self.output_len]
+        }
+    }
+
+    impl serial::Read<u8> for MockSerial<'_> {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            let (&b, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.input = rest;
+            Ok(b)
+        }
+    }
+
+    impl serial::Write<u8> for MockSerial<'_> {
+        type Error = ();
+
+        fn write(&mut self, b: u8) -> nb::Result<(), ()> {
+            *self.output.get_mut(self.output_len).ok_or(nb::Error::Other(()))? = b;
+            self.output_len += 1;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn version_command_is_answered() {
+        let mut focus = FocusSerial::new(MockSerial::new(b"vers"));
+
+        // Partial lines wait for the rest of the command.
+        focus.poll();
+        assert_eq!(focus.serial.output(), b"");
+
+        focus.serial.input = b"ion\r\n";
+        focus.poll();
+
+        assert_eq!(
+            focus.serial.output(),
+            concat!("Kaleidoscope ", env!("CARGO_PKG_VERSION"), "\r\n.\r\n").as_bytes()
+        );
+    }
+
+    #[test]
+    fn overlong_command_is_rejected() {
+        let mut input = [b'x'; FOCUS_INPUT_LEN + 2];
+        input[FOCUS_INPUT_LEN + 1] = b'\n';
+
+        let mut focus = FocusSerial::new(MockSerial::new(&input));
+        focus.poll();
+
+        assert_eq!(focus.serial.output(), b"error: command too long\r\n.\r\n");
+
+        // The next line is read from a clean buffer.
+        focus.serial.input = b"\n";
+        focus.poll();
+
+        assert_eq!(focus.serial.output(), b"error: command too long\r\n.\r\n");
+        assert_eq!(focus.len, 0);
+    }
+}
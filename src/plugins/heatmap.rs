@@ -0,0 +1,202 @@
+use ufmt::uWrite;
+
+use crate::driver::led::{LedMode, Rgb};
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::layers::NUM_KEYS;
+use crate::{key_addr::KeyAddr, key_event::KeyEvent, lock};
+
+/// Default time, in milliseconds, after which the press counts are halved.
+pub const DEFAULT_HEATMAP_DECAY_INTERVAL: u32 = 10_000;
+
+/// Global heatmap state, register it as an LED mode with
+/// [LedControl::set_modes](crate::driver::led::LedControl::set_modes).
+pub static HEATMAP: lock::Spinlock<Heatmap> = lock::Spinlock::new(Heatmap::new());
+
+/// Gets the color of a heat level, from blue (cold, `0`) through green to red (hot, `255`).
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::heatmap::heat_color;
+///
+/// assert_eq!(heat_color(0), [0, 0, 255]);
+/// assert_eq!(heat_color(64), [0, 128, 127]);
+/// assert_eq!(heat_color(128), [1, 254, 0]);
+/// assert_eq!(heat_color(255), [255, 0, 0]);
+/// ```
+pub const fn heat_color(heat: u8) -> Rgb {
+    if heat < 128 {
+        let green = heat * 2;
+        [0, green, 255 - green]
+    } else {
+        let red = (heat - 128) * 2 + 1;
+        [red, 255 - red, 0]
+    }
+}
+
+/// LED mode coloring each key by how often it was pressed.
+///
+/// Every physical key press increments a one-byte counter for its address, saturating at
+/// 255. Counts are mapped to [heat_color] relative to the most pressed key, so the hottest
+/// key is always red. Every decay interval, all counts are halved, so old presses fade.
+///
+/// LEDs are indexed like [KeyAddr]s, one LED per key. The `heatmap.reset` Focus command
+/// clears the counts.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::key_addr::KeyAddr;
+/// use kaleidoscope::plugins::heatmap::Heatmap;
+///
+/// let mut heatmap = Heatmap::new();
+/// heatmap.set_decay_interval(1000);
+///
+/// let (hot, warm) = (KeyAddr::new(0), KeyAddr::new(1));
+/// for _ in 0..8 {
+///     heatmap.record(hot);
+/// }
+/// heatmap.record(warm);
+///
+/// assert_eq!(heatmap.color(hot), [255, 0, 0]);
+/// assert_eq!(heatmap.color(warm), [0, 62, 193]);
+/// assert_eq!(heatmap.color(KeyAddr::new(2)), [0, 0, 255]);
+///
+/// // Halved once per elapsed interval.
+/// heatmap.decay(999);
+/// assert_eq!(heatmap.count(hot), 8);
+/// heatmap.decay(1000);
+/// assert_eq!((heatmap.count(hot), heatmap.count(warm)), (4, 0));
+/// heatmap.decay(3500);
+/// assert_eq!(heatmap.count(hot), 1);
+/// heatmap.decay(10_000);
+/// assert_eq!(heatmap.count(hot), 0);
+/// ```
+pub struct Heatmap {
+    counts: [u8; NUM_KEYS],
+    decay_interval: u32,
+    last_decay: u32,
+}
+
+impl Heatmap {
+    /// Creates a new [Heatmap] with all counts cleared.
+    pub const fn new() -> Self {
+        Self {
+            counts: [0u8; NUM_KEYS],
+            decay_interval: DEFAULT_HEATMAP_DECAY_INTERVAL,
+            last_decay: 0,
+        }
+    }
+
+    /// Gets the time, in milliseconds, after which the counts are halved.
+    pub fn decay_interval(&self) -> u32 {
+        self.decay_interval
+    }
+
+    /// Sets the time, in milliseconds, after which the counts are halved. Zero is treated
+    /// as one.
+    pub fn set_decay_interval(&mut self, interval: u32) {
+        self.decay_interval = interval.max(1);
+    }
+
+    /// Gets the press count of the key at `addr`.
+    pub fn count(&self, addr: KeyAddr) -> u8 {
+        self.counts.get(addr.index()).copied().unwrap_or(0)
+    }
+
+    /// Counts a press of the key at `addr`.
+    pub fn record(&mut self, addr: KeyAddr) {
+        if let Some(count) = self.counts.get_mut(addr.index()) {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// Clears all counts.
+    pub fn reset(&mut self) {
+        self.counts = [0u8; NUM_KEYS];
+    }
+
+    /// Halves the counts once per decay interval elapsed since the last decay, at `now`
+    /// milliseconds.
+    pub fn decay(&mut self, now: u32) {
+        let interval = self.decay_interval.max(1);
+        let periods = now.wrapping_sub(self.last_decay) / interval;
+
+        if periods == 0 {
+            return;
+        }
+
+        let shift = periods.min(8);
+
+        for count in self.counts.iter_mut() {
+            *count = (*count as u16 >> shift) as u8;
+        }
+
+        self.last_decay = self.last_decay.wrapping_add(periods * interval);
+    }
+
+    /// Gets the color of the key at `addr`, relative to the most pressed key.
+    pub fn color(&self, addr: KeyAddr) -> Rgb {
+        self.color_with_max(self.count(addr), self.max_count())
+    }
+
+    fn max_count(&self) -> u8 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    fn color_with_max(&self, count: u8, max: u8) -> Rgb {
+        let heat = if max == 0 {
+            0
+        } else {
+            (count as u16 * 255 / max as u16) as u8
+        };
+
+        heat_color(heat)
+    }
+}
+
+impl LedMode for Heatmap {
+    fn tick(&mut self, now: u32) {
+        self.decay(now);
+    }
+
+    fn update(&mut self, leds: &mut [Rgb]) {
+        let max = self.max_count();
+
+        for (led, &count) in leds.iter_mut().zip(self.counts.iter()) {
+            *led = self.color_with_max(count, max);
+        }
+    }
+}
+
+impl EventHandler for Heatmap {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Heatmap")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if event.state().key_toggled_on() && !event.state().key_is_injected() && event.addr().is_valid() {
+            HEATMAP.write().record(*event.addr());
+        }
+
+        Ok(())
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("heatmap.reset\r\n");
+            return Ok(());
+        }
+
+        if command != "heatmap.reset" {
+            return Ok(());
+        }
+
+        HEATMAP.write().reset();
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
@@ -0,0 +1,171 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::lock;
+use crate::persistable::Persistable;
+
+/// Global host OS selection.
+pub static HOST_OS: lock::Spinlock<HostOS> = lock::Spinlock::new(HostOS::new());
+
+/// Operating system of the host the keyboard is attached to.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Os {
+    #[default]
+    Linux,
+    MacOs,
+    Windows,
+}
+
+impl Os {
+    /// Gets the name of the OS, as used by the `hostos.os` Focus command.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Linux => "linux",
+            Self::MacOs => "macos",
+            Self::Windows => "windows",
+        }
+    }
+
+    /// Gets the OS with the provided name.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::plugins::host_os::Os;
+    ///
+    /// assert_eq!(Os::from_name("macos"), Some(Os::MacOs));
+    /// assert_eq!(Os::from_name(Os::Windows.name()), Some(Os::Windows));
+    /// assert_eq!(Os::from_name("beos"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linux" => Some(Self::Linux),
+            "macos" => Some(Self::MacOs),
+            "windows" => Some(Self::Windows),
+            _ => None,
+        }
+    }
+}
+
+impl From<u8> for Os {
+    fn from(b: u8) -> Self {
+        match b {
+            1 => Self::MacOs,
+            2 => Self::Windows,
+            _ => Self::Linux,
+        }
+    }
+}
+
+/// Stores the host OS, so plugins can adapt keys the host interprets differently.
+///
+/// The host cannot be detected reliably over USB HID, so the OS is selected by the user,
+/// with [set_os](Self::set_os) or the `hostos.os` Focus command, and persisted. Without
+/// an argument, the command prints the current OS. Plugins read the selection with
+/// [current](Self::current).
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::persistable::Persistable;
+/// use kaleidoscope::plugins::host_os::{HostOS, Os};
+///
+/// let mut host_os = HostOS::new();
+/// host_os.set_os(Os::Windows);
+///
+/// let mut buf = [0u8; HostOS::SIZE as usize];
+/// host_os.save(&mut buf);
+///
+/// let mut restored = HostOS::new();
+/// restored.restore(&buf);
+///
+/// assert_eq!(restored.os(), Os::Windows);
+/// ```
+pub struct HostOS {
+    os: Os,
+    slot: Option<SlotHandle>,
+}
+
+impl HostOS {
+    /// Creates a new [HostOS] set to [Os::Linux].
+    pub const fn new() -> Self {
+        Self {
+            os: Os::Linux,
+            slot: None,
+        }
+    }
+
+    /// Gets the selected OS from the global [HOST_OS] state.
+    pub fn current() -> Os {
+        HOST_OS.read().os()
+    }
+
+    /// Gets the selected OS.
+    pub fn os(&self) -> Os {
+        self.os
+    }
+
+    /// Sets the selected OS.
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the selection across reboots.
+    pub fn set_os(&mut self, os: Os) {
+        self.os = os;
+    }
+}
+
+impl Persistable for HostOS {
+    const SIZE: u16 = 1;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[0] = self.os as u8;
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.os = buf[0].into();
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for HostOS {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("HostOS")
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("hostos.os\r\n");
+            return Ok(());
+        }
+
+        if command != "hostos.os" {
+            return Ok(());
+        }
+
+        let args = args.trim();
+
+        if args.is_empty() {
+            let os = HostOS::current();
+            uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", os.name()).map_err(|_| EventHandlerError::Error)?;
+        } else {
+            let os = Os::from_name(args).ok_or(EventHandlerError::Error)?;
+            let mut host_os = HOST_OS.write();
+
+            host_os.set_os(os);
+            host_os.commit().map_err(|_| EventHandlerError::Error)?;
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
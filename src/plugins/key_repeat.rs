@@ -0,0 +1,269 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::persistable::Persistable;
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event_at;
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, lock, millis::millis};
+
+/// Default time, in milliseconds, a key is held before it starts repeating.
+pub const DEFAULT_KEY_REPEAT_DELAY: u16 = 500;
+
+/// Default time, in milliseconds, between repeats.
+///
+/// Zero: the repeat is disabled until an interval is set, since the host already
+/// repeats held keys.
+pub const DEFAULT_KEY_REPEAT_INTERVAL: u16 = 0;
+
+/// Global key repeat state.
+pub static KEY_REPEAT: lock::Spinlock<KeyRepeat> = lock::Spinlock::new(KeyRepeat::new());
+
+/// Repeats the held key, like the host's typematic repeat.
+///
+/// Once the most recently pressed key has been held for the delay, it is released and
+/// pressed again once every interval, until it is released. Pressing another key
+/// repeats that key instead. Only Keyboard keys are repeated, not modifiers, layer
+/// keys, or other synthetic keys. Holding a modifier does not stop the repeat.
+///
+/// Both timings are runtime-tunable, with setters or the `keyrepeat.delay` and
+/// `keyrepeat.interval` Focus commands, and persisted. An interval of zero disables
+/// the repeat, which is the default.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_addr::KeyAddr, key_defs::*};
+/// use kaleidoscope::plugins::key_repeat::KeyRepeat;
+///
+/// let mut repeat = KeyRepeat::new();
+/// repeat.set_delay(500);
+/// repeat.set_interval(50);
+///
+/// let addr = KeyAddr::new(3);
+/// assert!(repeat.press(addr, Key_A, 1000));
+///
+/// // Nothing is repeated before the initial delay.
+/// assert_eq!(repeat.update(1499), None);
+/// assert_eq!(repeat.update(1500), Some((addr, Key_A)));
+///
+/// // Then once per interval.
+/// assert_eq!(repeat.update(1549), None);
+/// assert_eq!(repeat.update(1550), Some((addr, Key_A)));
+///
+/// // Modifiers are not repeated, and don't stop the repeat.
+/// assert!(!repeat.press(KeyAddr::new(4), Key_LeftShift, 1560));
+/// assert_eq!(repeat.update(1600), Some((addr, Key_A)));
+///
+/// // Releasing the key stops the repeat.
+/// repeat.release(addr);
+/// assert_eq!(repeat.update(2000), None);
+/// ```
+pub struct KeyRepeat {
+    delay: u16,
+    interval: u16,
+    held: Option<(KeyAddr, Key)>,
+    repeating: bool,
+    last: u32,
+    slot: Option<SlotHandle>,
+}
+
+impl KeyRepeat {
+    /// Creates a new [KeyRepeat] with the default timings.
+    pub const fn new() -> Self {
+        Self {
+            delay: DEFAULT_KEY_REPEAT_DELAY,
+            interval: DEFAULT_KEY_REPEAT_INTERVAL,
+            held: None,
+            repeating: false,
+            last: 0,
+            slot: None,
+        }
+    }
+
+    /// Gets the time, in milliseconds, a key is held before it starts repeating.
+    pub fn delay(&self) -> u16 {
+        self.delay
+    }
+
+    /// Sets the time, in milliseconds, a key is held before it starts repeating.
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the delay across reboots.
+    pub fn set_delay(&mut self, delay: u16) {
+        self.delay = delay;
+    }
+
+    /// Gets the time, in milliseconds, between repeats.
+    pub fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Sets the time, in milliseconds, between repeats. Zero disables the repeat.
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the interval across reboots.
+    pub fn set_interval(&mut self, interval: u16) {
+        self.interval = interval;
+    }
+
+    /// Gets whether `key` is repeated: Keyboard keys, except modifiers and `Key_NoKey`.
+    pub fn is_repeatable(key: &Key) -> bool {
+        key.is_keyboard_key()
+            && !key.is_keyboard_modifier()
+            && !key.is_layer_key()
+            && !key.is_mod_layer_key()
+            && *key != Key_NoKey
+    }
+
+    /// Records a key press at `now` milliseconds.
+    ///
+    /// Returns whether the key is repeated, in which case it replaces the held key.
+    pub fn press(&mut self, addr: KeyAddr, key: Key, now: u32) -> bool {
+        if !Self::is_repeatable(&key) {
+            return false;
+        }
+
+        self.held = Some((addr, key));
+        self.repeating = false;
+        self.last = now;
+
+        true
+    }
+
+    /// Records the release of the key at `addr`, stopping the repeat if it is the held key.
+    pub fn release(&mut self, addr: KeyAddr) {
+        if matches!(self.held, Some((held, _)) if held == addr) {
+            self.held = None;
+        }
+    }
+
+    /// Checks the timings at `now` milliseconds.
+    ///
+    /// Returns the address and key to repeat, if a repeat is due.
+    pub fn update(&mut self, now: u32) -> Option<(KeyAddr, Key)> {
+        let held = self.held?;
+
+        if self.interval == 0 {
+            return None;
+        }
+
+        let wait = if self.repeating { self.interval } else { self.delay };
+
+        if now.wrapping_sub(self.last) < wait as u32 {
+            return None;
+        }
+
+        // Late cycles delay the next repeat, rather than sending a burst.
+        self.repeating = true;
+        self.last = now;
+
+        Some(held)
+    }
+
+    /// Parses an optional `u16` Focus argument.
+    fn parse_arg(args: &str) -> Result<Option<u16>> {
+        let args = args.trim();
+
+        if args.is_empty() {
+            Ok(None)
+        } else {
+            args.parse().map(Some).map_err(|_| EventHandlerError::Error)
+        }
+    }
+}
+
+impl Persistable for KeyRepeat {
+    const SIZE: u16 = 4;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[..2].copy_from_slice(&self.delay.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.interval.to_le_bytes());
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.delay = u16::from_le_bytes([buf[0], buf[1]]);
+        self.interval = u16::from_le_bytes([buf[2], buf[3]]);
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for KeyRepeat {
+    // Injected events, including our own repeats, never change the held key.
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("KeyRepeat")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let now = millis();
+        let repeat = KEY_REPEAT.write().update(now);
+
+        // Tap the held key again: a release, then a press, at its own address.
+        if let Some((addr, key)) = repeat {
+            let _ = Runtime::queue_key_events(&[
+                injected_event_at(addr, key, false),
+                injected_event_at(addr, key, true),
+            ]);
+        }
+
+        Ok(())
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if !event.addr().is_valid() {
+            return Ok(());
+        }
+
+        if event.state().key_toggled_on() {
+            let now = millis();
+            KEY_REPEAT.write().press(*event.addr(), *event.key(), now);
+        } else if event.state().key_toggled_off() {
+            KEY_REPEAT.write().release(*event.addr());
+        }
+
+        Ok(())
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let mut output = FOCUS_OUTPUT.write();
+            let _ = output.write_str("keyrepeat.delay\r\n");
+            let _ = output.write_str("keyrepeat.interval\r\n");
+            return Ok(());
+        }
+
+        let delay = match command {
+            "keyrepeat.delay" => true,
+            "keyrepeat.interval" => false,
+            _ => return Ok(()),
+        };
+
+        let mut repeat = KEY_REPEAT.write();
+
+        match Self::parse_arg(args)? {
+            Some(value) if delay => repeat.set_delay(value),
+            Some(value) => repeat.set_interval(value),
+            None => {
+                let value = if delay { repeat.delay() } else { repeat.interval() };
+
+                uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", value).map_err(|_| EventHandlerError::Error)?;
+
+                return Err(EventHandlerError::EventConsumed);
+            }
+        }
+
+        repeat.commit().map_err(|_| EventHandlerError::Error)?;
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
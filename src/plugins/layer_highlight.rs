@@ -0,0 +1,157 @@
+use crate::driver::led::{Rgb, LED_CONTROL, LED_COUNT};
+use crate::event_handler::{EventHandler, Result};
+use crate::layers::NUM_KEYS;
+use crate::{key_addr::KeyAddr, lock, LAYER};
+
+/// Default brightness, out of 255, of keys that are transparent on the top active layer.
+pub const DEFAULT_LAYER_HIGHLIGHT_DIM: u8 = 64;
+
+/// Global layer highlight state.
+pub static LAYER_HIGHLIGHT: lock::Spinlock<LayerHighlight> = lock::Spinlock::new(LayerHighlight::new());
+
+/// Colors keys by the layer they are looked up on.
+///
+/// Each layer with a color set with [set_colors](Self::set_colors) lights its keys in that
+/// color, over the current LED mode. Keys that are transparent on the top active layer,
+/// and fall through to a lower one, are dimmed. Keys of layers without a color keep the
+/// LED mode color.
+///
+/// LEDs are indexed like [KeyAddr]s, one LED per key. On boards without LEDs the plugin
+/// does nothing.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::layer_highlight::LayerHighlight;
+///
+/// let (red, green) = ([255, 0, 0], [0, 255, 0]);
+/// let colors: &'static [[u8; 3]] = vec![red, green].leak();
+///
+/// let mut highlight = LayerHighlight::new();
+/// highlight.set_colors(colors);
+///
+/// // Layer 1 is on top, keys 1 and 3 are transparent on it and come from layer 0.
+/// let active_layers = [1, 0, 1, 0, 2];
+/// let mut leds = [None; 5];
+/// highlight.compute(&mut leds, 1, |i| active_layers[i]);
+///
+/// let dim_red = Some([64, 0, 0]);
+/// assert_eq!(leds, [Some(green), dim_red, Some(green), dim_red, None]);
+/// ```
+pub struct LayerHighlight {
+    colors: &'static [Rgb],
+    dim: u8,
+    leds: [Option<Rgb>; LED_COUNT],
+    dirty: bool,
+}
+
+impl LayerHighlight {
+    /// Creates a new [LayerHighlight], with no layer colors.
+    pub const fn new() -> Self {
+        Self {
+            colors: &[],
+            dim: DEFAULT_LAYER_HIGHLIGHT_DIM,
+            leds: [None; LED_COUNT],
+            dirty: true,
+        }
+    }
+
+    /// Sets the colors of the layers, indexed by layer.
+    pub fn set_colors(&mut self, colors: &'static [Rgb]) {
+        self.colors = colors;
+        self.dirty = true;
+    }
+
+    /// Gets the color of `layer`, if it has one.
+    pub fn color(&self, layer: u8) -> Option<Rgb> {
+        self.colors.get(layer as usize).copied()
+    }
+
+    /// Gets the brightness, out of 255, of keys transparent on the top active layer.
+    pub fn dim(&self) -> u8 {
+        self.dim
+    }
+
+    /// Sets the brightness, out of 255, of keys transparent on the top active layer.
+    pub fn set_dim(&mut self, dim: u8) {
+        self.dim = dim;
+        self.dirty = true;
+    }
+
+    /// Computes the color of each key, `None` for keys keeping the LED mode color.
+    ///
+    /// `layer_of` gets the layer key `i` is looked up on, `top_layer` is the top active
+    /// layer.
+    pub fn compute<F: Fn(usize) -> u8>(&self, leds: &mut [Option<Rgb>], top_layer: u8, layer_of: F) {
+        for (i, led) in leds.iter_mut().enumerate() {
+            let layer = layer_of(i);
+
+            *led = self.color(layer).map(|color| {
+                if layer == top_layer {
+                    color
+                } else {
+                    color.map(|c| (c as u16 * self.dim as u16 / 255) as u8)
+                }
+            });
+        }
+    }
+
+    /// Recomputes the key colors from the current layer state, if it changed.
+    fn refresh(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        let layers = LAYER.read();
+        let top_layer = layers.most_recent_layer();
+        let keys = LED_COUNT.min(NUM_KEYS);
+
+        let mut leds = self.leds;
+        self.compute(&mut leds[..keys], top_layer, |i| {
+            layers.lookup_active_layer(&KeyAddr::new(i as u8))
+        });
+
+        self.leds = leds;
+        self.dirty = false;
+    }
+}
+
+impl EventHandler for LayerHighlight {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("LayerHighlight")
+    }
+
+    /// Only marks the colors for recomputing: the layer state is still locked while the
+    /// handlers run.
+    fn on_layer_change() -> Result<()> {
+        if LED_COUNT > 0 {
+            LAYER_HIGHLIGHT.write().dirty = true;
+        }
+
+        Ok(())
+    }
+
+    fn before_syncing_leds() -> Result<()> {
+        if LED_COUNT == 0 {
+            return Ok(());
+        }
+
+        let mut highlight = LAYER_HIGHLIGHT.write();
+
+        if highlight.colors.is_empty() {
+            return Ok(());
+        }
+
+        highlight.refresh();
+
+        let mut leds = LED_CONTROL.write();
+
+        for (i, color) in highlight.leds.iter().enumerate() {
+            if let Some(color) = color {
+                leds.set_crgb_at(i, *color);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{LEAD_FIRST, LEAD_LAST};
+use crate::{key_defs::*, key_event::KeyEvent, lock, millis::millis};
+
+/// Maximum number of keys captured after the leader key.
+pub const LEADER_MAX_SEQUENCE_LEN: usize = 16;
+
+/// Default time, in milliseconds, allowed between two keys of a sequence.
+pub const DEFAULT_LEADER_TIMEOUT: u16 = 1000;
+
+/// Action run when a sequence matches. It is passed the index of the matching dictionary entry.
+pub type LeaderAction = fn(usize);
+
+/// Dictionary entry: the key sequence following the leader key, and its action.
+pub type LeaderEntry = (&'static [Key], LeaderAction);
+
+/// Creates the leader key `n`.
+#[macro_export]
+macro_rules! LEAD {
+    ($n:tt) => {
+        $crate::key_defs::Key::from_raw($crate::plugins::ranges::LEAD_FIRST + $n as u16)
+    };
+}
+
+/// Global leader state.
+pub static LEADER: lock::Spinlock<Leader> = lock::Spinlock::new(Leader::new());
+
+/// Result of matching the captured sequence against the dictionary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LeaderMatch {
+    /// No entry starts with the sequence.
+    None,
+    /// Some entries start with the sequence, but none is equal to it.
+    Prefix,
+    /// An entry is equal to the sequence, and longer entries start with it.
+    Ambiguous(usize),
+    /// An entry is equal to the sequence, and no longer entry starts with it.
+    Exact(usize),
+}
+
+/// Runs actions for key sequences typed after a leader key.
+///
+/// After a `LEAD(n)` key toggles on, the following keys are captured (and masked, so they
+/// are not typed) and matched against the dictionary set with
+/// [set_dictionary](Self::set_dictionary). Matching is greedy: when a sequence is both a
+/// dictionary entry and the prefix of a longer one, the plugin waits for more keys, and
+/// runs the shorter entry only on timeout, or when the next key does not extend it.
+///
+/// If no entry matches, or no key is pressed within the timeout, the sequence is dropped
+/// quietly.
+pub struct Leader {
+    dictionary: &'static [LeaderEntry],
+    sequence: [Key; LEADER_MAX_SEQUENCE_LEN],
+    sequence_len: usize,
+    active: bool,
+    start_time: u32,
+    timeout: u16,
+}
+
+impl Leader {
+    /// Creates a new [Leader] with an empty dictionary.
+    pub const fn new() -> Self {
+        Self {
+            dictionary: &[],
+            sequence: [Key_NoKey; LEADER_MAX_SEQUENCE_LEN],
+            sequence_len: 0,
+            active: false,
+            start_time: 0,
+            timeout: DEFAULT_LEADER_TIMEOUT,
+        }
+    }
+
+    /// Sets the dictionary of sequences, and their actions.
+    pub fn set_dictionary(&mut self, dictionary: &'static [LeaderEntry]) {
+        self.dictionary = dictionary;
+        self.reset();
+    }
+
+    /// Gets the time, in milliseconds, allowed between two keys of a sequence.
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Sets the time, in milliseconds, allowed between two keys of a sequence.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout;
+    }
+
+    /// Gets whether a sequence is being captured.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Gets the captured sequence.
+    pub fn sequence(&self) -> &[Key] {
+        &self.sequence[..self.sequence_len]
+    }
+
+    /// Starts capturing a new sequence.
+    pub fn start(&mut self, now: u32) {
+        self.sequence_len = 0;
+        self.active = true;
+        self.start_time = now;
+    }
+
+    /// Stops capturing, and drops the captured sequence.
+    pub fn reset(&mut self) {
+        self.sequence_len = 0;
+        self.active = false;
+    }
+
+    /// Matches the captured sequence against the dictionary.
+    pub fn lookup(&self) -> LeaderMatch {
+        let sequence = self.sequence();
+        let mut exact = None;
+        let mut prefix = false;
+
+        for (i, (keys, _)) in self.dictionary.iter().enumerate() {
+            if !keys.starts_with(sequence) {
+                continue;
+            }
+
+            if keys.len() == sequence.len() {
+                exact.get_or_insert(i);
+            } else {
+                prefix = true;
+            }
+        }
+
+        match (exact, prefix) {
+            (Some(i), true) => LeaderMatch::Ambiguous(i),
+            (Some(i), false) => LeaderMatch::Exact(i),
+            (None, true) => LeaderMatch::Prefix,
+            (None, false) => LeaderMatch::None,
+        }
+    }
+
+    /// Adds a key to the captured sequence.
+    ///
+    /// Returns the index of the entry whose action should run, if the sequence is
+    /// complete.
+    pub fn push(&mut self, key: Key, now: u32) -> Option<usize> {
+        let previous = self.lookup();
+
+        if self.sequence_len == LEADER_MAX_SEQUENCE_LEN {
+            self.reset();
+            return None;
+        }
+
+        self.sequence[self.sequence_len] = key;
+        self.sequence_len += 1;
+        self.start_time = now;
+
+        match self.lookup() {
+            LeaderMatch::Prefix | LeaderMatch::Ambiguous(_) => None,
+            LeaderMatch::Exact(i) => {
+                self.reset();
+                Some(i)
+            }
+            LeaderMatch::None => {
+                self.reset();
+
+                // The new key does not extend the sequence, fall back to the shorter match.
+                match previous {
+                    LeaderMatch::Ambiguous(i) => Some(i),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Checks the sequence timeout.
+    ///
+    /// Returns the index of the entry whose action should run, if the captured sequence
+    /// matched an entry when it timed out.
+    pub fn check_timeout(&mut self, now: u32) -> Option<usize> {
+        if !self.active || now.wrapping_sub(self.start_time) < self.timeout as u32 {
+            return None;
+        }
+
+        let lookup = self.lookup();
+
+        self.reset();
+
+        match lookup {
+            LeaderMatch::Ambiguous(i) | LeaderMatch::Exact(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Runs the action of the dictionary entry at `index`.
+    ///
+    /// The lock is not held while the action runs, so it may use the plugin itself.
+    fn run_action(index: usize) {
+        let action = LEADER.read().dictionary.get(index).map(|(_, action)| *action);
+
+        if let Some(action) = action {
+            action(index);
+        }
+    }
+}
+
+impl EventHandler for Leader {
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Leader")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let action = LEADER.write().check_timeout(millis());
+
+        if let Some(index) = action {
+            Self::run_action(index);
+        }
+
+        Ok(())
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if (LEAD_FIRST..=LEAD_LAST).contains(&event.key().raw()) {
+            if event.state().key_toggled_on() {
+                LEADER.write().start(millis());
+            }
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if !event.state().key_toggled_on() || !LEADER.read().is_active() {
+            return Ok(());
+        }
+
+        let action = LEADER.write().push(*event.key(), millis());
+
+        if let Some(index) = action {
+            Self::run_action(index);
+        }
+
+        // Mask the captured key, so neither its press nor its release is typed.
+        event.set_key(Key_Masked);
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_index: usize) {}
+
+    static DICTIONARY: [LeaderEntry; 3] = [
+        (&[Key_A], noop),
+        (&[Key_A, Key_B], noop),
+        (&[Key_C, Key_D], noop),
+    ];
+
+    fn leader() -> Leader {
+        let mut leader = Leader::new();
+        leader.set_dictionary(&DICTIONARY);
+        leader.start(100);
+        leader
+    }
+
+    #[test]
+    fn exact_sequence_runs_its_action() {
+        let mut leader = leader();
+
+        assert_eq!(leader.push(Key_C, 110), None);
+        assert_eq!(leader.lookup(), LeaderMatch::Prefix);
+
+        assert_eq!(leader.push(Key_D, 120), Some(2));
+        assert!(!leader.is_active());
+    }
+
+    #[test]
+    fn ambiguous_sequence_waits_for_more_keys() {
+        let mut leader = leader();
+
+        assert_eq!(leader.push(Key_A, 110), None);
+        assert_eq!(leader.lookup(), LeaderMatch::Ambiguous(0));
+
+        assert_eq!(leader.push(Key_B, 120), Some(1));
+
+        // A key that does not extend the sequence runs the shorter match.
+        leader.start(200);
+        leader.push(Key_A, 210);
+
+        assert_eq!(leader.push(Key_X, 220), Some(0));
+        assert!(!leader.is_active());
+    }
+
+    #[test]
+    fn timeout_runs_the_pending_match() {
+        let mut leader = leader();
+        let timeout = leader.timeout() as u32;
+
+        leader.push(Key_A, 110);
+
+        assert_eq!(leader.check_timeout(110 + timeout - 1), None);
+        assert_eq!(leader.check_timeout(110 + timeout), Some(0));
+        assert!(!leader.is_active());
+
+        // An incomplete sequence is dropped quietly.
+        leader.start(200);
+        leader.push(Key_C, 210);
+
+        assert_eq!(leader.check_timeout(210 + timeout), None);
+        assert!(!leader.is_active());
+    }
+
+    #[test]
+    fn unknown_sequence_is_dropped() {
+        let mut leader = leader();
+
+        assert_eq!(leader.push(Key_Z, 110), None);
+        assert!(!leader.is_active());
+        assert!(leader.sequence().is_empty());
+    }
+}
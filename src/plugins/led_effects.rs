@@ -0,0 +1,169 @@
+use crate::driver::led::{LedMode, LedModeRef, Rgb, LED_CONTROL};
+use crate::event_handler::{EventHandler, Result};
+use crate::lock;
+
+/// Default breathing period in milliseconds.
+pub const DEFAULT_BREATHE_PERIOD: u16 = 4096;
+
+/// Solid color LED mode.
+pub static SOLID_COLOR: lock::Spinlock<SolidColor> = lock::Spinlock::new(SolidColor::new(0, 0, 0xff));
+
+/// Breathing LED mode.
+pub static BREATHE: lock::Spinlock<Breathe> = lock::Spinlock::new(Breathe::new(0, 0, 0xff));
+
+/// LED modes registered by [LedEffects], in mode order.
+pub static LED_EFFECT_MODES: [LedModeRef; 2] = [&SOLID_COLOR, &BREATHE];
+
+/// Sets every LED to the same color.
+pub struct SolidColor {
+    color: Rgb,
+}
+
+impl SolidColor {
+    /// Creates a new [SolidColor] effect.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { color: [r, g, b] }
+    }
+
+    /// Sets the color.
+    pub fn set_color(&mut self, r: u8, g: u8, b: u8) {
+        self.color = [r, g, b];
+    }
+}
+
+impl LedMode for SolidColor {
+    fn update(&mut self, leds: &mut [Rgb]) {
+        for led in leds.iter_mut() {
+            *led = self.color;
+        }
+    }
+}
+
+/// Fades every LED in and out of a color.
+pub struct Breathe {
+    color: Rgb,
+    period: u16,
+    now: u32,
+}
+
+impl Breathe {
+    /// Creates a new [Breathe] effect with the [DEFAULT_BREATHE_PERIOD].
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            color: [r, g, b],
+            period: DEFAULT_BREATHE_PERIOD,
+            now: 0,
+        }
+    }
+
+    /// Sets the color at full brightness.
+    pub fn set_color(&mut self, r: u8, g: u8, b: u8) {
+        self.color = [r, g, b];
+    }
+
+    /// Gets the time, in milliseconds, of a full fade in and out.
+    pub fn period(&self) -> u16 {
+        self.period
+    }
+
+    /// Sets the time, in milliseconds, of a full fade in and out. Zero is treated as one.
+    pub fn set_period(&mut self, period: u16) {
+        self.period = period.max(1);
+    }
+
+    /// Gets the brightness, from 0 to 255, at the provided time in milliseconds.
+    ///
+    /// The brightness rises and falls linearly over one period, then is squared, so the
+    /// fade looks even to the eye.
+    pub fn brightness(&self, now: u32) -> u8 {
+        let period = self.period.max(1) as u32;
+        let phase = (now % period) * 510 / period;
+        let level = if phase <= 255 { phase } else { 510 - phase };
+
+        (level * level / 255) as u8
+    }
+
+    /// Gets the color at the provided time in milliseconds.
+    pub fn color_at(&self, now: u32) -> Rgb {
+        let brightness = self.brightness(now) as u16;
+
+        self.color.map(|c| (c as u16 * brightness / 255) as u8)
+    }
+}
+
+impl LedMode for Breathe {
+    fn tick(&mut self, now: u32) {
+        self.now = now;
+    }
+
+    fn update(&mut self, leds: &mut [Rgb]) {
+        let color = self.color_at(self.now);
+
+        for led in leds.iter_mut() {
+            *led = color;
+        }
+    }
+}
+
+/// Registers the [SolidColor] and [Breathe] effects as LED modes.
+pub struct LedEffects;
+
+impl EventHandler for LedEffects {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("LedEffects")
+    }
+
+    fn on_setup() -> Result<()> {
+        LED_CONTROL.write().set_modes(&LED_EFFECT_MODES);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::led::LedControl;
+
+    #[test]
+    fn solid_color_sets_every_led() {
+        let mut leds = [[0u8; 3]; 4];
+
+        SolidColor::new(1, 2, 3).update(&mut leds);
+
+        assert_eq!(leds, [[1, 2, 3]; 4]);
+    }
+
+    #[test]
+    fn breathe_fades_in_and_out() {
+        let mut breathe = Breathe::new(0x80, 0, 0xff);
+        let period = breathe.period() as u32;
+
+        assert_eq!(breathe.brightness(0), 0);
+        assert_eq!(breathe.brightness(period / 4), 63);
+        assert_eq!(breathe.brightness(period / 2), 0xff);
+        assert_eq!(breathe.brightness(period * 3 / 4), 64);
+        assert_eq!(breathe.brightness(period), 0);
+
+        assert_eq!(breathe.color_at(period / 2), [0x80, 0, 0xff]);
+        assert_eq!(breathe.color_at(period / 4), [31, 0, 63]);
+
+        let mut leds = [[0u8; 3]; 2];
+        breathe.tick(period / 2);
+        breathe.update(&mut leds);
+
+        assert_eq!(leds, [[0x80, 0, 0xff]; 2]);
+
+        breathe.set_period(0);
+        assert_eq!(breathe.period(), 1);
+    }
+
+    #[test]
+    fn effects_are_registered_as_modes() {
+        let mut leds = LedControl::new();
+
+        leds.set_modes(&LED_EFFECT_MODES);
+
+        assert_eq!(leds.num_modes(), 2);
+        assert_eq!(leds.mode(), 0);
+    }
+}
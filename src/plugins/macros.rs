@@ -1 +1,110 @@
 pub mod key_defs;
+
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{MACRO_FIRST, MACRO_LAST};
+use crate::runtime::Runtime;
+use crate::util::typing::{key_events, str_event};
+use crate::{key_event::KeyEvent, lock};
+
+/// Macro typing [VERSION_INFO], and [BUILD_INFO] if it is set.
+pub const MACRO_VERSION_INFO: u8 = 1;
+
+/// Firmware name and version, typed by [MACRO_VERSION_INFO].
+pub const VERSION_INFO: &str = concat!("Kaleidoscope ", env!("CARGO_PKG_VERSION"));
+
+/// Build description, from the `KALEIDOSCOPE_BUILD_INFO` environment variable at build
+/// time (e.g. a git revision).
+pub const BUILD_INFO: Option<&str> = option_env!("KALEIDOSCOPE_BUILD_INFO");
+
+/// Action run when a macro key is pressed, with the macro number and the key event.
+pub type MacroAction = fn(u8, &KeyEvent);
+
+/// Global macros state.
+pub static MACROS: lock::Spinlock<Macros> = lock::Spinlock::new(Macros::new());
+
+/// Runs actions for the `M(n)` macro keys.
+///
+/// Without an action set with [set_action](Self::set_action), the built-in
+/// [default_action](Self::default_action) handles [MACRO_VERSION_INFO]. Custom actions
+/// can call it to keep the built-in macros.
+///
+/// Actions run from an event handler, so they queue the text they type with
+/// [Runtime::queue_key_source], see [str_event](crate::util::typing::str_event).
+pub struct Macros {
+    action: Option<MacroAction>,
+}
+
+impl Macros {
+    /// Creates a new [Macros], running the built-in macros.
+    pub const fn new() -> Self {
+        Self { action: None }
+    }
+
+    /// Sets the action run for macro keys, replacing the built-in macros.
+    pub fn set_action(&mut self, action: MacroAction) {
+        self.action = Some(action);
+    }
+
+    /// Runs the built-in macros, ignoring other macro numbers.
+    pub fn default_action(id: u8, _event: &KeyEvent) {
+        if id == MACRO_VERSION_INFO {
+            let _ = Self::type_version_info();
+        }
+    }
+
+    /// Types [VERSION_INFO], followed by [BUILD_INFO] if it is set.
+    ///
+    /// The text is queued, and typed once the current event handlers return.
+    ///
+    /// Returns [Error::NotAscii](crate::Error::NotAscii), without typing anything, if
+    /// either is not printable ASCII.
+    pub fn type_version_info() -> crate::Result<()> {
+        key_events(VERSION_INFO)?;
+        key_events(BUILD_INFO.unwrap_or_default())?;
+
+        Runtime::queue_key_source(Self::version_info_event, 0)
+    }
+
+    /// Gets the key event `index` of typing [VERSION_INFO] and [BUILD_INFO].
+    fn version_info_event(_: u32, index: usize) -> Option<KeyEvent> {
+        let version_len = VERSION_INFO.len() * 2;
+
+        if index < version_len {
+            return str_event(VERSION_INFO, index);
+        }
+
+        let build = BUILD_INFO?;
+
+        match index - version_len {
+            0 | 1 => str_event(" ", index - version_len),
+            build_index => str_event(build, build_index - 2),
+        }
+    }
+}
+
+impl EventHandler for Macros {
+    // Actions inject their output, which is never re-processed. Macro keys in
+    // injected events, e.g. from dynamic macro playback, are not run either.
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Macros")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let raw = event.key().raw();
+
+        if !(MACRO_FIRST..=MACRO_LAST).contains(&raw) {
+            return Ok(());
+        }
+
+        if event.state().key_toggled_on() {
+            // The lock is not held while the action runs, since the injected events pass
+            // through the event handlers again.
+            let action = MACROS.read().action.unwrap_or(Self::default_action);
+            action((raw - MACRO_FIRST) as u8, event);
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
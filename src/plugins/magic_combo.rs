@@ -0,0 +1,179 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::sketch::Sketch;
+use crate::{key_addr::KeyAddr, key_defs::*, lock, millis::millis, LIVE_KEYS};
+
+/// Default time, in milliseconds, a magic combo must be held before its action runs.
+pub const DEFAULT_MAGIC_COMBO_DWELL: u16 = 500;
+
+/// Action run when a magic combo is held. It is passed the index of the combo.
+pub type MagicComboAction = fn(usize);
+
+/// Magic combo definition: the addresses held together, and the action.
+pub type MagicComboEntry = (&'static [KeyAddr], MagicComboAction);
+
+/// Global magic combo state.
+pub static MAGIC_COMBO: lock::Spinlock<MagicCombo> = lock::Spinlock::new(MagicCombo::new());
+
+/// Runs actions when sets of keys are held together, like rebooting to the bootloader.
+///
+/// Unlike [Combos](crate::plugins::combos::Combos), combos are made of key addresses,
+/// so they work regardless of the active layer, and the held keys are still handled
+/// normally. After each cycle, the held addresses are matched against the combos set
+/// with [set_combos](Self::set_combos). The first combo whose addresses are all held,
+/// for at least the dwell time, runs its action once. It runs again only after the
+/// held set changes.
+pub struct MagicCombo {
+    combos: &'static [MagicComboEntry],
+    dwell: u16,
+    matched: Option<usize>,
+    start_time: u32,
+    fired: bool,
+}
+
+impl MagicCombo {
+    /// Creates a new [MagicCombo] with no combos.
+    pub const fn new() -> Self {
+        Self {
+            combos: &[],
+            dwell: DEFAULT_MAGIC_COMBO_DWELL,
+            matched: None,
+            start_time: 0,
+            fired: false,
+        }
+    }
+
+    /// Sets the combo definitions.
+    pub fn set_combos(&mut self, combos: &'static [MagicComboEntry]) {
+        self.combos = combos;
+        self.matched = None;
+    }
+
+    /// Gets the time, in milliseconds, a combo must be held before its action runs.
+    pub fn dwell(&self) -> u16 {
+        self.dwell
+    }
+
+    /// Sets the time, in milliseconds, a combo must be held before its action runs.
+    pub fn set_dwell(&mut self, dwell: u16) {
+        self.dwell = dwell;
+    }
+
+    /// Matches the held addresses against the combos.
+    ///
+    /// Returns the index of the combo whose action should run.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::key_addr::KeyAddr;
+    /// use kaleidoscope::plugins::magic_combo::{MagicCombo, MagicComboAction, MagicComboEntry};
+    ///
+    /// let keys: &'static [KeyAddr] = vec![KeyAddr::create(0, 0), KeyAddr::create(3, 11)].leak();
+    /// let combos: &'static [MagicComboEntry] = vec![(keys, (|_| ()) as MagicComboAction)].leak();
+    ///
+    /// let mut magic = MagicCombo::new();
+    /// magic.set_combos(combos);
+    /// magic.set_dwell(100);
+    ///
+    /// let both = |addr: &KeyAddr| keys.contains(addr);
+    /// let one = |addr: &KeyAddr| *addr == keys[0];
+    ///
+    /// // Tapped: released before the dwell time.
+    /// assert_eq!(magic.update(both, 0), None);
+    /// assert_eq!(magic.update(both, 50), None);
+    /// assert_eq!(magic.update(one, 60), None);
+    ///
+    /// // Held: the action runs once.
+    /// assert_eq!(magic.update(both, 200), None);
+    /// assert_eq!(magic.update(both, 300), Some(0));
+    /// assert_eq!(magic.update(both, 400), None);
+    /// ```
+    pub fn update<F: Fn(&KeyAddr) -> bool>(&mut self, is_held: F, now: u32) -> Option<usize> {
+        let matched = self
+            .combos
+            .iter()
+            .position(|(keys, _)| !keys.is_empty() && keys.iter().all(&is_held));
+
+        if matched != self.matched {
+            self.matched = matched;
+            self.start_time = now;
+            self.fired = false;
+        }
+
+        let index = self.matched?;
+
+        if self.fired || now.wrapping_sub(self.start_time) < self.dwell as u32 {
+            return None;
+        }
+
+        self.fired = true;
+
+        Some(index)
+    }
+
+    /// Gets whether all the combo addresses are inside the sketch's matrix.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_addr::KeyAddr, sketch::Sketch};
+    /// use kaleidoscope::plugins::magic_combo::{MagicCombo, MagicComboAction, MagicComboEntry};
+    ///
+    /// let keys: &'static [KeyAddr] = vec![KeyAddr::create(0, 0), KeyAddr::create(3, 11)].leak();
+    /// let combos: &'static [MagicComboEntry] = vec![(keys, (|_| ()) as MagicComboAction)].leak();
+    ///
+    /// let mut magic = MagicCombo::new();
+    /// magic.set_combos(combos);
+    ///
+    /// assert!(magic.fits_sketch(&Sketch::new(4, 12, 1, &[])));
+    /// assert!(!magic.fits_sketch(&Sketch::new(4, 11, 1, &[])));
+    /// ```
+    pub fn fits_sketch(&self, sketch: &Sketch) -> bool {
+        self.combos
+            .iter()
+            .all(|(keys, _)| keys.iter().all(|addr| sketch.contains_addr(addr)))
+    }
+
+    /// Runs the action of the combo at `index`.
+    ///
+    /// The lock is not held while the action runs, so it may use the plugin itself.
+    fn run_action(index: usize) {
+        let action = MAGIC_COMBO.read().combos.get(index).map(|(_, action)| *action);
+
+        if let Some(action) = action {
+            action(index);
+        }
+    }
+}
+
+impl EventHandler for MagicCombo {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("MagicCombo")
+    }
+
+    /// Fails setup if a combo refers to a key outside the matrix, since it could never
+    /// be held.
+    fn explore_sketch(sketch: &Sketch) -> Result<()> {
+        if MAGIC_COMBO.read().fits_sketch(sketch) {
+            Ok(())
+        } else {
+            Err(EventHandlerError::Error)
+        }
+    }
+
+    fn after_each_cycle() -> Result<()> {
+        let action = {
+            let live_keys = LIVE_KEYS.read();
+
+            MAGIC_COMBO
+                .write()
+                .update(|addr| live_keys[*addr] != Key_Inactive, millis())
+        };
+
+        if let Some(index) = action {
+            Self::run_action(index);
+        }
+
+        Ok(())
+    }
+}
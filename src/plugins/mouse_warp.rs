@@ -0,0 +1,211 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{MOUSE_WARP_FIRST, MOUSE_WARP_LAST};
+use crate::{try_with_hid, with_hid, key_defs::*, key_event::KeyEvent, lock};
+
+/// Warps to the center of the top-left quarter of the current area.
+#[allow(non_upper_case_globals)]
+pub const Key_MouseWarpNW: Key = Key::from_raw(MOUSE_WARP_FIRST);
+/// Warps to the center of the top-right quarter of the current area.
+#[allow(non_upper_case_globals)]
+pub const Key_MouseWarpNE: Key = Key::from_raw(MOUSE_WARP_FIRST + 1);
+/// Warps to the center of the bottom-left quarter of the current area.
+#[allow(non_upper_case_globals)]
+pub const Key_MouseWarpSW: Key = Key::from_raw(MOUSE_WARP_FIRST + 2);
+/// Warps to the center of the bottom-right quarter of the current area.
+#[allow(non_upper_case_globals)]
+pub const Key_MouseWarpSE: Key = Key::from_raw(MOUSE_WARP_FIRST + 3);
+/// Ends warping, the next warp key starts from the whole screen again.
+#[allow(non_upper_case_globals)]
+pub const Key_MouseWarpEnd: Key = Key::from_raw(MOUSE_WARP_LAST);
+
+/// Default screen size, in pixels, used by the relative fallback.
+pub const DEFAULT_MOUSE_WARP_SCREEN: (u16, u16) = (1920, 1080);
+
+/// Largest relative move in a single report.
+const MAX_STEP: i32 = 127;
+
+/// Global mouse warp state.
+pub static MOUSE_WARP: lock::Spinlock<MouseWarp> = lock::Spinlock::new(MouseWarp::new());
+
+/// Quarter of the current area selected by a warp key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarpDirection {
+    NorthWest,
+    NorthEast,
+    SouthWest,
+    SouthEast,
+}
+
+impl WarpDirection {
+    /// Gets the direction of a warp key, `None` for other keys.
+    pub fn from_key(key: &Key) -> Option<Self> {
+        match key.raw().checked_sub(MOUSE_WARP_FIRST)? {
+            0 => Some(Self::NorthWest),
+            1 => Some(Self::NorthEast),
+            2 => Some(Self::SouthWest),
+            3 => Some(Self::SouthEast),
+            _ => None,
+        }
+    }
+}
+
+/// Moves the mouse cursor by successively dividing the screen into quarters.
+///
+/// The first warp key press moves the cursor to the center of a quarter of the screen,
+/// each following press to the center of a quarter of that quarter, and so on.
+/// `Key_MouseWarpEnd`, or any other key press, ends warping.
+///
+/// Positions are sent with the absolute pointer. If the host ignores it (see
+/// [absolute_mouse_supported](crate::driver::hid::HIDKeyboard::absolute_mouse_supported)),
+/// the cursor is first pushed into the top-left corner with relative moves, then moved
+/// to the position on a screen of [set_screen_size](Self::set_screen_size) pixels. Host
+/// pointer acceleration makes the fallback approximate.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::mouse_warp::{MouseWarp, WarpDirection};
+///
+/// let mut warp = MouseWarp::new();
+///
+/// assert_eq!(warp.warp(WarpDirection::NorthWest), (16384, 16384));
+/// assert_eq!(warp.warp(WarpDirection::SouthEast), (24576, 24576));
+/// assert_eq!(warp.warp(WarpDirection::NorthEast), (28672, 20480));
+///
+/// warp.end();
+/// assert_eq!(warp.warp(WarpDirection::SouthWest), (16384, 49152));
+///
+/// // The relative fallback, on a 1920x1080 screen.
+/// assert_eq!(warp.to_pixels((16384, 49152)), (480, 810));
+/// ```
+pub struct MouseWarp {
+    x: u32,
+    y: u32,
+    size: u32,
+    screen: (u16, u16),
+}
+
+impl MouseWarp {
+    /// Creates a new [MouseWarp], covering the whole screen.
+    pub const fn new() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            size: 1 << 16,
+            screen: DEFAULT_MOUSE_WARP_SCREEN,
+        }
+    }
+
+    /// Sets the screen size, in pixels, used by the relative fallback.
+    pub fn set_screen_size(&mut self, width: u16, height: u16) {
+        self.screen = (width, height);
+    }
+
+    /// Gets whether a warp is in progress.
+    pub fn is_warping(&self) -> bool {
+        self.size < 1 << 16
+    }
+
+    /// Selects a quarter of the current area.
+    ///
+    /// Returns the center of the quarter, from `0` (left/top) to `u16::MAX` (right/bottom).
+    pub fn warp(&mut self, direction: WarpDirection) -> (u16, u16) {
+        if self.size > 1 {
+            self.size /= 2;
+        }
+
+        if matches!(direction, WarpDirection::NorthEast | WarpDirection::SouthEast) {
+            self.x += self.size;
+        }
+        if matches!(direction, WarpDirection::SouthWest | WarpDirection::SouthEast) {
+            self.y += self.size;
+        }
+
+        let center = |origin: u32| (origin + self.size / 2).min(u16::MAX as u32) as u16;
+
+        (center(self.x), center(self.y))
+    }
+
+    /// Ends warping, the next warp starts from the whole screen.
+    pub fn end(&mut self) {
+        self.x = 0;
+        self.y = 0;
+        self.size = 1 << 16;
+    }
+
+    /// Converts a warp position to screen pixels, for the relative fallback.
+    pub fn to_pixels(&self, (x, y): (u16, u16)) -> (u16, u16) {
+        let scale = |coordinate: u16, pixels: u16| ((coordinate as u32 * pixels as u32) >> 16) as u16;
+
+        (scale(x, self.screen.0), scale(y, self.screen.1))
+    }
+
+    /// Moves the cursor with relative moves: into the top-left corner, then to `(x, y)` pixels.
+    fn move_relative(&self, (x, y): (u16, u16)) -> crate::Result<()> {
+        let (width, height) = self.screen;
+        let home = width.max(height) as i32 / MAX_STEP + 1;
+
+        for _ in 0..home {
+            try_with_hid(|hid| hid.move_relative(-MAX_STEP as i8, -MAX_STEP as i8))?;
+        }
+
+        let (mut dx, mut dy) = (x as i32, y as i32);
+
+        while dx > 0 || dy > 0 {
+            let step_x = dx.min(MAX_STEP);
+            let step_y = dy.min(MAX_STEP);
+
+            try_with_hid(|hid| hid.move_relative(step_x as i8, step_y as i8))?;
+
+            dx -= step_x;
+            dy -= step_y;
+        }
+
+        Ok(())
+    }
+}
+
+impl EventHandler for MouseWarp {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("MouseWarp")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let key = *event.key();
+        let is_warp_key = (MOUSE_WARP_FIRST..=MOUSE_WARP_LAST).contains(&key.raw());
+
+        if !is_warp_key {
+            // Any other key press ends warping.
+            if event.state().key_toggled_on() && MOUSE_WARP.read().is_warping() {
+                MOUSE_WARP.write().end();
+            }
+
+            return Ok(());
+        }
+
+        if !event.state().key_toggled_on() {
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        let mut warp = MOUSE_WARP.write();
+
+        let Some(direction) = WarpDirection::from_key(&key) else {
+            warp.end();
+            return Err(EventHandlerError::EventConsumed);
+        };
+
+        let position = warp.warp(direction);
+
+        let absolute = with_hid(|hid| hid.absolute_mouse_supported()).unwrap_or(false);
+
+        let sent = if absolute {
+            try_with_hid(|hid| hid.set_absolute_position(position.0, position.1))
+        } else {
+            warp.move_relative(warp.to_pixels(position))
+        };
+
+        sent.map_err(|_| EventHandlerError::Error)?;
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
@@ -0,0 +1,388 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{OSL_FIRST, OSM_FIRST, OS_ACTIVE_STICKY, OS_CANCEL, OS_FIRST, OS_LAST, OS_META_STICKY};
+use crate::runtime::Runtime;
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, keyswitch_state::KeyswitchState, lock, LAYER};
+
+/// Number of one-shot keys in the `OS` range: eight modifiers, then eight layers.
+pub const NUM_ONE_SHOT_KEYS: usize = (OS_LAST - OS_FIRST + 1) as usize;
+
+/// Number of one-shot modifier keys, the first slots of the `OS` range.
+const NUM_ONE_SHOT_MODIFIERS: usize = (OSL_FIRST - OSM_FIRST) as usize;
+
+/// Creates the one-shot key for the modifier `modifier`.
+#[macro_export]
+macro_rules! OSM {
+    ($modifier:expr) => {
+        $crate::key_defs::Key::from_raw(
+            $crate::plugins::ranges::OSM_FIRST
+                + ($modifier.key_code() - $crate::key_defs::Key_LeftControl.key_code()) as u16,
+        )
+    };
+}
+
+/// Creates the one-shot key for layer `layer`, `0` to `7`.
+#[macro_export]
+macro_rules! OSL {
+    ($layer:tt) => {
+        $crate::key_defs::Key::from_raw($crate::plugins::ranges::OSL_FIRST + $layer as u16)
+    };
+}
+
+/// Makes the next one-shot key pressed sticky.
+#[allow(non_upper_case_globals)]
+pub const Key_OneShotMetaSticky: Key = Key::from_raw(OS_META_STICKY);
+/// Makes every active one-shot key sticky.
+#[allow(non_upper_case_globals)]
+pub const Key_OneShotActiveSticky: Key = Key::from_raw(OS_ACTIVE_STICKY);
+/// Releases every one-shot and sticky key.
+#[allow(non_upper_case_globals)]
+pub const Key_OneShotCancel: Key = Key::from_raw(OS_CANCEL);
+
+/// Global OneShot state.
+pub static ONE_SHOT: lock::Spinlock<OneShot> = lock::Spinlock::new(OneShot::new());
+
+/// One-shot modifiers and layers.
+///
+/// Tapping `OSM(m)` or `OSL(n)` keeps the modifier (or layer) active for the next key
+/// only: it is released once that key has been handled. Held while another key is
+/// pressed, a one-shot key acts as a normal modifier (or layer shift), and is released
+/// with the key.
+///
+/// A one-shot key becomes sticky, staying active across any number of keys, when:
+///
+/// - it is tapped twice,
+/// - it is pressed after `Key_OneShotMetaSticky`,
+/// - `Key_OneShotActiveSticky` is pressed while it is active.
+///
+/// Sticky modifiers stay in the `LIVE_KEYS` state, so they are part of every report until
+/// released. Tapping a sticky key releases it, `Key_OneShotCancel` releases every one-shot
+/// and sticky key, and clears a pending `Key_OneShotMetaSticky`.
+///
+/// Layer keys do not consume one-shot keys, so `OSL` keys combine with `OSM` keys.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::key_addr::KeyAddr;
+/// use kaleidoscope::plugins::one_shot::OneShot;
+///
+/// let mut one_shot = OneShot::new();
+/// let (shift, ctrl) = (1, 0);
+/// let (shift_addr, ctrl_addr) = (KeyAddr::create(3, 0), KeyAddr::create(3, 1));
+///
+/// // Stick: a double tap makes Shift sticky, it survives the next key.
+/// one_shot.press(shift, shift_addr);
+/// assert!(one_shot.release(shift));
+/// one_shot.press(shift, shift_addr);
+/// assert!(one_shot.release(shift));
+/// assert!(one_shot.is_sticky(shift));
+///
+/// one_shot.next_key();
+/// assert_eq!(one_shot.take_due(), None);
+/// assert!(one_shot.is_active(shift));
+///
+/// // Apply to next: a single tap of Ctrl is released after the next key.
+/// one_shot.press(ctrl, ctrl_addr);
+/// assert!(one_shot.release(ctrl));
+/// assert!(!one_shot.is_sticky(ctrl));
+///
+/// one_shot.next_key();
+/// assert_eq!(one_shot.take_due(), Some((ctrl, ctrl_addr)));
+/// assert_eq!(one_shot.take_due(), None);
+///
+/// // Meta-sticky makes the next one-shot key sticky on its first tap.
+/// one_shot.arm_meta_sticky();
+/// one_shot.press(ctrl, ctrl_addr);
+/// assert!(one_shot.release(ctrl));
+/// assert!(one_shot.is_sticky(ctrl));
+/// assert!(!one_shot.is_meta_sticky());
+///
+/// // Cancel: every sticky key is released.
+/// one_shot.cancel();
+/// assert_eq!(one_shot.take_due(), Some((ctrl, ctrl_addr)));
+/// assert_eq!(one_shot.take_due(), Some((shift, shift_addr)));
+/// assert_eq!(one_shot.take_due(), None);
+/// assert!(!one_shot.any_sticky());
+/// ```
+pub struct OneShot {
+    addrs: [KeyAddr; NUM_ONE_SHOT_KEYS],
+    pressed: u16,
+    active: u16,
+    sticky: u16,
+    due: u16,
+    meta_sticky: bool,
+}
+
+impl OneShot {
+    /// Creates a new [OneShot] with no active keys.
+    pub const fn new() -> Self {
+        Self {
+            addrs: [KeyAddr::default(); NUM_ONE_SHOT_KEYS],
+            pressed: 0,
+            active: 0,
+            sticky: 0,
+            due: 0,
+            meta_sticky: false,
+        }
+    }
+
+    /// Gets the slot of a one-shot key, `None` for other keys.
+    ///
+    /// Slots `0` to `7` are the modifiers, from `LeftControl` to `RightGui`, slots `8` to
+    /// `15` the layers.
+    pub fn slot_of(key: &Key) -> Option<usize> {
+        let raw = key.raw();
+
+        if (OS_FIRST..=OS_LAST).contains(&raw) {
+            Some((raw - OS_FIRST) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Gets whether the slot is active, either one-shot or sticky.
+    pub fn is_active(&self, slot: usize) -> bool {
+        (self.active | self.sticky) & Self::bit(slot) != 0
+    }
+
+    /// Gets whether the slot is sticky.
+    pub fn is_sticky(&self, slot: usize) -> bool {
+        self.sticky & Self::bit(slot) != 0
+    }
+
+    /// Gets whether any slot is sticky.
+    pub fn any_sticky(&self) -> bool {
+        self.sticky != 0
+    }
+
+    /// Gets whether the next one-shot key pressed becomes sticky.
+    pub fn is_meta_sticky(&self) -> bool {
+        self.meta_sticky
+    }
+
+    /// Makes the next one-shot key pressed sticky.
+    pub fn arm_meta_sticky(&mut self) {
+        self.meta_sticky = true;
+    }
+
+    /// Makes every active one-shot slot sticky.
+    pub fn activate_sticky(&mut self) {
+        self.sticky |= self.active;
+        self.active = 0;
+    }
+
+    /// Handles the press of the one-shot key of `slot`, at `addr`.
+    ///
+    /// A first press activates the slot for the next key, a second one (or a first one
+    /// after [arm_meta_sticky](Self::arm_meta_sticky)) makes it sticky. Pressing a sticky
+    /// slot turns it back into a normal key, released with the press.
+    pub fn press(&mut self, slot: usize, addr: KeyAddr) {
+        let bit = Self::bit(slot);
+
+        self.addrs[slot] = addr;
+        self.pressed |= bit;
+        self.due &= !bit;
+
+        if self.sticky & bit != 0 {
+            self.sticky &= !bit;
+        } else if self.active & bit != 0 || self.meta_sticky {
+            self.active &= !bit;
+            self.sticky |= bit;
+            self.meta_sticky = false;
+        } else {
+            self.active |= bit;
+        }
+    }
+
+    /// Handles the release of the one-shot key of `slot`.
+    ///
+    /// Returns whether the slot stays active after the release.
+    pub fn release(&mut self, slot: usize) -> bool {
+        self.pressed &= !Self::bit(slot);
+
+        self.is_active(slot)
+    }
+
+    /// Handles the press of another key.
+    ///
+    /// Held one-shot keys become normal keys, released with their key. Other one-shot
+    /// slots are due for release, see [take_due](Self::take_due). Sticky slots stay.
+    pub fn next_key(&mut self) {
+        self.due |= self.active & !self.pressed;
+        self.active = 0;
+    }
+
+    /// Releases every one-shot and sticky slot, and clears a pending meta-sticky.
+    ///
+    /// Held slots are released with their key.
+    pub fn cancel(&mut self) {
+        self.due |= (self.active | self.sticky) & !self.pressed;
+        self.active = 0;
+        self.sticky = 0;
+        self.meta_sticky = false;
+    }
+
+    /// Takes a slot due for release, with the address of its key.
+    pub fn take_due(&mut self) -> Option<(usize, KeyAddr)> {
+        if self.due == 0 {
+            return None;
+        }
+
+        let slot = self.due.trailing_zeros() as usize;
+        self.due &= !Self::bit(slot);
+
+        Some((slot, self.addrs[slot]))
+    }
+
+    /// Gets the pressed or active slot whose key is at `addr`.
+    fn slot_at(&self, addr: &KeyAddr) -> Option<usize> {
+        let on = self.pressed | self.active | self.sticky;
+
+        (0..NUM_ONE_SHOT_KEYS).find(|&slot| on & Self::bit(slot) != 0 && &self.addrs[slot] == addr)
+    }
+
+    /// Gets the modifier key of `slot`, `None` for layer slots.
+    fn modifier(slot: usize) -> Option<Key> {
+        if slot < NUM_ONE_SHOT_MODIFIERS {
+            Some(Key::from_raw(Key_LeftControl.raw() + slot as u16))
+        } else {
+            None
+        }
+    }
+
+    /// Gets the layer of `slot`.
+    fn layer(slot: usize) -> u8 {
+        (slot - NUM_ONE_SHOT_MODIFIERS) as u8
+    }
+
+    fn bit(slot: usize) -> u16 {
+        1 << slot
+    }
+
+    /// Releases the slots due for release.
+    ///
+    /// Modifier releases are queued, and handled by the runtime once the handlers
+    /// return.
+    fn release_due() -> Result<()> {
+        loop {
+            let due = ONE_SHOT.write().take_due();
+            let Some((slot, addr)) = due else {
+                break;
+            };
+
+            match Self::modifier(slot) {
+                Some(_) => Self::inject_release(addr),
+                None => Self::deactivate_layer(Self::layer(slot))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queues the release of the modifier held in the `LIVE_KEYS` state at `addr`.
+    fn inject_release(addr: KeyAddr) {
+        let mut state = KeyswitchState::default();
+        state.set_injected(true);
+        state.set_was_pressed(true);
+
+        let _ = Runtime::queue_key_event(KeyEvent::next(addr, state));
+    }
+
+    /// Deactivates a one-shot layer, unless something else already removed it.
+    fn deactivate_layer(layer: u8) -> Result<()> {
+        let mut layers = LAYER.write();
+
+        if layers.is_active_exact(layer) {
+            layers.deactivate(layer).map_err(|_| EventHandlerError::Error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl EventHandler for OneShot {
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("OneShot")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let key = *event.key();
+        let addr = *event.addr();
+
+        if key == Key_OneShotMetaSticky || key == Key_OneShotActiveSticky || key == Key_OneShotCancel {
+            if event.state().key_toggled_on() {
+                let mut one_shot = ONE_SHOT.write();
+
+                match key.raw() {
+                    OS_META_STICKY => one_shot.arm_meta_sticky(),
+                    OS_ACTIVE_STICKY => one_shot.activate_sticky(),
+                    _ => one_shot.cancel(),
+                }
+            }
+
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if event.state().key_toggled_off() {
+            let mut one_shot = ONE_SHOT.write();
+
+            let Some(slot) = one_shot.slot_at(&addr) else {
+                return Ok(());
+            };
+
+            let stays_active = one_shot.release(slot);
+            drop(one_shot);
+
+            return match (Self::modifier(slot), stays_active) {
+                // Keeps the modifier in `LIVE_KEYS`, so it stays in the reports.
+                (Some(_), true) => Err(EventHandlerError::Abort),
+                (Some(_), false) => Ok(()),
+                (None, true) => Err(EventHandlerError::EventConsumed),
+                (None, false) => {
+                    Self::deactivate_layer(Self::layer(slot))?;
+                    Err(EventHandlerError::EventConsumed)
+                }
+            };
+        }
+
+        // A key at the address of an active slot (e.g. a sticky modifier, whose `LIVE_KEYS`
+        // entry shadows the keymap) is a new press of that slot.
+        let mut one_shot = ONE_SHOT.write();
+        let slot = one_shot.slot_at(&addr).or_else(|| Self::slot_of(&key));
+
+        let Some(slot) = slot else {
+            if !key.is_layer_key() && !key.is_mod_layer_key() {
+                one_shot.next_key();
+            }
+
+            return Ok(());
+        };
+
+        let was_active = one_shot.is_active(slot);
+        one_shot.press(slot, addr);
+        drop(one_shot);
+
+        match Self::modifier(slot) {
+            Some(modifier) => {
+                event.set_key(modifier);
+                Ok(())
+            }
+            None => {
+                if !was_active {
+                    LAYER
+                        .write()
+                        .activate(Self::layer(slot))
+                        .map_err(|_| EventHandlerError::Error)?;
+                }
+
+                Err(EventHandlerError::EventConsumed)
+            }
+        }
+    }
+
+    fn after_each_cycle() -> Result<()> {
+        Self::release_due()
+    }
+}
@@ -0,0 +1,442 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::persistable::Persistable;
+use crate::plugins::ranges::{DUL_FIRST, DUL_LAST, DUM_FIRST, DUM_LAST};
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event_at;
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, key_ext::KeyExt, keyswitch_state::KeyswitchState, lock, millis::millis, shift_to_layer};
+
+/// Maximum number of key presses held back while a qukey is pending.
+pub const QUKEYS_QUEUE_CAPACITY: usize = 8;
+
+/// Default time, in milliseconds, after which a held qukey resolves to its hold key.
+pub const DEFAULT_QUKEYS_HOLD_TIMEOUT: u16 = 250;
+
+/// Default overlap, in milliseconds, after which a rollover resolves to the hold key.
+pub const DEFAULT_QUKEYS_OVERLAP_THRESHOLD: u16 = 80;
+
+/// Creates a qukey sending `key` when tapped, and the modifier `modifier` when held.
+#[macro_export]
+macro_rules! MT {
+    ($modifier:expr, $key:expr) => {
+        $crate::key_defs::Key::from_raw(
+            $crate::plugins::ranges::DUM_FIRST
+                + ((($modifier.key_code() - $crate::key_defs::Key_LeftControl.key_code()) as u16) << 8)
+                + $key.key_code() as u16,
+        )
+    };
+}
+
+/// Creates a qukey sending `key` when tapped, and shifting to `layer` when held.
+#[macro_export]
+macro_rules! LT {
+    ($layer:expr, $key:expr) => {
+        $crate::key_defs::Key::from_raw(
+            $crate::plugins::ranges::DUL_FIRST + (($layer as u16) << 8) + $key.key_code() as u16,
+        )
+    };
+}
+
+/// Global Qukeys state.
+pub static QUKEYS: lock::Spinlock<Qukeys> = lock::Spinlock::new(Qukeys::new());
+
+/// How a pending qukey was resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QukeyResolution {
+    /// The qukey sends its tap key.
+    Tap,
+    /// The qukey sends its hold key, a modifier or a layer shift.
+    Hold,
+}
+
+/// A qukey press waiting to be resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pending {
+    addr: KeyAddr,
+    tap: Key,
+    hold: Key,
+    start_time: u32,
+}
+
+/// A key press held back while a qukey is pending.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Queued {
+    addr: KeyAddr,
+    time: u32,
+}
+
+/// Dual-use keys, sending one key when tapped, and another when held.
+///
+/// `MT!(modifier, key)` keys hold a modifier, `LT!(layer, key)` keys shift to a layer.
+/// Presses of other keys while a qukey is pending are held back, then replayed once the
+/// qukey is resolved:
+///
+/// - released before the hold timeout, and before any other key: tap,
+/// - held past the hold timeout: hold,
+/// - another key pressed and released while the qukey is held: hold,
+/// - released while a later key is still held (rollover): hold if the later key was
+///   held for at least the overlap threshold, tap otherwise.
+///
+/// Both timings are runtime-tunable, with setters or the `qukeys.holdtimeout` and
+/// `qukeys.overlapthreshold` Focus commands, and persisted. The number of held back
+/// presses is bounded by [QUKEYS_QUEUE_CAPACITY]: a press that does not fit resolves
+/// the pending qukey to its hold key.
+pub struct Qukeys {
+    hold_timeout: u16,
+    overlap_threshold: u16,
+    pending: Option<Pending>,
+    queue: [Option<Queued>; QUKEYS_QUEUE_CAPACITY],
+    slot: Option<SlotHandle>,
+}
+
+impl Qukeys {
+    /// Creates a new [Qukeys] with the default timings.
+    pub const fn new() -> Self {
+        Self {
+            hold_timeout: DEFAULT_QUKEYS_HOLD_TIMEOUT,
+            overlap_threshold: DEFAULT_QUKEYS_OVERLAP_THRESHOLD,
+            pending: None,
+            queue: [None; QUKEYS_QUEUE_CAPACITY],
+            slot: None,
+        }
+    }
+
+    /// Gets the time, in milliseconds, after which a held qukey resolves to its hold key.
+    pub fn hold_timeout(&self) -> u16 {
+        self.hold_timeout
+    }
+
+    /// Sets the time, in milliseconds, after which a held qukey resolves to its hold key.
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the timeout across reboots.
+    pub fn set_hold_timeout(&mut self, timeout: u16) {
+        self.hold_timeout = timeout;
+    }
+
+    /// Gets the overlap, in milliseconds, after which a rollover resolves to the hold key.
+    pub fn overlap_threshold(&self) -> u16 {
+        self.overlap_threshold
+    }
+
+    /// Sets the overlap, in milliseconds, after which a rollover resolves to the hold key.
+    ///
+    /// Call [commit](Persistable::commit) afterwards to keep the threshold across reboots.
+    pub fn set_overlap_threshold(&mut self, threshold: u16) {
+        self.overlap_threshold = threshold;
+    }
+
+    /// Gets whether a qukey is waiting to be resolved.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Gets the tap and hold keys of a qukey, `None` for other keys.
+    pub fn decode(key: &Key) -> Option<(Key, Key)> {
+        let raw = key.raw();
+
+        let (offset, hold) = if (DUM_FIRST..DUM_LAST).contains(&raw) {
+            let offset = raw - DUM_FIRST;
+            (offset, Key::from_raw(Key_LeftControl.raw() + (offset >> 8)))
+        } else if (DUL_FIRST..DUL_LAST).contains(&raw) {
+            let offset = raw - DUL_FIRST;
+            (offset, shift_to_layer((offset >> 8) as u8))
+        } else {
+            return None;
+        };
+
+        Some((Key::keyboard((offset & 0xff) as u8, KeyFlags::NONE), hold))
+    }
+
+    /// Holds back the press of a qukey.
+    ///
+    /// Returns `false` if `key` is not a qukey.
+    pub fn press(&mut self, addr: KeyAddr, key: &Key, now: u32) -> bool {
+        match Self::decode(key) {
+            Some((tap, hold)) => {
+                self.pending = Some(Pending {
+                    addr,
+                    tap,
+                    hold,
+                    start_time: now,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Holds back the press of another key while a qukey is pending.
+    ///
+    /// Returns `false` if the queue is full.
+    pub fn queue_press(&mut self, addr: KeyAddr, now: u32) -> bool {
+        match self.queue.iter_mut().find(|q| q.is_none()) {
+            Some(slot) => {
+                *slot = Some(Queued { addr, time: now });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resolves the pending qukey to its hold key if it was held past the hold timeout.
+    pub fn check_timeout(&self, now: u32) -> Option<QukeyResolution> {
+        let pending = self.pending?;
+
+        if now.wrapping_sub(pending.start_time) >= self.hold_timeout as u32 {
+            Some(QukeyResolution::Hold)
+        } else {
+            None
+        }
+    }
+
+    /// Resolves the pending qukey on the release of the key at `addr`.
+    ///
+    /// Returns `None` if the release does not affect the pending qukey.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_addr::KeyAddr, key_defs::*, MT};
+    /// use kaleidoscope::plugins::qukeys::{QukeyResolution, Qukeys};
+    ///
+    /// let qukey = KeyAddr::create(0, 0);
+    /// let other = KeyAddr::create(0, 1);
+    ///
+    /// // The same timing, a qukey released after 200ms, resolves differently with
+    /// // different hold timeouts.
+    /// let mut qukeys = Qukeys::new();
+    /// qukeys.set_hold_timeout(250);
+    /// assert!(qukeys.press(qukey, &MT!(Key_LeftShift, Key_A), 0));
+    /// assert_eq!(qukeys.check_timeout(200), None);
+    /// assert_eq!(qukeys.release(&qukey, 200), Some(QukeyResolution::Tap));
+    ///
+    /// let mut qukeys = Qukeys::new();
+    /// qukeys.set_hold_timeout(150);
+    /// assert!(qukeys.press(qukey, &MT!(Key_LeftShift, Key_A), 0));
+    /// assert_eq!(qukeys.check_timeout(200), Some(QukeyResolution::Hold));
+    ///
+    /// // Rollover: the later key overlapped the qukey for 50ms.
+    /// let mut qukeys = Qukeys::new();
+    /// qukeys.set_overlap_threshold(40);
+    /// qukeys.press(qukey, &MT!(Key_LeftShift, Key_A), 0);
+    /// qukeys.queue_press(other, 50);
+    /// assert_eq!(qukeys.release(&qukey, 100), Some(QukeyResolution::Hold));
+    ///
+    /// let mut qukeys = Qukeys::new();
+    /// qukeys.set_overlap_threshold(60);
+    /// qukeys.press(qukey, &MT!(Key_LeftShift, Key_A), 0);
+    /// qukeys.queue_press(other, 50);
+    /// assert_eq!(qukeys.release(&qukey, 100), Some(QukeyResolution::Tap));
+    ///
+    /// // A later key pressed and released while the qukey is held.
+    /// let mut qukeys = Qukeys::new();
+    /// qukeys.press(qukey, &MT!(Key_LeftShift, Key_A), 0);
+    /// qukeys.queue_press(other, 20);
+    /// assert_eq!(qukeys.release(&other, 30), Some(QukeyResolution::Hold));
+    /// ```
+    pub fn release(&self, addr: &KeyAddr, now: u32) -> Option<QukeyResolution> {
+        let pending = self.pending?;
+
+        if &pending.addr == addr {
+            let threshold = self.overlap_threshold as u32;
+            let overlapped = self
+                .queue
+                .iter()
+                .flatten()
+                .any(|q| now.wrapping_sub(q.time) >= threshold);
+
+            return Some(if overlapped {
+                QukeyResolution::Hold
+            } else {
+                QukeyResolution::Tap
+            });
+        }
+
+        if self.queue.iter().flatten().any(|q| &q.addr == addr) {
+            Some(QukeyResolution::Hold)
+        } else {
+            None
+        }
+    }
+
+    /// Takes the pending qukey, and the presses held back behind it.
+    fn take(&mut self) -> Option<(Pending, [Option<Queued>; QUKEYS_QUEUE_CAPACITY])> {
+        let pending = self.pending.take()?;
+        let queue = self.queue;
+
+        self.queue = [None; QUKEYS_QUEUE_CAPACITY];
+
+        Some((pending, queue))
+    }
+
+    /// Queues the resolved key of the pending qukey, then the replays of the held back
+    /// presses, as keyswitch events.
+    ///
+    /// Must be called without holding the [QUKEYS] lock. Replayed presses of other qukeys
+    /// become pending in turn.
+    ///
+    /// Returns the key sent, and the address of the qukey.
+    fn resolve(resolution: QukeyResolution) -> Option<(KeyAddr, Key)> {
+        let (pending, queue) = QUKEYS.write().take()?;
+
+        let key = match resolution {
+            QukeyResolution::Tap => pending.tap,
+            QukeyResolution::Hold => pending.hold,
+        };
+
+        let _ = Runtime::queue_key_event(injected_event_at(pending.addr, key, true));
+
+        for queued in queue.iter().flatten() {
+            let mut state = KeyswitchState::default();
+            state.set_is_pressed(true);
+
+            let _ = Runtime::queue_keyswitch_event(KeyEvent::next(queued.addr, state));
+        }
+
+        Some((pending.addr, key))
+    }
+
+    /// Parses an optional `u16` Focus argument.
+    fn parse_arg(args: &str) -> Result<Option<u16>> {
+        let args = args.trim();
+
+        if args.is_empty() {
+            Ok(None)
+        } else {
+            args.parse().map(Some).map_err(|_| EventHandlerError::Error)
+        }
+    }
+}
+
+impl Persistable for Qukeys {
+    const SIZE: u16 = 4;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf[..2].copy_from_slice(&self.hold_timeout.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.overlap_threshold.to_le_bytes());
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.hold_timeout = u16::from_le_bytes([buf[0], buf[1]]);
+        self.overlap_threshold = u16::from_le_bytes([buf[2], buf[3]]);
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for Qukeys {
+    const PROCESS_INJECTED: bool = false;
+    // Qukeys resolves keys before the other keyswitch event handlers see them.
+    const PRIORITY: i8 = 10;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Qukeys")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let resolution = QUKEYS.read().check_timeout(millis());
+
+        if let Some(resolution) = resolution {
+            Self::resolve(resolution);
+        }
+
+        Ok(())
+    }
+
+    fn on_keyswitch_event(event: &mut KeyEvent) -> Result<()> {
+        let addr = *event.addr();
+        let now = millis();
+
+        if event.state().key_toggled_on() {
+            let pending = QUKEYS.read().is_pending();
+
+            if !pending {
+                let pressed = QUKEYS.write().press(addr, event.key(), now);
+
+                return if pressed {
+                    Err(EventHandlerError::Abort)
+                } else {
+                    Ok(())
+                };
+            }
+
+            let queued = QUKEYS.write().queue_press(addr, now);
+            if queued {
+                return Err(EventHandlerError::Abort);
+            }
+
+            // The queue is full, commit the qukey, and re-inject this press behind it.
+            Self::resolve(QukeyResolution::Hold);
+            let _ = Runtime::queue_keyswitch_event(*event);
+
+            return Err(EventHandlerError::Abort);
+        }
+
+        let resolution = QUKEYS.read().release(&addr, now);
+        let Some(resolution) = resolution else {
+            return Ok(());
+        };
+
+        match Self::resolve(resolution) {
+            // The qukey itself was released, release the key it resolved to.
+            Some((qukey_addr, key)) if qukey_addr == addr => {
+                let _ = Runtime::queue_key_event(injected_event_at(addr, key, false));
+                Err(EventHandlerError::Abort)
+            }
+            // A held back key was released, re-inject the release behind its replayed
+            // press.
+            _ => {
+                let _ = Runtime::queue_keyswitch_event(*event);
+                Err(EventHandlerError::Abort)
+            }
+        }
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let mut output = FOCUS_OUTPUT.write();
+            let _ = output.write_str("qukeys.holdtimeout\r\n");
+            let _ = output.write_str("qukeys.overlapthreshold\r\n");
+            return Ok(());
+        }
+
+        let hold_timeout = match command {
+            "qukeys.holdtimeout" => true,
+            "qukeys.overlapthreshold" => false,
+            _ => return Ok(()),
+        };
+
+        let mut qukeys = QUKEYS.write();
+
+        match Self::parse_arg(args)? {
+            Some(value) if hold_timeout => qukeys.set_hold_timeout(value),
+            Some(value) => qukeys.set_overlap_threshold(value),
+            None => {
+                let value = if hold_timeout {
+                    qukeys.hold_timeout()
+                } else {
+                    qukeys.overlap_threshold()
+                };
+
+                uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", value).map_err(|_| EventHandlerError::Error)?;
+
+                return Err(EventHandlerError::EventConsumed);
+            }
+        }
+
+        qukeys.commit().map_err(|_| EventHandlerError::Error)?;
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
@@ -60,5 +60,9 @@ pub const OS_ACTIVE_STICKY: u16 = OS_META_STICKY + 1;
 pub const OS_CANCEL: u16 = OS_ACTIVE_STICKY + 1;
 pub const CS_FIRST: u16 = OS_CANCEL + 1;
 pub const CS_LAST: u16 = CS_FIRST + MAX_CS_KEYS as u16;
-pub const SAFE_START: u16 = CS_LAST + 1;
+pub const MOUSE_WARP_FIRST: u16 = CS_LAST + 1;
+pub const MOUSE_WARP_LAST: u16 = MOUSE_WARP_FIRST + 4;
+pub const LAYER_TAP_FIRST: u16 = MOUSE_WARP_LAST + 1;
+pub const LAYER_TAP_LAST: u16 = LAYER_TAP_FIRST + (8 << 8);
+pub const SAFE_START: u16 = LAYER_TAP_LAST + 1;
 pub const KALEIDOSCOPE_SAFE_START: u16 = SAFE_START;
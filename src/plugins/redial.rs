@@ -0,0 +1,124 @@
+use crate::event_handler::{EventHandler, Result};
+use crate::plugins::ranges::REDIAL;
+use crate::{key_defs::*, key_event::KeyEvent, lock};
+
+/// Key repeating the last key pressed.
+#[allow(non_upper_case_globals)]
+pub const Key_Redial: Key = Key::from_raw(REDIAL);
+
+/// Keys not remembered by default: the keyboard modifiers.
+pub const DEFAULT_REDIAL_IGNORED: &[Key] = &[
+    Key_LeftControl,
+    Key_LeftShift,
+    Key_LeftAlt,
+    Key_LeftGui,
+    Key_RightControl,
+    Key_RightShift,
+    Key_RightAlt,
+    Key_RightGui,
+];
+
+/// Global redial state.
+pub static REDIAL_STATE: lock::Spinlock<Redial> = lock::Spinlock::new(Redial::new());
+
+/// Repeats the last key pressed.
+///
+/// Pressing the Redial key sends the last key pressed before it, as if that key was
+/// pressed instead. Keys in the ignore list (the modifiers, by default) are not
+/// remembered, but still apply when held with the Redial key. Layer keys are never
+/// remembered, so the remembered key is kept across layer changes. Before any key has
+/// been pressed, the Redial key does nothing.
+pub struct Redial {
+    last_key: Option<Key>,
+    ignored: &'static [Key],
+}
+
+impl Redial {
+    /// Creates a new [Redial] ignoring the [DEFAULT_REDIAL_IGNORED] keys.
+    pub const fn new() -> Self {
+        Self {
+            last_key: None,
+            ignored: DEFAULT_REDIAL_IGNORED,
+        }
+    }
+
+    /// Gets the remembered key.
+    pub fn last_key(&self) -> Option<Key> {
+        self.last_key
+    }
+
+    /// Sets the keys that are not remembered.
+    pub fn set_ignored(&mut self, ignored: &'static [Key]) {
+        self.ignored = ignored;
+    }
+
+    /// Gets whether the key should be remembered.
+    pub fn should_remember(&self, key: &Key) -> bool {
+        *key != Key_Redial
+            && !key.is_layer_key()
+            && !key.is_mod_layer_key()
+            && !self.ignored.contains(key)
+    }
+
+    /// Remembers the key, unless it is ignored.
+    pub fn remember(&mut self, key: Key) {
+        if self.should_remember(&key) {
+            self.last_key = Some(key);
+        }
+    }
+}
+
+impl EventHandler for Redial {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Redial")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if !event.state().key_toggled_on() || event.state().key_is_injected() {
+            return Ok(());
+        }
+
+        if *event.key() == Key_Redial {
+            // Replace the key in place, so releasing the Redial key releases the
+            // remembered key too.
+            match REDIAL_STATE.read().last_key() {
+                Some(key) => event.set_key(key),
+                None => event.set_key(Key_NoKey),
+            }
+        } else {
+            REDIAL_STATE.write().remember(*event.key());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_and_redial_are_not_remembered() {
+        let mut redial = Redial::new();
+
+        assert_eq!(redial.last_key(), None);
+
+        redial.remember(Key_A);
+        redial.remember(Key_LeftShift);
+        redial.remember(Key_Redial);
+
+        assert_eq!(redial.last_key(), Some(Key_A));
+    }
+
+    #[test]
+    fn ignore_list_can_be_replaced() {
+        let mut redial = Redial::new();
+        redial.set_ignored(&[Key_Escape]);
+
+        redial.remember(Key_LeftShift);
+        assert_eq!(redial.last_key(), Some(Key_LeftShift));
+
+        redial.remember(Key_Escape);
+        assert_eq!(redial.last_key(), Some(Key_LeftShift));
+    }
+}
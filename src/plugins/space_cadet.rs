@@ -0,0 +1,314 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::{SC_FIRST, SC_LAST};
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event_at;
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, lock, millis::millis};
+
+/// Number of SpaceCadet keys in the `SC` range.
+pub const NUM_SPACE_CADET_KEYS: usize = (SC_LAST - SC_FIRST + 1) as usize;
+
+/// Default time, in milliseconds, within which a release counts as a tap.
+pub const DEFAULT_SPACE_CADET_TIMEOUT: u16 = 200;
+
+/// Creates the SpaceCadet key `n`.
+#[macro_export]
+macro_rules! SC {
+    ($n:tt) => {
+        $crate::key_defs::Key::from_raw($crate::plugins::ranges::SC_FIRST + $n as u16)
+    };
+}
+
+/// Global SpaceCadet state.
+pub static SPACE_CADET: lock::Spinlock<SpaceCadet> = lock::Spinlock::new(SpaceCadet::new());
+
+/// Keys sent by a SpaceCadet key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpaceCadetMapping {
+    /// Key committed when the SpaceCadet key is held, usually a modifier.
+    pub hold: Key,
+    /// Key sent when the SpaceCadet key is tapped.
+    pub tap: Key,
+}
+
+/// A SpaceCadet key press waiting to be resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pending {
+    addr: KeyAddr,
+    start_time: u32,
+}
+
+/// How a pending SpaceCadet key was resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Resolution {
+    Tap,
+    Hold,
+}
+
+/// Keys that send a symbol when tapped, and act as a modifier when held.
+///
+/// `SC(0)` and `SC(1)` default to `Shift` when held, and parentheses when tapped. The
+/// press is held back until it is resolved:
+///
+/// - released within the timeout: the tap key is sent,
+/// - held past the timeout: the hold key is committed,
+/// - another key pressed meanwhile (rollover): the hold key is committed before the
+///   other key is processed.
+///
+/// Each SpaceCadet key resolves independently: pressing one SpaceCadet key while
+/// another is pending does not resolve the first one.
+pub struct SpaceCadet {
+    mappings: [SpaceCadetMapping; NUM_SPACE_CADET_KEYS],
+    pending: [Option<Pending>; NUM_SPACE_CADET_KEYS],
+    timeout: u16,
+}
+
+impl SpaceCadet {
+    /// Creates a new [SpaceCadet] with the default mappings.
+    pub const fn new() -> Self {
+        Self {
+            mappings: [
+                SpaceCadetMapping {
+                    hold: Key_LeftShift,
+                    tap: lshift!(Key_9),
+                },
+                SpaceCadetMapping {
+                    hold: Key_RightShift,
+                    tap: lshift!(Key_0),
+                },
+            ],
+            pending: [None; NUM_SPACE_CADET_KEYS],
+            timeout: DEFAULT_SPACE_CADET_TIMEOUT,
+        }
+    }
+
+    /// Gets the mapping of SpaceCadet key `n`.
+    pub fn mapping(&self, n: usize) -> Option<SpaceCadetMapping> {
+        self.mappings.get(n).copied()
+    }
+
+    /// Sets the mapping of SpaceCadet key `n`.
+    pub fn set_mapping(&mut self, n: usize, mapping: SpaceCadetMapping) {
+        if let Some(m) = self.mappings.get_mut(n) {
+            *m = mapping;
+        }
+    }
+
+    /// Gets the time, in milliseconds, within which a release counts as a tap.
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Sets the time, in milliseconds, within which a release counts as a tap.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout;
+    }
+
+    /// Gets whether any SpaceCadet key is waiting to be resolved.
+    pub fn is_pending(&self) -> bool {
+        self.pending.iter().any(|p| p.is_some())
+    }
+
+    /// Holds back the press of SpaceCadet key `n`.
+    fn press(&mut self, n: usize, addr: KeyAddr, now: u32) {
+        self.pending[n] = Some(Pending {
+            addr,
+            start_time: now,
+        });
+    }
+
+    /// Takes the pending SpaceCadet key at `addr`.
+    fn take_at(&mut self, addr: &KeyAddr) -> Option<(usize, Pending)> {
+        let n = self
+            .pending
+            .iter()
+            .position(|p| p.map_or(false, |p| &p.addr == addr))?;
+
+        self.pending[n].take().map(|p| (n, p))
+    }
+
+    /// Takes the pending SpaceCadet key that was pressed first.
+    fn take_oldest(&mut self) -> Option<(usize, Pending)> {
+        let mut oldest: Option<(usize, Pending)> = None;
+
+        for (n, pending) in self.pending.iter().enumerate() {
+            if let Some(p) = pending {
+                match oldest {
+                    Some((_, o)) if p.start_time.wrapping_sub(o.start_time) as i32 >= 0 => (),
+                    _ => oldest = Some((n, *p)),
+                }
+            }
+        }
+
+        let (n, _) = oldest?;
+        self.pending[n] = None;
+
+        oldest
+    }
+
+    /// Takes a pending SpaceCadet key held past the timeout.
+    fn take_expired(&mut self, now: u32) -> Option<(usize, Pending)> {
+        let timeout = self.timeout as u32;
+        let n = self
+            .pending
+            .iter()
+            .position(|p| p.map_or(false, |p| now.wrapping_sub(p.start_time) >= timeout))?;
+
+        self.pending[n].take().map(|p| (n, p))
+    }
+
+    /// Queues the resolved key for SpaceCadet key `n`, at the address of the SpaceCadet
+    /// key.
+    ///
+    /// Returns whether anything was queued. Must be called without holding the
+    /// [SPACE_CADET] lock.
+    fn resolve(n: usize, pending: Pending, resolution: Resolution) -> bool {
+        let mapping = match SPACE_CADET.read().mapping(n) {
+            Some(mapping) => mapping,
+            None => return false,
+        };
+
+        let queued = match resolution {
+            Resolution::Tap => Runtime::queue_key_events(&[
+                injected_event_at(pending.addr, mapping.tap, true),
+                injected_event_at(pending.addr, mapping.tap, false),
+            ]),
+            Resolution::Hold => Runtime::queue_key_event(injected_event_at(pending.addr, mapping.hold, true)),
+        };
+
+        queued.is_ok()
+    }
+}
+
+impl EventHandler for SpaceCadet {
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("SpaceCadet")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let now = millis();
+
+        loop {
+            let expired = SPACE_CADET.write().take_expired(now);
+            let Some((n, pending)) = expired else {
+                break;
+            };
+
+            Self::resolve(n, pending, Resolution::Hold);
+        }
+
+        Ok(())
+    }
+
+    fn on_keyswitch_event(event: &mut KeyEvent) -> Result<()> {
+        if event.state().key_toggled_off() {
+            let released = SPACE_CADET.write().take_at(event.addr());
+
+            // Released before the timeout: the press was never sent, send the tap instead.
+            if let Some((n, pending)) = released {
+                Self::resolve(n, pending, Resolution::Tap);
+                return Err(EventHandlerError::Abort);
+            }
+
+            return Ok(());
+        }
+
+        let raw = event.key().raw();
+
+        if (SC_FIRST..=SC_LAST).contains(&raw) {
+            SPACE_CADET
+                .write()
+                .press((raw - SC_FIRST) as usize, *event.addr(), millis());
+            return Err(EventHandlerError::Abort);
+        }
+
+        // Any other key pressed meanwhile commits the pending modifiers first, in the
+        // order they were pressed.
+        let mut committed = false;
+
+        loop {
+            let oldest = SPACE_CADET.write().take_oldest();
+            let Some((n, pending)) = oldest else {
+                break;
+            };
+
+            committed |= Self::resolve(n, pending, Resolution::Hold);
+        }
+
+        // The press must follow the queued modifiers, re-inject it behind them.
+        if committed {
+            let _ = Runtime::queue_keyswitch_event(*event);
+            return Err(EventHandlerError::Abort);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_takes_the_key_at_its_address() {
+        let mut space_cadet = SpaceCadet::new();
+        let (left, right) = (KeyAddr::create(3, 0), KeyAddr::create(3, 7));
+
+        space_cadet.press(0, left, 100);
+        space_cadet.press(1, right, 110);
+
+        assert_eq!(space_cadet.take_at(&KeyAddr::create(0, 0)), None);
+        assert_eq!(
+            space_cadet.take_at(&right),
+            Some((1, Pending { addr: right, start_time: 110 }))
+        );
+
+        // The other key is still pending.
+        assert!(space_cadet.is_pending());
+        assert_eq!(space_cadet.take_at(&left).map(|(n, _)| n), Some(0));
+        assert!(!space_cadet.is_pending());
+    }
+
+    #[test]
+    fn rollover_commits_keys_in_press_order() {
+        let mut space_cadet = SpaceCadet::new();
+
+        space_cadet.press(1, KeyAddr::create(3, 7), 100);
+        space_cadet.press(0, KeyAddr::create(3, 0), 110);
+
+        assert_eq!(space_cadet.take_oldest().map(|(n, _)| n), Some(1));
+        assert_eq!(space_cadet.take_oldest().map(|(n, _)| n), Some(0));
+        assert_eq!(space_cadet.take_oldest(), None);
+    }
+
+    #[test]
+    fn keys_held_past_the_timeout_expire() {
+        let mut space_cadet = SpaceCadet::new();
+        let timeout = space_cadet.timeout() as u32;
+
+        space_cadet.press(0, KeyAddr::create(3, 0), 100);
+        space_cadet.press(1, KeyAddr::create(3, 7), 150);
+
+        assert_eq!(space_cadet.take_expired(100 + timeout - 1), None);
+        assert_eq!(space_cadet.take_expired(100 + timeout).map(|(n, _)| n), Some(0));
+        assert_eq!(space_cadet.take_expired(100 + timeout), None);
+        assert_eq!(space_cadet.take_expired(150 + timeout).map(|(n, _)| n), Some(1));
+    }
+
+    #[test]
+    fn mappings_are_bounds_checked() {
+        let mut space_cadet = SpaceCadet::new();
+        let mapping = SpaceCadetMapping {
+            hold: Key_LeftControl,
+            tap: Key_Escape,
+        };
+
+        space_cadet.set_mapping(1, mapping);
+        space_cadet.set_mapping(NUM_SPACE_CADET_KEYS, mapping);
+
+        assert_eq!(space_cadet.mapping(1), Some(mapping));
+        assert_eq!(space_cadet.mapping(0).map(|m| m.hold), Some(Key_LeftShift));
+        assert_eq!(space_cadet.mapping(NUM_SPACE_CADET_KEYS), None);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::driver::serial::UsbSerial;
+use crate::plugins::ranges::{STENO_FIRST, STENO_LAST};
+use crate::{key_defs::Key, key_event::KeyEvent, lock};
+
+/// Length of a GeminiPR packet in bytes.
+pub const GEMINI_PR_PACKET_LEN: usize = 6;
+
+/// Number of steno keys with a bit in the GeminiPR packet.
+pub const NUM_STENO_KEYS: u16 = 42;
+
+/// Steno keys, in GeminiPR packet order.
+///
+/// Key `n` maps to bit `6 - n % 7` of byte `n / 7`. The high bit of the first byte marks
+/// the start of a packet, and is always set.
+pub mod keys {
+    use crate::key_defs::Key;
+    use crate::plugins::ranges::STENO_FIRST;
+
+    pub const S_FN: Key = Key::from_raw(STENO_FIRST);
+    pub const S_N1: Key = Key::from_raw(STENO_FIRST + 1);
+    pub const S_N2: Key = Key::from_raw(STENO_FIRST + 2);
+    pub const S_N3: Key = Key::from_raw(STENO_FIRST + 3);
+    pub const S_N4: Key = Key::from_raw(STENO_FIRST + 4);
+    pub const S_N5: Key = Key::from_raw(STENO_FIRST + 5);
+    pub const S_N6: Key = Key::from_raw(STENO_FIRST + 6);
+    pub const S_SL: Key = Key::from_raw(STENO_FIRST + 7);
+    pub const S_S2: Key = Key::from_raw(STENO_FIRST + 8);
+    pub const S_TL: Key = Key::from_raw(STENO_FIRST + 9);
+    pub const S_KL: Key = Key::from_raw(STENO_FIRST + 10);
+    pub const S_PL: Key = Key::from_raw(STENO_FIRST + 11);
+    pub const S_WL: Key = Key::from_raw(STENO_FIRST + 12);
+    pub const S_HL: Key = Key::from_raw(STENO_FIRST + 13);
+    pub const S_RL: Key = Key::from_raw(STENO_FIRST + 14);
+    pub const S_A: Key = Key::from_raw(STENO_FIRST + 15);
+    pub const S_O: Key = Key::from_raw(STENO_FIRST + 16);
+    pub const S_ST1: Key = Key::from_raw(STENO_FIRST + 17);
+    pub const S_ST2: Key = Key::from_raw(STENO_FIRST + 18);
+    pub const S_RE1: Key = Key::from_raw(STENO_FIRST + 19);
+    pub const S_RE2: Key = Key::from_raw(STENO_FIRST + 20);
+    pub const S_PWR: Key = Key::from_raw(STENO_FIRST + 21);
+    pub const S_ST3: Key = Key::from_raw(STENO_FIRST + 22);
+    pub const S_ST4: Key = Key::from_raw(STENO_FIRST + 23);
+    pub const S_E: Key = Key::from_raw(STENO_FIRST + 24);
+    pub const S_U: Key = Key::from_raw(STENO_FIRST + 25);
+    pub const S_FR: Key = Key::from_raw(STENO_FIRST + 26);
+    pub const S_RR: Key = Key::from_raw(STENO_FIRST + 27);
+    pub const S_PR: Key = Key::from_raw(STENO_FIRST + 28);
+    pub const S_BR: Key = Key::from_raw(STENO_FIRST + 29);
+    pub const S_LR: Key = Key::from_raw(STENO_FIRST + 30);
+    pub const S_GR: Key = Key::from_raw(STENO_FIRST + 31);
+    pub const S_TR: Key = Key::from_raw(STENO_FIRST + 32);
+    pub const S_SR: Key = Key::from_raw(STENO_FIRST + 33);
+    pub const S_DR: Key = Key::from_raw(STENO_FIRST + 34);
+    pub const S_N7: Key = Key::from_raw(STENO_FIRST + 35);
+    pub const S_N8: Key = Key::from_raw(STENO_FIRST + 36);
+    pub const S_N9: Key = Key::from_raw(STENO_FIRST + 37);
+    pub const S_NA: Key = Key::from_raw(STENO_FIRST + 38);
+    pub const S_NB: Key = Key::from_raw(STENO_FIRST + 39);
+    pub const S_NC: Key = Key::from_raw(STENO_FIRST + 40);
+    pub const S_ZR: Key = Key::from_raw(STENO_FIRST + 41);
+}
+
+/// Global steno state.
+pub static STENO: lock::Spinlock<Steno> = lock::Spinlock::new(Steno::new());
+
+/// Sends stenography chords to the host using the GeminiPR protocol.
+///
+/// Steno keys pressed together are accumulated into a chord, which is sent over the
+/// USB CDC-ACM serial port as a single GeminiPR packet when the last key of the chord
+/// is released. A new chord starts with the next steno key press.
+pub struct Steno {
+    packet: [u8; GEMINI_PR_PACKET_LEN],
+    held: u8,
+}
+
+impl Steno {
+    /// Creates a new [Steno] with an empty chord.
+    pub const fn new() -> Self {
+        Self {
+            packet: [0u8; GEMINI_PR_PACKET_LEN],
+            held: 0,
+        }
+    }
+
+    /// Gets whether the key is a steno key.
+    pub fn is_steno_key(key: &Key) -> bool {
+        (STENO_FIRST..=STENO_LAST).contains(&key.raw())
+    }
+
+    /// Gets the byte index and mask of a steno key in the GeminiPR packet.
+    pub fn packet_bit(key: &Key) -> Option<(usize, u8)> {
+        let index = key.raw().checked_sub(STENO_FIRST).filter(|&i| i < NUM_STENO_KEYS)?;
+
+        Some(((index / 7) as usize, 1 << (6 - index % 7)))
+    }
+
+    /// Builds the GeminiPR packet of a chord made of `keys`.
+    ///
+    /// Keys without a packet bit are ignored, and `None` is returned if no key has one.
+    pub fn chord_packet(keys: &[Key]) -> Option<[u8; GEMINI_PR_PACKET_LEN]> {
+        let mut packet = [0u8; GEMINI_PR_PACKET_LEN];
+
+        for (byte, mask) in keys.iter().filter_map(Self::packet_bit) {
+            packet[byte] |= mask;
+        }
+
+        Self::finish_packet(packet)
+    }
+
+    /// Adds a pressed key to the chord.
+    pub fn press(&mut self, key: &Key) {
+        if let Some((byte, mask)) = Self::packet_bit(key) {
+            self.packet[byte] |= mask;
+        }
+        self.held = self.held.saturating_add(1);
+    }
+
+    /// Records a released key.
+    ///
+    /// Returns the GeminiPR packet for the chord once its last key is released, and
+    /// starts a new chord.
+    pub fn release(&mut self) -> Option<[u8; GEMINI_PR_PACKET_LEN]> {
+        self.held = self.held.saturating_sub(1);
+
+        if self.held > 0 {
+            return None;
+        }
+
+        Self::finish_packet(core::mem::replace(&mut self.packet, [0u8; GEMINI_PR_PACKET_LEN]))
+    }
+
+    /// Sets the start of packet bit, dropping chords made only of keys without a packet bit.
+    fn finish_packet(mut packet: [u8; GEMINI_PR_PACKET_LEN]) -> Option<[u8; GEMINI_PR_PACKET_LEN]> {
+        if packet.iter().all(|&b| b == 0) {
+            return None;
+        }
+
+        packet[0] |= 0x80;
+
+        Some(packet)
+    }
+}
+
+impl EventHandler for Steno {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("GeminiPR")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if !Self::is_steno_key(event.key()) {
+            return Ok(());
+        }
+
+        if event.state().key_toggled_on() {
+            STENO.write().press(event.key());
+        } else if event.state().key_toggled_off() {
+            let packet = STENO.write().release();
+
+            if let Some(packet) = packet {
+                // Chords are dropped while no steno engine has the port open.
+                let _ = UsbSerial.write_bytes(&packet);
+            }
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::keys::*;
+    use super::*;
+
+    // S- T- A E -T: bits 7, 9, 15, 24 and 32.
+    const CHORD: [Key; 5] = [S_SL, S_TL, S_A, S_E, S_TR];
+    const PACKET: [u8; GEMINI_PR_PACKET_LEN] = [0x80, 0x50, 0x20, 0x08, 0x04, 0x00];
+
+    #[test]
+    fn chord_packet_sets_the_key_bits() {
+        assert_eq!(Steno::chord_packet(&CHORD), Some(PACKET));
+        assert_eq!(Steno::chord_packet(&[S_FN]), Some([0xc0, 0, 0, 0, 0, 0]));
+        assert_eq!(Steno::chord_packet(&[S_ZR]), Some([0x80, 0, 0, 0, 0, 0x01]));
+        assert_eq!(Steno::chord_packet(&[]), None);
+    }
+
+    #[test]
+    fn packet_is_sent_on_the_last_release() {
+        let mut steno = Steno::new();
+
+        for key in CHORD.iter() {
+            steno.press(key);
+        }
+
+        for _ in 1..CHORD.len() {
+            assert_eq!(steno.release(), None);
+        }
+
+        assert_eq!(steno.release(), Some(PACKET));
+        assert_eq!(steno.release(), None);
+    }
+}
@@ -0,0 +1,451 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, keyswitch_state::KeyswitchState, lock, runtime::Runtime};
+
+/// Number of modifiers, from `LeftControl` to `RightGui`.
+const NUM_MODIFIERS: usize = 8;
+
+/// Global StickyKeys state.
+pub static STICKY_KEYS: lock::Spinlock<StickyKeys> = lock::Spinlock::new(StickyKeys::new());
+
+/// Sticky state of a modifier.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StickyState {
+    /// Not sticky: held or released normally.
+    Released,
+    /// Tapped once, applied to the next key.
+    Latched,
+    /// Tapped twice, applied to every key until tapped again.
+    Locked,
+}
+
+/// Function called when a modifier changes sticky state, e.g. to sound a chime, or light
+/// an indicator.
+pub type StickyIndicator = fn(Key, StickyState);
+
+/// Accessibility sticky modifiers, on the regular modifier keys.
+///
+/// Tapping a modifier latches it: it stays active for the next key only, and is released
+/// once that key has been handled. Tapping it twice locks it, until it is tapped again.
+/// Held while another key is pressed, a modifier acts normally, and is released with its
+/// key.
+///
+/// Unlike [OneShot](crate::plugins::one_shot::OneShot), no dedicated keys are needed:
+/// every modifier key of the keymap becomes sticky while the plugin is enabled. The
+/// plugin is disabled by default, enable it with [set_enabled](Self::set_enabled). Sticky
+/// modifiers stay in the `LIVE_KEYS` state, so they are part of every report until
+/// released. Modifier keys, and keys other than Keyboard and Consumer Control keys, e.g.
+/// layer keys, don't release latched modifiers, so several modifiers can be latched for
+/// the same key.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::key_addr::KeyAddr;
+/// use kaleidoscope::plugins::sticky_keys::{StickyKeys, StickyState};
+///
+/// let mut sticky = StickyKeys::new();
+/// let (shift, ctrl) = (1, 0);
+/// let (shift_addr, ctrl_addr) = (KeyAddr::create(3, 0), KeyAddr::create(3, 1));
+///
+/// // Single tap: Shift applies to the next key, and is released after it.
+/// sticky.press(shift, shift_addr);
+/// assert!(sticky.release(shift));
+/// assert_eq!(sticky.state(shift), StickyState::Latched);
+///
+/// sticky.next_key();
+/// assert_eq!(sticky.take_due(), Some((shift, shift_addr)));
+/// assert_eq!(sticky.take_due(), None);
+/// assert_eq!(sticky.state(shift), StickyState::Released);
+///
+/// // Double tap: Ctrl stays locked across keys, until tapped again.
+/// sticky.press(ctrl, ctrl_addr);
+/// assert!(sticky.release(ctrl));
+/// sticky.press(ctrl, ctrl_addr);
+/// assert!(sticky.release(ctrl));
+/// assert_eq!(sticky.state(ctrl), StickyState::Locked);
+///
+/// sticky.next_key();
+/// sticky.next_key();
+/// assert_eq!(sticky.take_due(), None);
+///
+/// sticky.press(ctrl, ctrl_addr);
+/// assert!(!sticky.release(ctrl));
+/// assert_eq!(sticky.state(ctrl), StickyState::Released);
+///
+/// // Physical hold: Shift held while another key is pressed acts normally.
+/// sticky.press(shift, shift_addr);
+/// sticky.next_key();
+/// assert!(!sticky.release(shift));
+/// assert_eq!(sticky.state(shift), StickyState::Released);
+/// assert_eq!(sticky.take_due(), None);
+/// ```
+pub struct StickyKeys {
+    enabled: bool,
+    addrs: [KeyAddr; NUM_MODIFIERS],
+    pressed: u8,
+    interrupted: u8,
+    latched: u8,
+    locked: u8,
+    due: u8,
+    indicator: Option<StickyIndicator>,
+}
+
+impl StickyKeys {
+    /// Creates a new, disabled, [StickyKeys] with no sticky modifier.
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            addrs: [KeyAddr::default(); NUM_MODIFIERS],
+            pressed: 0,
+            interrupted: 0,
+            latched: 0,
+            locked: 0,
+            due: 0,
+            indicator: None,
+        }
+    }
+
+    /// Gets whether modifiers are sticky.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables sticky modifiers.
+    ///
+    /// Disabling releases every latched and locked modifier.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.cancel();
+        }
+
+        self.enabled = enabled;
+    }
+
+    /// Toggles sticky modifiers, see [set_enabled](Self::set_enabled).
+    pub fn toggle(&mut self) {
+        self.set_enabled(!self.enabled);
+    }
+
+    /// Sets the function called when a modifier changes sticky state.
+    pub fn set_indicator(&mut self, indicator: Option<StickyIndicator>) {
+        self.indicator = indicator;
+    }
+
+    /// Gets the slot of a modifier key, `0` (`LeftControl`) to `7` (`RightGui`), `None`
+    /// for other keys.
+    pub fn slot_of(key: &Key) -> Option<usize> {
+        if !key.is_keyboard_key() || !key.is_keyboard_modifier() {
+            return None;
+        }
+
+        key.key_code()
+            .checked_sub(Key_LeftControl.key_code())
+            .map(usize::from)
+            .filter(|&slot| slot < NUM_MODIFIERS)
+    }
+
+    /// Gets the sticky state of the modifier of `slot`.
+    pub fn state(&self, slot: usize) -> StickyState {
+        let bit = Self::bit(slot);
+
+        if self.locked & bit != 0 {
+            StickyState::Locked
+        } else if self.latched & bit != 0 {
+            StickyState::Latched
+        } else {
+            StickyState::Released
+        }
+    }
+
+    /// Handles the press of the modifier of `slot`, at `addr`.
+    ///
+    /// Pressing a latched modifier locks it. Pressing a locked modifier unlocks it, it is
+    /// then released normally.
+    pub fn press(&mut self, slot: usize, addr: KeyAddr) {
+        let bit = Self::bit(slot);
+
+        self.addrs[slot] = addr;
+        self.pressed |= bit;
+        self.interrupted &= !bit;
+        self.due &= !bit;
+
+        if self.locked & bit != 0 {
+            self.locked &= !bit;
+            self.interrupted |= bit;
+        } else if self.latched & bit != 0 {
+            self.latched &= !bit;
+            self.locked |= bit;
+        }
+    }
+
+    /// Handles the release of the modifier of `slot`.
+    ///
+    /// Returns whether the modifier stays active after the release: it was tapped, rather
+    /// than held while another key was pressed.
+    pub fn release(&mut self, slot: usize) -> bool {
+        let bit = Self::bit(slot);
+
+        self.pressed &= !bit;
+
+        if self.interrupted & bit != 0 {
+            self.interrupted &= !bit;
+            return false;
+        }
+
+        if self.locked & bit == 0 {
+            self.latched |= bit;
+        }
+
+        true
+    }
+
+    /// Handles the press of another key.
+    ///
+    /// Held modifiers act normally from now on. Latched modifiers are due for release, see
+    /// [take_due](Self::take_due). Locked modifiers stay.
+    pub fn next_key(&mut self) {
+        self.interrupted |= self.pressed;
+        self.due |= self.latched;
+        self.latched = 0;
+    }
+
+    /// Releases every latched and locked modifier.
+    ///
+    /// Held modifiers are released with their key.
+    pub fn cancel(&mut self) {
+        self.due |= (self.latched | self.locked) & !self.pressed;
+        self.interrupted |= self.pressed;
+        self.latched = 0;
+        self.locked = 0;
+    }
+
+    /// Takes a modifier due for release, with the address of its key.
+    pub fn take_due(&mut self) -> Option<(usize, KeyAddr)> {
+        if self.due == 0 {
+            return None;
+        }
+
+        let slot = self.due.trailing_zeros() as usize;
+        self.due &= !Self::bit(slot);
+
+        Some((slot, self.addrs[slot]))
+    }
+
+    /// Gets the pressed or sticky modifier whose key is at `addr`.
+    fn slot_at(&self, addr: &KeyAddr) -> Option<usize> {
+        let on = self.pressed | self.latched | self.locked;
+
+        (0..NUM_MODIFIERS).find(|&slot| on & Self::bit(slot) != 0 && &self.addrs[slot] == addr)
+    }
+
+    /// Gets the modifier key of `slot`.
+    fn modifier(slot: usize) -> Key {
+        Key::from_raw(Key_LeftControl.raw() + slot as u16)
+    }
+
+    /// Gets whether pressing `key` releases the latched modifiers.
+    fn consumes_latched(key: &Key) -> bool {
+        (key.is_keyboard_key() && !key.is_keyboard_modifier() && *key != Key_NoKey) || key.is_consumer_control_key()
+    }
+
+    fn bit(slot: usize) -> u8 {
+        1 << slot
+    }
+
+    /// Calls the indicator if the modifier of `slot` changed sticky state.
+    fn indicate(indicator: Option<StickyIndicator>, slot: usize, before: StickyState, after: StickyState) {
+        if let Some(indicator) = indicator {
+            if before != after {
+                indicator(Self::modifier(slot), after);
+            }
+        }
+    }
+
+    /// Queues the releases of the modifiers due for release.
+    ///
+    /// The runtime handles the releases once the `after_each_cycle()` handlers return. A
+    /// modifier whose release does not fit in the queue stays due, until the next cycle.
+    fn release_due() {
+        loop {
+            let mut sticky = STICKY_KEYS.write();
+
+            let Some((slot, addr)) = sticky.take_due() else {
+                break;
+            };
+
+            let mut state = KeyswitchState::default();
+            state.set_injected(true);
+            state.set_was_pressed(true);
+
+            if Runtime::queue_key_event(KeyEvent::next(addr, state)).is_err() {
+                sticky.due |= Self::bit(slot);
+                break;
+            }
+
+            let indicator = sticky.indicator;
+            drop(sticky);
+
+            if let Some(indicator) = indicator {
+                indicator(Self::modifier(slot), StickyState::Released);
+            }
+        }
+    }
+}
+
+impl EventHandler for StickyKeys {
+    // Our own releases, and modifiers injected by other plugins, are never made sticky.
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("StickyKeys")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let key = *event.key();
+        let addr = *event.addr();
+        let mut sticky = STICKY_KEYS.write();
+
+        if !sticky.enabled {
+            return Ok(());
+        }
+
+        if event.state().key_toggled_off() {
+            let Some(slot) = sticky.slot_at(&addr) else {
+                return Ok(());
+            };
+
+            let before = sticky.state(slot);
+            let stays_active = sticky.release(slot);
+            let (after, indicator) = (sticky.state(slot), sticky.indicator);
+            drop(sticky);
+
+            Self::indicate(indicator, slot, before, after);
+
+            // Keeps the modifier in `LIVE_KEYS`, so it stays in the reports.
+            return if stays_active { Err(EventHandlerError::Abort) } else { Ok(()) };
+        }
+
+        if !event.state().key_toggled_on() {
+            return Ok(());
+        }
+
+        match Self::slot_of(&key) {
+            Some(slot) => {
+                let before = sticky.state(slot);
+                sticky.press(slot, addr);
+                let (after, indicator) = (sticky.state(slot), sticky.indicator);
+                drop(sticky);
+
+                Self::indicate(indicator, slot, before, after);
+            }
+            None if Self::consumes_latched(&key) => sticky.next_key(),
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    fn after_each_cycle() -> Result<()> {
+        Self::release_due();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: usize = 1;
+    const ALT: usize = 2;
+
+    fn tap(sticky: &mut StickyKeys, slot: usize) -> bool {
+        sticky.press(slot, KeyAddr::create(3, slot as u8));
+        sticky.release(slot)
+    }
+
+    #[test]
+    fn slot_of_modifiers() {
+        assert_eq!(StickyKeys::slot_of(&Key_LeftControl), Some(0));
+        assert_eq!(StickyKeys::slot_of(&Key_LeftShift), Some(SHIFT));
+        assert_eq!(StickyKeys::slot_of(&Key_RightGui), Some(7));
+        assert_eq!(StickyKeys::slot_of(&Key_A), None);
+        assert_eq!(StickyKeys::slot_of(&Key_NoKey), None);
+    }
+
+    #[test]
+    fn tap_latches_then_locks_then_releases() {
+        let mut sticky = StickyKeys::new();
+
+        assert!(tap(&mut sticky, SHIFT));
+        assert_eq!(sticky.state(SHIFT), StickyState::Latched);
+
+        assert!(tap(&mut sticky, SHIFT));
+        assert_eq!(sticky.state(SHIFT), StickyState::Locked);
+
+        assert!(!tap(&mut sticky, SHIFT));
+        assert_eq!(sticky.state(SHIFT), StickyState::Released);
+        assert_eq!(sticky.take_due(), None);
+    }
+
+    #[test]
+    fn latched_modifiers_are_due_in_slot_order() {
+        let mut sticky = StickyKeys::new();
+
+        assert!(tap(&mut sticky, ALT));
+        assert!(tap(&mut sticky, SHIFT));
+        sticky.next_key();
+
+        assert_eq!(sticky.take_due(), Some((SHIFT, KeyAddr::create(3, SHIFT as u8))));
+        assert_eq!(sticky.take_due(), Some((ALT, KeyAddr::create(3, ALT as u8))));
+        assert_eq!(sticky.take_due(), None);
+    }
+
+    #[test]
+    fn cancel_releases_sticky_but_not_held_modifiers() {
+        let mut sticky = StickyKeys::new();
+
+        assert!(tap(&mut sticky, SHIFT));
+        assert!(tap(&mut sticky, ALT));
+        assert!(tap(&mut sticky, ALT));
+        sticky.press(0, KeyAddr::create(3, 0));
+
+        sticky.cancel();
+
+        assert_eq!(sticky.state(SHIFT), StickyState::Released);
+        assert_eq!(sticky.state(ALT), StickyState::Released);
+        assert_eq!(sticky.take_due().map(|(slot, _)| slot), Some(SHIFT));
+        assert_eq!(sticky.take_due().map(|(slot, _)| slot), Some(ALT));
+        assert_eq!(sticky.take_due(), None);
+
+        // The held modifier is released with its key, and does not latch.
+        assert!(!sticky.release(0));
+        assert_eq!(sticky.state(0), StickyState::Released);
+    }
+
+    #[test]
+    fn disabling_cancels() {
+        let mut sticky = StickyKeys::new();
+        sticky.set_enabled(true);
+
+        assert!(tap(&mut sticky, SHIFT));
+        sticky.toggle();
+
+        assert!(!sticky.is_enabled());
+        assert_eq!(sticky.state(SHIFT), StickyState::Released);
+        assert_eq!(sticky.take_due().map(|(slot, _)| slot), Some(SHIFT));
+    }
+
+    #[test]
+    fn repress_before_release_is_not_due() {
+        let mut sticky = StickyKeys::new();
+
+        assert!(tap(&mut sticky, SHIFT));
+        sticky.next_key();
+
+        // Pressed again before the release was taken: it stays held.
+        sticky.press(SHIFT, KeyAddr::create(3, SHIFT as u8));
+        assert_eq!(sticky.take_due(), None);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::SYSTER;
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event;
+use crate::{key_defs::*, key_event::KeyEvent, lock};
+
+/// Key starting an abbreviation.
+#[allow(non_upper_case_globals)]
+pub const Key_Syster: Key = Key::from_raw(SYSTER);
+
+/// Maximum length of an abbreviation.
+pub const SYSTER_MAX_SYMBOL_LEN: usize = 16;
+
+/// Dictionary entry: the abbreviation, and the keys it expands to.
+pub type SysterEntry = (&'static str, &'static [Key]);
+
+/// Phase passed to the [SysterCallback].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SysterPhase {
+    /// The Syster key was pressed, the token is empty.
+    Start,
+    /// The token was terminated, and erased from the host.
+    Symbol,
+    /// Capture ended, after the symbol was handled, on cancellation, or on overflow.
+    End,
+}
+
+/// User hook called on every [SysterPhase].
+///
+/// On [SysterPhase::Symbol], returning `true` marks the token as handled, and the
+/// dictionary is not searched.
+pub type SysterCallback = fn(token: &str, phase: SysterPhase) -> bool;
+
+/// Global Syster state.
+pub static SYSTER_STATE: lock::Spinlock<Syster> = lock::Spinlock::new(Syster::new());
+
+/// Expands abbreviations typed after the Syster key.
+///
+/// After the Syster key is pressed, letters and digits are captured as they are typed.
+/// `Space` terminates the token: it is erased with backspaces, then passed to the
+/// [SysterCallback] (see [syster_action](Self::syster_action)), or looked up in the
+/// dictionary and replaced with its expansion. `Escape` cancels the capture, as does
+/// any other non-modifier key. Tokens longer than [SYSTER_MAX_SYMBOL_LEN] abort the
+/// capture, leaving the typed text untouched: the callback gets the full token on
+/// [SysterPhase::End].
+pub struct Syster {
+    dictionary: &'static [SysterEntry],
+    callback: Option<SysterCallback>,
+    token: [u8; SYSTER_MAX_SYMBOL_LEN],
+    len: usize,
+    active: bool,
+    // Expansion being typed, see [expansion_event](Self::expansion_event).
+    expansion: &'static [Key],
+}
+
+impl Syster {
+    /// Creates a new [Syster] with an empty dictionary.
+    pub const fn new() -> Self {
+        Self {
+            dictionary: &[],
+            callback: None,
+            token: [0u8; SYSTER_MAX_SYMBOL_LEN],
+            len: 0,
+            active: false,
+            expansion: &[],
+        }
+    }
+
+    /// Sets the dictionary of abbreviations.
+    pub fn set_dictionary(&mut self, dictionary: &'static [SysterEntry]) {
+        self.dictionary = dictionary;
+    }
+
+    /// Sets the `syster_action` callback.
+    pub fn set_syster_action(&mut self, callback: Option<SysterCallback>) {
+        self.callback = callback;
+    }
+
+    /// Gets whether a token is being captured.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Gets the captured token.
+    pub fn token(&self) -> &str {
+        core::str::from_utf8(&self.token[..self.len]).unwrap_or("")
+    }
+
+    /// Starts capturing a new token.
+    pub fn start(&mut self) {
+        self.len = 0;
+        self.active = true;
+    }
+
+    /// Stops capturing, and drops the token.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.active = false;
+    }
+
+    /// Adds a character to the token.
+    ///
+    /// Returns [Error::TokenOverflow](crate::Error::TokenOverflow), leaving the token
+    /// unchanged, if it is full.
+    pub fn push(&mut self, c: u8) -> crate::Result<()> {
+        if self.len == SYSTER_MAX_SYMBOL_LEN {
+            return Err(crate::Error::TokenOverflow);
+        }
+
+        self.token[self.len] = c;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Removes the last character of the token.
+    pub fn pop(&mut self) {
+        self.len = self.len.saturating_sub(1);
+    }
+
+    /// Looks up the expansion of the token in the dictionary.
+    pub fn lookup(&self) -> Option<&'static [Key]> {
+        let token = self.token();
+
+        self.dictionary
+            .iter()
+            .find(|(symbol, _)| *symbol == token)
+            .map(|(_, keys)| *keys)
+    }
+
+    /// Calls the `syster_action` callback, if set.
+    ///
+    /// Returns whether the callback handled the token.
+    pub fn syster_action(&self, phase: SysterPhase) -> bool {
+        match self.callback {
+            Some(callback) => callback(self.token(), phase),
+            None => false,
+        }
+    }
+
+    /// Gets the character typed by an unmodified letter or digit key.
+    pub fn key_to_char(key: &Key) -> Option<u8> {
+        let code = key.key_code();
+
+        if *key == Key_0 {
+            Some(b'0')
+        } else if (Key_A.key_code()..=Key_Z.key_code()).contains(&code) {
+            Some(b'a' + (code - Key_A.key_code()))
+        } else if (Key_1.key_code()..=Key_9.key_code()).contains(&code) {
+            Some(b'1' + (code - Key_1.key_code()))
+        } else {
+            None
+        }
+    }
+
+    /// Erases the token from the host, and sends its expansion.
+    ///
+    /// Both are queued with [Runtime::queue_key_source], and typed once the current
+    /// event handlers return, in order with anything the callback types.
+    fn complete() {
+        let len = SYSTER_STATE.read().len;
+
+        let _ = Runtime::queue_key_source(Self::erase_event, len as u32);
+
+        let (handled, expansion) = {
+            let syster = SYSTER_STATE.read();
+            (syster.syster_action(SysterPhase::Symbol), syster.lookup())
+        };
+
+        let mut syster = SYSTER_STATE.write();
+
+        if let (false, Some(expansion)) = (handled, expansion) {
+            syster.expansion = expansion;
+            let _ = Runtime::queue_key_source(Self::expansion_event, 0);
+        }
+
+        syster.syster_action(SysterPhase::End);
+        syster.reset();
+    }
+
+    /// Gets the key event `index` of `count` backspace taps.
+    fn erase_event(count: u32, index: usize) -> Option<KeyEvent> {
+        (index < count as usize * 2).then(|| injected_event(Key_Backspace, index % 2 == 0))
+    }
+
+    /// Gets the key event `index` tapping the keys of the expansion.
+    fn expansion_event(_: u32, index: usize) -> Option<KeyEvent> {
+        let key = *SYSTER_STATE.read().expansion.get(index / 2)?;
+
+        Some(injected_event(key, index % 2 == 0))
+    }
+}
+
+impl EventHandler for Syster {
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Syster")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        let key = *event.key();
+
+        if key == Key_Syster {
+            if event.state().key_toggled_on() {
+                let mut syster = SYSTER_STATE.write();
+                syster.start();
+                syster.syster_action(SysterPhase::Start);
+            }
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if !event.state().key_toggled_on() || !SYSTER_STATE.read().is_active() {
+            return Ok(());
+        }
+
+        if key == Key_Spacebar {
+            Self::complete();
+            return Err(EventHandlerError::EventConsumed);
+        }
+
+        if key == Key_Backspace {
+            SYSTER_STATE.write().pop();
+            return Ok(());
+        }
+
+        if key.is_keyboard_modifier() {
+            return Ok(());
+        }
+
+        let mut syster = SYSTER_STATE.write();
+
+        match Self::key_to_char(&key) {
+            Some(c) if key.flags() == KeyFlags::NONE => {
+                if syster.push(c).is_err() {
+                    syster.syster_action(SysterPhase::End);
+                    syster.reset();
+                }
+            }
+            // Escape, or any other key, cancels the capture.
+            _ => {
+                syster.syster_action(SysterPhase::End);
+                syster.reset();
+            }
+        }
+
+        if key == Key_Escape {
+            Err(EventHandlerError::EventConsumed)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key) -> Result<()> {
+        Syster::on_key_event(&mut injected_event(key, true))
+    }
+
+    #[test]
+    fn matched_abbreviation_expands() {
+        static DICTIONARY: [SysterEntry; 2] = [("hi", &[Key_H, Key_E, Key_L, Key_L, Key_O]), ("a1", &[Key_B])];
+
+        let mut syster = Syster::new();
+        syster.set_dictionary(&DICTIONARY);
+        syster.start();
+
+        for key in [Key_A, Key_1] {
+            syster.push(Syster::key_to_char(&key).unwrap()).unwrap();
+        }
+
+        assert_eq!(syster.token(), "a1");
+        assert_eq!(syster.lookup(), Some(&[Key_B][..]));
+
+        syster.pop();
+        assert_eq!(syster.lookup(), None);
+    }
+
+    #[test]
+    fn full_token_rejects_more_characters() {
+        let mut syster = Syster::new();
+        syster.start();
+
+        for _ in 0..SYSTER_MAX_SYMBOL_LEN {
+            assert_eq!(syster.push(b'x'), Ok(()));
+        }
+
+        assert_eq!(syster.push(b'y'), Err(crate::Error::TokenOverflow));
+        assert_eq!(syster.token().len(), SYSTER_MAX_SYMBOL_LEN);
+        assert!(!syster.token().contains('y'));
+    }
+
+    #[test]
+    fn capture_ends_on_cancel_and_overflow() {
+        assert_eq!(press(Key_Syster), Err(EventHandlerError::EventConsumed));
+        assert_eq!(press(Key_A), Ok(()));
+        assert_eq!(SYSTER_STATE.read().token(), "a");
+
+        // Escape cancels the capture, and is consumed.
+        assert_eq!(press(Key_Escape), Err(EventHandlerError::EventConsumed));
+        assert!(!SYSTER_STATE.read().is_active());
+
+        // Any other non-modifier key cancels too, and is passed on.
+        let _ = press(Key_Syster);
+        assert_eq!(press(Key_LeftShift), Ok(()));
+        assert!(SYSTER_STATE.read().is_active());
+        assert_eq!(press(Key_Enter), Ok(()));
+        assert!(!SYSTER_STATE.read().is_active());
+
+        // One character too many aborts the capture, the typed text is left alone.
+        let _ = press(Key_Syster);
+        for _ in 0..SYSTER_MAX_SYMBOL_LEN {
+            assert_eq!(press(Key_X), Ok(()));
+        }
+        assert!(SYSTER_STATE.read().is_active());
+
+        assert_eq!(press(Key_X), Ok(()));
+        assert!(!SYSTER_STATE.read().is_active());
+        assert_eq!(SYSTER_STATE.read().token(), "");
+    }
+}
@@ -0,0 +1,86 @@
+use kaleidoscope_internal::driver::keyscanner::MatrixScanner;
+
+use crate::bootloader::BootloaderKind;
+use crate::device::{pins_and_ports::*, DeviceOps};
+use crate::driver::{bootloader::avr::Caterina, board::{Board, BoardProps}, keyscanner::{Atmega, KeyScannerProps}};
+
+pub type KeyScanner = Atmega;
+pub type Bootloader = Caterina;
+
+impl KeyScannerProps for TechnomancyAtreusProps {
+    const ROWS: usize = 4;
+    // The matrix is 4x11, but KeyAddr bounds come from the 4x12 Atreus matrix of
+    // kaleidoscope-internal. The twelfth column has no pin, and is never pressed.
+    const COLS: usize = 12;
+
+    const KEYSCAN_INTERVAL: u16 = 1500;
+}
+
+impl BoardProps for TechnomancyAtreusProps {
+    const SHORT_NAME: &'static str = "technomancy_atreus";
+
+    const MATRIX_ROW_PINS: &'static [u8] = &[PIN_D0, PIN_D1, PIN_D3, PIN_D2];
+    const MATRIX_COL_PINS: &'static [u8] = &[
+        PIN_B7, PIN_D6, PIN_F7, PIN_F6, PIN_B6, PIN_D4, PIN_E6, PIN_B4, PIN_B5, PIN_C6, PIN_D7,
+    ];
+
+    const BOOTLOADER: Option<BootloaderKind> = Some(BootloaderKind::Caterina);
+
+    const NUM_LAYERS: usize = 2;
+}
+
+/// Original (2014) Atreus by Technomancy, with a 4x11 matrix.
+///
+/// The matrix is addressed as 4x12, like the Keyboardio Atreus, with an unused last
+/// column.
+pub struct TechnomancyAtreusProps;
+
+pub struct TechnomancyAtreus {
+    key_scanner: KeyScanner,
+}
+
+impl TechnomancyAtreus {
+    pub const fn new() -> Self {
+        Self {
+            key_scanner: KeyScanner::new(),
+        }
+    }
+
+    pub const fn led_count() -> usize {
+        <TechnomancyAtreusProps as BoardProps>::LED_COUNT
+    }
+}
+
+impl Board for TechnomancyAtreus {
+    type Props = TechnomancyAtreusProps;
+
+    fn scan_matrix(&mut self) {
+        self.key_scanner.scan_matrix();
+    }
+}
+
+impl DeviceOps for TechnomancyAtreus {
+    type KeyScanner = Atmega;
+
+    fn key_scanner(&self) -> &Self::KeyScanner {
+        &self.key_scanner
+    }
+
+    fn key_scanner_mut(&mut self) -> &mut Self::KeyScanner {
+        &mut self.key_scanner
+    }
+}
+
+pub type Device = TechnomancyAtreus;
+pub type DeviceProps = TechnomancyAtreusProps;
+
+#[avr_device::interrupt(atmega32u4)]
+fn TIMER1_OVF() {
+    use crate::RUNTIME;
+
+    RUNTIME
+        .write()
+        .device_mut()
+        .key_scanner_mut()
+        .set_do_scan(true);
+}
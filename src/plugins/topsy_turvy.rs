@@ -0,0 +1,129 @@
+use crate::driver::hid::base::keyboard::Keyboard;
+use crate::event_handler::{EventHandler, Result};
+use crate::plugins::ranges::{TT_FIRST, TT_LAST};
+use crate::{with_hid, key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, lock, return_on_err, LIVE_KEYS};
+
+/// Creates the TopsyTurvy key wrapping the keyboard key `k`.
+#[macro_export]
+macro_rules! TOPSY {
+    ($k:expr) => {
+        $crate::key_defs::Key::from_raw($crate::plugins::ranges::TT_FIRST + $k.key_code() as u16)
+    };
+}
+
+/// Global TopsyTurvy state.
+pub static TOPSY_TURVY: lock::Spinlock<TopsyTurvy> = lock::Spinlock::new(TopsyTurvy::new());
+
+/// Keys with inverted Shift behavior.
+///
+/// A `TOPSY(k)` key sends `k` shifted when no Shift key is held, and unshifted when one
+/// is. While the TopsyTurvy key is held with a physical Shift key, Shift is removed from
+/// the reports; the real modifier state is restored once the key is released.
+pub struct TopsyTurvy {
+    addr: Option<KeyAddr>,
+    unshift: bool,
+}
+
+impl TopsyTurvy {
+    /// Creates a new [TopsyTurvy].
+    pub const fn new() -> Self {
+        Self {
+            addr: None,
+            unshift: false,
+        }
+    }
+
+    /// Gets whether the key is a TopsyTurvy key.
+    pub fn is_topsy_turvy_key(key: &Key) -> bool {
+        (TT_FIRST..=TT_LAST).contains(&key.raw())
+    }
+
+    /// Gets the keyboard key wrapped by a TopsyTurvy key.
+    pub fn decode(key: &Key) -> Key {
+        Key::from_raw(key.raw() - TT_FIRST)
+    }
+
+    fn release_shift() {
+        return_on_err!(with_hid(|hid| {
+            hid.release_key(Key_LeftShift);
+            hid.release_key(Key_RightShift);
+        }));
+    }
+
+    /// Gets whether a Shift key is held, ignoring flags on other keys.
+    pub fn shift_held() -> bool {
+        KeyAddr::iter().any(|addr| {
+            let key = LIVE_KEYS.read()[addr];
+            key == Key_LeftShift || key == Key_RightShift
+        })
+    }
+}
+
+impl EventHandler for TopsyTurvy {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("TopsyTurvy")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if event.state().key_toggled_off() {
+            let mut tt = TOPSY_TURVY.write();
+
+            if tt.addr.as_ref() == Some(event.addr()) {
+                tt.addr = None;
+                tt.unshift = false;
+            }
+
+            return Ok(());
+        }
+
+        if !Self::is_topsy_turvy_key(event.key()) {
+            return Ok(());
+        }
+
+        let mut key = Self::decode(event.key());
+        let unshift = Self::shift_held();
+
+        if !unshift {
+            key.set_flags(KeyFlags::SHIFT_HELD);
+        }
+
+        event.set_key(key);
+
+        let mut tt = TOPSY_TURVY.write();
+        tt.addr = Some(*event.addr());
+        tt.unshift = unshift;
+
+        Ok(())
+    }
+
+    fn before_reporting_state(_event: &KeyEvent) -> Result<()> {
+        if TOPSY_TURVY.read().unshift {
+            Self::release_shift();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topsy_keys_wrap_keyboard_keys() {
+        for key in [Key_A, Key_1, Key_Minus, Key_Slash] {
+            let topsy = TOPSY!(key);
+
+            assert!(TopsyTurvy::is_topsy_turvy_key(&topsy));
+            assert_eq!(TopsyTurvy::decode(&topsy), key);
+        }
+    }
+
+    #[test]
+    fn plain_keys_are_not_topsy_keys() {
+        assert!(!TopsyTurvy::is_topsy_turvy_key(&Key_1));
+        assert!(!TopsyTurvy::is_topsy_turvy_key(&lshift!(Key_1)));
+        assert!(!TopsyTurvy::is_topsy_turvy_key(&Key::from_raw(TT_FIRST - 1)));
+        assert!(!TopsyTurvy::is_topsy_turvy_key(&Key::from_raw(TT_LAST + 1)));
+    }
+}
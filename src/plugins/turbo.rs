@@ -0,0 +1,212 @@
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::plugins::ranges::TURBO;
+use crate::runtime::Runtime;
+use crate::{key_defs::*, key_event::KeyEvent, lock, millis::millis};
+
+/// Key repeating the target key while held.
+#[allow(non_upper_case_globals)]
+pub const Key_Turbo: Key = Key::from_raw(TURBO);
+
+/// Default time, in milliseconds, between injected presses and releases.
+pub const DEFAULT_TURBO_INTERVAL: u16 = 10;
+
+/// Global turbo state.
+pub static TURBO_STATE: lock::Spinlock<Turbo> = lock::Spinlock::new(Turbo::new());
+
+/// Key repeated by the [Turbo] plugin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TurboTarget {
+    /// The most recently pressed key, when the Turbo key is pressed.
+    LastPressed,
+    /// A fixed key.
+    Fixed(Key),
+}
+
+/// Repeatedly taps a target key while the Turbo key is held.
+///
+/// The target key is pressed and released in turn, once every interval. Injection stops
+/// as soon as the Turbo key is released, releasing the target key if needed.
+pub struct Turbo {
+    target: TurboTarget,
+    interval: u16,
+    last_key: Option<Key>,
+    active: Option<Key>,
+    pressed: bool,
+    last_toggle: u32,
+}
+
+impl Turbo {
+    /// Creates a new [Turbo] repeating the last pressed key.
+    pub const fn new() -> Self {
+        Self {
+            target: TurboTarget::LastPressed,
+            interval: DEFAULT_TURBO_INTERVAL,
+            last_key: None,
+            active: None,
+            pressed: false,
+            last_toggle: 0,
+        }
+    }
+
+    /// Gets the [TurboTarget].
+    pub fn target(&self) -> TurboTarget {
+        self.target
+    }
+
+    /// Sets the [TurboTarget].
+    pub fn set_target(&mut self, target: TurboTarget) {
+        self.target = target;
+    }
+
+    /// Gets the time, in milliseconds, between injected presses and releases.
+    pub fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Sets the time, in milliseconds, between injected presses and releases.
+    pub fn set_interval(&mut self, interval: u16) {
+        self.interval = interval;
+    }
+
+    /// Gets whether the Turbo key is held.
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Starts repeating the target key.
+    ///
+    /// Returns the key to press right away, if there is a target.
+    pub fn start(&mut self, now: u32) -> Option<Key> {
+        let key = match self.target {
+            TurboTarget::LastPressed => self.last_key?,
+            TurboTarget::Fixed(key) => key,
+        };
+
+        self.active = Some(key);
+        self.pressed = true;
+        self.last_toggle = now;
+
+        Some(key)
+    }
+
+    /// Stops repeating the target key.
+    ///
+    /// Returns the key to release, if it is still pressed.
+    pub fn stop(&mut self) -> Option<Key> {
+        let key = self.active.take()?;
+
+        if core::mem::replace(&mut self.pressed, false) {
+            Some(key)
+        } else {
+            None
+        }
+    }
+
+    /// Checks the interval.
+    ///
+    /// Returns the key to inject, and whether it should be pressed or released.
+    pub fn update(&mut self, now: u32) -> Option<(Key, bool)> {
+        let key = self.active?;
+
+        if now.wrapping_sub(self.last_toggle) < self.interval as u32 {
+            return None;
+        }
+
+        self.pressed = !self.pressed;
+        self.last_toggle = now;
+
+        Some((key, self.pressed))
+    }
+}
+
+impl EventHandler for Turbo {
+    // Injected events, including our own, never re-arm the turbo key.
+    const PROCESS_INJECTED: bool = false;
+
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Turbo")
+    }
+
+    fn before_each_cycle() -> Result<()> {
+        let update = TURBO_STATE.write().update(millis());
+
+        if let Some((key, pressed)) = update {
+            let _ = Runtime::queue_key(key, pressed);
+        }
+
+        Ok(())
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if *event.key() != Key_Turbo {
+            if event.state().key_toggled_on() && event.key().is_keyboard_key() {
+                TURBO_STATE.write().last_key = Some(*event.key());
+            }
+            return Ok(());
+        }
+
+        if event.state().key_toggled_on() {
+            let key = TURBO_STATE.write().start(millis());
+
+            if let Some(key) = key {
+                let _ = Runtime::queue_key(key, true);
+            }
+        } else if event.state().key_toggled_off() {
+            let key = TURBO_STATE.write().stop();
+
+            if let Some(key) = key {
+                let _ = Runtime::queue_key(key, false);
+            }
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_toggles_every_interval() {
+        let mut turbo = Turbo::new();
+        turbo.set_target(TurboTarget::Fixed(Key_Space));
+
+        assert_eq!(turbo.update(100), None);
+        assert_eq!(turbo.start(100), Some(Key_Space));
+        assert!(turbo.is_active());
+
+        assert_eq!(turbo.update(109), None);
+        assert_eq!(turbo.update(110), Some((Key_Space, false)));
+        assert_eq!(turbo.update(119), None);
+        assert_eq!(turbo.update(120), Some((Key_Space, true)));
+    }
+
+    #[test]
+    fn stop_releases_a_pressed_target() {
+        let mut turbo = Turbo::new();
+        turbo.set_target(TurboTarget::Fixed(Key_Space));
+
+        turbo.start(100);
+        assert_eq!(turbo.stop(), Some(Key_Space));
+        assert!(!turbo.is_active());
+        assert_eq!(turbo.update(200), None);
+
+        // Already released by the last update.
+        turbo.start(100);
+        turbo.update(110);
+        assert_eq!(turbo.stop(), None);
+        assert_eq!(turbo.stop(), None);
+    }
+
+    #[test]
+    fn last_pressed_target_needs_a_key() {
+        let mut turbo = Turbo::new();
+
+        assert_eq!(turbo.start(100), None);
+        assert!(!turbo.is_active());
+
+        turbo.last_key = Some(Key_F);
+        assert_eq!(turbo.start(100), Some(Key_F));
+    }
+}
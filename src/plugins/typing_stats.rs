@@ -0,0 +1,180 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::driver::storage::SlotHandle;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::persistable::Persistable;
+use crate::{key_event::KeyEvent, lock, LAST_ERROR};
+
+/// Number of key presses after which the total is written to storage.
+pub const TYPING_STATS_FLUSH_INTERVAL: u32 = 1000;
+
+/// Global typing statistics.
+pub static TYPING_STATS: lock::Spinlock<TypingStats> = lock::Spinlock::new(TypingStats::new());
+
+/// Counts key presses, without recording which keys were pressed.
+///
+/// Every physical Keyboard key press increments a persistent total. To limit storage
+/// wear, the total is written at most once every [TYPING_STATS_FLUSH_INTERVAL] presses,
+/// and when the keyboard goes idle (see
+/// [set_idle_timeout](crate::runtime::Runtime::set_idle_timeout)). Presses since the
+/// last write are lost on power loss.
+///
+/// The `stats.keys` Focus command prints the total, `stats.keys reset` clears it.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::plugins::typing_stats::{TypingStats, TYPING_STATS_FLUSH_INTERVAL};
+///
+/// let mut stats = TypingStats::new();
+///
+/// for _ in 1..TYPING_STATS_FLUSH_INTERVAL {
+///     assert!(!stats.record());
+/// }
+/// assert!(stats.record());
+/// assert_eq!(stats.total(), TYPING_STATS_FLUSH_INTERVAL);
+/// assert!(stats.has_unsaved());
+///
+/// stats.reset();
+/// assert_eq!(stats.total(), 0);
+/// assert!(!stats.record());
+/// ```
+pub struct TypingStats {
+    total: u32,
+    unsaved: u32,
+    slot: Option<SlotHandle>,
+}
+
+impl TypingStats {
+    /// Creates a new [TypingStats] with a zero total.
+    pub const fn new() -> Self {
+        Self {
+            total: 0,
+            unsaved: 0,
+            slot: None,
+        }
+    }
+
+    /// Gets the total number of key presses.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Gets whether some presses have not been written to storage yet.
+    pub fn has_unsaved(&self) -> bool {
+        self.unsaved > 0
+    }
+
+    /// Counts a key press.
+    ///
+    /// Returns whether the total is due to be written to storage.
+    pub fn record(&mut self) -> bool {
+        self.total = self.total.wrapping_add(1);
+        self.unsaved += 1;
+
+        self.unsaved >= TYPING_STATS_FLUSH_INTERVAL
+    }
+
+    /// Clears the total.
+    ///
+    /// Call [flush](Self::flush) afterwards to clear the stored total.
+    pub fn reset(&mut self) {
+        self.total = 0;
+        self.unsaved = 0;
+    }
+
+    /// Writes the total to storage.
+    pub fn flush(&mut self) -> crate::Result<()> {
+        self.commit()?;
+        self.unsaved = 0;
+
+        Ok(())
+    }
+}
+
+impl Persistable for TypingStats {
+    const SIZE: u16 = 4;
+
+    fn save(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.total.to_le_bytes());
+    }
+
+    fn restore(&mut self, buf: &[u8]) {
+        self.total = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    }
+
+    fn slot(&self) -> Option<SlotHandle> {
+        self.slot
+    }
+
+    fn set_slot(&mut self, slot: SlotHandle) {
+        self.slot = Some(slot);
+    }
+}
+
+impl EventHandler for TypingStats {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("TypingStats")
+    }
+
+    fn on_key_event(event: &mut KeyEvent) -> Result<()> {
+        if !event.state().key_toggled_on()
+            || event.state().key_is_injected()
+            || !event.key().is_keyboard_key()
+        {
+            return Ok(());
+        }
+
+        let mut stats = TYPING_STATS.write();
+
+        // A failed write must not drop the key press, record it instead.
+        if stats.record() {
+            if let Err(err) = stats.flush() {
+                LAST_ERROR.write().record(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_idle() -> Result<()> {
+        let mut stats = TYPING_STATS.write();
+
+        if stats.has_unsaved() {
+            if let Err(err) = stats.flush() {
+                LAST_ERROR.write().record(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("stats.keys\r\n");
+            return Ok(());
+        }
+
+        if command != "stats.keys" {
+            return Ok(());
+        }
+
+        let mut stats = TYPING_STATS.write();
+
+        match args.trim() {
+            "" => {
+                uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", stats.total()).map_err(|_| EventHandlerError::Error)?;
+            }
+            "reset" => {
+                stats.reset();
+                stats.flush().map_err(|_| EventHandlerError::Error)?;
+            }
+            _ => return Err(EventHandlerError::Error),
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
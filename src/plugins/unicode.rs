@@ -0,0 +1,342 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::plugins::host_os::{HostOS, Os};
+use crate::runtime::Runtime;
+use crate::util::typing::injected_event;
+use crate::{key_defs::*, key_event::KeyEvent, key_ext::KeyExt, key_flags_ext::KeyFlagsExt, lock, Error};
+
+/// Maximum number of steps needed to type a single code point, in any [UnicodeMode].
+pub const UNICODE_MAX_STEPS: usize = 16;
+
+/// Highest Unicode code point.
+pub const UNICODE_MAX_CODE_POINT: u32 = 0x10_ffff;
+
+/// Global Unicode state.
+pub static UNICODE: lock::Spinlock<Unicode> = lock::Spinlock::new(Unicode::new());
+
+/// Host input method used to enter code points.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnicodeMode {
+    /// IBus / GTK: `Ctrl+Shift+U`, the hex code point, then `Space`.
+    #[default]
+    Linux,
+    /// macOS "Unicode Hex Input" source: the hex UTF-16 code units, typed while `Option`
+    /// is held.
+    MacOs,
+    /// Windows hex numpad input: `Alt` held, `Keypad +`, then the hex UTF-16 code units.
+    ///
+    /// Requires the `EnableHexNumpad` registry setting on the host.
+    Windows,
+}
+
+impl UnicodeMode {
+    /// Gets the name of the mode, as used by the `unicode.mode` Focus command.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Linux => "linux",
+            Self::MacOs => "macos",
+            Self::Windows => "windows",
+        }
+    }
+
+    /// Gets the mode with the provided name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linux" => Some(Self::Linux),
+            "macos" => Some(Self::MacOs),
+            "windows" => Some(Self::Windows),
+            _ => None,
+        }
+    }
+}
+
+impl From<Os> for UnicodeMode {
+    fn from(os: Os) -> Self {
+        match os {
+            Os::Linux => Self::Linux,
+            Os::MacOs => Self::MacOs,
+            Os::Windows => Self::Windows,
+        }
+    }
+}
+
+/// Single key action sent to type a code point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnicodeStep {
+    /// Press, and hold the key.
+    Press(Key),
+    /// Release a held key.
+    Release(Key),
+    /// Press, and release the key.
+    Tap(Key),
+}
+
+/// Types Unicode code points using the host's input method.
+///
+/// The input method varies per host OS. It follows the [HostOS] selection, unless it is
+/// overridden with [set_mode](Self::set_mode), or the `unicode.mode` Focus command
+/// (`linux`, `macos`, or `windows`, `host` to follow [HostOS] again). Without an
+/// argument, the command prints the current mode.
+///
+/// Code points outside the Basic Multilingual Plane are entered as the host expects:
+/// as a single hex number on Linux, and as a UTF-16 surrogate pair on macOS and
+/// Windows.
+pub struct Unicode {
+    mode: Option<UnicodeMode>,
+}
+
+impl Unicode {
+    /// Creates a new [Unicode], following the [HostOS] selection.
+    pub const fn new() -> Self {
+        Self { mode: None }
+    }
+
+    /// Gets the host input method.
+    pub fn mode(&self) -> UnicodeMode {
+        match self.mode {
+            Some(mode) => mode,
+            None => HostOS::current().into(),
+        }
+    }
+
+    /// Sets the host input method, overriding the [HostOS] selection.
+    pub fn set_mode(&mut self, mode: UnicodeMode) {
+        self.mode = Some(mode);
+    }
+
+    /// Follows the [HostOS] selection again, after [set_mode](Self::set_mode).
+    pub fn clear_mode(&mut self) {
+        self.mode = None;
+    }
+
+    /// Writes the steps typing `code_point` in `mode` to `out`.
+    ///
+    /// Returns the number of steps written, zero if `code_point` is not a Unicode scalar
+    /// value, or `out` is too short. An `out` of [UNICODE_MAX_STEPS] is always long
+    /// enough.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use kaleidoscope::{key_defs::*, key_ext::KeyExt, key_flags_ext::KeyFlagsExt};
+    /// use kaleidoscope::plugins::unicode::{Unicode, UnicodeMode, UnicodeStep::*, UNICODE_MAX_STEPS};
+    ///
+    /// let mut steps = [Tap(Key_NoKey); UNICODE_MAX_STEPS];
+    ///
+    /// // U+00E9 LATIN SMALL LETTER E WITH ACUTE
+    /// let len = Unicode::key_sequence(UnicodeMode::Linux, 0xe9, &mut steps);
+    /// assert_eq!(
+    ///     &steps[..len],
+    ///     &[Tap(Key_U.with_flags(KeyFlags::ctrl() | KeyFlags::shift())), Tap(Key_E), Tap(Key_9), Tap(Key_Spacebar)]
+    /// );
+    ///
+    /// let len = Unicode::key_sequence(UnicodeMode::MacOs, 0xe9, &mut steps);
+    /// assert_eq!(
+    ///     &steps[..len],
+    ///     &[Press(Key_LeftAlt), Tap(Key_0), Tap(Key_0), Tap(Key_E), Tap(Key_9), Release(Key_LeftAlt)]
+    /// );
+    ///
+    /// let len = Unicode::key_sequence(UnicodeMode::Windows, 0xe9, &mut steps);
+    /// assert_eq!(
+    ///     &steps[..len],
+    ///     &[
+    ///         Press(Key_LeftAlt), Tap(Key_KeypadAdd), Tap(Key_Keypad0), Tap(Key_Keypad0), Tap(Key_E),
+    ///         Tap(Key_Keypad9), Release(Key_LeftAlt),
+    ///     ]
+    /// );
+    ///
+    /// // U+1F600 GRINNING FACE, a surrogate pair (D83D DE00) on macOS.
+    /// let len = Unicode::key_sequence(UnicodeMode::MacOs, 0x1f600, &mut steps);
+    /// assert_eq!(
+    ///     &steps[..len],
+    ///     &[
+    ///         Press(Key_LeftAlt), Tap(Key_D), Tap(Key_8), Tap(Key_3), Tap(Key_D),
+    ///         Tap(Key_D), Tap(Key_E), Tap(Key_0), Tap(Key_0), Release(Key_LeftAlt),
+    ///     ]
+    /// );
+    ///
+    /// // Surrogates are not scalar values.
+    /// assert_eq!(Unicode::key_sequence(UnicodeMode::Linux, 0xd800, &mut steps), 0);
+    /// ```
+    pub fn key_sequence(mode: UnicodeMode, code_point: u32, out: &mut [UnicodeStep]) -> usize {
+        if !Self::is_scalar_value(code_point) {
+            return 0;
+        }
+
+        let mut steps = StepWriter { out, len: 0 };
+
+        match mode {
+            UnicodeMode::Linux => {
+                steps.push(UnicodeStep::Tap(Key_U.with_flags(KeyFlags::ctrl() | KeyFlags::shift())));
+                steps.push_hex(code_point, Self::hex_digits(code_point), false);
+                steps.push(UnicodeStep::Tap(Key_Spacebar));
+            }
+            UnicodeMode::MacOs => {
+                steps.push(UnicodeStep::Press(Key_LeftAlt));
+                for unit in Self::utf16_units(code_point).into_iter().flatten() {
+                    steps.push_hex(unit as u32, 4, false);
+                }
+                steps.push(UnicodeStep::Release(Key_LeftAlt));
+            }
+            UnicodeMode::Windows => {
+                // Each code unit is entered separately, the host combines the surrogates.
+                for unit in Self::utf16_units(code_point).into_iter().flatten() {
+                    steps.push(UnicodeStep::Press(Key_LeftAlt));
+                    steps.push(UnicodeStep::Tap(Key_KeypadAdd));
+                    steps.push_hex(unit as u32, 4, true);
+                    steps.push(UnicodeStep::Release(Key_LeftAlt));
+                }
+            }
+        }
+
+        steps.finish()
+    }
+
+    /// Types a code point.
+    ///
+    /// The key events are queued with [Runtime::queue_key_source], and typed once the
+    /// current event handlers return. Returns [Error::InvalidCodePoint] if `code_point` is
+    /// not a Unicode scalar value, or an error if the queue is full.
+    pub fn type_codepoint(code_point: u32) -> crate::Result<()> {
+        if !Self::is_scalar_value(code_point) {
+            return Err(Error::InvalidCodePoint);
+        }
+
+        Runtime::queue_key_source(Self::code_point_event, code_point)
+    }
+
+    /// Types a string of code points.
+    ///
+    /// Stops at the first code point that is not a Unicode scalar value, or does not fit
+    /// in the queue.
+    pub fn type_string(code_points: &[u32]) -> crate::Result<()> {
+        for &code_point in code_points.iter() {
+            Self::type_codepoint(code_point)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_scalar_value(code_point: u32) -> bool {
+        code_point <= UNICODE_MAX_CODE_POINT && !(0xd800..=0xdfff).contains(&code_point)
+    }
+
+    /// Gets the number of significant hex digits in `value`, at least one.
+    fn hex_digits(value: u32) -> usize {
+        let bits = 32 - value.leading_zeros() as usize;
+        ((bits + 3) / 4).max(1)
+    }
+
+    /// Encodes a scalar value as UTF-16.
+    fn utf16_units(code_point: u32) -> [Option<u16>; 2] {
+        if code_point < 0x1_0000 {
+            [Some(code_point as u16), None]
+        } else {
+            let offset = code_point - 0x1_0000;
+            [
+                Some(0xd800 | (offset >> 10) as u16),
+                Some(0xdc00 | (offset & 0x3ff) as u16),
+            ]
+        }
+    }
+
+    /// Gets the key typing the hex digit `digit`.
+    ///
+    /// Windows hex numpad input reads the decimal digits from the keypad.
+    fn hex_key(digit: u8, keypad: bool) -> Key {
+        match digit {
+            0 if keypad => Key_Keypad0,
+            0 => Key_0,
+            1..=9 if keypad => Key::from_raw(Key_Keypad1.raw() + (digit - 1) as u16),
+            1..=9 => Key::from_raw(Key_1.raw() + (digit - 1) as u16),
+            _ => Key::from_raw(Key_A.raw() + (digit - 10) as u16),
+        }
+    }
+
+    /// Gets the key event `index` typing `code_point`, in the current mode.
+    fn code_point_event(code_point: u32, index: usize) -> Option<KeyEvent> {
+        let mode = UNICODE.read().mode();
+        let mut steps = [UnicodeStep::Tap(Key_NoKey); UNICODE_MAX_STEPS];
+        let len = Self::key_sequence(mode, code_point, &mut steps);
+
+        let mut index = index;
+
+        for step in steps[..len].iter() {
+            match *step {
+                UnicodeStep::Press(key) if index == 0 => return Some(injected_event(key, true)),
+                UnicodeStep::Release(key) if index == 0 => return Some(injected_event(key, false)),
+                UnicodeStep::Tap(key) if index < 2 => return Some(injected_event(key, index == 0)),
+                UnicodeStep::Tap(_) => index -= 2,
+                _ => index -= 1,
+            }
+        }
+
+        None
+    }
+}
+
+/// Appends steps to a slice, remembering if any did not fit.
+struct StepWriter<'a> {
+    out: &'a mut [UnicodeStep],
+    len: usize,
+}
+
+impl StepWriter<'_> {
+    fn push(&mut self, step: UnicodeStep) {
+        if let Some(slot) = self.out.get_mut(self.len) {
+            *slot = step;
+        }
+        self.len += 1;
+    }
+
+    fn push_hex(&mut self, value: u32, digits: usize, keypad: bool) {
+        for i in (0..digits).rev() {
+            let digit = ((value >> (i * 4)) & 0xf) as u8;
+            self.push(UnicodeStep::Tap(Unicode::hex_key(digit, keypad)));
+        }
+    }
+
+    fn finish(self) -> usize {
+        if self.len > self.out.len() {
+            0
+        } else {
+            self.len
+        }
+    }
+}
+
+impl EventHandler for Unicode {
+    fn on_name_query() -> Result<&'static str> {
+        Ok("Unicode")
+    }
+
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("unicode.mode\r\n");
+            return Ok(());
+        }
+
+        if command != "unicode.mode" {
+            return Ok(());
+        }
+
+        let args = args.trim();
+
+        if args.is_empty() {
+            let mode = UNICODE.read().mode();
+            uwrite!(&mut *FOCUS_OUTPUT.write(), "{}\r\n", mode.name()).map_err(|_| EventHandlerError::Error)?;
+        } else if args == "host" {
+            UNICODE.write().clear_mode();
+        } else {
+            let mode = UnicodeMode::from_name(args).ok_or(EventHandlerError::Error)?;
+            UNICODE.write().set_mode(mode);
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
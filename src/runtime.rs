@@ -1,36 +1,163 @@
-use avr_device::interrupt;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::{hid, hid_mut, LAYER, LIVE_KEYS, error::Result, event_handler::{EventHandler, EventHandlerError}, hooks::Hooks, key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, millis::millis, return_on_err};
-use crate::driver::{mcu::Mcu, hid::base::keyboard::Keyboard};
+use ufmt::uwrite;
 
-#[cfg(feature = "atreus")]
-use crate::plugins::atreus::Device;
+use crate::{lock, try_with_hid, with_cpu, with_hid, LAST_ERROR, LAYER, LIVE_KEYS, error::{Error, Result}, event_handler::{EventHandler, EventHandlerError}, hooks::Hooks, key_addr::KeyAddr, key_addr_ext::KeyAddrExt, key_defs::*, key_event::{KeyEvent, KeyEventId}, key_ext::KeyExt, keyswitch_state::KeyswitchState, millis::{micros, millis}, record_on_err, return_on_err};
+use crate::atomic::AtomicKey;
+use crate::bootloader::{clear_boot_key, detect_bootloader, set_active_bootloader, BootloaderKind};
+use crate::device::DeviceOps;
+use crate::focus::FOCUS_OUTPUT;
+use crate::layers::LayerTap;
+use crate::plugins::focus_serial;
+use crate::sketch::Sketch;
+use crate::util::typing::injected_event;
+use crate::driver::{board::{Board, BoardProps, Device}, keyscanner::{self, combo_held, Atmega}, led::LED_CONTROL, mcu::Mcu, wdt, hid::{base::keyboard::{ActiveKeyboard, Keyboard}, protocol, settings::{UsbIdentity, USB_IDENTITY}}};
+
+#[cfg(feature = "cycle_time")]
+mod cycle_time;
+mod inject_queue;
+mod last_error;
+mod mask_next;
+mod min_hold;
+mod reboot;
+mod report_rollover;
+mod scheduler;
+
+#[cfg(feature = "cycle_time")]
+pub use cycle_time::{CycleTime, CYCLE_TIME};
+pub use inject_queue::{InjectQueue, InjectStage, Injected, KeySource, INJECT_QUEUE_CAPACITY};
+pub use last_error::LastError;
+pub use mask_next::MaskNext;
+pub use min_hold::MinHold;
+pub use reboot::Reboot;
+pub use report_rollover::ReportRollover;
+pub use scheduler::{Scheduler, SCHEDULER_CAPACITY};
+
+/// Key queued with [Runtime::queue_tap_key], tapped at the start of the next cycle.
+static PENDING_TAP: AtomicKey = AtomicKey::new(Key_NoKey);
+
+/// Whether a single matrix scan was requested, see [Runtime::request_scan_once].
+static SCAN_ONCE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the key events handled are reported to Focus, during a requested scan.
+static SCAN_REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Whether the rollover state must be forgotten, see [Runtime::release_all].
+static FORGET_ROLLOVER: AtomicBool = AtomicBool::new(false);
+
+/// Events injected by plugin handlers, see [Runtime::queue_key_event].
+static INJECT_QUEUE: lock::Spinlock<InjectQueue> = lock::Spinlock::new(InjectQueue::new());
+
+/// When keyboard reports are sent to the host.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReportMode {
+    /// A report is sent for every key event.
+    #[default]
+    Immediate,
+    /// Reports are marked pending, and a single consolidated report is sent after each
+    /// cycle.
+    Deferred,
+}
 
 // FIXME: impl
-pub struct Runtime {
-    device: Device,
+pub struct Runtime<D: Board = Device> {
+    device: D,
     millis_at_cycle_start: u32,
-    last_addr_toggled_on: KeyAddr,
+    rollover: ReportRollover,
     has_leds: bool,
+    host_connected: bool,
+    min_hold: MinHold,
+    mask_next: MaskNext,
+    report_window: u16,
+    report_window_start: Option<u32>,
+    report_pending: bool,
+    report_mode: ReportMode,
+    host_leds: u8,
+    scheduler: Scheduler,
+    last_event_time: u32,
+    idle_timeout: u16,
+    idle: bool,
+    sleeping: bool,
+    bootloader: BootloaderKind,
+    usb_suspended: bool,
+    scanning_suspended: bool,
+    #[cfg(test)]
+    reports_sent: u16,
 }
 
-impl Runtime {
-    /// Creates a new runtime.
-    pub const fn new() -> Self {
-        let device = Device::new();
-        let has_leds = Device::led_count() > 0;
+impl<D: Board<KeyScanner = Atmega>> Runtime<D> {
+    /// Creates a new runtime driving the provided device.
+    pub const fn new(device: D) -> Self {
+        let has_leds = D::Props::LED_COUNT > 0;
 
         Self {
             device,
             millis_at_cycle_start: 0,
-            last_addr_toggled_on: KeyAddr::default(),
+            rollover: ReportRollover::new(),
             has_leds,
+            host_connected: false,
+            min_hold: MinHold::new(),
+            mask_next: MaskNext::new(),
+            report_window: 0,
+            report_window_start: None,
+            report_pending: false,
+            report_mode: ReportMode::Immediate,
+            host_leds: 0,
+            scheduler: Scheduler::new(),
+            last_event_time: 0,
+            idle_timeout: 0,
+            idle: false,
+            sleeping: false,
+            bootloader: match D::Props::BOOTLOADER {
+                Some(kind) => kind,
+                None => BootloaderKind::Unknown,
+            },
+            usb_suspended: false,
+            scanning_suspended: false,
+            #[cfg(test)]
+            reports_sent: 0,
         }
     }
 
     /// Handles all component setup necessary for the firmware runtime.
     pub fn setup(&mut self) -> Result<()> {
-        Device::setup();
+        <D as Mcu>::setup();
+
+        if D::Props::BOOTLOADER.is_none() {
+            self.bootloader = detect_bootloader();
+        }
+
+        set_active_bootloader(self.bootloader);
+
+        // A stale magic key would keep the next reset in the bootloader.
+        if matches!(self.bootloader, BootloaderKind::Caterina | BootloaderKind::Unknown) {
+            clear_boot_key();
+        }
+
+        // Checked before anything else can misbehave, so the matrix is read directly.
+        if !D::Props::BOOT_COMBO.is_empty()
+            && combo_held(&self.device.key_scanner().read_raw_matrix(), D::Props::BOOT_COMBO)
+        {
+            self.bootloader.reboot_bootloader();
+        }
+
+        // Event IDs restart from a known baseline, whatever the reboot left in the counter.
+        KeyEvent::reset_id_counter();
+
+        Hooks::setup_storage()?;
+
+        // The HID keyboard starts in Boot, apply the restored protocol setting.
+        let active = protocol::KEYBOARD_PROTOCOL.read().resolve(protocol::host_protocol());
+        self.set_active_protocol(active);
+
+        let sketch = Sketch::new(
+            D::Props::ROWS,
+            D::Props::COLS,
+            LAYER.read().layer_count(),
+            Hooks::PLUGIN_NAMES,
+        );
+        Hooks::explore_sketch(&sketch)?;
 
         Hooks::on_setup()?;
 
@@ -43,14 +170,57 @@ impl Runtime {
 
     /// Main execution loop for scanning keyswitch events, and updating internal state.
     pub fn main_loop(&mut self) {
+        #[cfg(feature = "cycle_time")]
+        let cycle_start = micros();
+
         // FIXME: implement millis for atmega32u4
         self.millis_at_cycle_start = millis();
 
-        if Device::poll_usb_reset() {
-            return_on_err!(hid_mut()).keyboard_mut().on_usb_reset();
+        if <D as Mcu>::poll_usb_reset() {
+            record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.keyboard_mut().on_usb_reset()));
+        }
+
+        self.usb_suspended = <D as Mcu>::poll_usb_suspend();
+
+        if let Some(protocol) = protocol::take_requested_protocol() {
+            let active = protocol::KEYBOARD_PROTOCOL.read().resolve(protocol);
+            self.set_active_protocol(active);
         }
 
-        return_on_err!(Hooks::before_each_cycle());
+        if let Some(debounce_ms) = keyscanner::take_requested_debounce() {
+            self.device.key_scanner_mut().set_debounce_ms(debounce_ms);
+        }
+
+        // Only notify plugins when the lock-LED state actually changes.
+        let host_leds = record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.leds()));
+        if host_leds != self.host_leds {
+            self.host_leds = host_leds;
+            record_on_err!(LAST_ERROR.write(), Hooks::on_host_led_change(host_leds));
+        }
+
+        self.poll_host_connected(<D as Mcu>::usb_configured());
+
+        record_on_err!(LAST_ERROR.write(), Hooks::before_each_cycle());
+
+        // Handle any scheduled events that are now due.
+        while let Some(mut event) = self.scheduler.take_due(self.millis_at_cycle_start) {
+            self.handle_key_event(&mut event);
+        }
+
+        let pending_tap = PENDING_TAP.swap(Key_NoKey, Ordering::SeqCst);
+        if pending_tap != Key_NoKey {
+            self.send_tap_key(pending_tap);
+        }
+
+        self.handle_queued_events();
+
+        // Register any presses that have now been held for the minimum hold time.
+        while let Some(key_addr) = self.min_hold.take_expired(self.millis_at_cycle_start) {
+            let mut state = KeyswitchState::default();
+            state.set_is_pressed(true);
+
+            self.process_keyswitch_event(KeyEvent::next(key_addr, state));
+        }
 
         // Next, we scan the keyswitches. Any toggle-on or toggle-off events will
         // trigger a call to `handleKeyswitchEvent()`, which in turn will
@@ -59,18 +229,53 @@ impl Runtime {
         // possible for more than one event to be handled like this in any given
         // cycle, resulting in multiple HID reports, but guaranteeing that only one
         // event is being handled at a time.
-        self.device.scan_matrix();
+        self.scan_keys();
+
+        // Send any report held back by the coalescing window once the window closes.
+        if let Some(start) = self.report_window_start {
+            if micros().wrapping_sub(start) >= self.report_window as u32 {
+                self.flush_report();
+            }
+        }
+
+        if !self.idle
+            && self.idle_timeout > 0
+            && self.millis_at_cycle_start.wrapping_sub(self.last_event_time) >= self.idle_timeout as u32
+        {
+            self.idle = true;
+            // Return to the base layer, keeping the sticky layers active.
+            record_on_err!(LAST_ERROR.write(), LAYER.write().auto_return());
+            record_on_err!(LAST_ERROR.write(), Hooks::on_idle());
+        }
+
+        record_on_err!(LAST_ERROR.write(), Hooks::after_each_cycle());
+
+        self.handle_queued_events();
+
+        // In deferred mode, send one report for all the events handled this cycle.
+        if self.report_mode == ReportMode::Deferred && self.report_pending {
+            self.flush_report();
+        }
 
-        return_on_err!(Hooks::after_each_cycle());
+        if self.has_leds {
+            self.sync_leds();
+        }
+
+        #[cfg(feature = "cycle_time")]
+        CYCLE_TIME.write().record(cycle_start, micros());
+
+        if self.sleeping {
+            Self::sleep_until_interrupt();
+        }
     }
 
     /// Gets a reference to the runtime device.
-    pub fn device(&self) -> &Device {
+    pub fn device(&self) -> &D {
         &self.device
     }
 
     /// Gets a mutable reference to the runtime device.
-    pub fn device_mut(&mut self) -> &mut Device {
+    pub fn device_mut(&mut self) -> &mut D {
         &mut self.device
     }
 
@@ -90,7 +295,7 @@ impl Runtime {
     /// The ID value is used to help plugins that delay events to coordinate with
     /// each other so that they can avoid re-processing the same event, possibly
     /// causing endless loops.
-    pub fn handle_keyswitch_event(&mut self, mut event: KeyEvent) {
+    pub fn handle_keyswitch_event(&mut self, event: KeyEvent) {
         // This function strictly handles physical key events. Any event without a
         // valid `KeyAddr` gets ignored.
         if !event.addr().is_valid() {
@@ -103,6 +308,35 @@ impl Runtime {
             return;
         }
 
+        // A key press while the host is suspended asks it to wake up.
+        if self.usb_suspended && event.state().key_toggled_on() && !event.state().key_is_injected() {
+            record_on_err!(LAST_ERROR.write(), <D as Mcu>::remote_wakeup());
+        }
+
+        // A plugin asked to swallow the next press: mask it, so its release is dropped too.
+        if event.state().key_toggled_on()
+            && !event.state().key_is_injected()
+            && self.mask_next.take(millis())
+        {
+            LIVE_KEYS.write().mask(*event.addr());
+            return;
+        }
+
+        // If a minimum hold time is set, physical presses are held back until the key
+        // has been held long enough, and dropped entirely if released sooner.
+        if self.min_hold.enabled() && !event.state().key_is_injected() {
+            if event.state().key_toggled_on() {
+                self.min_hold.press(event.addr(), millis());
+                return;
+            } else if self.min_hold.release(event.addr()) {
+                return;
+            }
+        }
+
+        self.process_keyswitch_event(event);
+    }
+
+    fn process_keyswitch_event(&mut self, mut event: KeyEvent) {
         // Set the `Key` value for this event.
         if event.state().key_toggled_off() {
             // When a key toggles off, set the event's key value to whatever the key's
@@ -117,7 +351,7 @@ impl Runtime {
             // When a key toggles on, unless the event already has a key value (i.e. we
             // were called by a plugin rather than `actOnMatrixScan()`), we look up the
             // value from the current keymap (overridden by `live_keys`).
-            event.set_key(Self::lookup_key(event.addr()));
+            event.set_key(Runtime::lookup_key(event.addr()));
         }
 
         // Run the plugin event handlers
@@ -133,6 +367,30 @@ impl Runtime {
         self.handle_key_event(&mut event);
     }
 
+    /// Handle a synthetic "held" keyswitch event
+    ///
+    /// Called by the key scanner for keys that remain pressed, when a repeat interval is
+    /// set with [Atmega::set_repeat_interval](crate::driver::keyscanner::Atmega::set_repeat_interval).
+    /// The event is only passed to the `on_keyswitch_event()` plugin handlers, so plugins
+    /// can implement auto-repeat or "held for N ms" behaviors. It never changes the
+    /// `LIVE_KEYS` state, or generates a HID report.
+    ///
+    /// Events for inactive or masked keys are dropped.
+    pub fn handle_held_event(&mut self, mut event: KeyEvent) {
+        if !event.addr().is_valid() {
+            return;
+        }
+
+        let key = LIVE_KEYS.read()[*event.addr()];
+        if key == Key_Inactive || key == Key_Masked {
+            return;
+        }
+
+        event.set_key(key);
+
+        let _ = Hooks::on_keyswitch_event(&mut event);
+    }
+
     /// Handle a logical key event
     ///
     /// This method triggers the handling of a logical "key event". Ususally that
@@ -140,14 +398,57 @@ impl Runtime {
     /// be called by plugins that need to generate extra events without a 1:1
     /// mapping to physical keyswitch state transitions.
     pub fn handle_key_event(&mut self, event: &mut KeyEvent) {
+        self.last_event_time = self.millis_at_cycle_start;
+        self.idle = false;
+
+        if self.sleeping {
+            self.wake();
+        }
+
+        // Only a one-shot layer activated before this event may be consumed by it.
+        let one_shot_pending = LAYER.read().one_shot_layer().is_some();
+
+        self.process_key_event(event);
+
+        if SCAN_REPORT.load(Ordering::SeqCst) && event.addr().is_valid() {
+            let addr = event.addr();
+            let pressed = event.state().key_toggled_on() as u8;
+            let _ = uwrite!(
+                &mut *FOCUS_OUTPUT.write(),
+                "{} {} {} {}\r\n",
+                addr.row(),
+                addr.col(),
+                event.key().to_focus(),
+                pressed
+            );
+        }
+
+        // The first physical, non-layer key pressed after a one-shot layer activation
+        // deactivates it. The key was already looked up on the one-shot layer.
+        if one_shot_pending
+            && event.state().key_toggled_on()
+            && !event.state().key_is_injected()
+            && !event.key().is_layer_key()
+            && !event.key().is_mod_layer_key()
+        {
+            record_on_err!(LAST_ERROR.write(), LAYER.write().release_one_shot());
+        }
+    }
+
+    fn process_key_event(&mut self, event: &mut KeyEvent) {
         // For events that didn't begin with `handleKeyswitchEvent()`, we need to look
         // up the `Key` value from the keymap (maybe overridden by `live_keys`).
         if event.addr().is_valid() {
             if event.state().key_toggled_off() || event.key() == &Key_Undefined {
-                event.set_key(Self::lookup_key(event.addr()));
+                event.set_key(Runtime::lookup_key(event.addr()));
             }
         }
 
+        // Any other key pressed while a layer-tap key is held resolves it to its layer.
+        if event.state().key_toggled_on() {
+            LAYER.write().interrupt_layer_tap(*event.addr());
+        }
+
         // If any `on_key_event()` handler returns `Error::EventAbort`, we return before updating
         // the Live Keys state array; as if the event didn't happen.
         let result = Hooks::on_key_event(event);
@@ -176,9 +477,22 @@ impl Runtime {
                 return;
             }
 
+        // Layer-tap keys are handled by the Layer object too, which tells us whether to
+        // send the tap key on release.
+        if LayerTap::decode(&key).is_some() {
+            let now = self.millis_at_cycle_start;
+            let tap = record_on_err!(LAST_ERROR.write(), LAYER.write().handle_layer_tap_event(event, now));
+
+            if let Some(tap) = tap {
+                self.send_tap_key(tap);
+            }
+
+            return;
+        }
+
         // Built-in layer change keys are handled by the Layer object.
         if key.is_layer_key() || key.is_mod_layer_key() {
-            return_on_err!(LAYER.write().handle_layer_key_event(*event));
+            record_on_err!(LAST_ERROR.write(), LAYER.write().handle_layer_key_event(*event));
         }
 
         // If the event is for a layer change key, there's no need to send a HID
@@ -192,13 +506,11 @@ impl Runtime {
         // significantly different from the way the other HID reports work, where held
         // keys remain in effect for subsequent reports.
         if key.is_system_control_key() {
-            interrupt::free(|_cs| {
-                if event.state().key_toggled_on() {
-                    return_on_err!(hid_mut()).press_system_control(key);
-                } else {
-                    return_on_err!(hid_mut()).release_system_control(key);
-                }
-            });
+            if event.state().key_toggled_on() {
+                record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.press_system_control(key)));
+            } else {
+                record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.release_system_control(key)));
+            }
             return;
         }
 
@@ -212,7 +524,7 @@ impl Runtime {
         // Now that the report has been sent, let plugins act on it after the fact.
         // This is useful for plugins that need to react to an event, but must wait
         // until after that event is processed to do so.
-        return_on_err!(Hooks::after_reporting_state(event));
+        record_on_err!(LAST_ERROR.write(), Hooks::after_reporting_state(event));
     }
 
     /// Prepare a new set of USB HID reports
@@ -225,7 +537,7 @@ impl Runtime {
     /// state array.
     pub fn prepare_keyboard_report(&mut self, event: &mut KeyEvent) {
         // before building the new report, start clean
-        return_on_err!(return_on_err!(hid_mut()).release_all_keys());
+        record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.release_all_keys()));
 
         // Build report from composite keymap cache. This can be much more efficient
         // with a bitfield. What we should be doing here is going through the array
@@ -233,7 +545,10 @@ impl Runtime {
         // adding them to their respective reports. This comes before the old plugin
         // hooks are called for the new event so that the report will be full complete
         // except for that new event.
-        for key_addr in KeyAddr::iter() {
+        let live_keys = LIVE_KEYS.read();
+
+        // Idle and masked keys are skipped by the iterator.
+        for (key_addr, key) in live_keys.iter_active() {
             // Skip this event's key addr; we will deal with that later. This is most
             // important in the case of a key release, because we can't safely remove
             // any keycode(s) added to the report later.
@@ -241,13 +556,6 @@ impl Runtime {
                 continue;
             }
 
-            let key = LIVE_KEYS.read()[key_addr];
-
-            // If the key is idle or masked, we can ignore it.
-            if key == Key_Inactive || key == Key_Masked {
-                continue;
-            }
-
             self.add_to_report(key);
         }
     }
@@ -279,12 +587,12 @@ impl Runtime {
                 key.set_flags(KeyFlags::NONE);
             }
 
-            return_on_err!(hid_mut()).press_key(key);
+            record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.press_key(key)));
             return;
         }
 
         if key.is_consumer_control_key() {
-            return_on_err!(hid_mut()).press_consumer_control(key);
+            record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.press_consumer_control(key)));
         }
     }
 
@@ -292,38 +600,34 @@ impl Runtime {
     ///
     /// This method is called by `handle_key_event()` after `prepare_keyboard_report()`
     /// is done. It uses the information about the new event to guard against
-    /// modifier and mod-flags rollover issues, and calls the
+    /// modifier and mod-flags rollover issues (see [ReportRollover]), and calls the
     /// `before_reporting_state()` plugin handler functions before sending the
     /// complete Keyboard and Consumer Control HID reports.
     pub fn send_keyboard_report(&mut self, event: &mut KeyEvent) {
+        if FORGET_ROLLOVER.swap(false, Ordering::SeqCst) {
+            self.rollover.forget();
+        }
+
         // If the keycode for this key is already in the report, we need to send an
         // extra report without that keycode in order to correctly process the
         // rollover. It might be better to exempt modifiers from this rule, but it's
         // not clear that would be better.
         if event.state().key_toggled_on() && event.key().is_keyboard_key() {
-            // last keyboard key toggled on
-            self.last_addr_toggled_on = *event.addr();
-
-            if return_on_err!(hid()).is_key_pressed(event.key()) {
+            if record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.is_key_pressed(event.key()))) {
                 // The keycode (flags ignored) for `event.key` is active in the current
-                // report. Should this be `wasKeyPressed()` instead? I don't think so,
-                // because (if I'm right) the new event hasn't been added yet.
-                return_on_err!(hid_mut()).release_key(*event.key());
-                return_on_err!(return_on_err!(hid_mut()).send_report());
+                // report, which doesn't include the new event yet.
+                record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.release_key(event.key().base())));
+                record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.send_report()));
             }
 
-            if event.key().flags() != KeyFlags::NONE {
-                // The keycode (flags ignored) for `event.key` is active in the current
-                // report. Should this be `wasKeyPressed()` instead? I don't think so,
-                // because (if I'm right) the new event hasn't been added yet.
-                return_on_err!(hid_mut()).press_modifiers(*event.key());
-                return_on_err!(return_on_err!(hid_mut()).send_report());
+            if self.rollover.press(*event.addr(), *event.key()) {
+                record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.press_modifiers(*event.key())));
+                record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.send_report()));
             }
-        } else if event.addr() != self.last_addr_toggled_on() {
-            // (not a keyboard key OR toggled off) AND not last keyboard key toggled on
-            let last_key = LIVE_KEYS.read()[self.last_addr_toggled_on];
-            if last_key.is_keyboard_key() {
-                return_on_err!(hid_mut()).press_modifiers(last_key);
+        } else {
+            let live = LIVE_KEYS.read()[*self.rollover.last_addr()];
+            if let Some(last_key) = self.rollover.restore(*event.addr(), live) {
+                record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.press_modifiers(last_key)));
             }
         }
 
@@ -338,8 +642,620 @@ impl Runtime {
             }
         }
 
-        // Finally, send the report:
-        return_on_err!(return_on_err!(hid_mut()).send_report());
+        // The extra rollover reports above are always sent immediately, only the final
+        // report is deferred.
+        if self.report_mode == ReportMode::Deferred {
+            self.report_pending = true;
+            return;
+        }
+
+        // Finally, send the report, unless the event falls in the coalescing window:
+        if self.report_window > 0 && event.state().key_toggled_on() {
+            let now = micros();
+            let start = *self.report_window_start.get_or_insert(now);
+
+            if now.wrapping_sub(start) < self.report_window as u32 {
+                self.report_pending = true;
+                return;
+            }
+        }
+
+        self.flush_report();
+    }
+
+    /// Sends the current keyboard report, including any report held back by the
+    /// coalescing window, or the deferred report mode.
+    ///
+    /// Plugins can call this to send a report immediately in deferred mode.
+    pub fn flush_report(&mut self) {
+        self.report_pending = false;
+        self.report_window_start = None;
+
+        #[cfg(test)]
+        {
+            self.reports_sent += 1;
+        }
+
+        record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.send_report()));
+    }
+
+    /// Gets whether a keyboard report is waiting to be sent.
+    pub fn report_pending(&self) -> bool {
+        self.report_pending
+    }
+
+    /// Gets the report mode.
+    pub fn report_mode(&self) -> ReportMode {
+        self.report_mode
+    }
+
+    /// Sets the report mode.
+    ///
+    /// In [ReportMode::Deferred], key events only update the pending report, and a single
+    /// report is sent after the `after_each_cycle()` plugin handlers. This reduces USB
+    /// traffic when several events are handled in one cycle. The extra reports sent to
+    /// handle rollover of repeated keycodes are still sent immediately. Switching back
+    /// to [ReportMode::Immediate] sends any pending report.
+    pub fn set_report_mode(&mut self, mode: ReportMode) {
+        self.report_mode = mode;
+
+        if mode == ReportMode::Immediate && self.report_pending {
+            self.flush_report();
+        }
+    }
+
+    /// Gets the report coalescing window in microseconds. Zero means disabled.
+    pub fn report_window(&self) -> u16 {
+        self.report_window
+    }
+
+    /// Sets the report coalescing window in microseconds.
+    ///
+    /// Toggle-on events arriving within the window after the first one are gathered into
+    /// a single report, sent when the window closes (or on the next toggle-off event).
+    /// This reduces the number of reports for chords, at the cost of up to one window of
+    /// latency. Set to zero to send a report for every event.
+    pub fn set_report_window(&mut self, window_us: u16) {
+        self.report_window = window_us;
+
+        if window_us == 0 && self.report_pending {
+            self.flush_report();
+        }
+    }
+
+    /// Schedules a key event to be handled `delay_ms` milliseconds from the start of the
+    /// current cycle.
+    ///
+    /// The event is passed to [handle_key_event](Self::handle_key_event) at the start of
+    /// the first cycle after it is due. Events fire in due-time order. Returns an error if
+    /// [SCHEDULER_CAPACITY] events are already waiting.
+    pub fn schedule_event(&mut self, event: KeyEvent, delay_ms: u16) -> Result<()> {
+        let now = self.millis_at_cycle_start;
+        self.scheduler.schedule(event, now.wrapping_add(delay_ms as u32), now)
+    }
+
+    /// Cancels a scheduled key event by its ID.
+    ///
+    /// Returns whether an event was cancelled.
+    pub fn cancel_scheduled(&mut self, id: KeyEventId) -> bool {
+        self.scheduler.cancel(id)
+    }
+
+    /// Simulates a press of the key at `addr`.
+    ///
+    /// The event is flagged as injected, and goes through
+    /// [handle_keyswitch_event](Self::handle_keyswitch_event) like a physical press, so
+    /// the key is looked up in the keymap and lands in `LIVE_KEYS`. Invalid addresses are
+    /// ignored.
+    pub fn inject_press(&mut self, addr: KeyAddr) {
+        self.inject_keyswitch_event(addr, true);
+    }
+
+    /// Simulates a release of the key at `addr`. Invalid addresses are ignored.
+    pub fn inject_release(&mut self, addr: KeyAddr) {
+        self.inject_keyswitch_event(addr, false);
+    }
+
+    /// Simulates a tap of the key at `addr`.
+    ///
+    /// The press is handled immediately, and the release is scheduled for the next cycle,
+    /// so the host sees two separate reports. Returns an error for an invalid address, or
+    /// if the scheduler is full, in which case the key is not pressed.
+    pub fn inject_tap(&mut self, addr: KeyAddr) -> Result<()> {
+        if !addr.is_valid() {
+            return Err(Error::InvalidKeyAddr);
+        }
+
+        if self.scheduler.len() >= SCHEDULER_CAPACITY {
+            return Err(Error::SchedulerFull);
+        }
+
+        self.inject_press(addr);
+
+        let mut state = KeyswitchState::default();
+        state.set_injected(true);
+        state.set_was_pressed(true);
+
+        self.schedule_event(KeyEvent::next(addr, state), 0)
+    }
+
+    /// Sends a tap of `key`, not bound to any key address.
+    ///
+    /// Like [inject_tap](Self::inject_tap), the release is scheduled for the next cycle,
+    /// unless the scheduler is full.
+    fn send_tap_key(&mut self, key: Key) {
+        let mut state = KeyswitchState::default();
+        state.set_injected(true);
+        state.set_is_pressed(true);
+
+        // The default KeyAddr is invalid, so the events do not touch the keymap.
+        let mut press = KeyEvent::next(KeyAddr::default(), state);
+        press.set_key(key);
+        self.handle_key_event(&mut press);
+
+        let mut state = KeyswitchState::default();
+        state.set_injected(true);
+        state.set_was_pressed(true);
+
+        let mut release = KeyEvent::next(KeyAddr::default(), state);
+        release.set_key(key);
+
+        if self.schedule_event(release, 0).is_err() {
+            self.handle_key_event(&mut release);
+        }
+    }
+
+    /// Runs the host-connected hooks on the first cycle the device is configured.
+    fn poll_host_connected(&mut self, configured: bool) {
+        if !self.host_connected && configured {
+            self.host_connected = true;
+            record_on_err!(LAST_ERROR.write(), Hooks::on_host_connected());
+        }
+    }
+
+    /// Scans the key matrix, unless scanning is suspended, or runs a scan requested with
+    /// [request_scan_once](Runtime::request_scan_once).
+    fn scan_keys(&mut self) {
+        if SCAN_ONCE.swap(false, Ordering::SeqCst) {
+            self.scan_once();
+        } else if !self.scanning_suspended {
+            self.device.scan_matrix();
+        }
+    }
+
+    /// Runs a scan requested with [request_scan_once](Runtime::request_scan_once), and
+    /// sends the resulting key events as the deferred Focus reply.
+    fn scan_once(&mut self) {
+        SCAN_REPORT.store(true, Ordering::SeqCst);
+        self.device.key_scanner_mut().scan_once();
+        SCAN_REPORT.store(false, Ordering::SeqCst);
+
+        focus_serial::finish_reply();
+    }
+
+    /// Handles the events queued by plugin handlers, see
+    /// [queue_key_event](Runtime::queue_key_event).
+    ///
+    /// Only the events waiting on entry are handled. Events queued meanwhile wait for the
+    /// next call, so a plugin queuing an event for every event can't stall the cycle.
+    fn handle_queued_events(&mut self) {
+        let len = INJECT_QUEUE.read().len();
+
+        for _ in 0..len {
+            let Some(entry) = INJECT_QUEUE.write().pop() else {
+                break;
+            };
+
+            match entry {
+                // Presses were already filtered on arrival, e.g. by the minimum hold time.
+                Injected::Event(event, InjectStage::Keyswitch) => {
+                    if event.addr().is_valid()
+                        && (event.state().key_toggled_on() || event.state().key_toggled_off())
+                    {
+                        self.process_keyswitch_event(event);
+                    }
+                }
+                Injected::Event(mut event, InjectStage::Key) => self.handle_key_event(&mut event),
+                Injected::Source(source, arg) => {
+                    let mut index = 0;
+
+                    while let Some(mut event) = source(arg, index) {
+                        self.handle_key_event(&mut event);
+                        index += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn inject_keyswitch_event(&mut self, addr: KeyAddr, pressed: bool) {
+        if !addr.is_valid() {
+            return;
+        }
+
+        let mut state = KeyswitchState::default();
+        state.set_injected(true);
+        if pressed {
+            state.set_is_pressed(true);
+        } else {
+            state.set_was_pressed(true);
+        }
+
+        self.handle_keyswitch_event(KeyEvent::next(addr, state));
+    }
+
+    /// Gets the idle timeout in milliseconds. Zero means disabled.
+    pub fn idle_timeout(&self) -> u16 {
+        self.idle_timeout
+    }
+
+    /// Sets the time, in milliseconds, without key events after which the `on_idle()`
+    /// plugin handlers are called. Set to zero to disable.
+    ///
+    /// The timeout is checked against [millis](crate::millis::millis), which only
+    /// advances every [MILLIS_INCREMENT](crate::millis::MILLIS_INCREMENT) (8 ms), so the
+    /// handlers run up to one increment later than `timeout`, and timeouts shorter than
+    /// one increment expire on the next tick.
+    ///
+    /// Every layer that is not sticky is deactivated when the timeout expires, see
+    /// [Layer::auto_return](crate::layers::Layer::auto_return).
+    pub fn set_idle_timeout(&mut self, timeout: u16) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Gets whether no key event has been handled for the idle timeout.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Gets whether the MCU sleeps between cycles.
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Puts the MCU in a low-power sleep at the end of each cycle, until [wake](Self::wake)
+    /// is called.
+    ///
+    /// Uses the AVR Idle sleep mode, which stops the CPU clock but keeps the timers and
+    /// the USB controller running. Each sleep lasts until the next interrupt, usually the
+    /// keyscan timer or USB activity, so keys are still scanned, and the device stays
+    /// attached to the host. The next key event wakes the runtime.
+    ///
+    /// Current draw has not been measured on hardware. From the ATmega32U4 datasheet,
+    /// Idle mode roughly halves the MCU supply current at 16 MHz (around 10 mA active,
+    /// versus 5 mA idle). Savings shrink with shorter keyscan intervals, since the CPU
+    /// wakes for every scan. Use [set_keyscan_interval](Self::set_keyscan_interval) to
+    /// save more power while idle.
+    pub fn enter_sleep(&mut self) {
+        self.sleeping = true;
+    }
+
+    /// Stops sleeping between cycles.
+    pub fn wake(&mut self) {
+        self.sleeping = false;
+    }
+
+    fn sleep_until_interrupt() {
+        return_on_err!(with_cpu(|cpu| cpu.smcr.write(|w| w.sm().idle().se().set_bit())));
+
+        avr_device::asm::sleep();
+
+        return_on_err!(with_cpu(|cpu| cpu.smcr.write(|w| w.se().clear_bit())));
+    }
+
+    /// Gets the latest lock-LED state sent by the host.
+    ///
+    /// See [HostLeds](crate::driver::hid::HostLeds) for the bit layout.
+    pub fn host_leds(&self) -> u8 {
+        self.host_leds
+    }
+
+    /// Gets the currently active keyboard protocol (boot or NKRO).
+    pub fn active_protocol(&self) -> ActiveKeyboard {
+        with_hid(|hid| hid.active_keyboard()).unwrap_or(ActiveKeyboard::None)
+    }
+
+    /// Switches the active keyboard protocol.
+    ///
+    /// Called when the host sends a HID SET_PROTOCOL request. To avoid stuck keys, an
+    /// empty report is sent on the old keyboard before switching, then the current state
+    /// of the `LIVE_KEYS` array is resent on the new one.
+    pub fn set_active_protocol(&mut self, active_keyboard: ActiveKeyboard) {
+        if self.active_protocol() == active_keyboard {
+            return;
+        }
+
+        record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.release_all_keys()));
+        record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.send_report()));
+
+        record_on_err!(LAST_ERROR.write(), with_hid(|hid| hid.set_active_keyboard(active_keyboard)));
+
+        for key_addr in KeyAddr::iter() {
+            let key = LIVE_KEYS.read()[key_addr];
+
+            if key != Key_Inactive && key != Key_Masked {
+                self.add_to_report(key);
+            }
+        }
+
+        self.flush_report();
+    }
+
+    /// Gets the milliseconds at cycle start.
+    pub fn millis_at_cycle_start(&self) -> u32 {
+        self.millis_at_cycle_start
+    }
+
+    /// Gets the address of the last Keyboard key toggled on, while it is held.
+    ///
+    /// The address is invalid once that key is released.
+    pub fn last_addr_toggled_on(&self) -> &KeyAddr {
+        self.rollover.last_addr()
+    }
+
+    /// Gets the rollover state, tracking the modifier flags kept in the reports.
+    pub fn rollover(&self) -> &ReportRollover {
+        &self.rollover
+    }
+
+    /// Gets whether the host has configured the device since setup.
+    pub fn host_connected(&self) -> bool {
+        self.host_connected
+    }
+
+    /// Gets the keyscan interval in microseconds.
+    pub fn keyscan_interval(&self) -> u16 {
+        self.device.key_scanner().scan_cycle_time()
+    }
+
+    /// Sets the keyscan interval in microseconds, reconfiguring the scan timer.
+    ///
+    /// Values outside 1 to 8191 microseconds are clamped. Shorter intervals improve
+    /// responsiveness, longer intervals save power.
+    pub fn set_keyscan_interval(&mut self, interval_us: u16) {
+        self.device.key_scanner_mut().set_scan_cycle_time(interval_us);
+    }
+
+    /// Masks the next key that toggles on, whatever its address, e.g. to consume a stray
+    /// press after a gesture.
+    ///
+    /// Both the press and the release of that key are dropped. The request is cleared
+    /// once it fires, or after the timeout set with
+    /// [set_mask_next_key_timeout](Self::set_mask_next_key_timeout).
+    pub fn mask_next_key(&mut self) {
+        self.mask_next.arm(millis());
+    }
+
+    /// Cancels a pending [mask_next_key](Self::mask_next_key) request.
+    pub fn cancel_mask_next_key(&mut self) {
+        self.mask_next.disarm();
+    }
+
+    /// Sets the time, in milliseconds, after which a [mask_next_key](Self::mask_next_key)
+    /// request expires. Zero means never.
+    pub fn set_mask_next_key_timeout(&mut self, timeout: u16) {
+        self.mask_next.set_timeout(timeout);
+    }
+
+    /// Gets the minimum time, in milliseconds, a key must be held before its press is
+    /// registered. Zero means disabled.
+    pub fn min_hold_time(&self) -> u16 {
+        self.min_hold.timeout()
+    }
+
+    /// Sets the minimum time, in milliseconds, a key must be held before its press is
+    /// registered.
+    ///
+    /// Keys released before this time produce no events at all. Set to zero to disable.
+    pub fn set_min_hold_time(&mut self, timeout: u16) {
+        self.min_hold.set_timeout(timeout);
+    }
+
+    /// Sends the LED colors to the hardware, at most once per LED sync interval.
+    ///
+    /// The current LED mode is updated first, then the `before_syncing_leds()` plugin
+    /// handlers are called, so they can override the colors set by the mode.
+    pub fn sync_leds(&mut self) {
+        if !LED_CONTROL.read().sync_due(self.millis_at_cycle_start) {
+            return;
+        }
+
+        LED_CONTROL.write().update(self.millis_at_cycle_start);
+
+        record_on_err!(LAST_ERROR.write(), Hooks::before_syncing_leds());
+
+        let mut leds = LED_CONTROL.write();
+        self.device.sync_leds(leds.leds());
+        leds.mark_synced(self.millis_at_cycle_start);
+    }
+
+    /// Gets the bootloader selected from the board properties, or detected at setup.
+    pub fn bootloader(&self) -> BootloaderKind {
+        self.bootloader
+    }
+
+    /// Gets the number of keys currently held, see [LiveKeys::active_count](crate::LiveKeys::active_count).
+    pub fn pressed_key_count(&self) -> usize {
+        LIVE_KEYS.read().active_count()
+    }
+
+    /// Gets whether the key at `addr` is pressed, see [LiveKeys::is_pressed](crate::LiveKeys::is_pressed).
+    ///
+    /// Masked keys are not pressed, even while physically held. Only the current state is
+    /// tracked, there is no record of the previous cycle.
+    pub fn is_key_pressed(&self, addr: KeyAddr) -> bool {
+        LIVE_KEYS.read().is_pressed(addr)
+    }
+
+    /// Gets whether the host has suspended the USB bus.
+    pub fn usb_suspended(&self) -> bool {
+        self.usb_suspended
+    }
+
+    /// Gets whether matrix scanning is suspended.
+    pub fn scanning_suspended(&self) -> bool {
+        self.scanning_suspended
+    }
+
+    /// Stops scanning the key matrix until [resume_scanning](Self::resume_scanning) is called.
+    ///
+    /// Intended for long blocking operations, like EEPROM commits or a bootloader handoff.
+    /// All held keys are released, and an empty report is sent, so no key stays stuck on
+    /// the host while scanning is paused. Keys still held when scanning resumes are only
+    /// registered again once they are pressed anew.
+    pub fn suspend_scanning(&mut self) {
+        if self.scanning_suspended {
+            return;
+        }
+
+        self.scanning_suspended = true;
+
+        LIVE_KEYS.write().clear_all();
+
+        record_on_err!(LAST_ERROR.write(), try_with_hid(|hid| hid.release_all_keys()));
+        self.flush_report();
+    }
+
+    /// Resumes scanning the key matrix after [suspend_scanning](Self::suspend_scanning).
+    pub fn resume_scanning(&mut self) {
+        self.scanning_suspended = false;
+    }
+
+    /// Gets the duration of the last `main_loop` iteration, in microseconds.
+    #[cfg(feature = "cycle_time")]
+    pub fn last_cycle_time(&self) -> u32 {
+        CYCLE_TIME.read().last()
+    }
+
+    /// Gets the duration of the longest `main_loop` iteration, in microseconds.
+    #[cfg(feature = "cycle_time")]
+    pub fn max_cycle_time(&self) -> u32 {
+        CYCLE_TIME.read().max()
+    }
+
+    /// Gets the `main_loop` iteration statistics.
+    #[cfg(feature = "cycle_time")]
+    pub fn cycle_time(&self) -> CycleTime {
+        *CYCLE_TIME.read()
+    }
+
+    /// Clears the `main_loop` iteration statistics.
+    #[cfg(feature = "cycle_time")]
+    pub fn reset_cycle_time(&mut self) {
+        CYCLE_TIME.write().reset();
+    }
+
+    /// Gets whether the device has LEDs.
+    pub fn has_leds(&self) -> bool {
+        self.has_leds
+    }
+}
+
+// Associated functions that do not depend on the device type.
+impl Runtime {
+    /// Gets the last error swallowed by the runtime, if any.
+    pub fn last_error() -> Option<Error> {
+        LAST_ERROR.read().get()
+    }
+
+    /// Takes the last error swallowed by the runtime, clearing it.
+    pub fn take_last_error() -> Option<Error> {
+        LAST_ERROR.write().take()
+    }
+
+    /// Queues `key` to be tapped at the start of the next cycle, like a key injected with
+    /// [inject_tap](Self::inject_tap), but without an address.
+    ///
+    /// Takes no lock, so it can be called from interrupt handlers. Only one key can be
+    /// pending: returns `false`, and drops `key`, if another key is already queued.
+    pub fn queue_tap_key(key: Key) -> bool {
+        PENDING_TAP
+            .compare_exchange(Key_NoKey, key, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Requests a single matrix scan, run by the next cycle even while scanning is
+    /// suspended.
+    ///
+    /// Used by the `hardware.scanOnce` Focus command: the reply to the command is
+    /// deferred, and lists the key events resulting from the scan, see
+    /// [Atmega::scan_once](crate::driver::keyscanner::Atmega::scan_once).
+    pub fn request_scan_once() {
+        SCAN_ONCE.store(true, Ordering::SeqCst);
+    }
+
+    /// Queues `event`, to be handled by [handle_key_event](Self::handle_key_event) once
+    /// the current plugin handlers return.
+    ///
+    /// Plugin handlers run while [RUNTIME](crate::RUNTIME) is borrowed, so they queue the
+    /// events they inject, instead of handling them. Queued events are handled in queue
+    /// order, after the `before_each_cycle()` handlers, and again after the
+    /// `after_each_cycle()` handlers. Returns an error if [INJECT_QUEUE_CAPACITY] entries
+    /// are already waiting. The error is also recorded as the
+    /// [last error](Self::last_error), so callers may ignore it.
+    pub fn queue_key_event(event: KeyEvent) -> Result<()> {
+        let queued = INJECT_QUEUE.write().push(event, InjectStage::Key);
+        Self::record_queue_error(queued)
+    }
+
+    /// Queues a keyswitch `event`, like [queue_key_event](Self::queue_key_event).
+    ///
+    /// The event passes the `on_keyswitch_event()` handlers, and its key is looked up in
+    /// the keymap, unless already set. Used by plugins re-injecting the keyswitch events
+    /// they held back: the filters [handle_keyswitch_event](Self::handle_keyswitch_event)
+    /// applies to new physical presses, like the minimum hold time, are not applied
+    /// again. Events without a valid address are dropped.
+    pub fn queue_keyswitch_event(event: KeyEvent) -> Result<()> {
+        let queued = INJECT_QUEUE.write().push(event, InjectStage::Keyswitch);
+        Self::record_queue_error(queued)
+    }
+
+    /// Queues an injected press, or release, of `key`, not bound to any key address.
+    pub fn queue_key(key: Key, pressed: bool) -> Result<()> {
+        Self::queue_key_event(injected_event(key, pressed))
+    }
+
+    /// Queues an injected tap of `key`: a press, then a release.
+    ///
+    /// Returns an error, and queues nothing, unless both events fit in the queue.
+    pub fn queue_key_tap(key: Key) -> Result<()> {
+        Self::queue_key_events(&[injected_event(key, true), injected_event(key, false)])
+    }
+
+    /// Queues the events generated by `source` with `arg`, handled in order like
+    /// [queue_key_event](Self::queue_key_event), all at once.
+    ///
+    /// For sequences that may not fit in the queue, like typed text or a macro playback.
+    /// The whole sequence takes a single queue entry.
+    pub fn queue_key_source(source: KeySource, arg: u32) -> Result<()> {
+        let queued = INJECT_QUEUE.write().push_source(source, arg);
+        Self::record_queue_error(queued)
+    }
+
+    /// Queues `events` in order, like [queue_key_event](Self::queue_key_event).
+    ///
+    /// Returns an error, and queues nothing, unless all the events fit in the queue, so
+    /// a press is never queued without its release.
+    pub fn queue_key_events(events: &[KeyEvent]) -> Result<()> {
+        let queued = {
+            let mut queue = INJECT_QUEUE.write();
+
+            if queue.free() < events.len() {
+                Err(Error::InjectQueueFull)
+            } else {
+                events.iter().try_for_each(|&event| queue.push(event, InjectStage::Key))
+            }
+        };
+
+        Self::record_queue_error(queued)
+    }
+
+    fn record_queue_error(queued: Result<()>) -> Result<()> {
+        if let Err(err) = queued {
+            LAST_ERROR.write().record(err);
+        }
+
+        queued
     }
 
     /// Gets the current value of a keymap entry.
@@ -369,8 +1285,14 @@ impl Runtime {
     ///
     /// Intended to be used in cases where we want to change some settings between
     /// detach and attach.
+    ///
+    /// All keys are released, and a final empty report is sent before detaching, so no key
+    /// stays stuck on the host. Keys still held are only registered again once they are
+    /// pressed anew.
     pub fn detach_from_host() {
-        return_on_err!(Device::detach_from_host());
+        Self::release_all();
+
+        return_on_err!(<Device as Mcu>::detach_from_host());
     }
 
     /// Detaching from / attaching to the host.
@@ -384,26 +1306,236 @@ impl Runtime {
     ///
     /// Intended to be used in cases where we want to change some settings between
     /// detach and attach.
+    ///
+    /// The reports are cleared before attaching, so the first report the host gets is
+    /// built from keys pressed after the attach.
     pub fn attach_to_host() {
-        return_on_err!(Device::attach_to_host());
+        Self::release_all();
+
+        return_on_err!(<Device as Mcu>::attach_to_host());
     }
 
-    /// Gets the milliseconds at cycle start.
-    pub fn millis_at_cycle_start(&self) -> u32 {
-        self.millis_at_cycle_start
+    /// Releases all keys, and sends empty keyboard, consumer control, and system
+    /// control reports.
+    ///
+    /// Keys still held are masked in the `LIVE_KEYS` state, so they are not reported
+    /// again until released and pressed anew. The rollover state is forgotten too, so
+    /// no modifier flag of a released key is restored.
+    pub fn release_all() {
+        let mut live_keys = LIVE_KEYS.write();
+
+        let _ = with_hid(|hid| {
+            for (_, key) in live_keys.iter_active() {
+                if key.is_consumer_control_key() {
+                    hid.release_consumer_control(key);
+                } else if key.is_system_control_key() {
+                    hid.release_system_control(key);
+                }
+            }
+        });
+
+        live_keys.mask_all();
+        FORGET_ROLLOVER.store(true, Ordering::SeqCst);
+
+        let _ = try_with_hid(|hid| hid.release_all_keys());
+        let _ = try_with_hid(|hid| hid.send_report());
+        let _ = try_with_hid(|hid| hid.send_control_reports());
     }
 
-    /// Gets the last key address toggled on.
-    pub fn last_addr_toggled_on(&self) -> &KeyAddr {
-        &self.last_addr_toggled_on
+    /// Reboots the keyboard into the application, not the bootloader.
+    ///
+    /// All keys are released and a final empty report is sent, then the keyboard detaches
+    /// from the host, so the host sees a clean disconnect rather than stuck keys. The MCU
+    /// is reset through the watchdog, see [system_reset](crate::driver::wdt::system_reset).
+    pub fn reboot() -> ! {
+        // Sends the final empty report.
+        Self::detach_from_host();
+
+        wdt::system_reset()
     }
 
-    /// Gets whether the device has LEDs.
-    pub fn has_leds(&self) -> bool {
-        self.has_leds
+    /// Re-enumerates with a new USB identity.
+    ///
+    /// Detaches from the host, swaps the identity, and attaches again, so the host sees
+    /// the new VID, PID, and strings.
+    pub fn reattach_with_identity(identity: UsbIdentity) {
+        Self::detach_from_host();
+        *USB_IDENTITY.write() = identity;
+        Self::attach_to_host();
     }
 
     pub fn on_focus_event(input: &str) -> Result<()> {
         Hooks::on_focus_event(input).map_err(|err| err.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::typing::injected_event_at;
+
+    #[test]
+    fn inject_press_updates_live_keys() {
+        let mut runtime = Runtime::new(Device::new());
+        let addr = KeyAddr::create(0, 0);
+        let key = LAYER.read().lookup_on_active_layer(&addr);
+
+        runtime.inject_press(addr);
+        assert_eq!(LIVE_KEYS.read()[addr], key);
+
+        runtime.inject_release(addr);
+        assert_eq!(LIVE_KEYS.read()[addr], Key_Inactive);
+    }
+
+    #[test]
+    fn inject_press_ignores_invalid_addresses() {
+        let mut runtime = Runtime::new(Device::new());
+
+        runtime.inject_press(KeyAddr::default());
+        assert!(runtime.inject_tap(KeyAddr::default()).is_err());
+    }
+
+    #[test]
+    fn min_hold_suppresses_short_taps() {
+        let mut runtime = Runtime::new(Device::new());
+        let addr = KeyAddr::create(0, 1);
+
+        // Long enough that the press never expires during the test.
+        runtime.set_min_hold_time(u16::MAX);
+
+        runtime.handle_keyswitch_event(KeyEvent::next(addr, KeyswitchState::from(0b10)));
+        assert_eq!(LIVE_KEYS.read()[addr], Key_Inactive);
+
+        runtime.handle_keyswitch_event(KeyEvent::next(addr, KeyswitchState::from(0b01)));
+        assert_eq!(LIVE_KEYS.read()[addr], Key_Inactive);
+
+        // The tap was dropped, nothing is left to register.
+        assert_eq!(runtime.min_hold.take_expired(u32::MAX), None);
+    }
+
+    #[test]
+    fn scan_once_request_is_taken_once() {
+        Runtime::request_scan_once();
+
+        assert!(SCAN_ONCE.swap(false, Ordering::SeqCst));
+        assert!(!SCAN_ONCE.swap(false, Ordering::SeqCst));
+    }
+
+    fn press_and_release(runtime: &mut Runtime, row: u8, keys: &[Key]) {
+        for pressed in [true, false] {
+            for (col, &key) in keys.iter().enumerate() {
+                let addr = KeyAddr::create(row, col as u8);
+                runtime.handle_key_event(&mut injected_event_at(addr, key, pressed));
+            }
+        }
+    }
+
+    #[test]
+    fn immediate_mode_sends_a_report_per_event() {
+        let mut runtime = Runtime::new(Device::new());
+
+        press_and_release(&mut runtime, 2, &[Key_A, Key_B, Key_C]);
+
+        assert_eq!(runtime.reports_sent, 6);
+        assert!(!runtime.report_pending());
+    }
+
+    #[test]
+    fn deferred_mode_sends_one_report_per_cycle() {
+        let mut runtime = Runtime::new(Device::new());
+        runtime.set_report_mode(ReportMode::Deferred);
+
+        press_and_release(&mut runtime, 3, &[Key_A, Key_B, Key_C]);
+
+        assert_eq!(runtime.reports_sent, 0);
+        assert!(runtime.report_pending());
+
+        // Switching back sends the pending report, like the end of the cycle.
+        runtime.set_report_mode(ReportMode::Immediate);
+
+        assert_eq!(runtime.reports_sent, 1);
+        assert!(!runtime.report_pending());
+    }
+
+    #[test]
+    fn scan_is_skipped_while_suspended() {
+        let mut runtime = Runtime::new(Device::new());
+        runtime.scanning_suspended = true;
+
+        runtime.device.key_scanner_mut().seed_row(3, 1 << 5);
+        runtime.scan_keys();
+        assert_eq!(runtime.device.key_scanner().previous_row(3), 0);
+
+        runtime.resume_scanning();
+        runtime.scan_keys();
+        assert_eq!(runtime.device.key_scanner().previous_row(3), 1 << 5);
+
+        runtime.device.key_scanner_mut().seed_row(3, 0);
+        runtime.scan_keys();
+    }
+
+    #[test]
+    fn release_all_masks_held_keys() {
+        let mut runtime = Runtime::new(Device::new());
+        let held = KeyAddr::create(3, 6);
+
+        runtime.handle_key_event(&mut injected_event_at(held, Key_A, true));
+        assert_eq!(LIVE_KEYS.read()[held], Key_A);
+
+        // Run before detaching from, and attaching to, the host.
+        Runtime::release_all();
+
+        assert_eq!(LIVE_KEYS.read()[held], Key_Masked);
+        assert!(FORGET_ROLLOVER.load(Ordering::SeqCst));
+
+        // The held key is only registered again once pressed anew.
+        runtime.handle_keyswitch_event(KeyEvent::next(held, KeyswitchState::from(0b01)));
+        assert_eq!(LIVE_KEYS.read()[held], Key_Inactive);
+    }
+
+    #[test]
+    fn host_connected_is_set_on_the_first_configured_cycle() {
+        let mut runtime = Runtime::new(Device::new());
+
+        runtime.poll_host_connected(false);
+        assert!(!runtime.host_connected());
+
+        runtime.poll_host_connected(true);
+        assert!(runtime.host_connected());
+
+        // Stays connected, so the hooks only run once.
+        runtime.poll_host_connected(false);
+        assert!(runtime.host_connected());
+    }
+
+    #[test]
+    fn report_window_coalesces_presses() {
+        let mut runtime = Runtime::new(Device::new());
+        let (a, b) = (KeyAddr::create(0, 4), KeyAddr::create(0, 5));
+
+        // Long enough that the window never closes during the test.
+        runtime.set_report_window(u16::MAX);
+
+        runtime.handle_key_event(&mut injected_event_at(a, Key_A, true));
+        runtime.handle_key_event(&mut injected_event_at(b, Key_B, true));
+
+        assert_eq!(runtime.reports_sent, 0);
+        assert!(runtime.report_pending());
+
+        // A release sends the gathered presses at once.
+        runtime.handle_key_event(&mut injected_event_at(a, Key_A, false));
+
+        assert_eq!(runtime.reports_sent, 1);
+        assert!(!runtime.report_pending());
+
+        // Disabling the window sends any pending report.
+        runtime.handle_key_event(&mut injected_event_at(a, Key_A, true));
+        runtime.set_report_window(0);
+
+        assert_eq!(runtime.reports_sent, 2);
+        assert!(!runtime.report_pending());
+
+        runtime.handle_key_event(&mut injected_event_at(a, Key_A, false));
+        runtime.handle_key_event(&mut injected_event_at(b, Key_B, false));
+    }
+}
@@ -0,0 +1,123 @@
+use ufmt::{uWrite, uwrite};
+
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::lock;
+
+/// Weight of the newest sample in the rolling average, as a power of two (1/8).
+const AVERAGE_SHIFT: u64 = 3;
+
+/// `main_loop` iteration statistics, recorded by the [Runtime](crate::runtime::Runtime).
+///
+/// Kept out of the runtime, so the `device.cycletime` Focus command can read and reset
+/// them without borrowing it.
+pub static CYCLE_TIME: lock::Spinlock<CycleTime> = lock::Spinlock::new(CycleTime::new());
+
+/// Tracks how long each `main_loop` iteration takes, in microseconds.
+///
+/// Times are passed in by the caller, so the statistics do not depend on the clock.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::runtime::CycleTime;
+///
+/// let mut cycle_time = CycleTime::new();
+///
+/// cycle_time.record(1_000, 1_400);
+/// cycle_time.record(2_000, 3_200);
+/// cycle_time.record(u32::MAX - 99, 100);
+///
+/// assert_eq!(cycle_time.last(), 200);
+/// assert_eq!(cycle_time.max(), 1_200);
+/// assert_eq!(cycle_time.average(), 462);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CycleTime {
+    last: u32,
+    max: u32,
+    average: u32,
+    samples: u32,
+}
+
+impl CycleTime {
+    /// Creates a new, empty [CycleTime].
+    pub const fn new() -> Self {
+        Self {
+            last: 0,
+            max: 0,
+            average: 0,
+            samples: 0,
+        }
+    }
+
+    /// Records a cycle that started at `start`, and ended at `end`.
+    pub fn record(&mut self, start: u32, end: u32) {
+        let delta = end.wrapping_sub(start);
+
+        self.last = delta;
+        self.max = self.max.max(delta);
+
+        // Seed the average with the first sample, then use an exponential moving average.
+        self.average = if self.samples == 0 {
+            delta
+        } else {
+            let average = self.average as u64;
+            (((average << AVERAGE_SHIFT) - average + delta as u64) >> AVERAGE_SHIFT) as u32
+        };
+
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    /// Gets the duration of the last cycle.
+    pub fn last(&self) -> u32 {
+        self.last
+    }
+
+    /// Gets the duration of the longest cycle since the last reset.
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Gets the rolling average cycle duration.
+    pub fn average(&self) -> u32 {
+        self.average
+    }
+
+    /// Clears the recorded statistics.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Writes the last, max, and average cycle durations.
+    pub fn write_to<W: uWrite>(&self, w: &mut W) -> core::result::Result<(), W::Error> {
+        uwrite!(w, "{} {} {}\r\n", self.last, self.max, self.average)
+    }
+}
+
+impl EventHandler for CycleTime {
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, args) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("device.cycletime\r\n");
+            return Ok(());
+        }
+
+        if command != "device.cycletime" {
+            return Ok(());
+        }
+
+        if args.trim() == "reset" {
+            CYCLE_TIME.write().reset();
+        } else {
+            let cycle_time = *CYCLE_TIME.read();
+
+            cycle_time
+                .write_to(&mut *FOCUS_OUTPUT.write())
+                .map_err(|_| EventHandlerError::Error)?;
+        }
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
@@ -0,0 +1,140 @@
+use crate::error::{Error, Result};
+use crate::key_event::KeyEvent;
+
+/// Maximum number of entries waiting in the [InjectQueue].
+pub const INJECT_QUEUE_CAPACITY: usize = 16;
+
+/// Entry point of a queued event into the runtime.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InjectStage {
+    /// Handled like a keyswitch event: passes the `on_keyswitch_event()` handlers, and
+    /// the key is looked up in the keymap, unless already set.
+    Keyswitch,
+    /// Handled like a key event: passes the `on_key_event()` handlers, with the key of
+    /// the event.
+    Key,
+}
+
+/// Generator of injected key events, for sequences longer than the [InjectQueue].
+///
+/// Called with the argument it was queued with, and the index of the event, from `0`,
+/// until it returns `None`. Each event is handled before the next one is generated, so
+/// the generator must not hold a lock the event handlers take.
+pub type KeySource = fn(u32, usize) -> Option<KeyEvent>;
+
+/// Entry of the [InjectQueue].
+#[derive(Clone, Copy, Debug)]
+pub enum Injected {
+    /// A single event, entering the runtime at the given stage.
+    Event(KeyEvent, InjectStage),
+    /// The events of a [KeySource], with its argument, handled as key events.
+    Source(KeySource, u32),
+}
+
+/// Fixed-capacity FIFO of key events injected by plugins.
+///
+/// Plugin handlers run while the runtime is borrowed, so they can't handle the events
+/// they inject themselves. They queue them instead, and the runtime handles them, in
+/// queue order, once the handlers return.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::key_addr::KeyAddr;
+/// use kaleidoscope::key_event::KeyEvent;
+/// use kaleidoscope::keyswitch_state::KeyswitchState;
+/// use kaleidoscope::runtime::{InjectQueue, InjectStage, Injected, INJECT_QUEUE_CAPACITY};
+///
+/// fn no_events(_: u32, _: usize) -> Option<KeyEvent> {
+///     None
+/// }
+///
+/// let mut queue = InjectQueue::new();
+/// let press = KeyEvent::next(KeyAddr::create(0, 0), KeyswitchState::default());
+/// let release = KeyEvent::next(KeyAddr::create(0, 0), KeyswitchState::default());
+///
+/// assert!(queue.push(press, InjectStage::Keyswitch).is_ok());
+/// assert!(queue.push_source(no_events, 7).is_ok());
+/// assert!(queue.push(release, InjectStage::Key).is_ok());
+///
+/// assert!(matches!(queue.pop(), Some(Injected::Event(e, InjectStage::Keyswitch)) if e == press));
+/// assert!(matches!(queue.pop(), Some(Injected::Source(_, 7))));
+/// assert!(matches!(queue.pop(), Some(Injected::Event(e, InjectStage::Key)) if e == release));
+/// assert!(queue.pop().is_none());
+///
+/// for _ in 0..INJECT_QUEUE_CAPACITY {
+///     assert!(queue.push(press, InjectStage::Key).is_ok());
+/// }
+/// assert!(queue.push(press, InjectStage::Key).is_err());
+/// assert!(queue.push_source(no_events, 0).is_err());
+/// ```
+pub struct InjectQueue {
+    entries: [Option<Injected>; INJECT_QUEUE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl InjectQueue {
+    /// Creates a new, empty [InjectQueue].
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; INJECT_QUEUE_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Gets the number of queued events.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether no event is queued.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the number of events that can still be queued.
+    pub fn free(&self) -> usize {
+        INJECT_QUEUE_CAPACITY - self.len
+    }
+
+    /// Queues `event`, to enter the runtime at `stage`.
+    ///
+    /// Returns an error if the queue is full.
+    pub fn push(&mut self, event: KeyEvent, stage: InjectStage) -> Result<()> {
+        self.push_entry(Injected::Event(event, stage))
+    }
+
+    /// Queues the events of `source`, generated with `arg`.
+    ///
+    /// The whole sequence takes a single entry. Returns an error if the queue is full.
+    pub fn push_source(&mut self, source: KeySource, arg: u32) -> Result<()> {
+        self.push_entry(Injected::Source(source, arg))
+    }
+
+    fn push_entry(&mut self, entry: Injected) -> Result<()> {
+        if self.len == INJECT_QUEUE_CAPACITY {
+            return Err(Error::InjectQueueFull);
+        }
+
+        self.entries[(self.head + self.len) % INJECT_QUEUE_CAPACITY] = Some(entry);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Takes the oldest queued entry.
+    pub fn pop(&mut self) -> Option<Injected> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let entry = self.entries[self.head].take();
+        self.head = (self.head + 1) % INJECT_QUEUE_CAPACITY;
+        self.len -= 1;
+
+        entry
+    }
+}
@@ -0,0 +1,90 @@
+use ufmt::uWrite;
+
+use crate::error::Error;
+use crate::event_handler::{EventHandler, EventHandlerError, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::runtime::Runtime;
+
+/// Remembers the last error swallowed by the runtime.
+///
+/// The runtime does not stop on errors, it skips the rest of the failed operation (see
+/// [record_on_err](crate::record_on_err)). The error is recorded in
+/// [LAST_ERROR](crate::LAST_ERROR), so it can be inspected later, e.g. with the `device.lasterror` Focus command. The command prints
+/// the error and clears it, or prints `none`.
+///
+/// [EventConsumed](Error::EventConsumed) and [EventAbort](Error::EventAbort) are normal
+/// results of the event handlers, and are not recorded.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{record_on_err, Error, event_handler::EventHandlerError, runtime::LastError};
+///
+/// fn send_report(last_error: &mut LastError, hid: Result<(), Error>) {
+///     record_on_err!(last_error, hid);
+/// }
+///
+/// let mut last_error = LastError::new();
+///
+/// send_report(&mut last_error, Err(Error::HID));
+/// assert_eq!(last_error.take(), Some(Error::HID));
+/// assert_eq!(last_error.take(), None);
+///
+/// last_error.record(EventHandlerError::EventConsumed.into());
+/// assert_eq!(last_error.get(), None);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LastError {
+    error: Option<Error>,
+}
+
+impl LastError {
+    /// Creates a new [LastError] without error.
+    pub const fn new() -> Self {
+        Self { error: None }
+    }
+
+    /// Records a failure, replacing the previous one.
+    pub fn record(&mut self, error: Error) {
+        match error {
+            Error::EventConsumed | Error::EventAbort => (),
+            _ => self.error = Some(error),
+        }
+    }
+
+    /// Gets the last recorded error.
+    pub fn get(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// Takes the last recorded error, clearing it.
+    pub fn take(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+impl EventHandler for LastError {
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("device.lasterror\r\n");
+            return Ok(());
+        }
+
+        if command != "device.lasterror" {
+            return Ok(());
+        }
+
+        let description = match Runtime::take_last_error() {
+            Some(error) => error.into(),
+            None => "none",
+        };
+
+        let mut output = FOCUS_OUTPUT.write();
+        output.write_str(description).map_err(|_| EventHandlerError::Error)?;
+        output.write_str("\r\n").map_err(|_| EventHandlerError::Error)?;
+
+        Err(EventHandlerError::EventConsumed)
+    }
+}
@@ -0,0 +1,75 @@
+/// One-shot request to mask the next key that toggles on, whatever its address.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::runtime::MaskNext;
+///
+/// let mut mask_next = MaskNext::new();
+/// mask_next.set_timeout(100);
+///
+/// // Only the next press is masked.
+/// mask_next.arm(0);
+/// assert!(mask_next.take(50));
+/// assert!(!mask_next.take(60));
+///
+/// // The request expires after the timeout.
+/// mask_next.arm(200);
+/// assert!(!mask_next.take(300));
+/// assert!(!mask_next.is_armed());
+/// ```
+pub struct MaskNext {
+    timeout: u16,
+    armed: bool,
+    start: u32,
+}
+
+impl MaskNext {
+    /// Creates a new, disarmed [MaskNext] without timeout.
+    pub const fn new() -> Self {
+        Self {
+            timeout: 0,
+            armed: false,
+            start: 0,
+        }
+    }
+
+    /// Gets the time, in milliseconds, after which a request expires. Zero means never.
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Sets the time, in milliseconds, after which a request expires. Zero means never.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout;
+    }
+
+    /// Gets whether the next key press will be masked.
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Requests masking the next key press, at the provided time (in milliseconds).
+    pub fn arm(&mut self, now: u32) {
+        self.armed = true;
+        self.start = now;
+    }
+
+    /// Cancels a pending request.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Records a key press at the provided time.
+    ///
+    /// Returns whether the press should be masked. The request is cleared either way.
+    pub fn take(&mut self, now: u32) -> bool {
+        if !self.armed {
+            return false;
+        }
+
+        self.armed = false;
+
+        self.timeout == 0 || now.wrapping_sub(self.start) < self.timeout as u32
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{key_addr::KeyAddr, layers::NUM_KEYS};
+
+/// Tracks key presses that must be held for a minimum duration before being registered.
+///
+/// This is distinct from debouncing: it filters out deliberate-but-brief contacts (e.g.
+/// brushing a key, or tremor), not electrical noise.
+pub struct MinHold {
+    timeout: u16,
+    pending: [bool; NUM_KEYS],
+    starts: [u16; NUM_KEYS],
+}
+
+impl MinHold {
+    /// Creates a new, disabled [MinHold].
+    pub const fn new() -> Self {
+        Self {
+            timeout: 0,
+            pending: [false; NUM_KEYS],
+            starts: [0u16; NUM_KEYS],
+        }
+    }
+
+    /// Gets the minimum hold time in milliseconds. Zero means disabled.
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Sets the minimum hold time in milliseconds. Zero disables the check.
+    ///
+    /// Any pending presses are dropped.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout;
+        self.pending = [false; NUM_KEYS];
+    }
+
+    /// Gets whether the minimum hold check is enabled.
+    pub fn enabled(&self) -> bool {
+        self.timeout > 0
+    }
+
+    /// Records a toggle-on at the provided time (in milliseconds).
+    pub fn press(&mut self, key_addr: &KeyAddr, now: u32) {
+        let index = key_addr.index();
+
+        if index < NUM_KEYS {
+            self.pending[index] = true;
+            self.starts[index] = now as u16;
+        }
+    }
+
+    /// Records a toggle-off.
+    ///
+    /// Returns `true` if the key was still pending, i.e. it was released before the minimum
+    /// hold time, and both events should be suppressed.
+    pub fn release(&mut self, key_addr: &KeyAddr) -> bool {
+        let index = key_addr.index();
+
+        if index < NUM_KEYS && self.pending[index] {
+            self.pending[index] = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the next pending key that has been held for the minimum time.
+    pub fn take_expired(&mut self, now: u32) -> Option<KeyAddr> {
+        for key_addr in KeyAddr::iter() {
+            let index = key_addr.index();
+
+            if index < NUM_KEYS
+                && self.pending[index]
+                && (now as u16).wrapping_sub(self.starts[index]) >= self.timeout
+            {
+                self.pending[index] = false;
+                return Some(key_addr);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_tap_is_suppressed() {
+        let mut min_hold = MinHold::new();
+        let addr = KeyAddr::create(0, 1);
+
+        min_hold.set_timeout(50);
+        min_hold.press(&addr, 100);
+
+        assert_eq!(min_hold.take_expired(149), None);
+        assert!(min_hold.release(&addr));
+        assert_eq!(min_hold.take_expired(1_000), None);
+    }
+
+    #[test]
+    fn sustained_press_passes() {
+        let mut min_hold = MinHold::new();
+        let addr = KeyAddr::create(1, 2);
+
+        min_hold.set_timeout(50);
+        min_hold.press(&addr, 100);
+
+        assert_eq!(min_hold.take_expired(120), None);
+        assert_eq!(min_hold.take_expired(150), Some(addr));
+        assert_eq!(min_hold.take_expired(200), None);
+
+        // The press was already registered, so the release goes through.
+        assert!(!min_hold.release(&addr));
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(!MinHold::new().enabled());
+    }
+}
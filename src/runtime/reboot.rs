@@ -0,0 +1,29 @@
+use ufmt::uWrite;
+
+use crate::event_handler::{EventHandler, Result};
+use crate::focus::{split_command, FOCUS_OUTPUT};
+use crate::runtime::Runtime;
+
+/// Adds the `device.reboot` Focus command, restarting the firmware.
+///
+/// Unlike `device.reset`, from the feature-gated `DeviceReset` plugin, the keyboard does
+/// not enter the bootloader, so this is always available. See [Runtime::reboot] for how
+/// the host is detached before the restart.
+pub struct Reboot;
+
+impl EventHandler for Reboot {
+    fn on_focus_event(input: &str) -> Result<()> {
+        let (command, _) = split_command(input);
+
+        if command == "help" {
+            let _ = FOCUS_OUTPUT.write().write_str("device.reboot\r\n");
+            return Ok(());
+        }
+
+        if command == "device.reboot" {
+            Runtime::reboot();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{key_ext::KeyExt, Key, KeyAddr, KeyFlags, Key_Inactive};
+
+/// Tracks the last Keyboard key toggled on, to keep its modifier flags in the reports.
+///
+/// Only the key that generated an event gets its modifier flags added to the report, so
+/// rolling from one flagged key to another sends the new flags alone. Every other event
+/// rebuilds the report from the live keys, with the flags stripped, so the flags of the
+/// tracked key are added back, for as long as it stays in the live keys.
+///
+/// Keys pressed at an invalid address, like typed or injected keys, never end up in the
+/// live keys, so they don't replace the tracked key: their release restores its flags.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_addr::KeyAddr, key_defs::*, key_ext::KeyExt};
+/// use kaleidoscope::runtime::ReportRollover;
+///
+/// let (a, b) = (KeyAddr::new(0), KeyAddr::new(1));
+/// let bang = Key_1.with_flags(KeyFlags::SHIFT_HELD);
+/// let copy = Key_C.with_flags(KeyFlags::CTRL_HELD);
+///
+/// let mut rollover = ReportRollover::new();
+///
+/// // Rolling from one flagged key to another: each sends its flags in their own report.
+/// assert!(rollover.press(a, bang));
+/// assert!(rollover.press(b, copy));
+/// assert_eq!(rollover.modifiers(), KeyFlags::CTRL_HELD);
+///
+/// // Releasing the first key keeps the flags of the second one.
+/// assert_eq!(rollover.restore(a, copy), Some(copy));
+///
+/// // Releasing the second key forgets it, so no modifier is left behind.
+/// assert_eq!(rollover.restore(b, Key_Inactive), None);
+/// assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+/// assert!(!rollover.last_addr().is_valid());
+///
+/// // A typed key doesn't replace the held key, whose flags are restored on its release.
+/// assert!(rollover.press(a, bang));
+/// assert!(rollover.press(KeyAddr::default(), Key_H.with_flags(KeyFlags::SHIFT_HELD)));
+/// assert_eq!(rollover.last_addr(), &a);
+/// assert_eq!(rollover.restore(KeyAddr::default(), bang), Some(bang));
+///
+/// // A tracked key no longer live, e.g. after all keys were released, is forgotten.
+/// assert_eq!(rollover.restore(b, Key_Inactive), None);
+/// assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+/// ```
+pub struct ReportRollover {
+    addr: KeyAddr,
+    key: Key,
+}
+
+impl ReportRollover {
+    /// Creates a new [ReportRollover], tracking no key.
+    pub const fn new() -> Self {
+        Self {
+            addr: KeyAddr::default(),
+            key: Key_Inactive,
+        }
+    }
+
+    /// Gets the address of the tracked key, invalid if no key is tracked.
+    pub fn last_addr(&self) -> &KeyAddr {
+        &self.addr
+    }
+
+    /// Gets the modifier flags of the tracked key, added to the reports.
+    pub fn modifiers(&self) -> KeyFlags {
+        if self.addr.is_valid() {
+            self.key.modifiers()
+        } else {
+            KeyFlags::NONE
+        }
+    }
+
+    /// Records a Keyboard key toggling on at `addr`.
+    ///
+    /// Returns whether the key carries flags, to send in their own report before the
+    /// keycode, so the host applies them first.
+    pub fn press(&mut self, addr: KeyAddr, key: Key) -> bool {
+        if addr.is_valid() {
+            self.addr = addr;
+            self.key = key;
+        }
+
+        key != key.base()
+    }
+
+    /// Records any other event at `addr`: a release, or a non-Keyboard key toggling on.
+    ///
+    /// `live` is the live key at [last_addr](Self::last_addr). Returns the key whose
+    /// modifier flags are to be added back to the report, if the tracked key is still
+    /// live. The tracked key is forgotten once released, or no longer live.
+    pub fn restore(&mut self, addr: KeyAddr, live: Key) -> Option<Key> {
+        if !self.addr.is_valid() {
+            return None;
+        }
+
+        if addr == self.addr || !live.is_keyboard_key() {
+            self.forget();
+            return None;
+        }
+
+        // Plugins may have changed the live key since, its flags are the ones to keep.
+        self.key = live;
+
+        Some(live)
+    }
+
+    /// Stops tracking the last key.
+    pub fn forget(&mut self) {
+        self.addr = KeyAddr::default();
+        self.key = Key_Inactive;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_defs::*;
+
+    #[test]
+    fn new_tracks_nothing() {
+        let rollover = ReportRollover::new();
+
+        assert!(!rollover.last_addr().is_valid());
+        assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+    }
+
+    #[test]
+    fn unflagged_press_is_tracked_without_extra_report() {
+        let mut rollover = ReportRollover::new();
+        let addr = KeyAddr::new(3);
+
+        assert!(!rollover.press(addr, Key_A));
+        assert_eq!(rollover.last_addr(), &addr);
+        assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+    }
+
+    #[test]
+    fn restore_follows_the_live_key() {
+        let mut rollover = ReportRollover::new();
+        let (a, b) = (KeyAddr::new(0), KeyAddr::new(1));
+        let shifted = Key_1.with_flags(KeyFlags::SHIFT_HELD);
+        let ctrl = Key_1.with_flags(KeyFlags::CTRL_HELD);
+
+        assert!(rollover.press(a, shifted));
+
+        // A plugin changed the live key: its flags are the ones restored.
+        assert_eq!(rollover.restore(b, ctrl), Some(ctrl));
+        assert_eq!(rollover.modifiers(), KeyFlags::CTRL_HELD);
+        assert_eq!(rollover.last_addr(), &a);
+    }
+
+    #[test]
+    fn restore_forgets_keys_no_longer_live() {
+        let mut rollover = ReportRollover::new();
+        let (a, b) = (KeyAddr::new(0), KeyAddr::new(1));
+
+        assert!(rollover.press(a, Key_1.with_flags(KeyFlags::SHIFT_HELD)));
+        assert_eq!(rollover.restore(b, Key_Inactive), None);
+        assert!(!rollover.last_addr().is_valid());
+
+        // Nothing is tracked anymore, even once the key is live again.
+        assert_eq!(rollover.restore(b, Key_1), None);
+    }
+
+    #[test]
+    fn forget_drops_the_tracked_key() {
+        let mut rollover = ReportRollover::new();
+
+        assert!(rollover.press(KeyAddr::new(0), Key_1.with_flags(KeyFlags::SHIFT_HELD)));
+        rollover.forget();
+
+        assert!(!rollover.last_addr().is_valid());
+        assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+        assert_eq!(rollover.restore(KeyAddr::new(1), Key_1), None);
+    }
+
+    #[test]
+    fn invalid_address_is_not_tracked() {
+        let mut rollover = ReportRollover::new();
+
+        assert!(rollover.press(KeyAddr::default(), Key_H.with_flags(KeyFlags::SHIFT_HELD)));
+        assert!(!rollover.last_addr().is_valid());
+        assert_eq!(rollover.modifiers(), KeyFlags::NONE);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::{Error, Result};
+use crate::key_event::{KeyEvent, KeyEventId};
+
+/// Maximum number of events waiting in the [Scheduler].
+pub const SCHEDULER_CAPACITY: usize = 8;
+
+/// An event waiting to be handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Scheduled {
+    event: KeyEvent,
+    due: u32,
+}
+
+/// Fixed-capacity queue of key events to be handled at a later time.
+///
+/// Entries are kept sorted by due time, so events fire in due-time order. Events due at
+/// the same time fire in the order they were scheduled.
+pub struct Scheduler {
+    entries: [Option<Scheduled>; SCHEDULER_CAPACITY],
+    len: usize,
+}
+
+impl Scheduler {
+    /// Creates a new, empty [Scheduler].
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; SCHEDULER_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Gets the number of scheduled events.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether no event is scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Schedules `event` to be handled at `due` (in milliseconds).
+    ///
+    /// Returns an error if the queue is full.
+    pub fn schedule(&mut self, event: KeyEvent, due: u32, now: u32) -> Result<()> {
+        if self.len == SCHEDULER_CAPACITY {
+            return Err(Error::SchedulerFull);
+        }
+
+        // Compare relative to `now`, so the order survives `millis` wrapping around.
+        let delay = due.wrapping_sub(now);
+        let pos = self.entries[..self.len]
+            .iter()
+            .position(|e| e.map_or(false, |e| e.due.wrapping_sub(now) > delay))
+            .unwrap_or(self.len);
+
+        self.entries.copy_within(pos..self.len, pos + 1);
+        self.entries[pos] = Some(Scheduled { event, due });
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Cancels the scheduled event with the provided ID.
+    ///
+    /// Returns whether an event was cancelled.
+    pub fn cancel(&mut self, id: KeyEventId) -> bool {
+        let pos = match self.entries[..self.len]
+            .iter()
+            .position(|e| e.map_or(false, |e| e.event.id() == id))
+        {
+            Some(pos) => pos,
+            None => return false,
+        };
+
+        self.entries.copy_within(pos + 1..self.len, pos);
+        self.len -= 1;
+        self.entries[self.len] = None;
+
+        true
+    }
+
+    /// Takes the next event due at, or before, `now`.
+    pub fn take_due(&mut self, now: u32) -> Option<KeyEvent> {
+        let next = self.entries[0].filter(|_| self.len > 0)?;
+
+        // Treat due times up to half the `millis` range in the past as expired.
+        if (now.wrapping_sub(next.due) as i32) < 0 {
+            return None;
+        }
+
+        self.entries.copy_within(1..self.len, 0);
+        self.len -= 1;
+        self.entries[self.len] = None;
+
+        Some(next.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_addr::KeyAddr, key_addr_ext::KeyAddrExt, keyswitch_state::KeyswitchState};
+
+    fn event(col: u8) -> KeyEvent {
+        KeyEvent::next(KeyAddr::create(0, col), KeyswitchState::from(0b10))
+    }
+
+    fn due_cols(scheduler: &mut Scheduler, now: u32) -> ([u8; SCHEDULER_CAPACITY], usize) {
+        let mut cols = [0u8; SCHEDULER_CAPACITY];
+        let mut len = 0;
+
+        while let Some(event) = scheduler.take_due(now) {
+            cols[len] = event.addr().col();
+            len += 1;
+        }
+
+        (cols, len)
+    }
+
+    #[test]
+    fn events_fire_in_due_time_order() {
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule(event(0), 130, 100).unwrap();
+        scheduler.schedule(event(1), 110, 100).unwrap();
+        scheduler.schedule(event(2), 130, 100).unwrap();
+        scheduler.schedule(event(3), 120, 100).unwrap();
+
+        assert_eq!(scheduler.take_due(109), None);
+
+        let (cols, len) = due_cols(&mut scheduler, 130);
+
+        assert_eq!(cols[..len], [1, 3, 0, 2]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn order_survives_millis_wrapping() {
+        let mut scheduler = Scheduler::new();
+        let now = u32::MAX - 5;
+
+        scheduler.schedule(event(0), 10, now).unwrap();
+        scheduler.schedule(event(1), u32::MAX, now).unwrap();
+
+        assert_eq!(scheduler.take_due(now), None);
+
+        let (cols, len) = due_cols(&mut scheduler, 10);
+
+        assert_eq!(cols[..len], [1, 0]);
+    }
+
+    #[test]
+    fn cancel_and_capacity() {
+        let mut scheduler = Scheduler::new();
+        let cancelled = event(0);
+
+        scheduler.schedule(cancelled, 100, 0).unwrap();
+
+        for col in 1..SCHEDULER_CAPACITY as u8 {
+            scheduler.schedule(event(col), 100, 0).unwrap();
+        }
+
+        assert_eq!(scheduler.schedule(event(9), 100, 0), Err(Error::SchedulerFull));
+
+        assert!(scheduler.cancel(cancelled.id()));
+        assert!(!scheduler.cancel(cancelled.id()));
+        assert_eq!(scheduler.len(), SCHEDULER_CAPACITY - 1);
+
+        let (cols, len) = due_cols(&mut scheduler, 100);
+
+        assert_eq!(cols[..len], [1, 2, 3, 4, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{key_addr::KeyAddr, key_addr_ext::KeyAddrExt};
+
+/// Description of the user's sketch: the keymap dimensions, and the registered plugins.
+///
+/// Passed to the `explore_sketch()` plugin handlers before `on_setup()`, so plugins can
+/// check their assumptions (e.g. that the addresses or layers they refer to exist), and
+/// fail setup with an error otherwise.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_addr::KeyAddr, sketch::Sketch};
+///
+/// let sketch = Sketch::new(4, 12, 3, &["Qukeys", "Leader"]);
+///
+/// assert_eq!(sketch.num_keys(), 48);
+/// assert!(sketch.contains_addr(&KeyAddr::create(3, 11)));
+/// assert!(!sketch.contains_addr(&KeyAddr::create(4, 0)));
+/// assert!(sketch.has_layer(2));
+/// assert!(!sketch.has_layer(3));
+/// assert!(sketch.has_plugin("Leader"));
+/// assert!(!sketch.has_plugin("Steno"));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sketch {
+    rows: usize,
+    cols: usize,
+    layers: usize,
+    plugins: &'static [&'static str],
+}
+
+impl Sketch {
+    /// Creates a new [Sketch].
+    pub const fn new(rows: usize, cols: usize, layers: usize, plugins: &'static [&'static str]) -> Self {
+        Self {
+            rows,
+            cols,
+            layers,
+            plugins,
+        }
+    }
+
+    /// Gets the number of matrix rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Gets the number of matrix columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Gets the number of keys in the matrix.
+    pub fn num_keys(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    /// Gets the number of keymap layers.
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    /// Gets the names of the registered plugins, in dispatch order.
+    pub fn plugins(&self) -> &'static [&'static str] {
+        self.plugins
+    }
+
+    /// Gets whether the address is inside the matrix.
+    pub fn contains_addr(&self, addr: &KeyAddr) -> bool {
+        addr.is_valid() && (addr.row() as usize) < self.rows && (addr.col() as usize) < self.cols
+    }
+
+    /// Gets whether the keymap has the provided layer.
+    pub fn has_layer(&self, layer: usize) -> bool {
+        layer < self.layers
+    }
+
+    /// Gets whether a plugin with the provided name is registered.
+    pub fn has_plugin(&self, name: &str) -> bool {
+        self.plugins.iter().any(|p| *p == name)
+    }
+}
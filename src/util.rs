@@ -1 +1,3 @@
 pub mod bits;
+pub mod timing;
+pub mod typing;
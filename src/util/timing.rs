@@ -0,0 +1,82 @@
+//! Precise delays, for timing that `delay_us` and `millis()` are too coarse for.
+
+use crate::device::F_CPU;
+use crate::millis::micros;
+
+/// Converts a duration in microseconds to a number of CPU cycles.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::util::timing::us_to_cycles;
+///
+/// // At 16 MHz.
+/// assert_eq!(us_to_cycles(1), 16);
+/// assert_eq!(us_to_cycles(250), 4000);
+/// ```
+pub const fn us_to_cycles(us: u32) -> u32 {
+    (F_CPU / 1_000_000) * us
+}
+
+/// Busy-waits for (at least) `cycles` CPU cycles.
+///
+/// The delay loop is calibrated by cycle count, so unlike [busy_wait_until] it works
+/// with interrupts disabled, and for delays shorter than one [micros] tick.
+#[inline(always)]
+pub fn delay_cycles(cycles: u32) {
+    #[cfg(target_arch = "avr")]
+    avr_device::asm::delay_cycles(cycles);
+
+    #[cfg(not(target_arch = "avr"))]
+    for _ in 0..cycles {
+        core::hint::spin_loop();
+    }
+}
+
+/// Busy-waits until [micros] reaches `deadline`.
+///
+/// The deadline is compared with `wrapping_sub`, so it may be past the [micros]
+/// wrap-around. Needs the timer interrupt, and has the resolution of [micros].
+///
+/// Returns the time read when the deadline was reached.
+pub fn busy_wait_until(deadline: u32) -> u32 {
+    busy_wait_until_with(deadline, micros)
+}
+
+/// Busy-waits until the clock `now` reaches `deadline`.
+///
+/// Returns the time read when the deadline was reached.
+///
+/// Example:
+///
+/// ```rust
+/// use core::cell::Cell;
+/// use kaleidoscope::util::timing::busy_wait_until_with;
+///
+/// let clock = Cell::new(u32::MAX - 100);
+/// let reads = Cell::new(0);
+/// let now = || {
+///     reads.set(reads.get() + 1);
+///     clock.set(clock.get().wrapping_add(64));
+///     clock.get()
+/// };
+///
+/// // The deadline is past the wrap-around.
+/// assert_eq!(busy_wait_until_with(200, now), 219);
+/// assert_eq!(reads.get(), 5);
+///
+/// // A deadline in the past returns immediately.
+/// assert_eq!(busy_wait_until_with(100, now), 283);
+/// assert_eq!(reads.get(), 6);
+/// ```
+pub fn busy_wait_until_with<F: FnMut() -> u32>(deadline: u32, mut now: F) -> u32 {
+    loop {
+        let time = now();
+
+        if (time.wrapping_sub(deadline) as i32) >= 0 {
+            return time;
+        }
+
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,159 @@
+//! Typing ASCII strings with injected key events, on a US layout.
+
+use crate::error::{Error, Result};
+use crate::runtime::Runtime;
+use crate::{key_addr::KeyAddr, key_defs::*, key_event::KeyEvent, keyswitch_state::KeyswitchState};
+
+/// First character of [ASCII_KEYS], the space.
+pub const ASCII_FIRST: u8 = b' ';
+
+/// Last character of [ASCII_KEYS], the tilde.
+pub const ASCII_LAST: u8 = b'~';
+
+/// Keys typing the printable ASCII characters, from [ASCII_FIRST] to [ASCII_LAST].
+///
+/// Uppercase letters and shifted symbols carry the Shift flag.
+#[rustfmt::skip]
+pub const ASCII_KEYS: [Key; (ASCII_LAST - ASCII_FIRST + 1) as usize] = [
+    // ' ' to '/'
+    Key_Spacebar, lshift!(Key_1), lshift!(Key_Quote), lshift!(Key_3),
+    lshift!(Key_4), lshift!(Key_5), lshift!(Key_7), Key_Quote,
+    lshift!(Key_9), lshift!(Key_0), lshift!(Key_8), lshift!(Key_Equals),
+    Key_Comma, Key_Minus, Key_Period, Key_Slash,
+    // '0' to '?'
+    Key_0, Key_1, Key_2, Key_3, Key_4, Key_5, Key_6, Key_7,
+    Key_8, Key_9, lshift!(Key_Semicolon), Key_Semicolon,
+    lshift!(Key_Comma), Key_Equals, lshift!(Key_Period), lshift!(Key_Slash),
+    // '@' to '_'
+    lshift!(Key_2), lshift!(Key_A), lshift!(Key_B), lshift!(Key_C),
+    lshift!(Key_D), lshift!(Key_E), lshift!(Key_F), lshift!(Key_G),
+    lshift!(Key_H), lshift!(Key_I), lshift!(Key_J), lshift!(Key_K),
+    lshift!(Key_L), lshift!(Key_M), lshift!(Key_N), lshift!(Key_O),
+    lshift!(Key_P), lshift!(Key_Q), lshift!(Key_R), lshift!(Key_S),
+    lshift!(Key_T), lshift!(Key_U), lshift!(Key_V), lshift!(Key_W),
+    lshift!(Key_X), lshift!(Key_Y), lshift!(Key_Z), Key_LeftBracket,
+    Key_Backslash, Key_RightBracket, lshift!(Key_6), lshift!(Key_Minus),
+    // '`' to '~'
+    Key_Backtick, Key_A, Key_B, Key_C, Key_D, Key_E, Key_F, Key_G,
+    Key_H, Key_I, Key_J, Key_K, Key_L, Key_M, Key_N, Key_O,
+    Key_P, Key_Q, Key_R, Key_S, Key_T, Key_U, Key_V, Key_W,
+    Key_X, Key_Y, Key_Z, lshift!(Key_LeftBracket),
+    lshift!(Key_Backslash), lshift!(Key_RightBracket), lshift!(Key_Backtick),
+];
+
+/// Gets the key typing the printable ASCII character `c`.
+///
+/// Returns [Error::NotAscii] for other bytes, including control characters.
+pub fn ascii_key(c: u8) -> Result<Key> {
+    match c {
+        ASCII_FIRST..=ASCII_LAST => Ok(ASCII_KEYS[(c - ASCII_FIRST) as usize]),
+        _ => Err(Error::NotAscii),
+    }
+}
+
+/// Gets the injected key events typing `s`: a press and a release per character.
+///
+/// The whole string is checked first, so nothing is typed if it contains a byte other
+/// than printable ASCII.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::{key_defs::*, key_ext::KeyExt, key_flags_ext::KeyFlagsExt, Error};
+/// use kaleidoscope::util::typing::key_events;
+///
+/// let shift = KeyFlags::shift();
+/// let events: Vec<_> = key_events("Hi!")
+///     .unwrap()
+///     .map(|event| (*event.key(), event.state().key_toggled_on(), event.state().key_is_injected()))
+///     .collect();
+///
+/// assert_eq!(
+///     events,
+///     [
+///         (Key_H.with_flags(shift), true, true),
+///         (Key_H.with_flags(shift), false, true),
+///         (Key_I, true, true),
+///         (Key_I, false, true),
+///         (Key_1.with_flags(shift), true, true),
+///         (Key_1.with_flags(shift), false, true),
+///     ]
+/// );
+///
+/// assert!(matches!(key_events("café"), Err(Error::NotAscii)));
+/// assert!(matches!(key_events("a\tb"), Err(Error::NotAscii)));
+/// ```
+pub fn key_events(s: &str) -> Result<impl Iterator<Item = KeyEvent> + '_> {
+    for &c in s.as_bytes() {
+        ascii_key(c)?;
+    }
+
+    Ok(s.bytes().flat_map(|c| {
+        let key = ASCII_KEYS[(c - ASCII_FIRST) as usize];
+        [injected_event(key, true), injected_event(key, false)]
+    }))
+}
+
+/// Gets the injected key event `index` of typing `s`, see [key_events].
+///
+/// Returns `None` past the end of `s`, or at a byte other than printable ASCII. Used by
+/// [KeySource](crate::runtime::KeySource) generators typing text longer than the inject
+/// queue.
+///
+/// Example:
+///
+/// ```rust
+/// use kaleidoscope::key_defs::*;
+/// use kaleidoscope::util::typing::str_event;
+///
+/// assert_eq!(str_event("ab", 2).map(|event| *event.key()), Some(Key_B));
+/// assert_eq!(str_event("ab", 3).map(|event| event.state().key_toggled_off()), Some(true));
+/// assert!(str_event("ab", 4).is_none());
+/// assert!(str_event("é", 0).is_none());
+/// ```
+pub fn str_event(s: &str, index: usize) -> Option<KeyEvent> {
+    let key = ascii_key(*s.as_bytes().get(index / 2)?).ok()?;
+
+    Some(injected_event(key, index % 2 == 0))
+}
+
+/// Types `s` by injecting its [key_events] through `runtime`.
+///
+/// Must not be called from event handlers, which run while the runtime is borrowed:
+/// they queue the events with [Runtime::queue_key_source] instead, see [str_event].
+///
+/// Returns [Error::NotAscii], without typing anything, if `s` contains a byte other than
+/// printable ASCII.
+pub fn type_str(runtime: &mut Runtime, s: &str) -> Result<()> {
+    for mut event in key_events(s)? {
+        runtime.handle_key_event(&mut event);
+    }
+
+    Ok(())
+}
+
+/// Creates an injected press, or release, of `key`, not bound to any key address.
+///
+/// The default [KeyAddr] is invalid, so the event does not touch the keymap or
+/// `LIVE_KEYS` slots.
+pub fn injected_event(key: Key, pressed: bool) -> KeyEvent {
+    injected_event_at(KeyAddr::default(), key, pressed)
+}
+
+/// Creates an injected press, or release, of `key` at `addr`.
+///
+/// Used by plugins resolving a held key to another key, so the event updates the
+/// `LIVE_KEYS` slot of the held key.
+pub fn injected_event_at(addr: KeyAddr, key: Key, pressed: bool) -> KeyEvent {
+    let mut state = KeyswitchState::default();
+    state.set_injected(true);
+    if pressed {
+        state.set_is_pressed(true);
+    } else {
+        state.set_was_pressed(true);
+    }
+
+    let mut event = KeyEvent::next(addr, state);
+    event.set_key(key);
+    event
+}